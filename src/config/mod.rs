@@ -1,9 +1,14 @@
 
+use crate::shared::utils::text::closest_match;
+use regex::Regex;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::Path;
 use thiserror::Error;
 
+pub mod validate;
+
 #[derive(Error, Debug)]
 pub enum ConfigError {
     #[error("Config file not found: {0}")]
@@ -17,42 +22,141 @@ pub enum ConfigError {
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct Config {
     pub project: ProjectConfig,
     pub editor: EditorConfig,
     pub backups: BackupConfig,
     pub mcp: McpConfig,
+    #[serde(default)]
+    pub tasks: TasksConfig,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct ProjectConfig {
     pub directory: Option<String>,
     pub default_extension: Option<String>,
+    // Whether new files named without an extension get default_extension
+    pub apply_default_extension: Option<bool>,
     pub exclude_patterns: Option<Vec<String>>,
+    pub auto_detect_root: Option<bool>,
+    // Whether directory walks descend into symlinks
+    pub follow_symlinks: Option<bool>,
+    // Deepest directory level walks descend to below the project root
+    pub max_depth: Option<usize>,
+    // Whether walks honor .gitignore and .mceditignore files
+    pub respect_ignore_files: Option<bool>,
+    // Most directories walks read at once; unset uses the number of CPUs,
+    // up to 16
+    pub walk_concurrency: Option<usize>,
+    // Where session state is kept, relative to the project directory
+    pub state_directory: Option<String>,
+    // Globs, relative to the project directory, of the files that may be
+    // created or modified; unset allows every file
+    pub write_allowlist: Option<Vec<String>>,
+    // Whether the project is checkpointed as each session starts, for
+    // get_file_tree_diff. Off by default: it hashes every file and writes
+    // into the state directory, which git must ignore
+    pub checkpoint_session_start: Option<bool>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct EditorConfig {
     pub tab_size: Option<usize>,
     pub indent_with_tabs: Option<bool>,
     pub line_endings: Option<String>,
     pub max_line_length: Option<usize>,
+    pub trim_trailing_whitespace: Option<bool>,
+    pub ensure_final_newline: Option<bool>,
+    // Whether .editorconfig files in the project override these settings
+    pub use_editorconfig: Option<bool>,
+    // What to do when writing a file changed on disk since mcedit last read
+    // it: "off", "error" to refuse the write, or "warn" to back it up and go on
+    pub detect_external_changes: Option<String>,
+    // Bytes of recently read file content kept in memory and served again
+    // while the file's modification time is unchanged; 0 disables the cache
+    pub read_cache_bytes: Option<usize>,
+    // Longest run of blank lines cleanup_whitespace keeps
+    pub max_blank_lines: Option<usize>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct BackupConfig {
     pub enabled: Option<bool>,
     pub max_backups_per_file: Option<usize>,
     pub backup_directory: Option<String>,
+    pub max_backup_age_days: Option<u64>,
+    pub min_backups: Option<usize>,
+    // Globs of files backed up before edits, relative to the project root;
+    // empty means every file
+    pub backup_include: Option<Vec<String>>,
+    // Globs of files never backed up before edits, such as `*.lock`
+    pub backup_exclude: Option<Vec<String>>,
+    // Files larger than this many bytes aren't backed up before edits
+    pub max_backup_file_bytes: Option<u64>,
+    // How backups are stored: "files", one file per backup, or "archive",
+    // all backups of a file appended to a single archive
+    pub backup_format: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct McpConfig {
     pub tools: Vec<String>,
+    // Token network transports require as `Authorization: Bearer <token>`
+    pub auth_token: Option<String>,
+    // Per-connection request limit for network transports; unset disables it
+    pub rate_limit_per_second: Option<f64>,
+    // Requests a connection may make in a burst before being limited
+    pub rate_limit_burst: Option<u32>,
+    // Transport `mcedit mcp` serves over when the command line doesn't say
+    pub transport: Option<TransportConfig>,
+    // Seconds between heartbeat log lines showing the server is alive while
+    // it waits for messages; unset disables them
+    pub heartbeat_seconds: Option<u64>,
+    // Whether heartbeats are also sent to the client as $/log notifications
+    pub heartbeat_to_client: Option<bool>,
+    // Least severe level logged: "debug", "info", "warn" or "error".
+    // --quiet and MCEDIT_LOG_LEVEL take precedence
+    pub log_level: Option<String>,
 }
 
-pub fn init_default() -> anyhow::Result<Config> {
-    // Check if config exists in the default location
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct TransportConfig {
+    // "stdio" or "http"
+    pub kind: Option<String>,
+    // Address a network transport listens on, like 127.0.0.1:3000
+    pub address: Option<String>,
+}
+
+impl TransportConfig {
+    // These settings, with any left unset taken from `fallback`
+    pub fn or(&self, fallback: &TransportConfig) -> TransportConfig {
+        TransportConfig {
+            kind: self.kind.clone().or_else(|| fallback.kind.clone()),
+            address: self.address.clone().or_else(|| fallback.address.clone()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct TasksConfig {
+    // Task names run_task accepts, mapped to the command each runs in the
+    // project directory. Commands are split on whitespace, not run by a shell
+    pub commands: Option<BTreeMap<String, String>>,
+    // Seconds a task may run before it is killed
+    pub timeout_seconds: Option<u64>,
+    // Bytes of stdout, and separately of stderr, kept from a task's output
+    pub max_output_bytes: Option<usize>,
+}
+
+// The config file loaded when none is given explicitly, if one exists
+pub fn default_config_path() -> Option<String> {
     let config_paths = [
         format!(
             "{}/.config/mcedit/config.json",
@@ -61,34 +165,65 @@ pub fn init_default() -> anyhow::Result<Config> {
         "./mcedit.json".to_string(),
     ];
 
-    for path in config_paths {
-        if Path::new(&path).exists() {
-            return init_from_path(&path);
-        }
+    config_paths.into_iter().find(|path| Path::new(path).exists())
+}
+
+pub fn init_default() -> anyhow::Result<Config> {
+    // Check if config exists in the default location
+    if let Some(path) = default_config_path() {
+        return init_from_path(&path);
     }
 
     // Return default config if no config file found
-    Ok(Config {
+    Ok(default_config())
+}
+
+// The built-in settings used when there is no config file
+pub fn default_config() -> Config {
+    Config {
         project: ProjectConfig {
             directory: None,
             default_extension: Some("txt".to_string()),
+            apply_default_extension: Some(false),
             exclude_patterns: Some(vec![
                 ".git".to_string(),
                 "node_modules".to_string(),
                 "target".to_string(),
                 ".backup".to_string(),
             ]),
+            auto_detect_root: Some(true),
+            follow_symlinks: Some(false),
+            max_depth: Some(64),
+            respect_ignore_files: Some(true),
+            state_directory: Some(".mcedit".to_string()),
+            walk_concurrency: None,
+            write_allowlist: None,
+            checkpoint_session_start: Some(false),
         },
         editor: EditorConfig {
             tab_size: Some(4),
-            indent_with_tabs: Some(false),
-            line_endings: Some("lf".to_string()),
+            // Leave indentation as written unless explicitly configured
+            indent_with_tabs: None,
+            // Keep each file's own line endings unless explicitly configured
+            line_endings: None,
             max_line_length: Some(100),
+            trim_trailing_whitespace: Some(false),
+            ensure_final_newline: Some(false),
+            use_editorconfig: Some(true),
+            detect_external_changes: Some("off".to_string()),
+            read_cache_bytes: Some(16 * 1024 * 1024),
+            max_blank_lines: Some(1),
         },
         backups: BackupConfig {
             enabled: Some(true),
             max_backups_per_file: Some(10),
             backup_directory: None,
+            max_backup_age_days: Some(30),
+            min_backups: Some(1),
+            backup_include: Some(Vec::new()),
+            backup_exclude: Some(Vec::new()),
+            max_backup_file_bytes: None,
+            backup_format: Some("files".to_string()),
         },
         mcp: McpConfig {
             tools: vec![
@@ -100,8 +235,23 @@ pub fn init_default() -> anyhow::Result<Config> {
                 "apply_suggestion".to_string(),
                 "generate_diff".to_string(),
             ],
+            auth_token: None,
+            rate_limit_per_second: None,
+            rate_limit_burst: None,
+            transport: Some(TransportConfig {
+                kind: Some("stdio".to_string()),
+                address: Some("127.0.0.1:3000".to_string()),
+            }),
+            heartbeat_seconds: None,
+            heartbeat_to_client: Some(false),
+            log_level: Some("info".to_string()),
         },
-    })
+        tasks: TasksConfig {
+            commands: Some(BTreeMap::new()),
+            timeout_seconds: Some(300),
+            max_output_bytes: Some(64 * 1024),
+        },
+    }
 }
 
 pub fn init_from_path(path: &str) -> anyhow::Result<Config> {
@@ -115,6 +265,54 @@ pub fn init_from_path(path: &str) -> anyhow::Result<Config> {
 
     match serde_json::from_str(&content) {
         Ok(config) => Ok(config),
-        Err(e) => Err(ConfigError::ParseError(e.to_string()).into()),
+        Err(e) => Err(ConfigError::ParseError(describe_parse_error(&e)).into()),
+    }
+}
+
+// Rewrites serde's unknown field error, which lists every valid key, into
+// one naming the closest valid key as the likely intended one
+fn describe_parse_error(error: &serde_json::Error) -> String {
+    let message = error.to_string();
+    let unknown_field = Regex::new(r"^unknown field `([^`]*)`, expected (.*) at line").unwrap();
+
+    let captures = match unknown_field.captures(&message) {
+        Some(captures) => captures,
+        None => return message,
+    };
+
+    let field = &captures[1];
+    let expected: Vec<&str> = captures[2]
+        .split('`')
+        .skip(1)
+        .step_by(2)
+        .collect();
+
+    let suggestion = match closest_match(field, &expected) {
+        Some(key) => format!("did you mean `{}`?", key),
+        None => format!("valid keys here are {}", captures[2].trim_start_matches("one of ")),
+    };
+
+    format!(
+        "unknown field `{}` at line {} column {}: {}",
+        field,
+        error.line(),
+        error.column(),
+        suggestion
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unknown_field_suggests_closest_key() {
+        let json = r#"{"directory": null, "exclude_pattern": ["target"]}"#;
+        let error = serde_json::from_str::<ProjectConfig>(json).unwrap_err();
+
+        assert_eq!(
+            describe_parse_error(&error),
+            "unknown field `exclude_pattern` at line 1 column 37: did you mean `exclude_patterns`?"
+        );
     }
 }
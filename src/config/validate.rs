@@ -0,0 +1,184 @@
+use super::Config;
+use crate::shared::logging::LogLevel;
+use std::path::Path;
+
+// Values accepted for editor.line_endings
+pub const LINE_ENDINGS: &[&str] = &["lf", "crlf", "native"];
+
+// Values accepted for editor.detect_external_changes
+pub const EXTERNAL_CHANGE_MODES: &[&str] = &["off", "error", "warn"];
+
+// Values accepted for backups.backup_format
+pub const BACKUP_FORMATS: &[&str] = &["files", "archive"];
+
+// Values accepted for mcp.transport.kind
+pub const TRANSPORT_KINDS: &[&str] = &["stdio", "http"];
+
+// Checks a loaded config for problems that would otherwise only surface
+// later as confusing errors. Returns one message per problem found
+pub fn validate(config: &Config, known_tools: &[String]) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    if let Some(directory) = &config.project.directory {
+        let path = Path::new(directory);
+        if path.exists() {
+            if !path.is_dir() {
+                problems.push(format!(
+                    "project.directory is not a directory: {}",
+                    directory
+                ));
+            }
+        } else if !can_create(path) {
+            problems.push(format!(
+                "project.directory does not exist and cannot be created: {}",
+                directory
+            ));
+        }
+    }
+
+    if let Some(directory) = &config.backups.backup_directory {
+        let path = Path::new(directory);
+        if path.exists() {
+            if !path.is_dir() {
+                problems.push(format!(
+                    "backups.backup_directory is not a directory: {}",
+                    directory
+                ));
+            } else if !is_writable(path) {
+                problems.push(format!(
+                    "backups.backup_directory is not writable: {}",
+                    directory
+                ));
+            }
+        } else if !can_create(path) {
+            problems.push(format!(
+                "backups.backup_directory does not exist and cannot be created: {}",
+                directory
+            ));
+        }
+    }
+
+    if let Some(line_endings) = &config.editor.line_endings {
+        if !LINE_ENDINGS.contains(&line_endings.as_str()) {
+            problems.push(format!(
+                "editor.line_endings must be one of {}, got: {}",
+                LINE_ENDINGS.join(", "),
+                line_endings
+            ));
+        }
+    }
+
+    let backup_globs = [
+        ("backups.backup_include", &config.backups.backup_include),
+        ("backups.backup_exclude", &config.backups.backup_exclude),
+        ("project.write_allowlist", &config.project.write_allowlist),
+    ];
+    for (key, globs) in backup_globs {
+        for glob in globs.iter().flatten() {
+            if let Err(e) = globset::Glob::new(glob) {
+                problems.push(format!("{} contains an invalid glob {}: {}", key, glob, e));
+            }
+        }
+    }
+
+    if let Some(mode) = &config.editor.detect_external_changes {
+        if !EXTERNAL_CHANGE_MODES.contains(&mode.as_str()) {
+            problems.push(format!(
+                "editor.detect_external_changes must be one of {}, got: {}",
+                EXTERNAL_CHANGE_MODES.join(", "),
+                mode
+            ));
+        }
+    }
+
+    if let Some(format) = &config.backups.backup_format {
+        if !BACKUP_FORMATS.contains(&format.as_str()) {
+            problems.push(format!(
+                "backups.backup_format must be one of {}, got: {}",
+                BACKUP_FORMATS.join(", "),
+                format
+            ));
+        }
+    }
+
+    if let Some(transport) = &config.mcp.transport {
+        if let Some(kind) = &transport.kind {
+            if !TRANSPORT_KINDS.contains(&kind.as_str()) {
+                problems.push(format!(
+                    "mcp.transport.kind must be one of {}, got: {}",
+                    TRANSPORT_KINDS.join(", "),
+                    kind
+                ));
+            }
+        }
+        if let Some(address) = &transport.address {
+            if address.parse::<std::net::SocketAddr>().is_err() {
+                problems.push(format!(
+                    "mcp.transport.address is not a valid address like 127.0.0.1:3000: {}",
+                    address
+                ));
+            }
+        }
+    }
+
+    if config.project.walk_concurrency == Some(0) {
+        problems.push("project.walk_concurrency must be at least 1".to_string());
+    }
+
+    if config.mcp.heartbeat_seconds == Some(0) {
+        problems.push("mcp.heartbeat_seconds must be at least 1".to_string());
+    }
+
+    if let Some(level) = &config.mcp.log_level {
+        if LogLevel::parse(level).is_none() {
+            problems.push(format!(
+                "mcp.log_level must be one of debug, info, warn, error, got: {}",
+                level
+            ));
+        }
+    }
+
+    for tool in &config.mcp.tools {
+        if !known_tools.contains(tool) {
+            problems.push(format!("mcp.tools contains an unknown tool: {}", tool));
+        }
+    }
+
+    problems
+}
+
+// Loads the config at `path`, or the built-in defaults without one, and
+// checks it. A config that fails to load is its own only problem
+pub fn load_and_validate(path: Option<&str>, known_tools: &[String]) -> Vec<String> {
+    let loaded = match path {
+        Some(path) => super::init_from_path(path),
+        None => super::init_default(),
+    };
+    match loaded {
+        Ok(config) => validate(&config, known_tools),
+        Err(err) => vec![err.to_string()],
+    }
+}
+
+// Whether a file can be created in the directory
+pub fn is_writable(dir: &Path) -> bool {
+    tempfile::tempfile_in(dir).is_ok()
+}
+
+// Whether a missing directory could be created, judged by the nearest
+// existing ancestor being a writable directory
+fn can_create(path: &Path) -> bool {
+    path.ancestors()
+        .skip(1)
+        // A relative path's last ancestor is empty, meaning the current directory
+        .map(|ancestor| {
+            if ancestor.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                ancestor
+            }
+        })
+        .find(|ancestor| ancestor.exists())
+        .map(|ancestor| ancestor.is_dir() && is_writable(ancestor))
+        .unwrap_or(false)
+}
@@ -0,0 +1,119 @@
+use anyhow::{anyhow, bail};
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+// Checkpoint taken when a session starts, diffed against when no other is named
+pub const SESSION_START: &str = "session-start";
+
+// Where checkpoints are kept, inside the state directory
+pub const CHECKPOINTS_DIRECTORY: &str = "checkpoints";
+
+// The project's files at one point in time, as path -> content hash
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub name: String,
+    pub created_at: String,
+    pub files: BTreeMap<String, String>,
+}
+
+// How the project's files differ from a checkpoint
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct TreeDiff {
+    pub added: Vec<String>,
+    pub modified: Vec<String>,
+    pub deleted: Vec<String>,
+}
+
+impl Checkpoint {
+    pub fn new(name: &str, files: BTreeMap<String, String>) -> Self {
+        Self {
+            name: name.to_string(),
+            created_at: Local::now().to_rfc3339(),
+            files,
+        }
+    }
+
+    // Compares `current`, a manifest of the project now, against the checkpoint
+    pub fn diff(&self, current: &BTreeMap<String, String>) -> TreeDiff {
+        let mut diff = TreeDiff::default();
+        for (path, hash) in current {
+            match self.files.get(path) {
+                None => diff.added.push(path.clone()),
+                Some(old) if old != hash => diff.modified.push(path.clone()),
+                Some(_) => {}
+            }
+        }
+        diff.deleted = self
+            .files
+            .keys()
+            .filter(|path| !current.contains_key(*path))
+            .cloned()
+            .collect();
+        diff
+    }
+
+    pub fn save(&self, state_directory: &Path) -> anyhow::Result<PathBuf> {
+        let path = checkpoint_path(state_directory, &self.name)?;
+        std::fs::create_dir_all(state_directory.join(CHECKPOINTS_DIRECTORY))?;
+        std::fs::write(&path, serde_json::to_string_pretty(self)?)?;
+        Ok(path)
+    }
+
+    pub fn load(state_directory: &Path, name: &str) -> anyhow::Result<Self> {
+        let path = checkpoint_path(state_directory, name)?;
+        let content = std::fs::read_to_string(&path)
+            .map_err(|_| anyhow!("No checkpoint named '{}'", name))?;
+        Ok(serde_json::from_str(&content)?)
+    }
+}
+
+// Names become file names, so they may not reach outside the checkpoints directory
+fn checkpoint_path(state_directory: &Path, name: &str) -> anyhow::Result<PathBuf> {
+    let valid = !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if !valid {
+        bail!(
+            "Invalid checkpoint name '{}': use letters, digits, '-', '_' and '.'",
+            name
+        );
+    }
+    Ok(state_directory
+        .join(CHECKPOINTS_DIRECTORY)
+        .join(format!("{}.json", name)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest(files: &[(&str, &str)]) -> BTreeMap<String, String> {
+        files
+            .iter()
+            .map(|(path, hash)| (path.to_string(), hash.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_diff_against_checkpoint() {
+        let checkpoint = Checkpoint::new(
+            SESSION_START,
+            manifest(&[("a.rs", "1"), ("b.rs", "2"), ("c.rs", "3")]),
+        );
+        let current = manifest(&[("a.rs", "1"), ("b.rs", "20"), ("d.rs", "4")]);
+
+        assert_eq!(
+            checkpoint.diff(&current),
+            TreeDiff {
+                added: vec!["d.rs".to_string()],
+                modified: vec!["b.rs".to_string()],
+                deleted: vec!["c.rs".to_string()],
+            }
+        );
+        assert!(checkpoint_path(Path::new(".mcedit"), "../state").is_err());
+    }
+}
@@ -0,0 +1,182 @@
+use crate::config::{self, validate};
+use crate::core::mcedit::{git_version, McEdit};
+use crate::mcp::handler::McpHandler;
+use serde_json::{json, Value};
+use std::path::Path;
+
+// Free space on the project's disk below which doctor warns
+const MIN_FREE_BYTES: u64 = 100 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CheckStatus {
+    Ok,
+    // Worth fixing, but mcedit works without it
+    Warn,
+    // mcedit won't start or can't edit files until it is fixed
+    Fail,
+}
+
+impl CheckStatus {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Ok => "ok",
+            Self::Warn => "warn",
+            Self::Fail => "FAIL",
+        }
+    }
+}
+
+// Outcome of one doctor check
+pub struct Check {
+    pub name: &'static str,
+    pub status: CheckStatus,
+    pub detail: String,
+}
+
+impl Check {
+    fn new(name: &'static str, status: CheckStatus, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            status,
+            detail: detail.into(),
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "name": self.name,
+            "status": self.status.label().to_lowercase(),
+            "detail": self.detail
+        })
+    }
+}
+
+// Runs every check against the config and project directory mcedit would
+// start with. Checks of the project need it to load, so they're left out
+// when the config or the project directory fails
+pub async fn run(config_path: Option<String>, project_dir: Option<String>) -> Vec<Check> {
+    let mut checks = vec![Check::new(
+        "version",
+        CheckStatus::Ok,
+        format!(
+            "mcedit {} ({})",
+            env!("CARGO_PKG_VERSION"),
+            env!("GIT_HASH")
+        ),
+    )];
+
+    let source = config_path.clone().or_else(config::default_config_path);
+    let problems = validate::load_and_validate(source.as_deref(), &McpHandler::tool_names());
+    let source = source.unwrap_or_else(|| "built-in defaults".to_string());
+    if problems.is_empty() {
+        checks.push(Check::new(
+            "config",
+            CheckStatus::Ok,
+            format!("{} is valid", source),
+        ));
+    } else {
+        checks.push(Check::new(
+            "config",
+            CheckStatus::Fail,
+            format!("{}: {}", source, problems.join("; ")),
+        ));
+        return checks;
+    }
+
+    let mcedit = match McEdit::new(config_path, project_dir, false) {
+        Ok(mcedit) => mcedit,
+        Err(err) => {
+            checks.push(Check::new(
+                "project directory",
+                CheckStatus::Fail,
+                err.to_string(),
+            ));
+            return checks;
+        }
+    };
+
+    let project = mcedit.get_current_directory();
+    checks.push(check_directory("project directory", &project));
+
+    let backups = mcedit.backup_directory();
+    checks.push(match std::fs::create_dir_all(backups) {
+        Ok(()) => check_directory("backup directory", backups),
+        Err(err) => Check::new(
+            "backup directory",
+            CheckStatus::Fail,
+            format!("{} cannot be created: {}", backups.display(), err),
+        ),
+    });
+
+    checks.push(match git_version().await {
+        Some(version) => Check::new("git", CheckStatus::Ok, version),
+        None => Check::new(
+            "git",
+            CheckStatus::Warn,
+            "git not found; git-aware features are unavailable",
+        ),
+    });
+
+    checks.push(match free_bytes(&project) {
+        Some(free) if free < MIN_FREE_BYTES => Check::new(
+            "disk space",
+            CheckStatus::Warn,
+            format!("only {} MiB free", free / (1024 * 1024)),
+        ),
+        Some(free) => Check::new(
+            "disk space",
+            CheckStatus::Ok,
+            format!("{} MiB free", free / (1024 * 1024)),
+        ),
+        None => Check::new(
+            "disk space",
+            CheckStatus::Warn,
+            "free space could not be determined",
+        ),
+    });
+
+    checks
+}
+
+// A directory mcedit must both read and write
+fn check_directory(name: &'static str, dir: &Path) -> Check {
+    if let Err(err) = std::fs::read_dir(dir) {
+        return Check::new(
+            name,
+            CheckStatus::Fail,
+            format!("{} cannot be read: {}", dir.display(), err),
+        );
+    }
+    if !validate::is_writable(dir) {
+        return Check::new(
+            name,
+            CheckStatus::Fail,
+            format!("{} is not writable", dir.display()),
+        );
+    }
+    Check::new(
+        name,
+        CheckStatus::Ok,
+        format!("{} is readable and writable", dir.display()),
+    )
+}
+
+// Bytes available to unprivileged users on the disk holding `path`
+#[cfg(unix)]
+fn free_bytes(path: &Path) -> Option<u64> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stats: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stats) } != 0 {
+        return None;
+    }
+    // The field types differ between platforms, so the casts aren't always no-ops
+    #[allow(clippy::unnecessary_cast)]
+    Some(stats.f_bavail as u64 * stats.f_frsize as u64)
+}
+
+#[cfg(not(unix))]
+fn free_bytes(_path: &Path) -> Option<u64> {
+    None
+}
@@ -1,14 +1,51 @@
 
-use crate::config::{self, Config};
-use crate::diff::generator::DiffGenerator;
-use crate::editor::file_editor::FileEditor;
-use crate::file_service::service::FileService;
+use crate::config::{self, Config, TransportConfig};
+use crate::core::checkpoint::{self, Checkpoint};
+use crate::core::state::StateStore;
+use crate::diff::generator::{DiffFormat, DiffGenerator, WhitespaceMode};
+use crate::editor::file_editor;
+use crate::editor::file_editor::{RegionEdit, WhitespaceCleanup};
+use crate::file_service::backup::{
+    BackupError, BackupFormat, MAX_BACKUPS_PER_FILE, MIN_BACKUPS_PER_FILE,
+};
+use crate::file_service::cache::DEFAULT_READ_CACHE_BYTES;
+use crate::file_service::service::{
+    hash_file, ChangeSummary, ExternalChangePolicy, FileService, HashAlgorithm, InsertPosition,
+};
 use crate::mcp::handler::McpHandler;
-use crate::mcp::stdio::StdioTransport;
-use crate::project::analyzer::ProjectAnalyzer;
-use crate::shared::logging;
+use crate::mcp::http::{HttpSseTransport, DEFAULT_HTTP_ADDRESS};
+use crate::mcp::heartbeat::Heartbeat;
+use crate::mcp::rate_limit::{RateLimiter, DEFAULT_RATE_LIMIT_BURST};
+use crate::mcp::stdio::{self, Message, StdioTransport, Transport};
+use crate::project::analyzer::{
+    sort_files, AnalysisSections, FileSort, ProjectAnalyzer, SearchOptions, SearchQuery,
+    MAX_REFERENCE_FILE_SIZE,
+};
+use crate::project::outline;
+use crate::project::tasks::{
+    TaskOutput, TaskRunner, DEFAULT_MAX_OUTPUT_BYTES, DEFAULT_TASK_TIMEOUT_SECS,
+};
+use crate::project::walker::{walk_concurrency, DEFAULT_MAX_DEPTH};
+use crate::shared::language;
+use crate::shared::logging::{self, LogLevel};
+use crate::shared::mime;
+use crate::shared::tokens;
+use crate::shared::utils::path::{
+    expand_tilde, find_project_root, path_from_file_uri, to_display_path,
+};
 use crate::suggestions::parser::SuggestionParser;
+use regex::Regex;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tokio::sync::broadcast;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::sync::watch;
+
+// Sender side of a transport's incoming messages, kept for as long as the
+// server runs so the message stream stays open
+type IncomingSender = broadcast::Sender<Result<Message, stdio::Error>>;
 
 #[derive(Debug, thiserror::Error)]
 pub enum McEditError {
@@ -41,18 +78,32 @@ pub enum JsonRpcErrorCode {
     PermissionDenied = -32001,
     InvalidPath = -32002,
     DiffError = -32003,
+    RateLimited = -32004,
 }
 
 pub struct McEdit {
     #[allow(dead_code)]
     config: Config,
+    // Where the config was loaded from, if a file, for reloading it
+    config_path: Option<PathBuf>,
     file_service: FileService,
     project_analyzer: ProjectAnalyzer,
+    // Session state of the current project, kept across runs
+    state: StateStore,
     current_directory: PathBuf,
+    // Directories the client allows access to, once it has listed them
+    client_roots: Option<Vec<PathBuf>>,
+    // Whether the session start checkpoint, taken in the background, was
+    // written, once it is done. None when no checkpoint is being taken
+    session_checkpoint: Option<watch::Receiver<Option<bool>>>,
 }
 
 impl McEdit {
-    pub fn new(config_path: Option<String>, project_dir: Option<String>) -> anyhow::Result<Self> {
+    pub fn new(
+        config_path: Option<String>,
+        project_dir: Option<String>,
+        create_dir: bool,
+    ) -> anyhow::Result<Self> {
         // Check environment variable for project directory first
         let env_project_dir = std::env::var("PROJECT_DIR").ok();
         if let Some(dir) = &env_project_dir {
@@ -63,12 +114,12 @@ impl McEdit {
         }
 
         // Initialize config
-        let config = match config_path {
+        let config_path = match config_path {
             Some(path) => {
                 let path_buf = PathBuf::from(&path);
                 if path_buf.is_absolute() {
                     logging::info(&format!("Using absolute config path: {}", path));
-                    config::init_from_path(&path)?
+                    Some(path_buf)
                 } else {
                     // Convert to absolute path
                     let abs_path = std::env::current_dir()?.join(&path);
@@ -76,20 +127,27 @@ impl McEdit {
                         "Converting relative config path to absolute: {}",
                         abs_path.display()
                     ));
-                    config::init_from_path(abs_path.to_str().unwrap_or(&path))?
+                    Some(abs_path)
                 }
             }
             None => {
                 logging::info("No config path provided, using default configuration");
-                config::init_default()?
+                // Remember the default file, if any, so it can be reloaded
+                config::default_config_path().map(PathBuf::from)
             }
         };
+        let config = load_config(config_path.as_deref())?;
+
+        // Fail fast on settings that would otherwise only break later
+        validate_config(&config)?;
+        logging::set_configured_level(config.mcp.log_level.as_deref().and_then(LogLevel::parse));
 
         // Priority for project directory:
         // 1. Command line argument
         // 2. Environment variable
         // 3. Config file
-        // 4. Current directory
+        // 4. Project root detected from the current directory (auto_detect_root)
+        // 5. Current directory
         let project_directory = match project_dir {
             Some(dir) => {
                 let dir_buf = PathBuf::from(&dir);
@@ -136,14 +194,23 @@ impl McEdit {
                                 }
                             }
                             None => {
-                                // If we're in root (/) directory, use HOME directory as fallback
                                 let current_dir = std::env::current_dir()?;
-                                if current_dir == PathBuf::from("/") {
-                                    // We're likely running from a container or restricted environment
-                                    let home_dir = dirs::home_dir().unwrap_or(current_dir.clone());
-                                    let project_dir = home_dir.join("project");
-                                    logging::info(&format!("Working directory is root (/), falling back to home directory: {}", project_dir.display()));
-                                    project_dir
+                                let detected_root = if config.project.auto_detect_root.unwrap_or(true) {
+                                    find_project_root(&current_dir)
+                                } else {
+                                    None
+                                };
+
+                                if let Some(root) = detected_root {
+                                    logging::info(&format!("Detected project root from current directory: {}", root.display()));
+                                    root
+                                } else if current_dir == PathBuf::from("/") {
+                                    // We're likely running from a container or restricted
+                                    // environment, where the root directory is never the
+                                    // intended project
+                                    return Err(McEditError::InvalidDirectory(
+                                        "Could not determine a project directory. Pass --dir, set the PROJECT_DIR environment variable, or set project.directory in the config file".to_string(),
+                                    ).into());
                                 } else {
                                     logging::info(&format!("No project directory specified, using current directory: {}", current_dir.display()));
                                     current_dir
@@ -155,8 +222,16 @@ impl McEdit {
             }
         };
 
-        // Create directory if it doesn't exist
+        // Only create a missing project directory when explicitly asked to
         if !project_directory.exists() {
+            if !create_dir {
+                return Err(McEditError::InvalidDirectory(format!(
+                    "Project directory does not exist: {} (pass --create-dir to create it)",
+                    project_directory.display()
+                ))
+                .into());
+            }
+
             logging::info(&format!(
                 "Creating project directory: {}",
                 project_directory.display()
@@ -165,90 +240,455 @@ impl McEdit {
         }
 
         // Create file service and project analyzer
-        let file_service = FileService::new(&project_directory)?;
-        let project_analyzer = ProjectAnalyzer::new(project_directory.clone());
+        let file_service = FileService::new(&project_directory, &config)?;
+        let project_analyzer = ProjectAnalyzer::new(project_directory.clone(), &config.project);
+        let state = StateStore::open(&project_directory, &config.project);
 
         logging::info("McEdit initialized successfully");
         Ok(Self {
             config,
+            config_path,
             file_service,
             project_analyzer,
+            state,
             current_directory: project_directory,
+            client_roots: None,
+            session_checkpoint: None,
         })
     }
 
-    pub async fn launch_mcp(&mut self) -> anyhow::Result<()> {
-        let (transport, _sender) = StdioTransport::new();
+    // Re-reads the config file and applies the settings that can change while
+    // running: exclude patterns, editor settings, the backup policy and the log
+    // level. The project directory and the network transport settings are
+    // fixed once the server has started, so changes to them are skipped
+    pub fn reload_config(&mut self) -> anyhow::Result<()> {
+        let mut config = load_config(self.config_path.as_deref())?;
+        validate_config(&config)?;
+
+        if config.project.directory != self.config.project.directory {
+            logging::warn("Config reload: project.directory cannot change while running, skipped (use change_directory instead)");
+            config.project.directory = self.config.project.directory.clone();
+        }
+        if config.mcp.auth_token != self.config.mcp.auth_token
+            || config.mcp.rate_limit_per_second != self.config.mcp.rate_limit_per_second
+            || config.mcp.rate_limit_burst != self.config.mcp.rate_limit_burst
+            || config.mcp.transport != self.config.mcp.transport
+            || config.mcp.heartbeat_seconds != self.config.mcp.heartbeat_seconds
+            || config.mcp.heartbeat_to_client != self.config.mcp.heartbeat_to_client
+        {
+            logging::warn("Config reload: mcp.transport, auth_token, rate limits and heartbeat are fixed once serving starts and need a restart, skipped");
+            config.mcp.auth_token = self.config.mcp.auth_token.clone();
+            config.mcp.rate_limit_per_second = self.config.mcp.rate_limit_per_second;
+            config.mcp.rate_limit_burst = self.config.mcp.rate_limit_burst;
+            config.mcp.transport = self.config.mcp.transport.clone();
+            config.mcp.heartbeat_seconds = self.config.mcp.heartbeat_seconds;
+            config.mcp.heartbeat_to_client = self.config.mcp.heartbeat_to_client;
+        }
+
+        // Rebuild the services holding config-derived state for the same directory
+        let file_service = FileService::new(&self.current_directory, &config)?;
+        self.project_analyzer =
+            ProjectAnalyzer::new(self.current_directory.clone(), &config.project);
+        self.project_analyzer.set_client_roots(self.client_roots.as_deref());
+        self.file_service = file_service;
+        self.file_service.set_client_roots(self.client_roots.as_deref());
+        logging::set_configured_level(config.mcp.log_level.as_deref().and_then(LogLevel::parse));
+        self.config = config;
+
+        logging::info(&format!(
+            "Reloaded config from {}",
+            self.config_path
+                .as_ref()
+                .map(|path| path.display().to_string())
+                .unwrap_or_else(|| "built-in defaults".to_string())
+        ));
+        Ok(())
+    }
+
+    // Serves MCP over the transport `requested` names, with settings it
+    // leaves unset taken from the config. Stdio unless either says otherwise
+    pub async fn launch_mcp(&mut self, requested: &TransportConfig) -> anyhow::Result<()> {
+        let transport_config =
+            requested.or(&self.config.mcp.transport.clone().unwrap_or_default());
+        let (transport, _sender) = self.open_transport(&transport_config).await?;
+
+        self.serve(transport.as_ref()).await
+    }
+
+    // Opens the transport `config` describes
+    async fn open_transport(
+        &self,
+        config: &TransportConfig,
+    ) -> anyhow::Result<(Box<dyn Transport>, IncomingSender)> {
+        match config.kind.as_deref().unwrap_or("stdio") {
+            "stdio" => {
+                let (transport, sender) = StdioTransport::new();
+                Ok((Box::new(transport), sender))
+            }
+            "http" => {
+                let address = config.address.as_deref().unwrap_or(DEFAULT_HTTP_ADDRESS);
+                let addr = address
+                    .parse()
+                    .map_err(|_| anyhow::anyhow!("Invalid transport address: {}", address))?;
+                let auth_token = self.config.mcp.auth_token.clone();
+                let (transport, sender) = HttpSseTransport::bind(addr, auth_token).await?;
+                Ok((Box::new(transport), sender))
+            }
+            other => Err(anyhow::anyhow!(
+                "Unknown transport: {} (expected stdio or http)",
+                other
+            )),
+        }
+    }
 
+    // Serves MCP over an open transport until its messages end or the server
+    // is shut down
+    pub async fn serve(&mut self, transport: &dyn Transport) -> anyhow::Result<()> {
         // Log environment information
         let cwd = std::env::current_dir()?;
         logging::info(&format!("Current working directory: {}", cwd.display()));
 
-        // Check if PROJECT_DIR environment variable is set, and if not, set it
-        if std::env::var("PROJECT_DIR").is_err() {
-            let home_dir = dirs::home_dir().unwrap_or_else(|| PathBuf::from("."));
-            let default_project_dir = home_dir.join("project");
+        // Network clients share the server, so one aggressive client must not
+        // be able to monopolize it
+        let rate_limiter = match transport.name() {
+            "stdio" => None,
+            _ => self.config.mcp.rate_limit_per_second.map(|rate| {
+                let burst = self
+                    .config
+                    .mcp
+                    .rate_limit_burst
+                    .unwrap_or(DEFAULT_RATE_LIMIT_BURST);
+                RateLimiter::new(rate, burst)
+            }),
+        };
 
-            // Create the directory if it doesn't exist
-            if !default_project_dir.exists() {
-                logging::info(&format!(
-                    "Creating default project directory: {}",
-                    default_project_dir.display()
-                ));
-                std::fs::create_dir_all(&default_project_dir)?;
-            }
+        let heartbeat = self
+            .config
+            .mcp
+            .heartbeat_seconds
+            .filter(|seconds| *seconds > 0)
+            .map(|seconds| {
+                Heartbeat::new(
+                    Duration::from_secs(seconds),
+                    self.config.mcp.heartbeat_to_client.unwrap_or(false),
+                )
+            });
 
-            // Create a sample README.md file to help users get started
-            let readme_path = default_project_dir.join("README.md");
-            if !readme_path.exists() {
-                logging::info(&format!(
-                    "Creating sample README.md file at: {}",
-                    readme_path.display()
-                ));
-                let sample_content = r#"# Sample Project
+        self.checkpoint_session_start();
+
+        // Create the handler and launch MCP
+        let mut handler = McpHandler::new(self)
+            .with_rate_limiter(rate_limiter)
+            .with_heartbeat(heartbeat);
+        let result = handler.launch_mcp(transport).await;
+        self.save_state();
+        result
+    }
+
+    // Session state
+
+    // Writes session state to the state directory, logging rather than
+    // failing since losing it only loses history
+    pub fn save_state(&self) {
+        if let Err(err) = self.state.save() {
+            logging::warn(&format!(
+                "Failed to save session state to {}: {}",
+                self.state.directory().display(),
+                err
+            ));
+        }
+    }
+
+    pub fn record_tool_call(&self, tool: &str, path: Option<&str>) {
+        self.state.record_tool_call(tool, path);
+    }
+
+    pub fn cache_stats(&self) -> serde_json::Value {
+        self.file_service.cache_stats()
+    }
+
+    // Forgets all session state, returning what was held before
+    pub fn clear_state(&self) -> anyhow::Result<serde_json::Value> {
+        let cleared = self.state.summary();
+        self.state.clear()?;
+        logging::info(&format!(
+            "Cleared session state in {}",
+            self.state.directory().display()
+        ));
+
+        Ok(serde_json::json!({
+            "state_directory": to_display_path(self.state.directory()),
+            "cleared": cleared
+        }))
+    }
+
+    // Checkpoints
+
+    async fn tree_manifest(&self) -> anyhow::Result<BTreeMap<String, String>> {
+        tree_manifest(&self.project_analyzer, &self.current_directory, self.state.directory()).await
+    }
+
+    // Snapshots the project's files under `name`, replacing any checkpoint
+    // of that name
+    pub async fn create_checkpoint(&self, name: &str) -> anyhow::Result<serde_json::Value> {
+        let checkpoint = Checkpoint::new(name, self.tree_manifest().await?);
+        let path = checkpoint.save(self.state.directory())?;
+        logging::info(&format!(
+            "Created checkpoint '{}' of {} files in {}",
+            name,
+            checkpoint.files.len(),
+            path.display()
+        ));
 
-This is a sample project created by McEdit.
+        Ok(serde_json::json!({
+            "name": checkpoint.name,
+            "created_at": checkpoint.created_at,
+            "files": checkpoint.files.len()
+        }))
+    }
 
-## Getting Started
+    // Checkpoints the project as the session starts when
+    // checkpoint_session_start is set, so the session's changes can be listed
+    // later. Hashing every file takes a while in a large project, so it runs
+    // in the background rather than holding up the client's first requests.
+    // The checkpoint lands in the project's state directory, so it is skipped
+    // unless git ignores that. Failing only loses that summary
+    pub fn checkpoint_session_start(&mut self) {
+        self.session_checkpoint = None;
+        if !self.config.project.checkpoint_session_start.unwrap_or(false) {
+            return;
+        }
 
-You can edit this file or create new files in this directory.
+        let analyzer = self.project_analyzer.clone();
+        let root = self.current_directory.clone();
+        let state_directory = self.state.directory().to_path_buf();
+        let (written, finished) = watch::channel(None);
+        self.session_checkpoint = Some(finished);
 
-## Features
+        tokio::spawn(async move {
+            let checkpoints = state_directory.join(checkpoint::CHECKPOINTS_DIRECTORY);
+            if !git_ignores(&root, &checkpoints).await {
+                logging::warn(&format!(
+                    "Not checkpointing session start: git doesn't ignore {}",
+                    checkpoints.display()
+                ));
+                let _ = written.send(Some(false));
+                return;
+            }
 
-- Edit any text file
-- Analyze project structure
-- Apply suggested changes
-"#;
-                std::fs::write(&readme_path, sample_content)?;
+            let saved = match tree_manifest(&analyzer, &root, &state_directory).await {
+                Ok(files) => Checkpoint::new(checkpoint::SESSION_START, files).save(&state_directory),
+                Err(err) => Err(err),
+            };
+            match &saved {
+                Ok(path) => logging::info(&format!("Checkpointed session start in {}", path.display())),
+                Err(err) => logging::warn(&format!("Failed to checkpoint session start: {}", err)),
             }
+            let _ = written.send(Some(saved.is_ok()));
+        });
+    }
 
-            // Set the environment variable for future uses in this process
-            std::env::set_var(
-                "PROJECT_DIR",
-                default_project_dir.to_string_lossy().to_string(),
-            );
-            logging::info(&format!(
-                "Set PROJECT_DIR to: {}",
-                default_project_dir.display()
-            ));
+    // Files added, modified and deleted since checkpoint `name`, by default
+    // the one taken at session start
+    pub async fn file_tree_diff(&self, name: Option<&str>) -> anyhow::Result<serde_json::Value> {
+        let name = name.unwrap_or(checkpoint::SESSION_START);
+        // Wait for a session start checkpoint still being taken, rather than
+        // reading the one left by the previous session
+        if let (checkpoint::SESSION_START, Some(mut finished)) = (name, self.session_checkpoint.clone()) {
+            let written = finished
+                .wait_for(Option::is_some)
+                .await
+                .map(|written| *written == Some(true))
+                .unwrap_or(false);
+            if !written {
+                anyhow::bail!("The session start checkpoint couldn't be taken; see the server log");
+            }
         }
+        let checkpoint = Checkpoint::load(self.state.directory(), name)?;
+        let diff = checkpoint.diff(&self.tree_manifest().await?);
 
-        // Create the handler and launch MCP
-        let mut handler = McpHandler::new(self);
-        handler.launch_mcp(&transport).await
+        Ok(serde_json::json!({
+            "checkpoint": checkpoint.name,
+            "created_at": checkpoint.created_at,
+            "added": diff.added,
+            "modified": diff.modified,
+            "deleted": diff.deleted
+        }))
     }
 
     // File operations
 
+    // Where the server would take `path` to be, after expanding `~` and
+    // resolving it against `cwd` and the project, or why it is rejected
+    pub fn resolve_path(&self, path: &str, cwd: Option<&Path>) -> anyhow::Result<serde_json::Value> {
+        let expanded = self.path_in(cwd, &expand_tilde(path))?;
+        let (resolved, writable) = self.file_service.preview_path(&expanded)?;
+        let relative = resolved
+            .strip_prefix(&self.current_directory)
+            .ok()
+            .or_else(|| resolved.strip_prefix(self.current_directory.canonicalize().ok()?).ok())
+            .map(to_display_path);
+
+        Ok(serde_json::json!({
+            "path": path,
+            "resolved": to_display_path(&resolved),
+            "relative": relative,
+            "exists": resolved.exists(),
+            "is_dir": resolved.is_dir(),
+            "writable": writable
+        }))
+    }
+
     pub async fn read_file(&self, path: &Path) -> anyhow::Result<String> {
         self.file_service.read_file(path).await
     }
 
-    pub async fn write_file(&self, path: &Path, content: &str) -> anyhow::Result<()> {
-        self.file_service.write_file(path, content).await
+    pub async fn read_line(&self, path: &Path, line: usize) -> anyhow::Result<(String, usize)> {
+        self.file_service.read_line(path, line).await
+    }
+
+    pub async fn file_hash(
+        &self,
+        path: &Path,
+        algorithm: HashAlgorithm,
+    ) -> anyhow::Result<(String, u64)> {
+        self.file_service.file_hash(path, algorithm).await
+    }
+
+    // Expands tabs in content being returned for display, using the
+    // configured tab size unless one is given
+    // Reads several files in order. Once `max_total_bytes` of content has
+    // been returned, the file crossing the cap is cut short and the rest come
+    // back empty, all flagged truncated. Unreadable files carry an error
+    // instead of failing the whole read
+    pub async fn read_many(&self, paths: &[PathBuf], max_total_bytes: usize) -> serde_json::Value {
+        let mut remaining = max_total_bytes;
+        let mut any_truncated = false;
+        let mut files = Vec::new();
+
+        for path in paths {
+            let content = match self.read_file(path).await {
+                Ok(content) => content,
+                Err(err) => {
+                    files.push(serde_json::json!({
+                        "path": to_display_path(path),
+                        "error": err.to_string()
+                    }));
+                    continue;
+                }
+            };
+
+            let size = content.len();
+            let mut end = size.min(remaining);
+            while !content.is_char_boundary(end) {
+                end -= 1;
+            }
+            remaining -= end;
+            let truncated = end < size;
+            any_truncated |= truncated;
+
+            files.push(serde_json::json!({
+                "path": to_display_path(path),
+                "content": &content[..end],
+                "size": size,
+                "truncated": truncated
+            }));
+        }
+
+        serde_json::json!({
+            "files": files,
+            "total_bytes": max_total_bytes - remaining,
+            "truncated": any_truncated
+        })
+    }
+
+    pub async fn list_files_matching_glob(&self, glob: &str) -> anyhow::Result<Vec<PathBuf>> {
+        self.project_analyzer.list_files_matching_glob(glob).await
+    }
+
+    pub fn expand_tabs(&self, content: &str, tab_size: Option<usize>, all: bool) -> String {
+        let tab_size = tab_size.or(self.config.editor.tab_size).unwrap_or(4);
+        file_editor::expand_tabs(content, tab_size, all)
     }
 
-    pub async fn append_to_file(&self, path: &Path, content: &str) -> anyhow::Result<()> {
+    // Reads a file, returning it whole if it fits in `budget_tokens`. Larger
+    // files are summarized by their outline plus as much of their head as fits
+    pub async fn read_file_within_budget(
+        &self,
+        path: &Path,
+        budget_tokens: usize,
+    ) -> anyhow::Result<serde_json::Value> {
+        let content = self.file_service.read_file(path).await?;
+        let total_tokens = tokens::estimate_tokens(&content);
+        let total_lines = content.lines().count();
+
+        if total_tokens <= budget_tokens {
+            return Ok(serde_json::json!({
+                "content": content,
+                "path": to_display_path(path),
+                "budget_used": total_tokens,
+                "elided": null
+            }));
+        }
+
+        // The outline gets at most half the budget. Leading-line entries are
+        // left out since the head of the file follows anyway
+        let language = language::detect_language(path, content.lines().next());
+        let mut used = 0;
+        let mut entries = Vec::new();
+        for entry in outline::outline(language, &content) {
+            if entry.kind == "line" {
+                continue;
+            }
+            let entry = entry.to_json();
+            let cost = tokens::estimate_tokens(&entry.to_string());
+            if used + cost > budget_tokens / 2 {
+                break;
+            }
+            used += cost;
+            entries.push(entry);
+        }
+
+        let mut head = String::new();
+        let mut head_lines = 0;
+        for line in content.split_inclusive('\n') {
+            let cost = tokens::estimate_tokens(line);
+            if used + cost > budget_tokens {
+                break;
+            }
+            used += cost;
+            head.push_str(line);
+            head_lines += 1;
+        }
+
+        Ok(serde_json::json!({
+            "content": head,
+            "path": to_display_path(path),
+            "outline": entries,
+            "budget_used": used,
+            "elided": {
+                "total_lines": total_lines,
+                "lines_returned": head_lines,
+                "lines_elided": total_lines - head_lines,
+                "total_tokens": total_tokens
+            }
+        }))
+    }
+
+    pub async fn write_file(
+        &self,
+        path: &Path,
+        content: &str,
+        create_dirs: bool,
+    ) -> anyhow::Result<ChangeSummary> {
+        self.file_service.write_file(path, content, create_dirs).await
+    }
+
+    pub async fn append_to_file(
+        &self,
+        path: &Path,
+        content: &str,
+    ) -> anyhow::Result<ChangeSummary> {
         self.file_service.append_to_file(path, content).await
     }
 
@@ -258,46 +698,505 @@ You can edit this file or create new files in this directory.
         start_line: usize,
         end_line: usize,
         new_content: &str,
-    ) -> anyhow::Result<()> {
+    ) -> anyhow::Result<ChangeSummary> {
         self.file_service
             .edit_region(path, start_line, end_line, new_content)
             .await
     }
 
+    pub async fn join_lines(
+        &self,
+        path: &Path,
+        start_line: usize,
+        end_line: usize,
+        separator: &str,
+    ) -> anyhow::Result<serde_json::Value> {
+        self.file_service
+            .join_lines(path, start_line, end_line, separator)
+            .await
+    }
+
+    pub async fn split_line(
+        &self,
+        path: &Path,
+        line: usize,
+        column: usize,
+    ) -> anyhow::Result<serde_json::Value> {
+        self.file_service.split_line(path, line, column).await
+    }
+
+    pub async fn multi_region_edit(
+        &self,
+        path: &Path,
+        edits: &[RegionEdit],
+    ) -> anyhow::Result<serde_json::Value> {
+        self.file_service.edit_regions(path, edits).await
+    }
+
+    pub async fn replace_matching_region(
+        &self,
+        path: &Path,
+        pattern: &Regex,
+        replacement: &str,
+        all: bool,
+    ) -> anyhow::Result<serde_json::Value> {
+        self.file_service
+            .replace_matching_region(path, pattern, replacement, all)
+            .await
+    }
+
+    pub async fn insert_at_match(
+        &self,
+        path: &Path,
+        pattern: &Regex,
+        content: &str,
+        after: bool,
+        require_unique: bool,
+    ) -> anyhow::Result<(usize, ChangeSummary)> {
+        self.file_service
+            .insert_at_match(path, pattern, content, after, require_unique)
+            .await
+    }
+
+    pub async fn insert_file_contents(
+        &self,
+        target: &Path,
+        source: &Path,
+        position: &InsertPosition,
+    ) -> anyhow::Result<(usize, ChangeSummary)> {
+        self.file_service
+            .insert_file_contents(target, source, position)
+            .await
+    }
+
+    // Dry run of a regex replacement against sample text, showing what each
+    // match expands to. Touches no files
+    pub fn test_replacement(
+        &self,
+        pattern: &Regex,
+        replacement: &str,
+        text: &str,
+        all: bool,
+    ) -> serde_json::Value {
+        let limit = if all { 0 } else { 1 };
+
+        let matches: Vec<serde_json::Value> = pattern
+            .captures_iter(text)
+            .take(if all { usize::MAX } else { 1 })
+            .map(|caps| {
+                let mut expansion = String::new();
+                caps.expand(replacement, &mut expansion);
+                let found = caps.get(0).map(|m| m.as_str()).unwrap_or_default();
+                serde_json::json!({ "match": found, "expansion": expansion })
+            })
+            .collect();
+
+        serde_json::json!({
+            "result": pattern.replacen(text, limit, replacement),
+            "replacements": matches.len(),
+            "matches": matches
+        })
+    }
+
     pub async fn delete_file(&self, path: &Path) -> anyhow::Result<()> {
         self.file_service.delete_file(path).await
     }
 
-    pub async fn rename_file(&self, from_path: &Path, to_path: &Path) -> anyhow::Result<()> {
-        self.file_service.rename_file(from_path, to_path).await
+    pub async fn rename_file(
+        &self,
+        from_path: &Path,
+        to_path: &Path,
+        overwrite: bool,
+    ) -> anyhow::Result<PathBuf> {
+        self.file_service
+            .rename_file(from_path, to_path, overwrite)
+            .await
+    }
+
+    pub async fn create_file(
+        &self,
+        path: &Path,
+        content: &str,
+        create_dirs: bool,
+    ) -> anyhow::Result<(PathBuf, ChangeSummary)> {
+        self.file_service.create_file(path, content, create_dirs).await
+    }
+
+    // The path to write to for `path`: unchanged for existing files, with
+    // the default extension added (when enabled) for new ones
+    pub async fn path_for_write(&self, path: &Path) -> PathBuf {
+        if self.file_service.file_exists(path).await {
+            path.to_path_buf()
+        } else {
+            self.file_service.with_default_extension(path)
+        }
+    }
+
+    pub async fn detect_language(&self, path: &Path) -> anyhow::Result<serde_json::Value> {
+        let first_line = self.file_service.read_first_line(path).await?;
+        let detection = language::detect(path, first_line.as_deref());
+
+        Ok(serde_json::json!({
+            "path": to_display_path(path),
+            "mime_type": mime::mime_type(path, None),
+            "language": detection.map(|d| d.language.name()),
+            "confidence": detection.map(|d| d.confidence.as_str()),
+            "source": detection.map(|d| d.source.as_str())
+        }))
     }
 
-    pub async fn create_file(&self, path: &Path, content: &str) -> anyhow::Result<()> {
-        self.file_service.create_file(path, content).await
+    // The content of a project file addressed by a `file://` URI, as an MCP
+    // resources/read result. The URI's path may be absolute within the
+    // project or relative to it, as the `file:///{path}` template gives it
+    pub async fn read_resource(&self, uri: &str) -> anyhow::Result<serde_json::Value> {
+        let decoded = path_from_file_uri(uri)
+            .ok_or_else(|| anyhow::anyhow!("Unsupported resource URI: {}", uri))?;
+
+        let absolute = PathBuf::from(&decoded);
+        let path = if absolute.starts_with(&self.current_directory) {
+            absolute
+        } else {
+            PathBuf::from(decoded.trim_start_matches('/'))
+        };
+
+        let content = self.file_service.read_file(&path).await?;
+
+        Ok(serde_json::json!({
+            "contents": [{
+                "uri": uri,
+                "mimeType": mime::mime_type(&path, None),
+                "text": content
+            }]
+        }))
+    }
+
+    pub async fn summarize_file(&self, path: &Path) -> anyhow::Result<serde_json::Value> {
+        let content = self.file_service.read_file(path).await?;
+        let language = language::detect_language(path, content.lines().next());
+        let entries: Vec<serde_json::Value> = outline::outline(language, &content)
+            .iter()
+            .map(|entry| entry.to_json())
+            .collect();
+
+        Ok(serde_json::json!({
+            "path": to_display_path(path),
+            "language": language.map(|l| l.name()),
+            "total_lines": content.lines().count(),
+            "entries": entries
+        }))
+    }
+
+    pub async fn path_info(&self, path: &Path) -> anyhow::Result<serde_json::Value> {
+        let info = match self.file_service.metadata(path).await? {
+            Some(metadata) => {
+                let mime_type = if metadata.is_file() {
+                    let head = self.file_service.read_head(path, mime::SNIFF_LENGTH).await?;
+                    Some(mime::mime_type(path, Some(&head)))
+                } else {
+                    None
+                };
+
+                serde_json::json!({
+                    "path": to_display_path(path),
+                    "exists": true,
+                    "is_file": metadata.is_file(),
+                    "is_dir": metadata.is_dir(),
+                    "size": metadata.len(),
+                    "modified": metadata
+                        .modified()
+                        .ok()
+                        .map(|t| chrono::DateTime::<chrono::Utc>::from(t).to_rfc3339()),
+                    "mime_type": mime_type
+                })
+            }
+            None => serde_json::json!({
+                "path": to_display_path(path),
+                "exists": false,
+                "is_file": false,
+                "is_dir": false
+            }),
+        };
+
+        Ok(info)
+    }
+
+    pub async fn touch(
+        &self,
+        path: &Path,
+        modified: Option<SystemTime>,
+    ) -> anyhow::Result<serde_json::Value> {
+        let created = self.file_service.touch(path, modified).await?;
+        let modified = self.file_service.modified_time(path).await?;
+
+        Ok(serde_json::json!({
+            "path": to_display_path(path),
+            "created": created,
+            "modified": chrono::DateTime::<chrono::Utc>::from(modified).to_rfc3339()
+        }))
+    }
+
+    pub async fn get_permissions(&self, path: &Path) -> anyhow::Result<serde_json::Value> {
+        let mode = self.file_service.get_permissions(path).await?;
+        Ok(permissions_json(path, mode))
+    }
+
+    pub async fn set_permissions(
+        &self,
+        path: &Path,
+        mode: u32,
+    ) -> anyhow::Result<serde_json::Value> {
+        self.file_service.set_permissions(path, mode).await?;
+        let mode = self.file_service.get_permissions(path).await?;
+        Ok(permissions_json(path, mode))
     }
 
     // Project operations
 
-    pub async fn analyze_project(&self) -> anyhow::Result<serde_json::Value> {
-        self.project_analyzer.analyze_project().await
+    // Analyzes the project, or reuses the last analysis of the same
+    // directory if it is no older than `max_age`
+    pub async fn analyze_project(
+        &self,
+        subdirectory: Option<&Path>,
+        sections: &AnalysisSections,
+        max_age: Option<Duration>,
+        include_dependencies: bool,
+        early: Option<UnboundedSender<serde_json::Value>>,
+    ) -> anyhow::Result<serde_json::Value> {
+        let mut key = subdirectory.map(to_display_path).unwrap_or_default();
+        // Analyses of different parts of a directory are cached apart
+        if *sections != AnalysisSections::default() {
+            key.push_str(&format!("#{:?}", sections));
+        }
+        if include_dependencies {
+            key.push_str("#dependencies");
+        }
+
+        if let Some(max_age) = max_age {
+            if let Some(cached) = self.state.cached_analysis(&key, max_age) {
+                let mut result = cached.result;
+                result["cached_at"] = serde_json::json!(chrono::DateTime::from_timestamp(
+                    cached.analyzed_at as i64,
+                    0
+                )
+                .map(|time| time.to_rfc3339()));
+                return Ok(result);
+            }
+        }
+
+        let result = self
+            .project_analyzer
+            .analyze_project(subdirectory, sections, include_dependencies, early)
+            .await?;
+        self.state.store_analysis(&key, &result);
+        Ok(result)
+    }
+
+    // The project analyzer, narrowed to `cwd` for a single call if given
+    fn analyzer_for(&self, cwd: Option<&Path>) -> anyhow::Result<Cow<'_, ProjectAnalyzer>> {
+        match cwd {
+            Some(cwd) => Ok(Cow::Owned(self.project_analyzer.scoped(cwd)?)),
+            None => Ok(Cow::Borrowed(&self.project_analyzer)),
+        }
+    }
+
+    // Resolves `path` against `cwd`, a directory inside the project, the way
+    // tools taking a per-call working directory see it
+    pub fn path_in(&self, cwd: Option<&Path>, path: &Path) -> anyhow::Result<PathBuf> {
+        match cwd {
+            Some(cwd) => Ok(self.project_analyzer.resolve_subdirectory(cwd)?.join(path)),
+            None => Ok(path.to_path_buf()),
+        }
+    }
+
+    // Lists the files matching `pattern`, along with whether there were more
+    // than `max_results` of them. A capped listing holds the first files the
+    // walk found, sorted, rather than the first in sort order
+    pub async fn list_files(
+        &self,
+        pattern: Option<&str>,
+        sort: FileSort,
+        reverse: bool,
+        cwd: Option<&Path>,
+        max_results: Option<usize>,
+    ) -> anyhow::Result<(Vec<PathBuf>, bool)> {
+        let (files, truncated) = self.capped_files(pattern, sort, reverse, cwd, max_results).await?;
+        Ok((files.into_iter().map(|(path, _)| path).collect(), truncated))
+    }
+
+    pub async fn list_files_with_metadata(
+        &self,
+        pattern: Option<&str>,
+        sort: FileSort,
+        reverse: bool,
+        cwd: Option<&Path>,
+        max_results: Option<usize>,
+    ) -> anyhow::Result<(Vec<serde_json::Value>, bool)> {
+        let (files, truncated) = self.capped_files(pattern, sort, reverse, cwd, max_results).await?;
+
+        let files = files
+            .iter()
+            .map(|(path, metadata)| {
+                serde_json::json!({
+                    "path": to_display_path(path),
+                    "size": metadata.len(),
+                    "modified": metadata
+                        .modified()
+                        .ok()
+                        .map(|t| chrono::DateTime::<chrono::Utc>::from(t).to_rfc3339()),
+                    "is_dir": metadata.is_dir()
+                })
+            })
+            .collect();
+        Ok((files, truncated))
+    }
+
+    async fn capped_files(
+        &self,
+        pattern: Option<&str>,
+        sort: FileSort,
+        reverse: bool,
+        cwd: Option<&Path>,
+        max_results: Option<usize>,
+    ) -> anyhow::Result<(Vec<(PathBuf, std::fs::Metadata)>, bool)> {
+        // One file past the cap tells whether the listing was cut short
+        let limit = max_results.map(|max| max.saturating_add(1));
+        let mut files = self
+            .analyzer_for(cwd)?
+            .list_files_with_metadata(pattern, limit)
+            .await?;
+
+        let truncated = max_results.is_some_and(|max| files.len() > max);
+        if let Some(max) = max_results {
+            files.truncate(max);
+        }
+        sort_files(&mut files, sort, reverse);
+        Ok((files, truncated))
+    }
+
+    pub async fn directory_tree(
+        &self,
+        depth: usize,
+        dirs_only: bool,
+        max_entries: usize,
+        cwd: Option<&Path>,
+    ) -> anyhow::Result<serde_json::Value> {
+        self.analyzer_for(cwd)?
+            .directory_tree(depth, dirs_only, max_entries)
+            .await
+    }
+
+    pub async fn search_files(
+        &self,
+        query: &SearchQuery,
+        options: &SearchOptions,
+        cwd: Option<&Path>,
+    ) -> anyhow::Result<serde_json::Value> {
+        self.analyzer_for(cwd)?.search_files(query, options).await
+    }
+
+    pub async fn count_matches(&self, query: &SearchQuery) -> anyhow::Result<serde_json::Value> {
+        self.project_analyzer.count_matches(query).await
+    }
+
+    // Searches like search_files, keeping only as many matches as fit in
+    // `budget_tokens`
+    pub async fn search_files_within_budget(
+        &self,
+        query: &SearchQuery,
+        options: &SearchOptions,
+        budget_tokens: usize,
+        cwd: Option<&Path>,
+    ) -> anyhow::Result<serde_json::Value> {
+        let mut results = self.search_files(query, options, cwd).await?;
+        let files = match results.get_mut("results").and_then(|r| r.as_array_mut()) {
+            Some(files) => std::mem::take(files),
+            None => return Ok(results),
+        };
+
+        let mut used = 0;
+        let mut kept = Vec::new();
+        let mut files_omitted = 0;
+        let mut matches_omitted = 0;
+        // Once a match doesn't fit, everything after it is dropped so the
+        // kept results are a prefix of the full results
+        let mut exhausted = false;
+
+        for mut file in files {
+            let matches = match file.get_mut("matches").and_then(|m| m.as_array_mut()) {
+                Some(matches) => std::mem::take(matches),
+                // Skipped files carry no matches and cost next to nothing
+                None => {
+                    kept.push(file);
+                    continue;
+                }
+            };
+
+            let mut kept_matches = Vec::new();
+            for line_match in matches {
+                let cost = tokens::estimate_tokens(&line_match.to_string());
+                if exhausted || used + cost > budget_tokens {
+                    exhausted = true;
+                    matches_omitted += 1;
+                    continue;
+                }
+                used += cost;
+                kept_matches.push(line_match);
+            }
+
+            if kept_matches.is_empty() {
+                files_omitted += 1;
+                continue;
+            }
+            file["matches"] = serde_json::json!(kept_matches);
+            used += tokens::estimate_tokens(&file["file"].to_string());
+            kept.push(file);
+        }
+
+        results["results"] = serde_json::json!(kept);
+        results["budget_used"] = serde_json::json!(used);
+        results["elided"] = if matches_omitted > 0 {
+            serde_json::json!({
+                "files_omitted": files_omitted,
+                "matches_omitted": matches_omitted
+            })
+        } else {
+            serde_json::Value::Null
+        };
+
+        Ok(results)
     }
 
-    pub async fn list_files(&self, pattern: Option<&str>) -> anyhow::Result<Vec<PathBuf>> {
-        self.project_analyzer.list_files(pattern).await
+    // Runs a task from the tasks section of the config in the project
+    // directory, passing its output to `progress` as it runs
+    pub async fn run_task(
+        &self,
+        name: &str,
+        progress: Option<UnboundedSender<TaskOutput>>,
+    ) -> anyhow::Result<serde_json::Value> {
+        let runner = TaskRunner::new(&self.current_directory, &self.config.tasks);
+        Ok(runner.run(name, progress).await?)
     }
 
-    pub async fn search_files(&self, query: &str) -> anyhow::Result<serde_json::Value> {
-        self.project_analyzer.search_files(query).await
+    pub async fn find_references(&self, identifier: &str) -> anyhow::Result<serde_json::Value> {
+        self.project_analyzer.find_references(identifier).await
     }
 
     // Diff operations
 
+    // Diffs two texts, first normalizing away the whitespace differences
+    // `whitespace` ignores, so the diff shows the normalized lines
     pub async fn generate_diff(
         &self,
         original_content: &str,
         modified_content: &str,
+        whitespace: WhitespaceMode,
+        format: DiffFormat,
     ) -> anyhow::Result<String> {
-        DiffGenerator::generate_unified_diff(original_content, modified_content)
+        DiffGenerator::generate(
+            &whitespace.normalize(original_content),
+            &whitespace.normalize(modified_content),
+            format,
+        )
     }
 
     pub async fn preview_file_changes(
@@ -306,7 +1205,277 @@ You can edit this file or create new files in this directory.
         new_content: &str,
     ) -> anyhow::Result<String> {
         let original_content = self.read_file(path).await?;
-        self.generate_diff(&original_content, new_content).await
+        self.generate_diff(
+            &original_content,
+            new_content,
+            WhitespaceMode::None,
+            DiffFormat::default(),
+        )
+        .await
+    }
+
+    // Snapshots each file as a save point before risky edits, reporting the
+    // backup made (or reused, when unchanged since the last one) per file
+    pub async fn create_backups(&self, paths: &[PathBuf]) -> serde_json::Value {
+        let mut backups = Vec::new();
+
+        for path in paths {
+            let backup_path = match self.file_service.create_backup(path).await {
+                Ok(backup_path) => backup_path,
+                Err(err) => {
+                    backups.push(serde_json::json!({
+                        "path": to_display_path(path),
+                        "error": err.to_string()
+                    }));
+                    continue;
+                }
+            };
+
+            // The backup made or reused is the file's newest
+            let created_at = self
+                .file_service
+                .backup_times(path)
+                .await
+                .ok()
+                .and_then(|times| times.first().copied())
+                .map(|t| chrono::DateTime::<chrono::Utc>::from(t).to_rfc3339());
+            backups.push(serde_json::json!({
+                "path": to_display_path(path),
+                "backup": to_display_path(&backup_path),
+                "created_at": created_at
+            }));
+        }
+
+        serde_json::json!({ "backups": backups })
+    }
+
+    // Converts every text file in the project to the configured line ending,
+    // or with `dry_run` only reports which files would change
+    pub async fn normalize_project_line_endings(&self, dry_run: bool) -> anyhow::Result<serde_json::Value> {
+        let files = self.project_analyzer.list_text_files().await?;
+        let mut changed = Vec::new();
+        let mut skipped = 0;
+        let mut errors = Vec::new();
+
+        for path in &files {
+            match self.file_service.normalize_line_endings(path, dry_run).await {
+                Ok(Some(true)) => changed.push(to_display_path(path)),
+                Ok(Some(false)) => {}
+                Ok(None) => skipped += 1,
+                Err(err) => errors.push(serde_json::json!({
+                    "path": to_display_path(path),
+                    "error": err.to_string()
+                })),
+            }
+        }
+
+        if !dry_run {
+            logging::info(&format!(
+                "Normalized line endings in {} of {} files",
+                changed.len(),
+                files.len()
+            ));
+        }
+
+        Ok(serde_json::json!({
+            "line_endings": self.config.editor.line_endings,
+            "dry_run": dry_run,
+            "files_checked": files.len(),
+            "files_changed": changed.len(),
+            // Files with no line ending configured, by config or .editorconfig
+            "files_skipped": skipped,
+            "changed": changed,
+            "errors": errors
+        }))
+    }
+
+    // Tidies the whitespace of each file, or with `dry_run` only reports what
+    // would change. A missing max_blank_lines falls back to the config
+    pub async fn cleanup_whitespace(
+        &self,
+        paths: &[PathBuf],
+        max_blank_lines: Option<usize>,
+        trim_trailing_whitespace: Option<bool>,
+        ensure_final_newline: Option<bool>,
+        dry_run: bool,
+    ) -> serde_json::Value {
+        let cleanup = WhitespaceCleanup {
+            max_blank_lines: max_blank_lines
+                .or(self.config.editor.max_blank_lines)
+                .unwrap_or(1),
+            trim_trailing_whitespace,
+            ensure_final_newline,
+        };
+
+        let mut files = Vec::new();
+        let mut errors = Vec::new();
+        for path in paths {
+            match self.file_service.cleanup_whitespace(path, &cleanup, dry_run).await {
+                Ok(Some(diff)) => files.push(serde_json::json!({
+                    "path": to_display_path(path),
+                    "diff": diff
+                })),
+                Ok(None) => {}
+                Err(err) => errors.push(serde_json::json!({
+                    "path": to_display_path(path),
+                    "error": err.to_string()
+                })),
+            }
+        }
+
+        serde_json::json!({
+            "dry_run": dry_run,
+            "files_checked": paths.len(),
+            "files_changed": files.len(),
+            "files": files,
+            "errors": errors
+        })
+    }
+
+    // Replaces the text matched by `query` in every line it matches, across
+    // the files a search with `options` covers. Each changed file is backed
+    // up; with `dry_run` only the diffs are returned
+    pub async fn replace_in_files(
+        &self,
+        query: &SearchQuery,
+        options: &SearchOptions,
+        replacement: &str,
+        dry_run: bool,
+        cwd: Option<&Path>,
+    ) -> anyhow::Result<serde_json::Value> {
+        let paths = self.analyzer_for(cwd)?.search_targets(options).await?;
+
+        let mut files = Vec::new();
+        let mut errors = Vec::new();
+        let mut total_lines = 0;
+        for path in &paths {
+            let display_path = to_display_path(path.strip_prefix(&self.current_directory).unwrap_or(path));
+            let mut lines_changed = 0;
+            let change = |content: &str| {
+                let (replaced, lines) = query.replace_in(content, replacement);
+                lines_changed = lines;
+                replaced
+            };
+            match self.file_service.rewrite_file(path, change, dry_run).await {
+                Ok(Some(diff)) => {
+                    total_lines += lines_changed;
+                    files.push(serde_json::json!({
+                        "path": display_path,
+                        "lines_changed": lines_changed,
+                        "diff": diff
+                    }));
+                }
+                Ok(None) => {}
+                Err(err) => errors.push(serde_json::json!({
+                    "path": display_path,
+                    "error": err.to_string()
+                })),
+            }
+        }
+
+        Ok(serde_json::json!({
+            "dry_run": dry_run,
+            "files_searched": paths.len(),
+            "files_changed": files.len(),
+            "lines_changed": total_lines,
+            "files": files,
+            "errors": errors
+        }))
+    }
+
+    pub async fn purge_backups(
+        &self,
+        path: Option<&Path>,
+        older_than: Option<Duration>,
+        keep_latest: Option<usize>,
+    ) -> anyhow::Result<serde_json::Value> {
+        let summary = self
+            .file_service
+            .purge_backups(path, older_than, keep_latest)
+            .await?;
+
+        Ok(serde_json::json!({
+            "path": path.map(to_display_path),
+            "removed": summary.removed,
+            "bytes_freed": summary.bytes,
+            "files_affected": summary.files
+        }))
+    }
+
+    // Reads a backup of a file without restoring it: the one at `index`, or
+    // the newest taken at or before `taken_at`, by default the most recent
+    pub async fn read_backup(
+        &self,
+        path: &Path,
+        index: Option<usize>,
+        taken_at: Option<SystemTime>,
+    ) -> anyhow::Result<serde_json::Value> {
+        let times = self.file_service.backup_times(path).await?;
+        let index = match taken_at {
+            Some(taken_at) => times
+                .iter()
+                .position(|time| *time <= taken_at)
+                .ok_or_else(|| {
+                    BackupError::NoBackupAvailable(format!(
+                        "{} (none taken at or before {}, {} backups available)",
+                        path.display(),
+                        chrono::DateTime::<chrono::Utc>::from(taken_at).to_rfc3339(),
+                        times.len()
+                    ))
+                })?,
+            None => index.unwrap_or(0),
+        };
+
+        let (backup_path, content) = self.file_service.read_backup(path, index).await?;
+
+        Ok(serde_json::json!({
+            "path": to_display_path(path),
+            "backup": to_display_path(&backup_path),
+            "backup_index": index,
+            "backup_count": times.len(),
+            "timestamp": times
+                .get(index)
+                .map(|time| chrono::DateTime::<chrono::Utc>::from(*time).to_rfc3339()),
+            "content": content
+        }))
+    }
+
+    pub async fn diff_against_backup(
+        &self,
+        path: &Path,
+        index: usize,
+        whitespace: WhitespaceMode,
+        format: DiffFormat,
+    ) -> anyhow::Result<serde_json::Value> {
+        let backups = self.file_service.list_backups(path).await?;
+        if backups.is_empty() {
+            return Ok(serde_json::json!({
+                "path": to_display_path(path),
+                "backup_count": 0,
+                "message": format!("No backups available for {}", path.display())
+            }));
+        }
+
+        let (backup_path, backup_content) = self.file_service.read_backup(path, index).await?;
+
+        // A file deleted since its backup compares against empty content
+        let current_content = if self.file_service.file_exists(path).await {
+            self.read_file(path).await?
+        } else {
+            String::new()
+        };
+
+        let diff = self
+            .generate_diff(&backup_content, &current_content, whitespace, format)
+            .await?;
+
+        Ok(serde_json::json!({
+            "path": to_display_path(path),
+            "backup": to_display_path(&backup_path),
+            "backup_index": index,
+            "backup_count": backups.len(),
+            "diff": diff
+        }))
     }
 
     // Suggestion operations
@@ -321,12 +1490,26 @@ You can edit this file or create new files in this directory.
         suggestion: &str,
     ) -> anyhow::Result<serde_json::Value> {
         let parsed = SuggestionParser::parse_suggestion(suggestion)?;
+
+        // Multi-file suggestions name their own paths
+        if parsed.get("type").and_then(|t| t.as_str()) == Some("multi") {
+            let files = parsed
+                .get("files")
+                .and_then(|f| f.as_array())
+                .cloned()
+                .unwrap_or_default();
+            return self.file_service.apply_multi_suggestion(&files).await;
+        }
+
         self.file_service.apply_suggestion(path, &parsed).await
     }
 
     // Directory operations
 
-    pub fn change_current_directory(&mut self, new_directory: String) -> anyhow::Result<()> {
+    // Switches the project to `new_directory`, creating it if missing. An
+    // unreadable directory is refused; returns whether it is writable, so
+    // a read-only project shows up here rather than on the first edit
+    pub fn change_current_directory(&mut self, new_directory: String) -> anyhow::Result<bool> {
         let dir_path = PathBuf::from(new_directory);
         let project_directory = if dir_path.is_absolute() {
             logging::info(&format!(
@@ -353,11 +1536,25 @@ You can edit this file or create new files in this directory.
             std::fs::create_dir_all(&project_directory)?;
         }
 
+        let writable = check_directory_access(&project_directory)?;
+        if !writable {
+            logging::warn(&format!(
+                "Project directory is not writable: {}",
+                project_directory.display()
+            ));
+        }
+
         // Update file service with new directory
         self.file_service.change_directory(&project_directory)?;
 
         // Update project analyzer
-        self.project_analyzer = ProjectAnalyzer::new(project_directory.clone());
+        self.project_analyzer =
+            ProjectAnalyzer::new(project_directory.clone(), &self.config.project);
+        self.project_analyzer.set_client_roots(self.client_roots.as_deref());
+
+        // State belongs to the project, so switch to the new project's
+        self.save_state();
+        self.state = StateStore::open(&project_directory, &self.config.project);
 
         // Update current directory
         self.current_directory = project_directory.clone();
@@ -373,10 +1570,389 @@ You can edit this file or create new files in this directory.
             project_directory.display()
         ));
 
-        Ok(())
+        Ok(writable)
+    }
+
+    // Restricts file access to the roots the client listed, on top of the
+    // project directory; None lifts the restriction
+    pub fn set_client_roots(&mut self, roots: Option<Vec<PathBuf>>) {
+        match &roots {
+            Some(roots) => logging::info(&format!(
+                "Client roots: {}",
+                roots
+                    .iter()
+                    .map(|root| root.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )),
+            None => logging::info("Client roots lifted"),
+        }
+        self.file_service.set_client_roots(roots.as_deref());
+        self.project_analyzer.set_client_roots(roots.as_deref());
+        self.client_roots = roots;
     }
 
     pub fn get_current_directory(&self) -> PathBuf {
         self.current_directory.clone()
     }
+
+    pub fn backup_directory(&self) -> &Path {
+        self.file_service.backup_directory()
+    }
+
+    // What this server can actually do as configured and run, for clients
+    // adapting to it rather than assuming every feature is available
+    pub async fn capabilities(&self, transport: &str) -> serde_json::Value {
+        let git_version = git_version().await;
+
+        let backups = &self.config.backups;
+        let tasks: Vec<&String> = self
+            .config
+            .tasks
+            .commands
+            .iter()
+            .flat_map(|commands| commands.keys())
+            .collect();
+        let read_cache_bytes = self
+            .config
+            .editor
+            .read_cache_bytes
+            .unwrap_or(DEFAULT_READ_CACHE_BYTES);
+
+        serde_json::json!({
+            "server": {
+                "name": "mcedit",
+                "version": env!("CARGO_PKG_VERSION")
+            },
+            "transport": transport,
+            // There is no read-only mode; every tool listed can write
+            "read_only": false,
+            "auth_required": transport == "http" && self.config.mcp.auth_token.is_some(),
+            "rate_limit": self.config.mcp.rate_limit_per_second.map(|rate| serde_json::json!({
+                "per_second": rate,
+                "burst": self.config.mcp.rate_limit_burst.unwrap_or(DEFAULT_RATE_LIMIT_BURST)
+            })),
+            "git": {
+                "available": git_version.is_some(),
+                "version": git_version
+            },
+            // Formatting is limited to the editor settings; no external
+            // formatters can be configured
+            "formatters": {
+                "configured": false
+            },
+            "tasks": tasks,
+            "external_changes": self.config.editor.detect_external_changes.as_deref().unwrap_or("off"),
+            "backups": {
+                // Every edit is backed up; backups.enabled isn't honored
+                "enabled": true,
+                "directory": to_display_path(self.file_service.backup_directory()),
+                "max_backups_per_file": backups.max_backups_per_file,
+                "max_backup_age_days": backups.max_backup_age_days,
+                "min_backups": backups.min_backups,
+                "format": backups.backup_format
+            },
+            "limits": {
+                "max_depth": self.config.project.max_depth.unwrap_or(DEFAULT_MAX_DEPTH),
+                "walk_concurrency": walk_concurrency(&self.config.project),
+                // Larger files are skipped by find_references
+                "max_reference_file_bytes": MAX_REFERENCE_FILE_SIZE,
+                // Larger files are read from disk every time
+                "max_cached_file_bytes": read_cache_bytes / 2,
+                "max_task_output_bytes": self
+                    .config
+                    .tasks
+                    .max_output_bytes
+                    .unwrap_or(DEFAULT_MAX_OUTPUT_BYTES)
+            }
+        })
+    }
+
+    // The configuration in effect, with the directories as actually resolved
+    // and secrets redacted so it can be shown to clients
+    pub fn effective_config(&self) -> anyhow::Result<serde_json::Value> {
+        let mut config = self.config.clone();
+        config.project.directory = Some(to_display_path(&self.current_directory));
+        config.backups.backup_directory = Some(to_display_path(self.file_service.backup_directory()));
+        if config.mcp.auth_token.is_some() {
+            config.mcp.auth_token = Some("<redacted>".to_string());
+        }
+
+        // Settings left unset are reported as the values the server falls
+        // back to, not those of default_config(). Settings with no fallback,
+        // like line_endings, stay null
+        let project = &mut config.project;
+        project.walk_concurrency = Some(walk_concurrency(project));
+        project.state_directory = Some(to_display_path(self.state.directory()));
+        project.apply_default_extension.get_or_insert(false);
+        project.exclude_patterns.get_or_insert_with(Vec::new);
+        project.auto_detect_root.get_or_insert(true);
+        project.follow_symlinks.get_or_insert(false);
+        project.max_depth.get_or_insert(DEFAULT_MAX_DEPTH);
+        project.respect_ignore_files.get_or_insert(true);
+        project.checkpoint_session_start.get_or_insert(false);
+
+        let settings = file_editor::EditorSettings::from_config(&config.editor);
+        let external_changes = match ExternalChangePolicy::from_config(&config) {
+            ExternalChangePolicy::Error => "error",
+            ExternalChangePolicy::Warn => "warn",
+            ExternalChangePolicy::Ignore => "off",
+        };
+        let editor = &mut config.editor;
+        editor.tab_size = Some(settings.tab_size);
+        editor.line_endings = settings.line_ending.map(|ending| {
+            if ending == "\r\n" { "crlf" } else { "lf" }.to_string()
+        });
+        editor.trim_trailing_whitespace = Some(settings.trim_trailing_whitespace);
+        editor.ensure_final_newline = Some(settings.ensure_final_newline);
+        editor.use_editorconfig.get_or_insert(true);
+        editor.read_cache_bytes.get_or_insert(DEFAULT_READ_CACHE_BYTES);
+        editor.max_blank_lines.get_or_insert(1);
+        editor.detect_external_changes = Some(external_changes.to_string());
+
+        let backups = &mut config.backups;
+        // Every edit is backed up; backups.enabled isn't honored
+        backups.enabled = Some(true);
+        backups.max_backups_per_file.get_or_insert(MAX_BACKUPS_PER_FILE);
+        backups.min_backups.get_or_insert(MIN_BACKUPS_PER_FILE);
+        backups.backup_include.get_or_insert_with(Vec::new);
+        backups.backup_exclude.get_or_insert_with(Vec::new);
+        backups.backup_format = Some(
+            match BackupFormat::from_config(backups) {
+                BackupFormat::Files => "files",
+                BackupFormat::Archive => "archive",
+            }
+            .to_string(),
+        );
+
+        let mcp = &mut config.mcp;
+        let transport = mcp.transport.get_or_insert_with(TransportConfig::default);
+        transport.kind.get_or_insert_with(|| "stdio".to_string());
+        transport.address.get_or_insert_with(|| DEFAULT_HTTP_ADDRESS.to_string());
+        if mcp.rate_limit_per_second.is_some() {
+            mcp.rate_limit_burst.get_or_insert(DEFAULT_RATE_LIMIT_BURST);
+        }
+        mcp.heartbeat_to_client.get_or_insert(false);
+        mcp.log_level = Some(
+            mcp.log_level
+                .as_deref()
+                .and_then(LogLevel::parse)
+                .unwrap_or(LogLevel::Info)
+                .to_string(),
+        );
+
+        let tasks = &mut config.tasks;
+        tasks.commands.get_or_insert_with(BTreeMap::new);
+        tasks.timeout_seconds.get_or_insert(DEFAULT_TASK_TIMEOUT_SECS);
+        tasks.max_output_bytes.get_or_insert(DEFAULT_MAX_OUTPUT_BYTES);
+
+        Ok(serde_json::to_value(config)?)
+    }
+}
+
+// Every file under `root` as path -> content hash, leaving out the state
+// directory the checkpoints themselves live in. A file that can't be read is
+// left out with a warning rather than failing the whole manifest
+async fn tree_manifest(
+    analyzer: &ProjectAnalyzer,
+    root: &Path,
+    state_directory: &Path,
+) -> anyhow::Result<BTreeMap<String, String>> {
+    let mut manifest = BTreeMap::new();
+    for (path, _) in analyzer.list_files_with_metadata(None, None).await? {
+        if path.starts_with(state_directory) {
+            continue;
+        }
+        match hash_file(&path, HashAlgorithm::Blake3).await {
+            Ok((hash, _)) => {
+                let relative = path.strip_prefix(root).unwrap_or(&path);
+                manifest.insert(to_display_path(relative), hash);
+            }
+            Err(err) => logging::warn(&format!(
+                "Leaving {} out of the checkpoint: {}",
+                path.display(),
+                err
+            )),
+        }
+    }
+    Ok(manifest)
+}
+
+// Loads the config file at `path`, or the built-in defaults if there is none
+fn load_config(path: Option<&Path>) -> anyhow::Result<Config> {
+    match path {
+        Some(path) => config::init_from_path(&path.to_string_lossy()),
+        None => Ok(config::default_config()),
+    }
+}
+
+fn validate_config(config: &Config) -> anyhow::Result<()> {
+    let problems = config::validate::validate(config, &McpHandler::tool_names());
+    if !problems.is_empty() {
+        return Err(anyhow::anyhow!(
+            "Invalid configuration:\n  {}",
+            problems.join("\n  ")
+        ));
+    }
+    Ok(())
+}
+
+// Refuses `directory` unless it is a directory that can be listed, and
+// tells whether files can be created in it by creating and removing one
+fn check_directory_access(directory: &Path) -> Result<bool, McEditError> {
+    if !directory.is_dir() {
+        return Err(McEditError::InvalidDirectory(format!(
+            "{} is not a directory",
+            directory.display()
+        )));
+    }
+    std::fs::read_dir(directory).map_err(|e| {
+        McEditError::PermissionDenied(format!("Cannot read {}: {}", directory.display(), e))
+    })?;
+
+    let probe = directory.join(format!(".mcedit-write-check-{}", std::process::id()));
+    let writable = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&probe)
+        .is_ok();
+    if writable {
+        let _ = std::fs::remove_file(&probe);
+    }
+    Ok(writable)
+}
+
+fn permissions_json(path: &Path, mode: u32) -> serde_json::Value {
+    serde_json::json!({
+        "path": to_display_path(path),
+        "mode": format!("{:o}", mode),
+        "executable": mode & 0o111 != 0
+    })
+}
+
+// The installed git's version line, or None when git can't be run
+pub async fn git_version() -> Option<String> {
+    tokio::process::Command::new("git")
+        .arg("--version")
+        .output()
+        .await
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+// Whether git ignores `path` in the repository holding `root`, false when
+// git can't be run or `root` isn't in a repository
+async fn git_ignores(root: &Path, path: &Path) -> bool {
+    tokio::process::Command::new("git")
+        .arg("check-ignore")
+        .arg("--quiet")
+        .arg(path)
+        .current_dir(root)
+        .output()
+        .await
+        .map(|output| output.status.success())
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reload_config_applies_log_level() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("config.json");
+        let write_config = |level: &str| {
+            let mut config = config::default_config();
+            config.mcp.log_level = Some(level.to_string());
+            std::fs::write(&config_path, serde_json::to_string(&config).unwrap()).unwrap();
+        };
+
+        write_config("error");
+        let mut mcedit = McEdit::new(
+            Some(config_path.display().to_string()),
+            Some(dir.path().display().to_string()),
+            false,
+        )
+        .unwrap();
+        assert!(!logging::enabled(LogLevel::Warning));
+
+        write_config("debug");
+        mcedit.reload_config().unwrap();
+        assert!(logging::enabled(LogLevel::Debug));
+
+        logging::set_level(LogLevel::Info);
+    }
+
+    #[test]
+    fn test_effective_config_reports_runtime_fallbacks() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("config.json");
+        let mut config = config::default_config();
+        config.editor.line_endings = None;
+        config.editor.tab_size = None;
+        config.project.exclude_patterns = None;
+        config.backups.backup_format = None;
+        std::fs::write(&config_path, serde_json::to_string(&config).unwrap()).unwrap();
+        let mcedit = McEdit::new(
+            Some(config_path.display().to_string()),
+            Some(dir.path().display().to_string()),
+            false,
+        )
+        .unwrap();
+
+        let effective = mcedit.effective_config().unwrap();
+        // Files keep their own line endings, so none is reported
+        assert!(effective["editor"]["line_endings"].is_null());
+        assert_eq!(effective["editor"]["tab_size"], 4);
+        // Unset, nothing is excluded from walks
+        assert_eq!(effective["project"]["exclude_patterns"], serde_json::json!([]));
+        assert_eq!(effective["backups"]["backup_format"], "files");
+    }
+
+    #[tokio::test]
+    async fn test_session_start_checkpoint_needs_opt_in_and_gitignore() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("config.json");
+        let open = |checkpoint_session_start| {
+            let mut config = config::default_config();
+            config.project.checkpoint_session_start = Some(checkpoint_session_start);
+            std::fs::write(&config_path, serde_json::to_string(&config).unwrap()).unwrap();
+            McEdit::new(
+                Some(config_path.display().to_string()),
+                Some(dir.path().display().to_string()),
+                false,
+            )
+            .unwrap()
+        };
+        let checkpoints = dir.path().join(".mcedit").join(checkpoint::CHECKPOINTS_DIRECTORY);
+
+        // Off by default
+        let mut mcedit = open(false);
+        mcedit.checkpoint_session_start();
+        assert!(mcedit.file_tree_diff(None).await.is_err());
+        assert!(!checkpoints.exists());
+
+        // Not taken where git would pick the checkpoint up
+        let mut mcedit = open(true);
+        mcedit.checkpoint_session_start();
+        assert!(mcedit.file_tree_diff(None).await.is_err());
+        assert!(!checkpoints.exists());
+
+        if git_version().await.is_none() {
+            return;
+        }
+        let init = std::process::Command::new("git")
+            .arg("init")
+            .arg("--quiet")
+            .current_dir(dir.path())
+            .status()
+            .unwrap();
+        assert!(init.success());
+        std::fs::write(dir.path().join(".gitignore"), ".mcedit/\n").unwrap();
+        mcedit.checkpoint_session_start();
+        assert!(mcedit.file_tree_diff(None).await.is_ok());
+        assert!(checkpoints.join("session-start.json").exists());
+    }
 }
@@ -1,3 +1,6 @@
+pub mod checkpoint;
+pub mod doctor;
 pub mod mcedit;
+pub mod state;
 
 pub use mcedit::McEdit;
@@ -0,0 +1,154 @@
+use crate::config::ProjectConfig;
+use crate::shared::logging;
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// Where state is kept when the config doesn't say, relative to the project
+pub const DEFAULT_STATE_DIRECTORY: &str = ".mcedit";
+
+const STATE_FILE: &str = "state.json";
+
+// Oldest audit entries are dropped beyond this many
+const MAX_AUDIT_ENTRIES: usize = 1000;
+
+// A tool call, as recorded in the audit log
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub time: String,
+    pub tool: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+}
+
+// A project analysis and when it was made
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedAnalysis {
+    // Seconds since the Unix epoch
+    pub analyzed_at: u64,
+    pub result: Value,
+}
+
+// Everything persisted between runs. Unknown fields are ignored so state
+// written by other versions still loads
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SessionState {
+    // Latest analysis of each analyzed directory, keyed by its path relative
+    // to the project root ("" for the root itself)
+    pub analyses: HashMap<String, CachedAnalysis>,
+    pub audit_log: Vec<AuditEntry>,
+}
+
+// Session state for one project, loaded from its state directory on startup
+// and written back on shutdown
+pub struct StateStore {
+    directory: PathBuf,
+    state: Mutex<SessionState>,
+}
+
+impl StateStore {
+    pub fn open(project_root: &Path, config: &ProjectConfig) -> Self {
+        let directory = project_root.join(
+            config
+                .state_directory
+                .as_deref()
+                .unwrap_or(DEFAULT_STATE_DIRECTORY),
+        );
+
+        let state_file = directory.join(STATE_FILE);
+        let state = match std::fs::read_to_string(&state_file) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                logging::warn(&format!(
+                    "Ignoring unreadable state file {}: {}",
+                    state_file.display(),
+                    e
+                ));
+                SessionState::default()
+            }),
+            Err(_) => SessionState::default(),
+        };
+
+        Self {
+            directory,
+            state: Mutex::new(state),
+        }
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        let content = serde_json::to_string_pretty(&*self.state.lock().unwrap())?;
+        std::fs::create_dir_all(&self.directory)?;
+        std::fs::write(self.directory.join(STATE_FILE), content)?;
+
+        logging::debug(&format!(
+            "Saved session state to {}",
+            self.directory.display()
+        ));
+        Ok(())
+    }
+
+    // Forgets all state, in memory and on disk
+    pub fn clear(&self) -> anyhow::Result<()> {
+        *self.state.lock().unwrap() = SessionState::default();
+
+        let state_file = self.directory.join(STATE_FILE);
+        if state_file.exists() {
+            std::fs::remove_file(&state_file)?;
+        }
+        Ok(())
+    }
+
+    pub fn record_tool_call(&self, tool: &str, path: Option<&str>) {
+        let mut state = self.state.lock().unwrap();
+        state.audit_log.push(AuditEntry {
+            time: Local::now().to_rfc3339(),
+            tool: tool.to_string(),
+            path: path.map(String::from),
+        });
+
+        let excess = state.audit_log.len().saturating_sub(MAX_AUDIT_ENTRIES);
+        state.audit_log.drain(..excess);
+    }
+
+    // The cached analysis of `key` if it is no older than `max_age`
+    pub fn cached_analysis(&self, key: &str, max_age: Duration) -> Option<CachedAnalysis> {
+        let state = self.state.lock().unwrap();
+        let cached = state.analyses.get(key)?;
+        let age = unix_now().saturating_sub(cached.analyzed_at);
+        (age <= max_age.as_secs()).then(|| cached.clone())
+    }
+
+    pub fn store_analysis(&self, key: &str, result: &Value) {
+        self.state.lock().unwrap().analyses.insert(
+            key.to_string(),
+            CachedAnalysis {
+                analyzed_at: unix_now(),
+                result: result.clone(),
+            },
+        );
+    }
+
+    // Number of analyses and audit entries held, for reporting
+    pub fn summary(&self) -> Value {
+        let state = self.state.lock().unwrap();
+        serde_json::json!({
+            "analyses": state.analyses.len(),
+            "audit_entries": state.audit_log.len()
+        })
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
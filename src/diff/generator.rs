@@ -1,5 +1,6 @@
 use crate::shared::logging;
 use similar::{ChangeTag, TextDiff};
+use std::borrow::Cow;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -8,11 +9,95 @@ pub enum DiffError {
     GenerationFailed(String),
 }
 
+// Whitespace differences a diff leaves out
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum WhitespaceMode {
+    #[default]
+    None,
+    // Whitespace at the end of lines
+    Trailing,
+    // Any change in the amount of whitespace, including indentation
+    All,
+}
+
+impl WhitespaceMode {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "none" => Some(Self::None),
+            "trailing" => Some(Self::Trailing),
+            "all" => Some(Self::All),
+            _ => None,
+        }
+    }
+
+    // Rewrites text so the whitespace this mode ignores can no longer differ:
+    // trailing whitespace is dropped, and for All every run of whitespace in
+    // a line becomes a single space
+    pub fn normalize(self, text: &str) -> Cow<'_, str> {
+        let normalize_line = match self {
+            Self::None => return Cow::Borrowed(text),
+            Self::Trailing => |line: &str| line.trim_end().to_string(),
+            Self::All => |line: &str| line.split_whitespace().collect::<Vec<_>>().join(" "),
+        };
+
+        let mut normalized = text
+            .lines()
+            .map(normalize_line)
+            .collect::<Vec<_>>()
+            .join("\n");
+        if text.ends_with('\n') {
+            normalized.push('\n');
+        }
+        Cow::Owned(normalized)
+    }
+}
+
+// Total width of a side-by-side diff when none is given
+pub const DEFAULT_SIDE_BY_SIDE_WIDTH: usize = 160;
+
+// How a diff is rendered
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DiffFormat {
+    // With `inline`, changed line pairs mark the words that differ
+    Unified { inline: bool },
+    // Old and new text in two columns, fitting lines of `width` characters
+    SideBySide { width: usize },
+}
+
+impl Default for DiffFormat {
+    fn default() -> Self {
+        Self::Unified { inline: false }
+    }
+}
+
+impl DiffFormat {
+    pub fn parse(value: &str, width: Option<usize>, inline: bool) -> Option<Self> {
+        match value {
+            "unified" => Some(Self::Unified { inline }),
+            "side_by_side" => Some(Self::SideBySide {
+                width: width.unwrap_or(DEFAULT_SIDE_BY_SIDE_WIDTH),
+            }),
+            _ => None,
+        }
+    }
+}
+
 pub struct DiffGenerator;
 
 impl DiffGenerator {
     // Generates a unified diff between two strings
     pub fn generate_unified_diff(original: &str, modified: &str) -> anyhow::Result<String> {
+        Self::generate_unified_diff_with(original, modified, false)
+    }
+
+    // Like generate_unified_diff. With `inline`, each deleted line followed
+    // by the inserted line replacing it marks the words that changed, as
+    // [-removed-] and {+added+}
+    pub fn generate_unified_diff_with(
+        original: &str,
+        modified: &str,
+        inline: bool,
+    ) -> anyhow::Result<String> {
         let diff = TextDiff::from_lines(original, modified);
 
         let mut unified_diff = String::new();
@@ -96,6 +181,10 @@ impl DiffGenerator {
                         hunk_size_mod
                     ));
 
+                    if inline {
+                        mark_changed_words(&mut hunk_lines);
+                    }
+
                     // Add hunk lines
                     for line in &hunk_lines {
                         unified_diff.push_str(&format!("{}\n", line));
@@ -126,6 +215,10 @@ impl DiffGenerator {
                     hunk_size_mod
                 ));
 
+                if inline {
+                    mark_changed_words(&mut hunk_lines);
+                }
+
                 // Add hunk lines
                 for line in &hunk_lines {
                     unified_diff.push_str(&format!("{}\n", line));
@@ -136,6 +229,50 @@ impl DiffGenerator {
         Ok(unified_diff)
     }
 
+    // Renders a diff in `format`
+    pub fn generate(original: &str, modified: &str, format: DiffFormat) -> anyhow::Result<String> {
+        match format {
+            DiffFormat::Unified { inline } => {
+                Self::generate_unified_diff_with(original, modified, inline)
+            }
+            DiffFormat::SideBySide { width } => {
+                Ok(Self::generate_side_by_side(original, modified, width))
+            }
+        }
+    }
+
+    // Renders the old text on the left and the new on the right, in lines of
+    // at most `width` characters. The marker between the columns is blank
+    // for unchanged lines, `|` for changed, `<` for deleted and `>` for
+    // inserted ones. Longer lines wrap within their column; tabs become four
+    // spaces so the columns stay aligned
+    pub fn generate_side_by_side(original: &str, modified: &str, width: usize) -> String {
+        let diff = TextDiff::from_lines(original, modified);
+        let column = (width.saturating_sub(3) / 2).max(1);
+
+        let mut output = String::new();
+        let mut deleted: Vec<String> = Vec::new();
+        let mut inserted: Vec<String> = Vec::new();
+
+        for change in diff.iter_all_changes() {
+            let line = change
+                .value()
+                .trim_end_matches(['\r', '\n'])
+                .replace('\t', "    ");
+            match change.tag() {
+                ChangeTag::Delete => deleted.push(line),
+                ChangeTag::Insert => inserted.push(line),
+                ChangeTag::Equal => {
+                    push_changed_rows(&mut output, &mut deleted, &mut inserted, column);
+                    push_row(&mut output, &line, ' ', &line, column);
+                }
+            }
+        }
+        push_changed_rows(&mut output, &mut deleted, &mut inserted, column);
+
+        output
+    }
+
     // Generate a simple HTML diff for visual representation
     pub fn generate_html_diff(original: &str, modified: &str) -> anyhow::Result<String> {
         let diff = TextDiff::from_lines(original, modified);
@@ -197,3 +334,156 @@ impl DiffGenerator {
         Ok(word_diff)
     }
 }
+
+// Marks the words that differ in each `-` line of a hunk and the `+` line
+// replacing it, pairing a run of deletions with the run of insertions right
+// after it, in order. Lines without a partner are left as they are
+fn mark_changed_words(hunk_lines: &mut [String]) {
+    let mut i = 0;
+    while i < hunk_lines.len() {
+        let deleted = hunk_lines[i..]
+            .iter()
+            .take_while(|line| line.starts_with('-'))
+            .count();
+        let inserted = hunk_lines[i + deleted..]
+            .iter()
+            .take_while(|line| line.starts_with('+'))
+            .count();
+        if deleted == 0 || inserted == 0 {
+            i += deleted.max(1);
+            continue;
+        }
+
+        for pair in 0..deleted.min(inserted) {
+            let (old_index, new_index) = (i + pair, i + deleted + pair);
+            let (old, new) = mark_words(&hunk_lines[old_index][1..], &hunk_lines[new_index][1..]);
+            hunk_lines[old_index] = format!("-{}", old);
+            hunk_lines[new_index] = format!("+{}", new);
+        }
+        i += deleted + inserted;
+    }
+}
+
+// The old and new line with their differing words wrapped in [-...-] and
+// {+...+}, like generate_word_diff. Line terminators are kept unmarked
+fn mark_words(old: &str, new: &str) -> (String, String) {
+    let old_body = old.trim_end_matches(['\r', '\n']);
+    let new_body = new.trim_end_matches(['\r', '\n']);
+    let diff = TextDiff::from_words(old_body, new_body);
+
+    let mut marked_old = String::new();
+    let mut marked_new = String::new();
+    for change in diff.iter_all_changes() {
+        match change.tag() {
+            ChangeTag::Equal => {
+                marked_old.push_str(change.value());
+                marked_new.push_str(change.value());
+            }
+            ChangeTag::Delete => {
+                marked_old.push_str(&format!("[-{}-]", change.value()));
+            }
+            ChangeTag::Insert => {
+                marked_new.push_str(&format!("{{+{}+}}", change.value()));
+            }
+        }
+    }
+
+    marked_old.push_str(&old[old_body.len()..]);
+    marked_new.push_str(&new[new_body.len()..]);
+    (marked_old, marked_new)
+}
+
+// Pairs up a run of deleted lines with the inserted lines that replace them,
+// row by row, then empties both
+fn push_changed_rows(
+    output: &mut String,
+    deleted: &mut Vec<String>,
+    inserted: &mut Vec<String>,
+    column: usize,
+) {
+    for i in 0..deleted.len().max(inserted.len()) {
+        match (deleted.get(i), inserted.get(i)) {
+            (Some(old), Some(new)) => push_row(output, old, '|', new, column),
+            (Some(old), None) => push_row(output, old, '<', "", column),
+            (None, Some(new)) => push_row(output, "", '>', new, column),
+            (None, None) => {}
+        }
+    }
+    deleted.clear();
+    inserted.clear();
+}
+
+// One line of each side, wrapped at `column` characters
+fn push_row(output: &mut String, left: &str, marker: char, right: &str, column: usize) {
+    let wrap = |text: &str| -> Vec<String> {
+        let chars: Vec<char> = text.chars().collect();
+        if chars.is_empty() {
+            return vec![String::new()];
+        }
+        chars
+            .chunks(column)
+            .map(|chunk| chunk.iter().collect())
+            .collect()
+    };
+    let (left, right) = (wrap(left), wrap(right));
+
+    for i in 0..left.len().max(right.len()) {
+        let left = left.get(i).map(String::as_str).unwrap_or("");
+        let right = right.get(i).map(String::as_str).unwrap_or("");
+        let row = format!("{:<column$} {} {}", left, marker, right, column = column);
+        output.push_str(row.trim_end());
+        output.push('\n');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_whitespace_mode_normalize() {
+        let text = "fn a() {  \n\t  x( 1,  2 );\n}\n";
+        assert_eq!(WhitespaceMode::None.normalize(text), text);
+        assert_eq!(
+            WhitespaceMode::Trailing.normalize(text),
+            "fn a() {\n\t  x( 1,  2 );\n}\n"
+        );
+        assert_eq!(
+            WhitespaceMode::All.normalize(text),
+            "fn a() {\nx( 1, 2 );\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_inline_unified_diff_marks_changed_words() {
+        let original = "let x = 1;\nkeep\n";
+        let modified = "let y = 1;\nkeep\n";
+        let diff = DiffGenerator::generate_unified_diff_with(original, modified, true).unwrap();
+        assert!(diff.contains("-let [-x-] = 1;"));
+        assert!(diff.contains("+let {+y+} = 1;"));
+        assert!(diff.contains(" keep"));
+
+        let plain = DiffGenerator::generate_unified_diff(original, modified).unwrap();
+        assert!(plain.contains("-let x = 1;"));
+    }
+
+    #[test]
+    fn test_side_by_side_aligns_and_wraps() {
+        let original = "same\nold\ngone\n";
+        let modified = "same\nnew\nlonger line\nadded\n";
+        // Columns of 5 characters
+        let diff = DiffGenerator::generate_side_by_side(original, modified, 13);
+        let rows: Vec<&str> = diff.lines().collect();
+        assert_eq!(
+            rows,
+            vec![
+                "same    same",
+                "old   | new",
+                "gone  | longe",
+                "      | r lin",
+                "      | e",
+                "      > added",
+            ]
+        );
+    }
+}
@@ -1,3 +1,4 @@
 pub mod generator;
+pub mod patch;
 
 pub use generator::DiffGenerator;
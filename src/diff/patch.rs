@@ -0,0 +1,182 @@
+use regex::Regex;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum PatchError {
+    #[error("Invalid diff: {0}")]
+    InvalidDiff(String),
+
+    #[error("Hunk {hunk} does not apply: expected lines not found near line {line}")]
+    HunkFailed { hunk: usize, line: usize },
+}
+
+// One `@@ -a,b +c,d @@` section of a unified diff
+struct Hunk {
+    // 1-based line in the original the hunk starts at
+    old_start: usize,
+    // Lines the hunk expects to find (context and removals)
+    old_lines: Vec<String>,
+    // Lines the hunk leaves in their place (context and additions)
+    new_lines: Vec<String>,
+    // Whether the new side ends with a "\ No newline at end of file" marker
+    new_missing_newline: bool,
+    // Whether the old side ends with a "\ No newline at end of file" marker
+    old_missing_newline: bool,
+}
+
+// Applies a unified diff to `original`. Each hunk is matched against its
+// expected lines, looking outward from the line numbers in its header so a
+// diff made against a slightly different version still applies
+pub fn apply_unified_diff(original: &str, diff: &str) -> Result<String, PatchError> {
+    let hunks = parse_hunks(diff)?;
+    if hunks.is_empty() {
+        return Err(PatchError::InvalidDiff("no hunks found".to_string()));
+    }
+
+    let line_ending = if original.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    };
+    let mut trailing_newline = original.is_empty() || original.ends_with('\n');
+    let lines: Vec<&str> = original.lines().collect();
+
+    let mut result: Vec<String> = Vec::new();
+    // Index of the first original line not yet copied to the result
+    let mut cursor = 0;
+
+    for (index, hunk) in hunks.iter().enumerate() {
+        let expected = hunk.old_start.saturating_sub(1).max(cursor);
+        let position =
+            find_hunk(&lines, &hunk.old_lines, cursor, expected).ok_or(PatchError::HunkFailed {
+                hunk: index + 1,
+                line: hunk.old_start,
+            })?;
+
+        result.extend(lines[cursor..position].iter().map(|l| l.to_string()));
+        result.extend(hunk.new_lines.iter().cloned());
+        cursor = position + hunk.old_lines.len();
+
+        // A hunk touching the last line decides whether the file ends in a newline
+        if cursor == lines.len() {
+            if hunk.new_missing_newline {
+                trailing_newline = false;
+            } else if hunk.old_missing_newline || !hunk.new_lines.is_empty() {
+                trailing_newline = true;
+            }
+        }
+    }
+    result.extend(lines[cursor..].iter().map(|l| l.to_string()));
+
+    let mut patched = result.join(line_ending);
+    if trailing_newline && !result.is_empty() {
+        patched.push_str(line_ending);
+    }
+    Ok(patched)
+}
+
+// Where `needle` occurs in `lines` at or after `min`, preferring the
+// occurrence closest to `expected`
+fn find_hunk(lines: &[&str], needle: &[String], min: usize, expected: usize) -> Option<usize> {
+    let matches_at = |start: usize| {
+        start + needle.len() <= lines.len()
+            && needle.iter().zip(&lines[start..]).all(|(a, b)| a == b)
+    };
+
+    let last = lines.len().saturating_sub(needle.len());
+    let expected = expected.min(last).max(min);
+    for offset in 0..=lines.len() {
+        let after = expected + offset;
+        if after <= last && matches_at(after) {
+            return Some(after);
+        }
+        if offset <= expected - min && matches_at(expected - offset) {
+            return Some(expected - offset);
+        }
+        if after > last && offset > expected - min {
+            break;
+        }
+    }
+    None
+}
+
+fn parse_hunks(diff: &str) -> Result<Vec<Hunk>, PatchError> {
+    let header = Regex::new(r"^@@ -(\d+)(?:,\d+)? \+\d+(?:,\d+)? @@").unwrap();
+    let mut hunks: Vec<Hunk> = Vec::new();
+    // Which side the previous line belonged to, for "\ No newline" markers
+    let mut last_kind = ' ';
+
+    for line in diff.lines() {
+        if let Some(captures) = header.captures(line) {
+            hunks.push(Hunk {
+                old_start: captures[1].parse().unwrap_or(1),
+                old_lines: Vec::new(),
+                new_lines: Vec::new(),
+                new_missing_newline: false,
+                old_missing_newline: false,
+            });
+            continue;
+        }
+
+        let hunk = match hunks.last_mut() {
+            Some(hunk) => hunk,
+            // File headers and anything else before the first hunk
+            None => continue,
+        };
+
+        if line.starts_with('\\') {
+            if last_kind != '+' {
+                hunk.old_missing_newline = true;
+            }
+            if last_kind != '-' {
+                hunk.new_missing_newline = true;
+            }
+            continue;
+        }
+
+        let (kind, text) = match line.chars().next() {
+            Some(kind @ (' ' | '-' | '+')) => (kind, &line[1..]),
+            // Some tools drop the space from empty context lines
+            None => (' ', ""),
+            Some(_) => {
+                return Err(PatchError::InvalidDiff(format!(
+                    "unexpected line: {}",
+                    line
+                )));
+            }
+        };
+
+        match kind {
+            ' ' => {
+                hunk.old_lines.push(text.to_string());
+                hunk.new_lines.push(text.to_string());
+            }
+            '-' => hunk.old_lines.push(text.to_string()),
+            _ => hunk.new_lines.push(text.to_string()),
+        }
+        last_kind = kind;
+    }
+
+    Ok(hunks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_unified_diff_with_offset() {
+        let original = "a\nb\nc\nd\ne\n";
+        // Line numbers are off by one, as if made against an older version
+        let diff = "--- a/f\n+++ b/f\n@@ -3,3 +3,3 @@\n b\n-c\n+C\n d\n";
+
+        assert_eq!(
+            apply_unified_diff(original, diff).unwrap(),
+            "a\nb\nC\nd\ne\n"
+        );
+        assert!(matches!(
+            apply_unified_diff(original, "@@ -1,1 +1,1 @@\n-x\n+y\n"),
+            Err(PatchError::HunkFailed { hunk: 1, .. })
+        ));
+    }
+}
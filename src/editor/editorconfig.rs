@@ -0,0 +1,250 @@
+use super::file_editor::EditorSettings;
+use crate::shared::logging;
+use globset::{GlobBuilder, GlobMatcher};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+const EDITORCONFIG_FILE: &str = ".editorconfig";
+
+// A `[glob]` section of an .editorconfig file
+struct Section {
+    matcher: GlobMatcher,
+    // Lowercased keys and values, in file order
+    properties: Vec<(String, String)>,
+}
+
+// A parsed .editorconfig file
+struct EditorConfigFile {
+    // Whether `root = true` stops the search for files further up
+    root: bool,
+    sections: Vec<Section>,
+}
+
+impl EditorConfigFile {
+    fn parse(content: &str) -> Self {
+        let mut root = false;
+        let mut sections: Vec<Section> = Vec::new();
+        // Properties of a section whose glob failed to compile are dropped
+        let mut in_bad_section = false;
+
+        for line in content.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+
+            if let Some(header) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                match section_matcher(header) {
+                    Some(matcher) => {
+                        sections.push(Section {
+                            matcher,
+                            properties: Vec::new(),
+                        });
+                        in_bad_section = false;
+                    }
+                    None => {
+                        logging::warn(&format!(
+                            "Ignoring invalid .editorconfig section: [{}]",
+                            header
+                        ));
+                        in_bad_section = true;
+                    }
+                }
+                continue;
+            }
+
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim().to_lowercase(), value.trim().to_lowercase()),
+                None => continue,
+            };
+
+            if in_bad_section {
+                continue;
+            }
+            match sections.last_mut() {
+                Some(section) => section.properties.push((key, value)),
+                // Only `root` is meaningful before the first section
+                None => root = key == "root" && value == "true",
+            }
+        }
+
+        Self { root, sections }
+    }
+}
+
+// Compiles a section glob. Globs without a slash match file names at any
+// depth; globs with one are relative to the directory of the file
+fn section_matcher(glob: &str) -> Option<GlobMatcher> {
+    let pattern = if glob.contains('/') {
+        glob.trim_start_matches('/').to_string()
+    } else {
+        format!("**/{}", glob)
+    };
+
+    GlobBuilder::new(&pattern)
+        .literal_separator(true)
+        .build()
+        .ok()
+        .map(|glob| glob.compile_matcher())
+}
+
+// Finds the .editorconfig files that apply to a path and merges their
+// settings over the configured ones. Parsed files are cached and re-read
+// when they change on disk
+pub struct EditorConfigResolver {
+    project_root: PathBuf,
+    cache: Mutex<HashMap<PathBuf, (SystemTime, Arc<EditorConfigFile>)>>,
+}
+
+impl EditorConfigResolver {
+    pub fn new(project_root: &Path) -> Self {
+        Self {
+            project_root: project_root.to_path_buf(),
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // The settings to write `path` with: `base` overridden by every matching
+    // .editorconfig section between the project root and the file
+    pub fn settings_for(&self, path: &Path, base: &EditorSettings) -> EditorSettings {
+        let mut properties: HashMap<String, String> = HashMap::new();
+
+        // Nearest files win, so apply them from the outermost one in
+        for (dir, file) in self.files_for(path).iter().rev() {
+            let relative = match path.strip_prefix(dir) {
+                Ok(relative) => relative,
+                Err(_) => continue,
+            };
+            for section in file
+                .sections
+                .iter()
+                .filter(|s| s.matcher.is_match(relative))
+            {
+                for (key, value) in &section.properties {
+                    properties.insert(key.clone(), value.clone());
+                }
+            }
+        }
+
+        apply_properties(&properties, base)
+    }
+
+    // The .editorconfig files from the file's directory up to the project
+    // root, nearest first, stopping at one marked `root = true`
+    fn files_for(&self, path: &Path) -> Vec<(PathBuf, Arc<EditorConfigFile>)> {
+        let mut files = Vec::new();
+
+        for dir in path.ancestors().skip(1) {
+            if !dir.starts_with(&self.project_root) {
+                break;
+            }
+            if let Some(file) = self.load(&dir.join(EDITORCONFIG_FILE)) {
+                let is_root = file.root;
+                files.push((dir.to_path_buf(), file));
+                if is_root {
+                    break;
+                }
+            }
+        }
+
+        files
+    }
+
+    fn load(&self, path: &Path) -> Option<Arc<EditorConfigFile>> {
+        let modified = std::fs::metadata(path).and_then(|m| m.modified()).ok()?;
+
+        let mut cache = self.cache.lock().unwrap();
+        if let Some((cached_at, file)) = cache.get(path) {
+            if *cached_at == modified {
+                return Some(file.clone());
+            }
+        }
+
+        let content = std::fs::read_to_string(path).ok()?;
+        let file = Arc::new(EditorConfigFile::parse(&content));
+        cache.insert(path.to_path_buf(), (modified, file.clone()));
+        Some(file)
+    }
+}
+
+// Overrides `base` with the recognized .editorconfig properties. `unset`
+// and unrecognized values keep the configured setting
+fn apply_properties(properties: &HashMap<String, String>, base: &EditorSettings) -> EditorSettings {
+    let mut settings = base.clone();
+    let property = |key: &str| properties.get(key).map(String::as_str);
+
+    match property("indent_style") {
+        Some("tab") => settings.indent_with_tabs = Some(true),
+        Some("space") => settings.indent_with_tabs = Some(false),
+        _ => {}
+    }
+
+    // tab_width defaults to indent_size, and is the width used for tab stops
+    let tab_width = property("tab_width")
+        .or(property("indent_size"))
+        .and_then(|size| size.parse::<usize>().ok())
+        .filter(|size| *size > 0);
+    if let Some(tab_width) = tab_width {
+        settings.tab_size = tab_width;
+    }
+
+    match property("end_of_line") {
+        Some("lf") => settings.line_ending = Some("\n"),
+        Some("crlf") => settings.line_ending = Some("\r\n"),
+        _ => {}
+    }
+
+    match property("insert_final_newline") {
+        Some("true") => settings.ensure_final_newline = true,
+        Some("false") => settings.ensure_final_newline = false,
+        _ => {}
+    }
+
+    match property("trim_trailing_whitespace") {
+        Some("true") => settings.trim_trailing_whitespace = true,
+        Some("false") => settings.trim_trailing_whitespace = false,
+        _ => {}
+    }
+
+    settings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nearest_editorconfig_section_wins() {
+        let root = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(root.path().join("web/src")).unwrap();
+        std::fs::write(
+            root.path().join(".editorconfig"),
+            "root = true\n\n[*]\nindent_style = space\nindent_size = 4\nend_of_line = lf\n\n[*.md]\ntrim_trailing_whitespace = false\n",
+        )
+        .unwrap();
+        std::fs::write(
+            root.path().join("web/.editorconfig"),
+            "[src/*.{js,ts}]\nindent_style = tab\nindent_size = 2\n[*.md]\nindent_style = unset\n",
+        )
+        .unwrap();
+
+        let resolver = EditorConfigResolver::new(root.path());
+        let base = EditorSettings::default();
+
+        let js = resolver.settings_for(&root.path().join("web/src/app.js"), &base);
+        assert_eq!(js.indent_with_tabs, Some(true));
+        assert_eq!(js.tab_size, 2);
+        assert_eq!(js.line_ending, Some("\n"));
+
+        // Anchored globs only match relative to their own directory
+        let nested = resolver.settings_for(&root.path().join("web/src/lib/util.js"), &base);
+        assert_eq!(nested.indent_with_tabs, Some(false));
+        assert_eq!(nested.tab_size, 4);
+
+        let md = resolver.settings_for(&root.path().join("web/README.md"), &base);
+        assert_eq!(md.indent_with_tabs, None);
+        assert!(!md.trim_trailing_whitespace);
+    }
+}
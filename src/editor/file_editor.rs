@@ -1,9 +1,21 @@
+use std::ops::Range;
 use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
 use tokio::fs::{self, File, OpenOptions};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use regex::Regex;
 use thiserror::Error;
+use crate::config::EditorConfig;
+use crate::editor::editorconfig::EditorConfigResolver;
 use crate::shared::logging;
 
+// Default number of columns a tab occupies
+const DEFAULT_TAB_SIZE: usize = 4;
+
+// Numbers the temporary files of atomic writes, so concurrent writes to the
+// same file never share one
+static TEMP_FILE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
 #[derive(Error, Debug)]
 pub enum EditorError {
     #[error("File not found: {0}")]
@@ -17,15 +29,189 @@ pub enum EditorError {
 
     #[error("Invalid range: {start}-{end}")]
     InvalidRange { start: usize, end: usize },
+
+    #[error("No line matches pattern: {0}")]
+    PatternNotFound(String),
+
+    #[error("Pattern matches {count} lines, expected exactly one: {pattern}")]
+    AmbiguousMatch { pattern: String, count: usize },
+
+    #[error("Edits {first} and {second} overlap")]
+    OverlappingEdits { first: usize, second: usize },
+
+    #[error("Directory does not exist: {0} (pass create_dirs to create it)")]
+    MissingDirectory(String),
+
+    #[error("Column {column} is past the end of line {line}, which has {length} characters")]
+    ColumnOutOfRange { line: usize, column: usize, length: usize },
+}
+
+// Formatting applied to content written by the editor
+#[derive(Debug, Clone)]
+pub struct EditorSettings {
+    pub tab_size: usize,
+    // Some(true) converts leading spaces to tabs, Some(false) converts leading
+    // tabs to spaces, None leaves indentation as written
+    pub indent_with_tabs: Option<bool>,
+    // Line terminator to normalize to, None leaves line endings as written
+    pub line_ending: Option<&'static str>,
+    pub trim_trailing_whitespace: bool,
+    pub ensure_final_newline: bool,
+}
+
+impl Default for EditorSettings {
+    fn default() -> Self {
+        Self {
+            tab_size: DEFAULT_TAB_SIZE,
+            indent_with_tabs: None,
+            line_ending: None,
+            trim_trailing_whitespace: false,
+            ensure_final_newline: false,
+        }
+    }
+}
+
+impl EditorSettings {
+    pub fn from_config(config: &EditorConfig) -> Self {
+        let line_ending = match config.line_endings.as_deref() {
+            Some("lf") => Some("\n"),
+            Some("crlf") => Some("\r\n"),
+            Some("native") => Some(if cfg!(windows) { "\r\n" } else { "\n" }),
+            Some(other) => {
+                logging::warn(&format!("Ignoring unknown line_endings setting: {}", other));
+                None
+            }
+            None => None,
+        };
+
+        Self {
+            tab_size: config
+                .tab_size
+                .filter(|size| *size > 0)
+                .unwrap_or(DEFAULT_TAB_SIZE),
+            indent_with_tabs: config.indent_with_tabs,
+            line_ending,
+            trim_trailing_whitespace: config.trim_trailing_whitespace.unwrap_or(false),
+            ensure_final_newline: config.ensure_final_newline.unwrap_or(false),
+        }
+    }
+
+    fn is_noop(&self) -> bool {
+        self.indent_with_tabs.is_none()
+            && self.line_ending.is_none()
+            && !self.trim_trailing_whitespace
+            && !self.ensure_final_newline
+    }
+
+    // Applies the settings to content about to be written to `path`
+    pub fn normalize(&self, path: &Path, content: &str) -> String {
+        if self.is_noop() || content.is_empty() {
+            return content.to_string();
+        }
+
+        // Makefiles require tab indentation, so never convert them to spaces
+        let is_makefile = path
+            .file_name()
+            .map(|n| {
+                let name = n.to_string_lossy();
+                name == "Makefile"
+                    || name == "makefile"
+                    || name == "GNUmakefile"
+                    || name.ends_with(".mk")
+            })
+            .unwrap_or(false);
+        let indent_with_tabs = match self.indent_with_tabs {
+            Some(false) if is_makefile => None,
+            other => other,
+        };
+
+        // Without a configured line ending, keep the one the content uses
+        let line_ending = self.line_ending.unwrap_or(if content.contains("\r\n") {
+            "\r\n"
+        } else {
+            "\n"
+        });
+        let had_final_newline = content.ends_with('\n');
+
+        let lines: Vec<String> = content
+            .lines()
+            .map(|line| {
+                let line = match indent_with_tabs {
+                    Some(use_tabs) => self.reindent(line, use_tabs),
+                    None => line.to_string(),
+                };
+                if self.trim_trailing_whitespace {
+                    line.trim_end().to_string()
+                } else {
+                    line
+                }
+            })
+            .collect();
+
+        let mut result = lines.join(line_ending);
+        if had_final_newline || self.ensure_final_newline {
+            result.push_str(line_ending);
+        }
+
+        result
+    }
+
+    // Rewrites the leading whitespace of a line using tabs or spaces
+    fn reindent(&self, line: &str, use_tabs: bool) -> String {
+        let indent_len = line.len() - line.trim_start_matches([' ', '\t']).len();
+        let (indent, rest) = line.split_at(indent_len);
+
+        // Measure the indentation width in columns, honoring tab stops
+        let width = indent.chars().fold(0, |col, c| {
+            if c == '\t' {
+                col + self.tab_size - col % self.tab_size
+            } else {
+                col + 1
+            }
+        });
+
+        let new_indent = if use_tabs {
+            format!(
+                "{}{}",
+                "\t".repeat(width / self.tab_size),
+                " ".repeat(width % self.tab_size)
+            )
+        } else {
+            " ".repeat(width)
+        };
+
+        format!("{}{}", new_indent, rest)
+    }
 }
 
+#[derive(Default)]
 pub struct FileEditor {
-    // Could add configuration options here in the future
+    settings: EditorSettings,
+    // Per-path overrides from .editorconfig files, when enabled
+    editorconfig: Option<EditorConfigResolver>,
 }
 
 impl FileEditor {
-    pub fn new() -> Self {
-        Self {}
+    pub fn with_config(config: &EditorConfig) -> Self {
+        Self {
+            settings: EditorSettings::from_config(config),
+            editorconfig: None,
+        }
+    }
+
+    // Honors .editorconfig files found between `project_root` and each file
+    // written, overriding the configured settings
+    pub fn with_editorconfig(mut self, project_root: &Path) -> Self {
+        self.editorconfig = Some(EditorConfigResolver::new(project_root));
+        self
+    }
+
+    // The settings files at `path` are written with
+    pub fn settings_for(&self, path: &Path) -> EditorSettings {
+        match &self.editorconfig {
+            Some(resolver) => resolver.settings_for(path, &self.settings),
+            None => self.settings.clone(),
+        }
     }
 
     // Basic file operations
@@ -43,20 +229,42 @@ impl FileEditor {
     }
 
     pub async fn write_file(&self, path: &Path, content: &str) -> Result<(), EditorError> {
-        // Ensure parent directory exists
+        self.write_file_with_dirs(path, content, true).await
+    }
+
+    // Writes a file, creating missing parent directories only when
+    // `create_dirs` is set; otherwise a missing parent is an error
+    pub async fn write_file_with_dirs(
+        &self,
+        path: &Path,
+        content: &str,
+        create_dirs: bool,
+    ) -> Result<(), EditorError> {
         if let Some(parent) = path.parent() {
             if !parent.exists() {
+                if !create_dirs {
+                    return Err(EditorError::MissingDirectory(
+                        parent.to_string_lossy().to_string(),
+                    ));
+                }
                 fs::create_dir_all(parent).await?;
             }
         }
 
-        let mut file = File::create(path).await?;
-        file.write_all(content.as_bytes()).await?;
+        let content = self.settings_for(path).normalize(path, content);
+        write_atomically(path, content.as_bytes()).await?;
 
         logging::info(&format!("Wrote file: {}", path.display()));
         Ok(())
     }
 
+    // Writes bytes exactly as given, skipping the editor settings. Used to
+    // put back content captured earlier
+    pub async fn write_raw(&self, path: &Path, content: &[u8]) -> Result<(), EditorError> {
+        write_atomically(path, content).await?;
+        Ok(())
+    }
+
     pub async fn append_to_file(&self, path: &Path, content: &str) -> Result<(), EditorError> {
         if !path.exists() {
             return Err(EditorError::FileNotFound(path.to_string_lossy().to_string()));
@@ -81,18 +289,17 @@ impl FileEditor {
         }
 
         let file_content = self.read_file(path).await?;
-        let mut lines: Vec<&str> = file_content.lines().collect();
+        let mut buffer = LineBuffer::parse(&file_content);
 
-        // If line_num is beyond the file length, we'll append to the end
-        if line_num > lines.len() {
+        // A line_num of the line count appends to the end
+        if line_num > buffer.lines.len() {
             return Err(EditorError::LineOutOfRange(line_num));
         }
 
         // Insert the new line at the specified position
-        lines.insert(line_num, content);
-        let new_content = lines.join("\n");
+        buffer.lines.insert(line_num, content.to_string());
 
-        self.write_file(path, &new_content).await?;
+        self.write_file(path, &buffer.render()).await?;
 
         logging::info(&format!("Inserted line {} in file: {}", line_num, path.display()));
         Ok(())
@@ -104,17 +311,16 @@ impl FileEditor {
         }
 
         let file_content = self.read_file(path).await?;
-        let mut lines: Vec<&str> = file_content.lines().collect();
+        let mut buffer = LineBuffer::parse(&file_content);
 
-        if line_num >= lines.len() {
+        if line_num >= buffer.lines.len() {
             return Err(EditorError::LineOutOfRange(line_num));
         }
 
         // Replace the line at the specified position
-        lines[line_num] = content;
-        let new_content = lines.join("\n");
+        buffer.lines[line_num] = content.to_string();
 
-        self.write_file(path, &new_content).await?;
+        self.write_file(path, &buffer.render()).await?;
 
         logging::info(&format!("Replaced line {} in file: {}", line_num, path.display()));
         Ok(())
@@ -126,22 +332,90 @@ impl FileEditor {
         }
 
         let file_content = self.read_file(path).await?;
-        let mut lines: Vec<&str> = file_content.lines().collect();
+        let mut buffer = LineBuffer::parse(&file_content);
 
-        if line_num >= lines.len() {
+        if line_num >= buffer.lines.len() {
             return Err(EditorError::LineOutOfRange(line_num));
         }
 
         // Remove the line at the specified position
-        lines.remove(line_num);
-        let new_content = lines.join("\n");
+        buffer.lines.remove(line_num);
 
-        self.write_file(path, &new_content).await?;
+        self.write_file(path, &buffer.render()).await?;
 
         logging::info(&format!("Deleted line {} in file: {}", line_num, path.display()));
         Ok(())
     }
 
+    // Inserts content after (or before) the first line matching `pattern`,
+    // returning the 1-based line number the inserted content starts at.
+    // Unindented content takes on the indentation of the matched line
+    pub async fn insert_at_match(
+        &self,
+        path: &Path,
+        pattern: &Regex,
+        content: &str,
+        after: bool,
+        require_unique: bool,
+    ) -> Result<usize, EditorError> {
+        let file_content = self.read_file(path).await?;
+        let matched = find_matching_line(&file_content, pattern, require_unique)?;
+        let mut buffer = LineBuffer::parse(&file_content);
+
+        let indent: String = buffer.lines[matched]
+            .chars()
+            .take_while(|c| c.is_whitespace())
+            .collect();
+        let inserted = LineBuffer::parse(content);
+        let content_is_unindented = inserted
+            .lines
+            .iter()
+            .find(|line| !line.trim().is_empty())
+            .map(|line| !line.starts_with(char::is_whitespace))
+            .unwrap_or(false);
+
+        let new_lines = inserted.lines.into_iter().map(|line| {
+            if content_is_unindented && !line.trim().is_empty() {
+                format!("{}{}", indent, line)
+            } else {
+                line
+            }
+        });
+
+        let index = if after { matched + 1 } else { matched };
+        buffer.lines.splice(index..index, new_lines);
+        self.write_file(path, &buffer.render()).await?;
+
+        logging::info(&format!(
+            "Inserted content {} line {} in file: {}",
+            if after { "after" } else { "before" },
+            matched + 1,
+            path.display()
+        ));
+
+        Ok(index + 1)
+    }
+
+    // Inserts content before 1-based line `line`, or at the end of the file
+    // when `line` is one past the last line. Content keeps its own lines as
+    // given, rather than becoming a single line like insert_line's
+    pub async fn insert_content(&self, path: &Path, line: usize, content: &str) -> Result<(), EditorError> {
+        let file_content = self.read_file(path).await?;
+        let mut buffer = LineBuffer::parse(&file_content);
+
+        if line == 0 || line > buffer.lines.len() + 1 {
+            return Err(EditorError::LineOutOfRange(line));
+        }
+
+        splice_lines(&mut buffer, line - 1, line - 1, content);
+        self.write_file(path, &buffer.render()).await?;
+
+        logging::info(&format!("Inserted content at line {} in file: {}", line, path.display()));
+        Ok(())
+    }
+
+    // Replaces lines `start_line` through `end_line` (1-based, inclusive) with
+    // `new_content`; empty content deletes them
     pub async fn edit_region(
         &self,
         path: &Path,
@@ -153,57 +427,551 @@ impl FileEditor {
             return Err(EditorError::FileNotFound(path.to_string_lossy().to_string()));
         }
 
-        // Validate the range
-        if start_line > end_line {
+        let file_content = self.read_file(path).await?;
+        let result = splice_region(&file_content, start_line, end_line, new_content)?;
+
+        self.write_file(path, &result).await?;
+
+        logging::info(&format!(
+            "Edited region lines {}-{} in file: {}",
+            start_line,
+            end_line,
+            path.display()
+        ));
+
+        Ok(())
+    }
+}
+
+// Rewrites every line terminator as `line_ending`, leaving the rest of the
+// content untouched
+pub fn convert_line_endings(content: &str, line_ending: &str) -> String {
+    let unix = content.replace("\r\n", "\n");
+    if line_ending == "\n" {
+        unix
+    } else {
+        unix.replace('\n', line_ending)
+    }
+}
+
+// A whitespace tidy-up of a whole file. Trimming and the final newline
+// follow the editor settings for the file unless set here
+#[derive(Debug, Clone, Copy)]
+pub struct WhitespaceCleanup {
+    // Longest run of blank lines kept
+    pub max_blank_lines: usize,
+    pub trim_trailing_whitespace: Option<bool>,
+    pub ensure_final_newline: Option<bool>,
+}
+
+impl WhitespaceCleanup {
+    pub fn apply(&self, content: &str, settings: &EditorSettings) -> String {
+        let trim = self
+            .trim_trailing_whitespace
+            .unwrap_or(settings.trim_trailing_whitespace);
+        let mut buffer = LineBuffer::parse(content);
+
+        let mut blank_run = 0;
+        let mut lines = Vec::with_capacity(buffer.lines.len());
+        for line in buffer.lines {
+            let line = if trim { line.trim_end().to_string() } else { line };
+            if line.trim().is_empty() {
+                blank_run += 1;
+                if blank_run > self.max_blank_lines {
+                    continue;
+                }
+            } else {
+                blank_run = 0;
+            }
+            lines.push(line);
+        }
+
+        buffer.lines = lines;
+        buffer.trailing_newline |= self
+            .ensure_final_newline
+            .unwrap_or(settings.ensure_final_newline);
+        buffer.render()
+    }
+}
+
+// Replaces tabs with spaces up to the next tab stop, either only in the
+// indentation of each line or everywhere. Used for display, never on disk
+pub fn expand_tabs(content: &str, tab_size: usize, all: bool) -> String {
+    let tab_size = tab_size.max(1);
+    let mut result = String::with_capacity(content.len());
+
+    for line in content.split_inclusive('\n') {
+        let mut column = 0;
+        let mut in_indent = true;
+        for c in line.chars() {
+            in_indent = in_indent && (c == ' ' || c == '\t');
+            if c == '\t' && (all || in_indent) {
+                let width = tab_size - column % tab_size;
+                result.extend(std::iter::repeat(' ').take(width));
+                column += width;
+            } else {
+                result.push(c);
+                column += 1;
+            }
+        }
+    }
+
+    result
+}
+
+// Index of the first line matching `pattern`, erroring if nothing matches
+// or, when `require_unique` is set, if more than one line does
+pub fn find_matching_line(content: &str, pattern: &Regex, require_unique: bool) -> Result<usize, EditorError> {
+    let matching: Vec<usize> = content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| pattern.is_match(line))
+        .map(|(i, _)| i)
+        .collect();
+
+    match matching.first() {
+        None => Err(EditorError::PatternNotFound(pattern.to_string())),
+        Some(_) if require_unique && matching.len() > 1 => Err(EditorError::AmbiguousMatch {
+            pattern: pattern.to_string(),
+            count: matching.len(),
+        }),
+        Some(&index) => Ok(index),
+    }
+}
+
+// Writes to a temporary file next to `path` and renames it into place, so
+// readers never see a half-written file. An existing file keeps its permissions
+async fn write_atomically(path: &Path, content: &[u8]) -> std::io::Result<()> {
+    let file_name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let temp_path = path.with_file_name(format!(
+        ".{}.mcedit-{}-{}.tmp",
+        file_name,
+        std::process::id(),
+        TEMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    // Never reuse a file that is already there, which may be another write's
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&temp_path)
+        .await?;
+
+    let result = async {
+        file.write_all(content).await?;
+        file.sync_all().await?;
+
+        if let Ok(metadata) = fs::metadata(path).await {
+            fs::set_permissions(&temp_path, metadata.permissions()).await?;
+        }
+
+        fs::rename(&temp_path, path).await
+    }
+    .await;
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path).await;
+    }
+    result
+}
+
+// A file's content split into lines, remembering how the lines were
+// terminated so the content can be rebuilt exactly. Line operations go
+// through it so a file keeps (or keeps lacking) its final newline
+pub struct LineBuffer {
+    pub lines: Vec<String>,
+    trailing_newline: bool,
+    line_ending: &'static str,
+}
+
+impl LineBuffer {
+    pub fn parse(content: &str) -> Self {
+        let line_ending = if content.contains("\r\n") { "\r\n" } else { "\n" };
+
+        Self {
+            lines: content.lines().map(|line| line.to_string()).collect(),
+            trailing_newline: content.ends_with('\n'),
+            line_ending,
+        }
+    }
+
+    pub fn render(&self) -> String {
+        let mut content = self.lines.join(self.line_ending);
+        if self.trailing_newline && !self.lines.is_empty() {
+            content.push_str(self.line_ending);
+        }
+        content
+    }
+}
+
+// A replacement of lines `start_line..end_line` (0-based, end exclusive)
+#[derive(Debug, Clone)]
+pub struct RegionEdit {
+    pub start_line: usize,
+    pub end_line: usize,
+    pub content: String,
+}
+
+// The lines a region from `start_line` through `end_line` (1-based,
+// inclusive) covers in a file of `line_count` lines, as 0-based indices. An
+// end past the last line stops at it
+pub fn region_range(
+    start_line: usize,
+    end_line: usize,
+    line_count: usize,
+) -> Result<Range<usize>, EditorError> {
+    if start_line == 0 || start_line > end_line {
+        return Err(EditorError::InvalidRange {
+            start: start_line,
+            end: end_line,
+        });
+    }
+    if start_line > line_count {
+        return Err(EditorError::LineOutOfRange(start_line));
+    }
+
+    Ok(start_line - 1..end_line.min(line_count))
+}
+
+// Replaces lines `start_line` through `end_line` (1-based, inclusive) of
+// `content` with `new_content`. A newline ending `new_content` terminates its
+// last line rather than adding a blank one, and the original trailing
+// newline is kept
+fn splice_region(
+    content: &str,
+    start_line: usize,
+    end_line: usize,
+    new_content: &str,
+) -> Result<String, EditorError> {
+    let mut buffer = LineBuffer::parse(content);
+    let range = region_range(start_line, end_line, buffer.lines.len())?;
+
+    splice_lines(&mut buffer, range.start, range.end, new_content);
+    Ok(buffer.render())
+}
+
+// Applies several region edits, all addressed against the lines of
+// `content` as given. Edits may not overlap, and are applied from the
+// bottom up so earlier ones don't shift the lines of later ones
+pub fn splice_regions(content: &str, edits: &[RegionEdit]) -> Result<String, EditorError> {
+    let mut buffer = LineBuffer::parse(content);
+
+    for edit in edits {
+        if edit.start_line > edit.end_line {
             return Err(EditorError::InvalidRange {
-                start: start_line,
-                end: end_line,
+                start: edit.start_line,
+                end: edit.end_line,
             });
         }
+        if edit.start_line >= buffer.lines.len() {
+            return Err(EditorError::LineOutOfRange(edit.start_line));
+        }
+    }
 
-        let file_content = self.read_file(path).await?;
-        let lines: Vec<&str> = file_content.lines().collect();
+    // Indices into `edits`, from the top of the file down
+    let mut order: Vec<usize> = (0..edits.len()).collect();
+    order.sort_by_key(|&i| (edits[i].start_line, edits[i].end_line));
 
-        if start_line >= lines.len() {
-            return Err(EditorError::LineOutOfRange(start_line));
+    for pair in order.windows(2) {
+        let (above, below) = (&edits[pair[0]], &edits[pair[1]]);
+        if below.start_line < above.end_line || below.start_line == above.start_line {
+            return Err(EditorError::OverlappingEdits {
+                first: pair[0].min(pair[1]) + 1,
+                second: pair[0].max(pair[1]) + 1,
+            });
         }
+    }
 
-        // Use the min of end_line and lines.len() to handle cases where
-        // end_line is beyond the file
-        let effective_end = end_line.min(lines.len());
+    for &i in order.iter().rev() {
+        let edit = &edits[i];
+        splice_lines(&mut buffer, edit.start_line, edit.end_line, &edit.content);
+    }
 
-        // Create a new content by joining:
-        // 1. Lines before the start
-        // 2. The new content
-        // 3. Lines after the end
-        let mut result = String::new();
+    Ok(buffer.render())
+}
 
-        // Add lines before the start
-        if start_line > 0 {
-            result.push_str(&lines[0..start_line].join("\n"));
-            result.push_str("\n");
-        }
+// Joins lines `start_line` through `end_line` (1-based, inclusive) into one,
+// putting `separator` between them. Lines after the first lose their
+// leading indentation, the way an editor's join does
+pub fn join_line_range(
+    content: &str,
+    start_line: usize,
+    end_line: usize,
+    separator: &str,
+) -> Result<String, EditorError> {
+    let mut buffer = LineBuffer::parse(content);
+    let range = region_range(start_line, end_line, buffer.lines.len())?;
+
+    let mut joined = buffer.lines[range.start].clone();
+    for line in &buffer.lines[range.start + 1..range.end] {
+        joined.push_str(separator);
+        joined.push_str(line.trim_start());
+    }
+    buffer.lines.splice(range, [joined]);
 
-        // Add the new content
-        result.push_str(new_content);
+    Ok(buffer.render())
+}
 
-        // Add lines after the end
-        if effective_end < lines.len() {
-            if !result.ends_with('\n') {
-                result.push_str("\n");
-            }
-            result.push_str(&lines[effective_end..].join("\n"));
+// Breaks 1-based line `line` in two, keeping its first `column` characters
+// on it and moving the rest to a new line below
+pub fn split_line_at(content: &str, line: usize, column: usize) -> Result<String, EditorError> {
+    let mut buffer = LineBuffer::parse(content);
+    if line == 0 || line > buffer.lines.len() {
+        return Err(EditorError::LineOutOfRange(line));
+    }
+
+    let text = &buffer.lines[line - 1];
+    let length = text.chars().count();
+    if column > length {
+        return Err(EditorError::ColumnOutOfRange { line, column, length });
+    }
+
+    let at = text.char_indices().nth(column).map_or(text.len(), |(i, _)| i);
+    let (head, tail) = text.split_at(at);
+    let (head, tail) = (head.to_string(), tail.to_string());
+    buffer.lines.splice(line - 1..line, [head, tail]);
+
+    Ok(buffer.render())
+}
+
+// Replaces lines `start_line..end_line` of the buffer, clamping `end_line`
+// to the end of the file
+pub fn splice_lines(buffer: &mut LineBuffer, start_line: usize, end_line: usize, new_content: &str) {
+    // Use the min of end_line and the line count to handle cases where
+    // end_line is beyond the file
+    let effective_end = end_line.min(buffer.lines.len());
+    let replacement = LineBuffer::parse(new_content);
+
+    // Content replacing the end of the file decides how the file ends too
+    if effective_end == buffer.lines.len() && replacement.trailing_newline {
+        buffer.trailing_newline = true;
+    }
+
+    buffer
+        .lines
+        .splice(start_line..effective_end, replacement.lines);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> EditorSettings {
+        EditorSettings {
+            tab_size: 4,
+            ..EditorSettings::default()
         }
+    }
 
-        self.write_file(path, &result).await?;
+    #[test]
+    fn test_normalize_default_is_unchanged() {
+        let content = "fn main() {\r\n\tlet x = 1;   \r\n}";
+        assert_eq!(EditorSettings::default().normalize(Path::new("a.rs"), content), content);
+    }
 
-        logging::info(&format!(
-            "Edited region lines {}-{} in file: {}",
+    #[test]
+    fn test_normalize_line_endings_and_whitespace() {
+        let settings = EditorSettings {
+            line_ending: Some("\n"),
+            trim_trailing_whitespace: true,
+            ensure_final_newline: true,
+            ..settings()
+        };
+        let content = "a  \r\nb\t\r\nc";
+        assert_eq!(settings.normalize(Path::new("a.txt"), content), "a\nb\nc\n");
+    }
+
+    #[test]
+    fn test_convert_line_endings_keeps_whitespace() {
+        let content = "a  \r\nb\t\nc";
+        assert_eq!(convert_line_endings(content, "\n"), "a  \nb\t\nc");
+        assert_eq!(convert_line_endings(content, "\r\n"), "a  \r\nb\t\r\nc");
+    }
+
+    #[test]
+    fn test_whitespace_cleanup_collapses_blank_lines() {
+        let cleanup = WhitespaceCleanup {
+            max_blank_lines: 1,
+            trim_trailing_whitespace: Some(true),
+            ensure_final_newline: None,
+        };
+        let content = "a  \n\n  \n\nb\r\n\nc";
+        assert_eq!(cleanup.apply(content, &settings()), "a\r\n\r\nb\r\n\r\nc");
+
+        let final_newline = EditorSettings {
+            ensure_final_newline: true,
+            ..settings()
+        };
+        assert_eq!(cleanup.apply("a\n\n\n", &final_newline), "a\n\n");
+    }
+
+    #[test]
+    fn test_normalize_indentation() {
+        let to_spaces = EditorSettings {
+            indent_with_tabs: Some(false),
+            ..settings()
+        };
+        assert_eq!(to_spaces.normalize(Path::new("a.py"), "\tx = 1\n"), "    x = 1\n");
+        assert_eq!(to_spaces.normalize(Path::new("Makefile"), "\techo\n"), "\techo\n");
+
+        let to_tabs = EditorSettings {
+            indent_with_tabs: Some(true),
+            ..settings()
+        };
+        assert_eq!(to_tabs.normalize(Path::new("a.go"), "      x := 1\n"), "\t  x := 1\n");
+    }
+
+    #[test]
+    fn test_expand_tabs() {
+        let content = "\tfn main() {\n  \tlet x\t= 1;\r\n}";
+        assert_eq!(expand_tabs(content, 4, false), "    fn main() {\n    let x\t= 1;\r\n}");
+        assert_eq!(expand_tabs(content, 4, true), "    fn main() {\n    let x   = 1;\r\n}");
+    }
+
+    #[test]
+    fn test_splice_region_replaces_last_line() {
+        assert_eq!(splice_region("a\nb\nc\n", 3, 3, "C").unwrap(), "a\nb\nC\n");
+        assert_eq!(splice_region("a\nb\nc", 3, 3, "C").unwrap(), "a\nb\nC");
+    }
+
+    #[test]
+    fn test_join_and_split_lines() {
+        let content = "call(\n    a,\n    b)\nrest\n";
+        assert_eq!(join_line_range(content, 1, 3, "").unwrap(), "call(a,b)\nrest\n");
+        assert_eq!(join_line_range(content, 2, 3, " ").unwrap(), "call(\n    a, b)\nrest\n");
+
+        assert_eq!(split_line_at("héllo world\n", 1, 5).unwrap(), "héllo\n world\n");
+        assert_eq!(split_line_at("ab\n", 1, 2).unwrap(), "ab\n\n");
+        assert!(matches!(
+            split_line_at("ab\n", 1, 3),
+            Err(EditorError::ColumnOutOfRange { length: 2, .. })
+        ));
+        assert!(matches!(split_line_at("ab\n", 2, 0), Err(EditorError::LineOutOfRange(2))));
+    }
+
+    #[test]
+    fn test_splice_region_replaces_first_line() {
+        assert_eq!(splice_region("a\nb\nc\n", 1, 1, "A").unwrap(), "A\nb\nc\n");
+        assert_eq!(splice_region("a\r\nb\r\n", 1, 1, "A").unwrap(), "A\r\nb\r\n");
+    }
+
+    #[test]
+    fn test_splice_region_replaces_entire_file() {
+        assert_eq!(splice_region("a\nb\n", 1, 2, "x\ny").unwrap(), "x\ny\n");
+        assert_eq!(splice_region("a\nb", 1, 10, "x\ny\n").unwrap(), "x\ny\n");
+        assert_eq!(splice_region("a\nb\n", 1, 2, "").unwrap(), "");
+    }
+
+    #[test]
+    fn test_splice_region_content_ending_in_newline() {
+        assert_eq!(splice_region("a\nb\nc\n", 2, 2, "x\n").unwrap(), "a\nx\nc\n");
+        assert_eq!(splice_region("a\nb\nc\n", 2, 3, "x\ny\n").unwrap(), "a\nx\ny\n");
+        assert!(splice_region("a\n", 2, 2, "x").is_err());
+    }
+
+    #[test]
+    fn test_splice_regions_uses_original_line_numbers() {
+        let edit = |start_line, end_line, content: &str| RegionEdit {
             start_line,
-            effective_end,
-            path.display()
+            end_line,
+            content: content.to_string(),
+        };
+        let content = "a\nb\nc\nd\ne\n";
+
+        // Given out of order, and the first edit grows the file
+        let edits = [edit(3, 4, "D"), edit(0, 1, "A1\nA2"), edit(4, 5, "")];
+        assert_eq!(splice_regions(content, &edits).unwrap(), "A1\nA2\nb\nc\nD\n");
+
+        let overlapping = [edit(0, 2, "x"), edit(3, 4, "y"), edit(1, 3, "z")];
+        assert!(matches!(
+            splice_regions(content, &overlapping),
+            Err(EditorError::OverlappingEdits { first: 1, second: 3 })
         ));
+    }
 
-        Ok(())
+    #[tokio::test]
+    async fn test_line_ops_preserve_trailing_newline() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("f.txt");
+        let editor = FileEditor::default();
+
+        for ending in ["\n", ""] {
+            let expect = |content: &str| format!("{}{}", content, ending);
+            std::fs::write(&path, expect("a\nb\nc")).unwrap();
+
+            editor.insert_line(&path, 3, "d").await.unwrap();
+            assert_eq!(std::fs::read_to_string(&path).unwrap(), expect("a\nb\nc\nd"));
+
+            editor.replace_line(&path, 3, "D").await.unwrap();
+            assert_eq!(std::fs::read_to_string(&path).unwrap(), expect("a\nb\nc\nD"));
+
+            editor.delete_line(&path, 3).await.unwrap();
+            assert_eq!(std::fs::read_to_string(&path).unwrap(), expect("a\nb\nc"));
+
+            editor.edit_region(&path, 2, 3, "B").await.unwrap();
+            assert_eq!(std::fs::read_to_string(&path).unwrap(), expect("a\nB"));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_write_file_creates_dirs_only_when_asked() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a").join("b").join("f.txt");
+        let editor = FileEditor::default();
+
+        let missing = editor.write_file_with_dirs(&path, "x", false).await;
+        assert!(matches!(missing, Err(EditorError::MissingDirectory(_))));
+        assert!(!dir.path().join("a").exists());
+
+        editor.write_file_with_dirs(&path, "x", true).await.unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "x");
+    }
+
+    #[tokio::test]
+    async fn test_edit_region_is_one_based_inclusive() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("f.txt");
+        let editor = FileEditor::default();
+
+        let cases = [
+            // A single line
+            (2, 2, "B", "a\nB\nc\nd\n"),
+            // The last lines, with an end past the end of the file
+            (3, 9, "C", "a\nb\nC\n"),
+            // Empty content deletes the lines
+            (2, 3, "", "a\nd\n"),
+        ];
+        for (start, end, content, expected) in cases {
+            std::fs::write(&path, "a\nb\nc\nd\n").unwrap();
+            editor.edit_region(&path, start, end, content).await.unwrap();
+            assert_eq!(std::fs::read_to_string(&path).unwrap(), expected);
+        }
+
+        assert!(matches!(
+            editor.edit_region(&path, 0, 1, "x").await,
+            Err(EditorError::InvalidRange { start: 0, end: 1 })
+        ));
+        assert!(matches!(
+            editor.edit_region(&path, 5, 5, "x").await,
+            Err(EditorError::LineOutOfRange(5))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_atomic_writes_leave_no_temp_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("f.txt");
+        let contents: Vec<String> = (0..8).map(|i| format!("write {}\n", i)).collect();
+
+        let writes = contents.iter().map(|content| write_atomically(&path, content.as_bytes()));
+        for result in futures::future::join_all(writes).await {
+            result.unwrap();
+        }
+
+        assert!(contents.contains(&std::fs::read_to_string(&path).unwrap()));
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
     }
 }
@@ -1,3 +1,4 @@
+pub mod editorconfig;
 pub mod file_editor;
 
 pub use file_editor::FileEditor;
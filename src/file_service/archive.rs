@@ -0,0 +1,144 @@
+use serde::{Deserialize, Serialize};
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+
+// Extension of the archive holding all backups of one file
+pub const ARCHIVE_EXTENSION: &str = "archive";
+
+// A backup as stored in an archive. On disk each record is a one-line JSON
+// header followed by the content bytes and a newline, so an archive can be
+// read with a pager and appended to without rewriting it
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArchiveRecord {
+    // Same name the backup would have as a file, with its content hash
+    pub name: String,
+    pub taken_at: SystemTime,
+    pub content: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct RecordHeader {
+    name: String,
+    // Milliseconds since the Unix epoch
+    taken_at: u64,
+    len: usize,
+}
+
+impl ArchiveRecord {
+    fn encode(&self) -> Vec<u8> {
+        let header = RecordHeader {
+            name: self.name.clone(),
+            taken_at: self
+                .taken_at
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or(0),
+            len: self.content.len(),
+        };
+        // Serializing a struct of plain fields can't fail
+        let mut bytes = serde_json::to_vec(&header).unwrap_or_default();
+        bytes.push(b'\n');
+        bytes.extend_from_slice(&self.content);
+        bytes.push(b'\n');
+        bytes
+    }
+}
+
+// Reads every record of an archive, oldest first; a missing archive has none
+pub async fn read_archive(path: &Path) -> std::io::Result<Vec<ArchiveRecord>> {
+    let bytes = match fs::read(path).await {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    let corrupt = || {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!("Corrupt backup archive: {}", path.display()),
+        )
+    };
+    let mut records = Vec::new();
+    let mut rest = &bytes[..];
+    while !rest.is_empty() {
+        let header_end = rest.iter().position(|b| *b == b'\n').ok_or_else(corrupt)?;
+        let header: RecordHeader =
+            serde_json::from_slice(&rest[..header_end]).map_err(|_| corrupt())?;
+        let content_start = header_end + 1;
+        let content_end = content_start + header.len;
+        if rest.len() <= content_end {
+            return Err(corrupt());
+        }
+
+        records.push(ArchiveRecord {
+            name: header.name,
+            taken_at: UNIX_EPOCH + Duration::from_millis(header.taken_at),
+            content: rest[content_start..content_end].to_vec(),
+        });
+        rest = &rest[content_end + 1..];
+    }
+    Ok(records)
+}
+
+// Adds a record to the end of an archive, creating it if needed
+pub async fn append_record(path: &Path, record: &ArchiveRecord) -> std::io::Result<()> {
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await?;
+    file.write_all(&record.encode()).await?;
+    file.flush().await
+}
+
+// Replaces an archive's records, removing the archive once none are left.
+// The new archive is written aside and renamed over the old one, so a
+// failed write leaves the old one intact
+pub async fn write_archive(path: &Path, records: &[ArchiveRecord]) -> std::io::Result<()> {
+    if records.is_empty() {
+        return match fs::remove_file(path).await {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        };
+    }
+
+    let temp_path = path.with_extension(format!("{}.tmp", ARCHIVE_EXTENSION));
+    let bytes: Vec<u8> = records.iter().flat_map(ArchiveRecord::encode).collect();
+    fs::write(&temp_path, bytes).await?;
+    fs::rename(&temp_path, path).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_archive_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file.archive");
+        let record = |name: &str, content: &[u8]| ArchiveRecord {
+            name: name.to_string(),
+            taken_at: UNIX_EPOCH + Duration::from_millis(1_700_000_000_123),
+            content: content.to_vec(),
+        };
+
+        let first = record("a.txt_1.bak", b"line one\nline two\n");
+        let second = record("a.txt_2.bak", b"");
+        append_record(&path, &first).await.unwrap();
+        append_record(&path, &second).await.unwrap();
+        assert_eq!(
+            read_archive(&path).await.unwrap(),
+            vec![first.clone(), second]
+        );
+
+        write_archive(&path, &[first.clone()]).await.unwrap();
+        assert_eq!(read_archive(&path).await.unwrap(), vec![first]);
+
+        write_archive(&path, &[]).await.unwrap();
+        assert!(!path.exists());
+        assert!(read_archive(&path).await.unwrap().is_empty());
+    }
+}
@@ -1,12 +1,25 @@
 use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
 use tokio::fs::{self, File};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use thiserror::Error;
+use crate::config::BackupConfig;
+use crate::file_service::archive::{append_record, read_archive, write_archive, ArchiveRecord, ARCHIVE_EXTENSION};
+use globset::{Glob, GlobSet, GlobSetBuilder};
 use crate::shared::logging;
+use crate::shared::utils::path::to_display_path;
 
 // Maximum number of backups to keep per file
-const MAX_BACKUPS_PER_FILE: usize = 10;
+pub const MAX_BACKUPS_PER_FILE: usize = 10;
+
+// Minimum number of backups kept per file regardless of their age
+pub const MIN_BACKUPS_PER_FILE: usize = 1;
+
+// Number of hex digits of the content hash stored in backup filenames
+const BACKUP_HASH_LENGTH: usize = 16;
 
 #[derive(Error, Debug)]
 pub enum BackupError {
@@ -19,16 +32,89 @@ pub enum BackupError {
     #[error("No backup available for: {0}")]
     NoBackupAvailable(String),
 
+    #[error("Invalid backup glob: {0}")]
+    InvalidGlob(String),
+
     #[error("IO error: {0}")]
     IoError(#[from] std::io::Error),
 }
 
+// Which files are backed up before an edit, from the backup config
+struct BackupPolicy {
+    // None backs up every file not excluded
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+    max_file_bytes: Option<u64>,
+}
+
+impl BackupPolicy {
+    fn new(config: &BackupConfig) -> Result<Self, BackupError> {
+        let build = |globs: &[String]| -> Result<GlobSet, BackupError> {
+            let mut builder = GlobSetBuilder::new();
+            for glob in globs {
+                builder.add(Glob::new(glob).map_err(|e| BackupError::InvalidGlob(e.to_string()))?);
+            }
+            builder.build().map_err(|e| BackupError::InvalidGlob(e.to_string()))
+        };
+
+        let include = config.backup_include.as_deref().unwrap_or_default();
+        Ok(Self {
+            include: if include.is_empty() { None } else { Some(build(include)?) },
+            exclude: build(config.backup_exclude.as_deref().unwrap_or_default())?,
+            max_file_bytes: config.max_backup_file_bytes,
+        })
+    }
+}
+
+// How backups are stored, from backups.backup_format. Backups already
+// stored in the other format are still listed, read, and restored
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BackupFormat {
+    // One file per backup, in a directory per backed-up file
+    Files,
+    // All backups of a file appended to one archive, saving an inode per
+    // backup. A backup's path is the archive's joined with its record name
+    Archive,
+}
+
+impl BackupFormat {
+    pub fn from_config(config: &BackupConfig) -> Self {
+        match config.backup_format.as_deref() {
+            Some("archive") => Self::Archive,
+            _ => Self::Files,
+        }
+    }
+}
+
+// A backup in either format, with when it was taken and its size
+struct StoredBackup {
+    path: PathBuf,
+    taken_at: SystemTime,
+    size: u64,
+}
+
+// What a purge removed
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PurgeSummary {
+    pub removed: usize,
+    pub bytes: u64,
+    // Number of files that lost backups
+    pub files: usize,
+}
+
 pub struct BackupManager {
+    // Project root, which backup globs are relative to
+    base_directory: PathBuf,
+    policy: BackupPolicy,
+    format: BackupFormat,
     backup_dir: PathBuf,
+    max_backups_per_file: usize,
+    min_backups: usize,
+    max_backup_age: Option<Duration>,
 }
 
 impl BackupManager {
-    pub fn new(base_directory: &PathBuf) -> Result<Self, BackupError> {
+    pub fn new(base_directory: &PathBuf, config: &BackupConfig) -> Result<Self, BackupError> {
         // Create a .backups directory inside the base directory
         let backup_dir = base_directory.join(".backups");
 
@@ -45,11 +131,129 @@ impl BackupManager {
 
         logging::info(&format!("Backup directory set to: {}", backup_dir.display()));
 
-        Ok(Self { backup_dir })
+        Ok(Self {
+            base_directory: base_directory
+                .canonicalize()
+                .unwrap_or_else(|_| base_directory.clone()),
+            policy: BackupPolicy::new(config)?,
+            format: BackupFormat::from_config(config),
+            backup_dir,
+            max_backups_per_file: config.max_backups_per_file.unwrap_or(MAX_BACKUPS_PER_FILE),
+            min_backups: config.min_backups.unwrap_or(MIN_BACKUPS_PER_FILE),
+            max_backup_age: config
+                .max_backup_age_days
+                .map(|days| Duration::from_secs(days * 24 * 60 * 60)),
+        })
+    }
+
+    pub fn backup_directory(&self) -> &Path {
+        &self.backup_dir
+    }
+
+    // Why a file about to be edited shouldn't be backed up under the backup
+    // policy, or None if it should be
+    pub fn skip_reason(&self, path: &Path) -> Option<String> {
+        let relative = path.strip_prefix(&self.base_directory).unwrap_or(path);
+
+        if let Some(include) = &self.policy.include {
+            if !include.is_match(relative) {
+                return Some("not matched by backup_include".to_string());
+            }
+        }
+        if self.policy.exclude.is_match(relative) {
+            return Some("matched by backup_exclude".to_string());
+        }
+        if let Some(max_bytes) = self.policy.max_file_bytes {
+            let size = std::fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+            if size > max_bytes {
+                return Some(format!("{} bytes is over max_backup_file_bytes", size));
+            }
+        }
+
+        None
+    }
+
+    // Hashes file content for backup deduplication
+    fn content_hash(content: &[u8]) -> String {
+        let digest = format!("{:x}", Sha256::digest(content));
+        digest[..BACKUP_HASH_LENGTH].to_string()
+    }
+
+    // Extracts the content hash from a backup filename, if it has one
+    // Format is: filename_timestamp_hash.bak
+    fn hash_from_backup_filename(backup_path: &Path) -> Option<String> {
+        let name = backup_path.file_name()?.to_string_lossy();
+        let stem = name.strip_suffix(".bak")?;
+        let (_, hash) = stem.rsplit_once('_')?;
+
+        if hash.len() == BACKUP_HASH_LENGTH && hash.chars().all(|c| c.is_ascii_hexdigit()) {
+            Some(hash.to_string())
+        } else {
+            None
+        }
+    }
+
+    // Gets the content hash of an existing backup, reading the backup when
+    // its filename predates hashed backup names
+    async fn backup_hash(backup_path: &Path) -> Result<String, BackupError> {
+        if let Some(hash) = Self::hash_from_backup_filename(backup_path) {
+            return Ok(hash);
+        }
+
+        let content = Self::backup_content(backup_path).await?;
+        Ok(Self::content_hash(&content))
+    }
+
+    // Name of the backups of a file, a hash of its canonical path. This
+    // preserves the original directory structure in a flattened way
+    fn backup_key(path: &Path) -> String {
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::{Hash, Hasher};
+
+        let canonical_path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        let mut hasher = DefaultHasher::new();
+        canonical_path.to_string_lossy().hash(&mut hasher);
+        format!("{:016x}", hasher.finish())
+    }
+
+    // Directory holding the backup files of a file
+    fn backup_subdirectory(&self, path: &Path) -> PathBuf {
+        self.backup_dir.join(Self::backup_key(path))
+    }
+
+    // Archive holding the archived backups of a file
+    fn backup_archive(&self, path: &Path) -> PathBuf {
+        self.backup_dir
+            .join(format!("{}.{}", Self::backup_key(path), ARCHIVE_EXTENSION))
     }
 
-    // Generates a unique backup filename based on original path and timestamp
-    fn generate_backup_filename(&self, path: &Path) -> Result<PathBuf, BackupError> {
+    // The archive and record name of a backup stored in an archive
+    fn archived(backup_path: &Path) -> Option<(&Path, String)> {
+        let archive = backup_path.parent()?;
+        if archive.extension()? != ARCHIVE_EXTENSION {
+            return None;
+        }
+        Some((archive, backup_path.file_name()?.to_string_lossy().into_owned()))
+    }
+
+    // Reads a backup in either format
+    async fn backup_content(backup_path: &Path) -> Result<Vec<u8>, BackupError> {
+        let not_found = || BackupError::FileNotFound(backup_path.to_string_lossy().to_string());
+        match Self::archived(backup_path) {
+            Some((archive, name)) => read_archive(archive)
+                .await?
+                .into_iter()
+                .find(|record| record.name == name)
+                .map(|record| record.content)
+                .ok_or_else(not_found),
+            None if backup_path.is_file() => Ok(fs::read(backup_path).await?),
+            None => Err(not_found()),
+        }
+    }
+
+    // Unique backup name based on original path, timestamp and content hash:
+    // original_name_timestamp_hash.bak
+    fn backup_name(path: &Path, hash: &str) -> Result<String, BackupError> {
         // Get the filename without the directory path
         let filename = path.file_name()
             .ok_or_else(|| BackupError::FileNotFound(
@@ -60,26 +264,15 @@ impl BackupManager {
         // Generate a timestamp
         let timestamp = Utc::now().format("%Y%m%d_%H%M%S%.3f");
 
-        // Create backup filename: original_name.timestamp.bak
-        let backup_filename = format!("{}_{}.bak", filename, timestamp);
-
-        // Create a hash of the original path to use as a directory name
-        // This preserves the original directory structure in a flattened way
-        let path_hash = {
-            let canonical_path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
-            let path_str = canonical_path.to_string_lossy();
-
-            // Create a simple hash of the path
-            use std::collections::hash_map::DefaultHasher;
-            use std::hash::{Hash, Hasher};
+        Ok(format!("{}_{}_{}.bak", filename, timestamp, hash))
+    }
 
-            let mut hasher = DefaultHasher::new();
-            path_str.hash(&mut hasher);
-            format!("{:016x}", hasher.finish())
-        };
+    // Generates a unique backup filename based on original path, timestamp and content hash
+    fn generate_backup_filename(&self, path: &Path, hash: &str) -> Result<PathBuf, BackupError> {
+        let backup_filename = Self::backup_name(path, hash)?;
 
         // Create backup subdirectory based on path hash
-        let backup_subdir = self.backup_dir.join(path_hash);
+        let backup_subdir = self.backup_subdirectory(path);
         if !backup_subdir.exists() {
             std::fs::create_dir_all(&backup_subdir).map_err(|e| {
                 BackupError::DirectoryCreationFailed(format!(
@@ -109,15 +302,43 @@ impl BackupManager {
             )));
         }
 
-        let backup_path = self.generate_backup_filename(path)?;
-
-        // Copy the file content
+        // Read the file content
         let mut source = File::open(path).await?;
         let mut content = Vec::new();
         source.read_to_end(&mut content).await?;
 
-        let mut destination = File::create(&backup_path).await?;
-        destination.write_all(&content).await?;
+        // Skip the backup if the content is identical to the most recent one
+        let hash = Self::content_hash(&content);
+        if let Some(latest_backup) = self.list_backups(path).await?.into_iter().next() {
+            if Self::backup_hash(&latest_backup).await? == hash {
+                logging::info(&format!(
+                    "Content of {} unchanged since backup {}, skipping",
+                    path.display(),
+                    latest_backup.display()
+                ));
+                return Ok(latest_backup);
+            }
+        }
+
+        let backup_path = match self.format {
+            BackupFormat::Files => {
+                let backup_path = self.generate_backup_filename(path, &hash)?;
+                let mut destination = File::create(&backup_path).await?;
+                destination.write_all(&content).await?;
+                destination.flush().await?;
+                backup_path
+            }
+            BackupFormat::Archive => {
+                let archive = self.backup_archive(path);
+                let record = ArchiveRecord {
+                    name: Self::backup_name(path, &hash)?,
+                    taken_at: SystemTime::now(),
+                    content,
+                };
+                append_record(&archive, &record).await?;
+                archive.join(record.name)
+            }
+        };
 
         logging::info(&format!(
             "Created backup of {} at {}",
@@ -125,72 +346,198 @@ impl BackupManager {
             backup_path.display()
         ));
 
-        // Clean up old backups if we have too many
+        // Clean up backups that are too many or too old
         self.cleanup_old_backups(path).await?;
 
         Ok(backup_path)
     }
 
-    // Lists all available backups for a file
+    // Lists all available backups for a file, newest first
     pub async fn list_backups(&self, path: &Path) -> Result<Vec<PathBuf>, BackupError> {
-        // Get the hash directory name for this file
-        let canonical_path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
-        let path_str = canonical_path.to_string_lossy();
-
-        // Create a simple hash of the path
-        let path_hash = {
-            use std::collections::hash_map::DefaultHasher;
-            use std::hash::{Hash, Hasher};
-
-            let mut hasher = DefaultHasher::new();
-            path_str.hash(&mut hasher);
-            format!("{:016x}", hasher.finish())
-        };
+        let backups = self.stored_backups(path).await?;
+        Ok(backups.into_iter().map(|backup| backup.path).collect())
+    }
 
-        let backup_subdir = self.backup_dir.join(path_hash);
-        if !backup_subdir.exists() {
-            return Ok(Vec::new()); // No backups yet
-        }
+    // When each backup of a file was taken, newest first like list_backups
+    pub async fn backup_times(&self, path: &Path) -> Result<Vec<SystemTime>, BackupError> {
+        let backups = self.stored_backups(path).await?;
+        Ok(backups.into_iter().map(|backup| backup.taken_at).collect())
+    }
 
+    // The backups of a file in both formats, newest first
+    async fn stored_backups(&self, path: &Path) -> Result<Vec<StoredBackup>, BackupError> {
         let filename = path.file_name()
             .ok_or_else(|| BackupError::FileNotFound(
                 "Invalid path: no filename component".to_string()
             ))?
             .to_string_lossy();
 
-        // Read directory entries and filter for backups of this file
-        let mut backups = Vec::new();
-        let mut entries = fs::read_dir(&backup_subdir).await?;
+        let mut backups = stored_in_directory(&self.backup_subdirectory(path), &filename).await?;
+        backups.extend(stored_in_archive(&self.backup_archive(path), &filename).await?);
+        sort_newest_first(&mut backups);
+
+        Ok(backups)
+    }
+
+    // Deletes backups in either format, rewriting each archive involved once
+    async fn remove_backups(backups: &[PathBuf]) -> Result<(), BackupError> {
+        let mut archives: HashMap<&Path, Vec<String>> = HashMap::new();
+        for backup in backups {
+            match Self::archived(backup) {
+                Some((archive, name)) => archives.entry(archive).or_default().push(name),
+                None => fs::remove_file(backup).await?,
+            }
+        }
+
+        for (archive, names) in archives {
+            let mut records = read_archive(archive).await?;
+            records.retain(|record| !names.contains(&record.name));
+            write_archive(archive, &records).await?;
+        }
+        Ok(())
+    }
 
-        while let Some(entry) = entries.next_entry().await? {
-            let entry_path = entry.path();
-            let entry_name = entry_path.file_name()
-                .unwrap_or_default()
-                .to_string_lossy();
+    // Moves the backups of a file that was renamed from `from` to `to` over
+    // to its new path, so its history can still be listed and restored.
+    // Called after the rename. Returns how many backups were moved
+    pub async fn move_backups(&self, from: &Path, to: &Path) -> Result<usize, BackupError> {
+        let backups = self.list_backups(from).await?;
+        if backups.is_empty() {
+            return Ok(0);
+        }
 
-            // Check if this is a backup for our file
-            // Format is: filename_timestamp.bak
-            if entry_name.starts_with(&*filename) && entry_name.ends_with(".bak") {
-                backups.push(entry_path);
+        let file_name = |path: &Path| -> Result<String, BackupError> {
+            path.file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .ok_or_else(|| BackupError::FileNotFound(
+                    "Invalid path: no filename component".to_string()
+                ))
+        };
+        let old_name = file_name(from)?;
+        let new_name = file_name(to)?;
+        let renamed = |backup_name: &str| format!("{}{}", new_name, &backup_name[old_name.len()..]);
+
+        let (archived, files): (Vec<&PathBuf>, Vec<&PathBuf>) = backups
+            .iter()
+            .partition(|backup| Self::archived(backup).is_some());
+
+        if !files.is_empty() {
+            let old_subdir = self.backup_subdirectory(from);
+            let new_subdir = self.backup_subdirectory(to);
+            fs::create_dir_all(&new_subdir).await?;
+
+            // Renaming keeps each backup's modification time, which orders them
+            for backup in files {
+                fs::rename(backup, new_subdir.join(renamed(&file_name(backup)?))).await?;
             }
+
+            // Fails, harmlessly, if anything else is left in it
+            let _ = fs::remove_dir(&old_subdir).await;
         }
 
-        // Sort backups by modification time (newest first)
-        backups.sort_by(|a, b| {
-            let a_meta = std::fs::metadata(a).ok();
-            let b_meta = std::fs::metadata(b).ok();
-
-            match (a_meta, b_meta) {
-                (Some(a_m), Some(b_m)) => {
-                    let b_time = b_m.modified().unwrap_or_else(|_| std::time::SystemTime::now());
-                    let a_time = a_m.modified().unwrap_or_else(|_| std::time::SystemTime::now());
-                    b_time.cmp(&a_time)
-                },
-                _ => std::cmp::Ordering::Equal,
+        // Archived backups carry their time in their record
+        if !archived.is_empty() {
+            let old_archive = self.backup_archive(from);
+            let new_archive = self.backup_archive(to);
+            let (moved, kept): (Vec<ArchiveRecord>, Vec<ArchiveRecord>) = read_archive(&old_archive)
+                .await?
+                .into_iter()
+                .partition(|record| record.name.starts_with(&old_name));
+            for mut record in moved {
+                record.name = renamed(&record.name);
+                append_record(&new_archive, &record).await?;
             }
-        });
+            write_archive(&old_archive, &kept).await?;
+        }
 
-        Ok(backups)
+        logging::info(&format!(
+            "Moved {} backups of {} to {}",
+            backups.len(),
+            from.display(),
+            to.display()
+        ));
+        Ok(backups.len())
+    }
+
+    // Deletes backups, of one file or of every file. Each file's newest
+    // `keep_latest` backups are kept, and with `older_than` only backups older
+    // than that are deleted; without either, all of them are
+    pub async fn purge(
+        &self,
+        path: Option<&Path>,
+        older_than: Option<Duration>,
+        keep_latest: Option<usize>,
+    ) -> Result<PurgeSummary, BackupError> {
+        // The backups of each file, newest first
+        let histories = match path {
+            Some(path) => vec![self.stored_backups(path).await?],
+            None => {
+                let mut histories = Vec::new();
+                let mut entries = fs::read_dir(&self.backup_dir).await?;
+                while let Some(entry) = entries.next_entry().await? {
+                    let entry_path = entry.path();
+                    let mut backups = if entry.file_type().await?.is_dir() {
+                        stored_in_directory(&entry_path, "").await?
+                    } else if entry_path.extension().is_some_and(|ext| ext == ARCHIVE_EXTENSION) {
+                        stored_in_archive(&entry_path, "").await?
+                    } else {
+                        continue;
+                    };
+                    sort_newest_first(&mut backups);
+                    histories.push(backups);
+                }
+                histories
+            }
+        };
+
+        let now = SystemTime::now();
+        let mut summary = PurgeSummary::default();
+
+        for backups in &histories {
+            let mut purged = Vec::new();
+            for (index, backup) in backups.iter().enumerate() {
+                if index < keep_latest.unwrap_or(0) {
+                    continue;
+                }
+                if let Some(older_than) = older_than {
+                    let age = now.duration_since(backup.taken_at).ok();
+                    if !age.map(|age| age > older_than).unwrap_or(false) {
+                        continue;
+                    }
+                }
+
+                summary.removed += 1;
+                summary.bytes += backup.size;
+                purged.push(backup.path.clone());
+            }
+
+            if !purged.is_empty() {
+                Self::remove_backups(&purged).await?;
+                summary.files += 1;
+                // Drop the file's directory once it holds no backups
+                if let Some(subdir) = purged.first().and_then(|b| b.parent()) {
+                    let _ = fs::remove_dir(subdir).await;
+                }
+            }
+        }
+
+        logging::info(&format!(
+            "Purged {} backups ({} bytes) of {} files",
+            summary.removed, summary.bytes, summary.files
+        ));
+        Ok(summary)
+    }
+
+    // Reads the content of a backup
+    pub async fn read_backup(&self, backup_path: &Path) -> Result<String, BackupError> {
+        if !backup_path.starts_with(&self.backup_dir) {
+            return Err(BackupError::FileNotFound(
+                backup_path.to_string_lossy().to_string()
+            ));
+        }
+
+        let content = Self::backup_content(backup_path).await?;
+        Ok(String::from_utf8_lossy(&content).into_owned())
     }
 
     // Restores the latest backup for a file
@@ -207,9 +554,7 @@ impl BackupManager {
         let latest_backup = &backups[0];
 
         // Read the backup content
-        let mut backup_file = File::open(latest_backup).await?;
-        let mut content = Vec::new();
-        backup_file.read_to_end(&mut content).await?;
+        let content = Self::backup_content(latest_backup).await?;
 
         // Ensure target directory exists
         if let Some(parent) = path.parent() {
@@ -221,6 +566,7 @@ impl BackupManager {
         // Write the content back to the original file
         let mut dest_file = File::create(path).await?;
         dest_file.write_all(&content).await?;
+        dest_file.flush().await?;
 
         logging::info(&format!(
             "Restored file {} from backup {}",
@@ -233,16 +579,8 @@ impl BackupManager {
 
     // Restores a specific backup
     pub async fn restore_specific_backup(&self, backup_path: &Path, target_path: &Path) -> Result<(), BackupError> {
-        if !backup_path.exists() {
-            return Err(BackupError::FileNotFound(
-                backup_path.to_string_lossy().to_string()
-            ));
-        }
-
         // Read the backup content
-        let mut backup_file = File::open(backup_path).await?;
-        let mut content = Vec::new();
-        backup_file.read_to_end(&mut content).await?;
+        let content = Self::backup_content(backup_path).await?;
 
         // Ensure target directory exists
         if let Some(parent) = target_path.parent() {
@@ -254,6 +592,7 @@ impl BackupManager {
         // Write the content back to the target file
         let mut dest_file = File::create(target_path).await?;
         dest_file.write_all(&content).await?;
+        dest_file.flush().await?;
 
         logging::info(&format!(
             "Restored file {} from specific backup {}",
@@ -264,28 +603,45 @@ impl BackupManager {
         Ok(())
     }
 
-    // Cleans up old backups, keeping only the most recent MAX_BACKUPS_PER_FILE
+    // Cleans up old backups, keeping at most max_backups_per_file and dropping
+    // backups older than max_backup_age, while always keeping the newest
+    // min_backups so a rarely-edited file never loses its only copy
     async fn cleanup_old_backups(&self, path: &Path) -> Result<(), BackupError> {
-        let backups = self.list_backups(path).await?;
-
-        if backups.len() <= MAX_BACKUPS_PER_FILE {
+        let backups = self.stored_backups(path).await?;
+        let now = SystemTime::now();
+
+        // Backups are sorted newest first
+        let mut old_backups = Vec::new();
+        for (index, backup) in backups.into_iter().enumerate() {
+            let over_count = index >= self.max_backups_per_file;
+            let expired = index >= self.min_backups
+                && match self.max_backup_age {
+                    Some(max_age) => now
+                        .duration_since(backup.taken_at)
+                        .map(|age| age > max_age)
+                        .unwrap_or(false),
+                    None => false,
+                };
+
+            if over_count || expired {
+                old_backups.push(backup.path);
+            }
+        }
+        if old_backups.is_empty() {
             return Ok(());
         }
 
-        // Remove older backups (everything after the max)
-        for backup_to_remove in &backups[MAX_BACKUPS_PER_FILE..] {
-            if let Err(e) = fs::remove_file(backup_to_remove).await {
-                logging::warn(&format!(
-                    "Failed to remove old backup {}: {}",
-                    backup_to_remove.display(),
-                    e
-                ));
-            } else {
-                logging::info(&format!(
-                    "Removed old backup: {}",
-                    backup_to_remove.display()
-                ));
-            }
+        match Self::remove_backups(&old_backups).await {
+            Ok(()) => logging::info(&format!(
+                "Removed {} old backups of {}",
+                old_backups.len(),
+                path.display()
+            )),
+            Err(e) => logging::warn(&format!(
+                "Failed to remove old backups of {}: {}",
+                path.display(),
+                e
+            )),
         }
 
         Ok(())
@@ -293,29 +649,151 @@ impl BackupManager {
 
     // Gets metadata about backups
     pub async fn get_backup_stats(&self, path: &Path) -> Result<serde_json::Value, BackupError> {
-        let backups = self.list_backups(path).await?;
+        let backups = self.stored_backups(path).await?;
 
         // Collect metadata for each backup
-        let mut backup_info = Vec::new();
-        for backup in &backups {
-            if let Ok(metadata) = fs::metadata(backup).await {
-                let modified = metadata.modified().unwrap_or_else(|_| std::time::SystemTime::now());
-                let modified_str = DateTime::<Utc>::from(modified).to_rfc3339();
-
-                let size_bytes = metadata.len();
-
-                backup_info.push(serde_json::json!({
-                    "path": backup.to_string_lossy(),
-                    "modified": modified_str,
-                    "size_bytes": size_bytes
-                }));
-            }
-        }
+        let backup_info: Vec<serde_json::Value> = backups
+            .iter()
+            .map(|backup| {
+                serde_json::json!({
+                    "path": to_display_path(&backup.path),
+                    "modified": DateTime::<Utc>::from(backup.taken_at).to_rfc3339(),
+                    "size_bytes": backup.size
+                })
+            })
+            .collect();
 
         Ok(serde_json::json!({
-            "file": path.to_string_lossy(),
+            "file": to_display_path(path),
             "backup_count": backups.len(),
             "backups": backup_info
         }))
     }
 }
+
+// The backup files in a directory whose names start with `prefix`
+async fn stored_in_directory(directory: &Path, prefix: &str) -> Result<Vec<StoredBackup>, BackupError> {
+    if !directory.is_dir() {
+        return Ok(Vec::new()); // No backups yet
+    }
+
+    let mut backups = Vec::new();
+    let mut entries = fs::read_dir(directory).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let entry_name = entry.file_name().to_string_lossy().into_owned();
+
+        // Format is: filename_timestamp_hash.bak (older backups have no hash)
+        if !entry_name.starts_with(prefix) || !entry_name.ends_with(".bak") {
+            continue;
+        }
+        if let Ok(metadata) = entry.metadata().await {
+            backups.push(StoredBackup {
+                path: entry.path(),
+                taken_at: metadata.modified().unwrap_or_else(|_| SystemTime::now()),
+                size: metadata.len(),
+            });
+        }
+    }
+    Ok(backups)
+}
+
+// The backups in an archive whose names start with `prefix`
+async fn stored_in_archive(archive: &Path, prefix: &str) -> Result<Vec<StoredBackup>, BackupError> {
+    Ok(read_archive(archive)
+        .await?
+        .into_iter()
+        .filter(|record| record.name.starts_with(prefix))
+        .map(|record| StoredBackup {
+            path: archive.join(&record.name),
+            taken_at: record.taken_at,
+            size: record.content.len() as u64,
+        })
+        .collect())
+}
+
+// Sorts backups by when they were taken, newest first
+fn sort_newest_first(backups: &mut [StoredBackup]) {
+    backups.sort_by(|a, b| b.taken_at.cmp(&a.taken_at));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn backup_config(backup_format: Option<&str>) -> BackupConfig {
+        BackupConfig {
+            enabled: None,
+            max_backups_per_file: None,
+            backup_directory: None,
+            max_backup_age_days: None,
+            min_backups: None,
+            backup_include: None,
+            backup_exclude: None,
+            max_backup_file_bytes: None,
+            backup_format: backup_format.map(String::from),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_move_backups_follows_rename() {
+        let root = tempfile::tempdir().unwrap();
+        let manager = BackupManager::new(&root.path().to_path_buf(), &backup_config(None)).unwrap();
+
+        let from = root.path().join("old.txt");
+        let to = root.path().join("sub/new.txt");
+        std::fs::write(&from, "first").unwrap();
+        manager.create_backup(&from).await.unwrap();
+        std::fs::write(&from, "second").unwrap();
+        manager.create_backup(&from).await.unwrap();
+
+        std::fs::create_dir(root.path().join("sub")).unwrap();
+        std::fs::rename(&from, &to).unwrap();
+        assert_eq!(manager.move_backups(&from, &to).await.unwrap(), 2);
+
+        assert!(manager.list_backups(&from).await.unwrap().is_empty());
+        let backups = manager.list_backups(&to).await.unwrap();
+        assert_eq!(backups.len(), 2);
+        assert_eq!(manager.read_backup(&backups[0]).await.unwrap(), "second");
+    }
+
+    #[tokio::test]
+    async fn test_archive_backups_alongside_files() {
+        let root = tempfile::tempdir().unwrap();
+        let base = root.path().to_path_buf();
+        let files = BackupManager::new(&base, &backup_config(Some("files"))).unwrap();
+        let archive = BackupManager::new(&base, &backup_config(Some("archive"))).unwrap();
+
+        let from = root.path().join("old.txt");
+        let to = root.path().join("new.txt");
+        for (manager, content) in [(&files, "zero"), (&archive, "first"), (&archive, "second")] {
+            std::fs::write(&from, content).unwrap();
+            manager.create_backup(&from).await.unwrap();
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        // Both formats are listed together, newest first
+        let backups = archive.list_backups(&from).await.unwrap();
+        assert_eq!(backups.len(), 3);
+        assert_eq!(archive.read_backup(&backups[0]).await.unwrap(), "second");
+        assert_eq!(archive.read_backup(&backups[2]).await.unwrap(), "zero");
+        let archives = std::fs::read_dir(archive.backup_directory())
+            .unwrap()
+            .filter(|entry| entry.as_ref().unwrap().path().extension().is_some_and(|ext| ext == ARCHIVE_EXTENSION))
+            .count();
+        assert_eq!(archives, 1);
+
+        std::fs::rename(&from, &to).unwrap();
+        assert_eq!(archive.move_backups(&from, &to).await.unwrap(), 3);
+        assert!(archive.list_backups(&from).await.unwrap().is_empty());
+        std::fs::write(&to, "changed").unwrap();
+        archive.restore_latest_backup(&to).await.unwrap();
+        assert_eq!(std::fs::read_to_string(&to).unwrap(), "second");
+
+        // The archive keeps its newest backup, the directory its only one
+        let summary = archive.purge(None, None, Some(1)).await.unwrap();
+        assert_eq!(summary.removed, 1);
+        let backups = archive.list_backups(&to).await.unwrap();
+        assert_eq!(archive.read_backup(&backups[0]).await.unwrap(), "second");
+        assert_eq!(archive.read_backup(&backups[1]).await.unwrap(), "zero");
+    }
+}
@@ -0,0 +1,184 @@
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+// Bytes of file content kept when the config doesn't say
+pub const DEFAULT_READ_CACHE_BYTES: usize = 16 * 1024 * 1024;
+
+struct CachedFile {
+    content: String,
+    // Modification time and size the content was read at, which must still
+    // match for it to be served
+    modified: SystemTime,
+    len: u64,
+    // Tick of the last lookup that served this entry
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct CacheState {
+    entries: HashMap<PathBuf, CachedFile>,
+    bytes: usize,
+    tick: u64,
+    hits: u64,
+    misses: u64,
+    evictions: u64,
+}
+
+// Content of recently read files, served again for as long as their
+// modification time and size are unchanged. Least recently used files are
+// dropped to stay within the byte limit; a limit of 0 disables the cache
+pub struct ReadCache {
+    max_bytes: usize,
+    state: Mutex<CacheState>,
+}
+
+impl ReadCache {
+    pub fn new(max_bytes: usize) -> Self {
+        Self {
+            max_bytes,
+            state: Mutex::new(CacheState::default()),
+        }
+    }
+
+    // The cached content of a file, if it was read at this modification time
+    // and size. A stale entry is dropped
+    pub fn get(&self, path: &Path, modified: SystemTime, len: u64) -> Option<String> {
+        if self.max_bytes == 0 {
+            return None;
+        }
+
+        let mut state = self.state.lock().unwrap();
+        state.tick += 1;
+        let tick = state.tick;
+
+        let fresh = match state.entries.get_mut(path) {
+            Some(entry) if entry.modified == modified && entry.len == len => {
+                entry.last_used = tick;
+                Some(entry.content.clone())
+            }
+            Some(_) => None,
+            None => {
+                state.misses += 1;
+                return None;
+            }
+        };
+
+        match fresh {
+            Some(content) => {
+                state.hits += 1;
+                Some(content)
+            }
+            None => {
+                state.misses += 1;
+                state.remove(path);
+                None
+            }
+        }
+    }
+
+    pub fn insert(&self, path: &Path, modified: SystemTime, len: u64, content: &str) {
+        // Files that would fill most of the cache aren't worth evicting for
+        if self.max_bytes == 0 || content.len() > self.max_bytes / 2 {
+            return;
+        }
+
+        let mut state = self.state.lock().unwrap();
+        state.remove(path);
+
+        while state.bytes + content.len() > self.max_bytes {
+            let oldest = state
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(path, _)| path.clone());
+            match oldest {
+                Some(oldest) => {
+                    state.remove(&oldest);
+                    state.evictions += 1;
+                }
+                None => break,
+            }
+        }
+
+        state.tick += 1;
+        let tick = state.tick;
+        state.bytes += content.len();
+        state.entries.insert(
+            path.to_path_buf(),
+            CachedFile {
+                content: content.to_string(),
+                modified,
+                len,
+                last_used: tick,
+            },
+        );
+    }
+
+    // Forgets a file, after mcedit changed, moved or deleted it
+    pub fn invalidate(&self, path: &Path) {
+        self.state.lock().unwrap().remove(path);
+    }
+
+    pub fn clear(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.entries.clear();
+        state.bytes = 0;
+    }
+
+    pub fn stats(&self) -> Value {
+        let state = self.state.lock().unwrap();
+        json!({
+            "enabled": self.max_bytes > 0,
+            "entries": state.entries.len(),
+            "bytes": state.bytes,
+            "max_bytes": self.max_bytes,
+            "hits": state.hits,
+            "misses": state.misses,
+            "evictions": state.evictions
+        })
+    }
+}
+
+impl CacheState {
+    fn remove(&mut self, path: &Path) {
+        if let Some(entry) = self.entries.remove(path) {
+            self.bytes -= entry.content.len();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_read_cache_checks_mtime_and_evicts_least_recently_used() {
+        let cache = ReadCache::new(20);
+        let t0 = SystemTime::UNIX_EPOCH;
+        let t1 = t0 + Duration::from_secs(1);
+
+        cache.insert(Path::new("a"), t0, 6, "aaaaaa");
+        cache.insert(Path::new("b"), t0, 6, "bbbbbb");
+        assert_eq!(cache.get(Path::new("a"), t0, 6).as_deref(), Some("aaaaaa"));
+        assert_eq!(cache.get(Path::new("b"), t1, 6), None);
+
+        // b was dropped as stale, so a and c fit; d then evicts a, the older
+        cache.insert(Path::new("c"), t0, 8, "cccccccc");
+        cache.insert(Path::new("d"), t0, 8, "dddddddd");
+        assert_eq!(cache.get(Path::new("a"), t0, 6), None);
+        assert_eq!(
+            cache.get(Path::new("c"), t0, 8).as_deref(),
+            Some("cccccccc")
+        );
+
+        let stats = cache.stats();
+        assert_eq!(stats["entries"], 2);
+        assert_eq!(stats["bytes"], 16);
+        assert_eq!(stats["hits"], 2);
+        assert_eq!(stats["evictions"], 1);
+    }
+}
@@ -1,5 +1,7 @@
 pub mod service;
+pub mod archive;
 pub mod backup;
+pub mod cache;
 
 pub use service::FileService;
 pub use backup::BackupManager;
@@ -1,8 +1,23 @@
-use crate::editor::file_editor::FileEditor;
-use crate::file_service::backup::BackupManager;
+use crate::config::Config;
+use crate::diff::{patch, DiffGenerator};
+use crate::editor::file_editor::{
+    convert_line_endings, find_matching_line, join_line_range, split_line_at, splice_regions,
+    EditorError, FileEditor, RegionEdit, WhitespaceCleanup,
+};
+use crate::file_service::backup::{BackupError, BackupManager, PurgeSummary};
+use crate::file_service::cache::{ReadCache, DEFAULT_READ_CACHE_BYTES};
 use crate::shared::logging;
-use std::path::{Path, PathBuf};
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use crate::shared::mime;
+use crate::shared::utils::path::to_display_path;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use regex::Regex;
+use md5::Md5;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
 use thiserror::Error;
 use serde_json::json;
 
@@ -23,21 +38,145 @@ pub enum FileServiceError {
     #[error("Line number out of range: {0}")]
     LineNumberOutOfRange(usize),
 
+    #[error("Line {line} is out of range, the file has {line_count} lines")]
+    LineBeyondEnd { line: usize, line_count: usize },
+
     #[error("File already exists: {0}")]
     FileAlreadyExists(String),
 
     #[error("Backup error: {0}")]
-    BackupError(#[from] crate::file_service::backup::BackupError),
+    BackupError(#[from] BackupError),
+
+    #[cfg(not(unix))]
+    #[error("Not supported: {0}")]
+    Unsupported(String),
+
+    #[error("File appears to be binary: {0}")]
+    BinaryFile(String),
+
+    #[error("File changed on disk since it was last read, read it again before editing: {0}")]
+    Conflict(String),
+}
+
+// What a write does when its file changed on disk since mcedit last read or
+// wrote it, for example through the user's own editor
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExternalChangePolicy {
+    Ignore,
+    // Refuse the write with a Conflict error
+    Error,
+    // Back up the changed file, log a warning, and write anyway
+    Warn,
+}
+
+impl ExternalChangePolicy {
+    pub fn from_config(config: &Config) -> Self {
+        match config.editor.detect_external_changes.as_deref() {
+            Some("error") => Self::Error,
+            Some("warn") => Self::Warn,
+            _ => Self::Ignore,
+        }
+    }
+}
+
+// Digest computed by file_hash
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum HashAlgorithm {
+    #[default]
+    Sha256,
+    Md5,
+    Blake3,
+}
+
+impl HashAlgorithm {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "sha256" => Some(Self::Sha256),
+            "md5" => Some(Self::Md5),
+            "blake3" => Some(Self::Blake3),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Sha256 => "sha256",
+            Self::Md5 => "md5",
+            Self::Blake3 => "blake3",
+        }
+    }
+}
+
+// Where insert_file_contents puts the content it copies
+pub enum InsertPosition {
+    // Before this 1-based line; one past the last line appends
+    Line(usize),
+    // After (or before) the first line matching the pattern
+    Anchor { pattern: Regex, after: bool },
+}
+
+/// How much a mutating operation changed a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChangeSummary {
+    pub lines_before: usize,
+    pub lines_after: usize,
+    // Size of the changed span: the longer of the old and new text left
+    // once the common prefix and suffix are removed
+    pub bytes_changed: usize,
+}
+
+impl ChangeSummary {
+    pub fn between(before: &str, after: &str) -> Self {
+        let before_bytes = before.as_bytes();
+        let after_bytes = after.as_bytes();
+
+        let prefix = before_bytes
+            .iter()
+            .zip(after_bytes)
+            .take_while(|(a, b)| a == b)
+            .count();
+        let suffix = before_bytes[prefix..]
+            .iter()
+            .rev()
+            .zip(after_bytes[prefix..].iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+
+        Self {
+            lines_before: before.lines().count(),
+            lines_after: after.lines().count(),
+            bytes_changed: before_bytes.len().max(after_bytes.len()) - prefix - suffix,
+        }
+    }
+
+    pub fn to_json(self) -> serde_json::Value {
+        json!({
+            "lines_before": self.lines_before,
+            "lines_after": self.lines_after,
+            "bytes_changed": self.bytes_changed
+        })
+    }
 }
 
 pub struct FileService {
     base_directory: PathBuf,
+    config: Config,
     editor: FileEditor,
     backup_manager: BackupManager,
+    external_changes: ExternalChangePolicy,
+    // Hash of each file's content as mcedit last read or wrote it
+    known_content: Mutex<HashMap<PathBuf, String>>,
+    read_cache: ReadCache,
+    // Canonical roots the client allows access to, if it said. Paths must
+    // lie inside one of them as well as inside the base directory
+    client_roots: Option<Vec<PathBuf>>,
+    // Files that may be created or modified, relative to the base
+    // directory; None allows every file
+    write_allowlist: Option<GlobSet>,
 }
 
 impl FileService {
-    pub fn new(base_directory: &PathBuf) -> Result<Self, FileServiceError> {
+    pub fn new(base_directory: &PathBuf, config: &Config) -> Result<Self, FileServiceError> {
         if !base_directory.exists() {
             return Err(FileServiceError::InvalidPath(format!(
                 "Base directory does not exist: {}",
@@ -45,16 +184,72 @@ impl FileService {
             )));
         }
 
-        let editor = FileEditor::new();
-        let backup_manager = BackupManager::new(base_directory)?;
+        let editor = editor_for(base_directory, config);
+        let backup_manager = BackupManager::new(base_directory, &config.backups)?;
+        let write_allowlist = match &config.project.write_allowlist {
+            Some(globs) => {
+                let mut builder = GlobSetBuilder::new();
+                for glob in globs {
+                    builder.add(Glob::new(glob).map_err(|e| {
+                        FileServiceError::InvalidPath(format!("Invalid write_allowlist glob {}: {}", glob, e))
+                    })?);
+                }
+                Some(builder.build().map_err(|e| {
+                    FileServiceError::InvalidPath(format!("Invalid write_allowlist: {}", e))
+                })?)
+            }
+            None => None,
+        };
 
         Ok(Self {
             base_directory: base_directory.clone(),
+            config: config.clone(),
             editor,
             backup_manager,
+            external_changes: ExternalChangePolicy::from_config(config),
+            known_content: Mutex::new(HashMap::new()),
+            read_cache: ReadCache::new(
+                config.editor.read_cache_bytes.unwrap_or(DEFAULT_READ_CACHE_BYTES),
+            ),
+            client_roots: None,
+            write_allowlist,
         })
     }
 
+    // Limits access to paths inside one of `roots` as well, or lifts the
+    // limit with None. Roots that don't exist can hold nothing and are dropped
+    pub fn set_client_roots(&mut self, roots: Option<&[PathBuf]>) {
+        self.client_roots = roots.map(|roots| {
+            roots
+                .iter()
+                .filter_map(|root| root.canonicalize().ok())
+                .collect()
+        });
+    }
+
+    // Whether `path` lies inside the client's roots, judged by its nearest
+    // existing ancestor so paths of files yet to be created are checked too
+    fn within_client_roots(&self, path: &Path) -> bool {
+        let roots = match &self.client_roots {
+            Some(roots) => roots,
+            None => return true,
+        };
+
+        let canonical = path.ancestors().find_map(|ancestor| {
+            let rest = path.strip_prefix(ancestor).ok()?;
+            Some((ancestor.canonicalize().ok()?, rest))
+        });
+        match canonical {
+            // A `..` past the existing part could climb out of any root
+            Some((_, rest)) if rest.components().any(|c| c == Component::ParentDir) => false,
+            Some((ancestor, rest)) => {
+                let canonical = ancestor.join(rest);
+                roots.iter().any(|root| canonical.starts_with(root))
+            }
+            None => false,
+        }
+    }
+
     pub fn change_directory(&mut self, new_directory: &PathBuf) -> Result<(), FileServiceError> {
         if !new_directory.exists() {
             return Err(FileServiceError::InvalidPath(format!(
@@ -64,7 +259,10 @@ impl FileService {
         }
 
         self.base_directory = new_directory.clone();
-        self.backup_manager = BackupManager::new(new_directory)?;
+        self.editor = editor_for(new_directory, &self.config);
+        self.backup_manager = BackupManager::new(new_directory, &self.config.backups)?;
+        self.known_content.lock().unwrap().clear();
+        self.read_cache.clear();
 
         logging::info(&format!(
             "File service directory changed to: {}",
@@ -97,6 +295,13 @@ impl FileService {
             resolved_path.push(path);
         }
 
+        if !self.within_client_roots(&resolved_path) {
+            return Err(FileServiceError::PermissionDenied(format!(
+                "Path is outside the roots the client allows: {}",
+                path.display()
+            )));
+        }
+
         // Canonicalize to resolve any .. or symlinks, then verify still in base directory
         match resolved_path.canonicalize() {
             Ok(canon_path) => {
@@ -112,23 +317,161 @@ impl FileService {
             Err(e) => {
                 // If canonicalization fails (e.g., file doesn't exist), just return the joined path
                 // This is needed for operations like creating a new file
+                // A `..` past the existing part could still climb out of the project
+                let canon_base = self.base_directory.canonicalize()?;
+                let within = resolved_path.ancestors().find_map(|ancestor| {
+                    let rest = resolved_path.strip_prefix(ancestor).ok()?;
+                    Some((ancestor.canonicalize().ok()?, rest))
+                });
+                let escapes = match within {
+                    Some((ancestor, rest)) => {
+                        !ancestor.starts_with(&canon_base)
+                            || rest.components().any(|c| c == Component::ParentDir)
+                    }
+                    None => true,
+                };
+                if escapes {
+                    return Err(FileServiceError::PermissionDenied(format!(
+                        "Path escapes the project directory: {}",
+                        path.display()
+                    )));
+                }
                 Ok(resolved_path)
             }
         }
     }
 
+    // Resolves the path of a file about to be created or modified, refusing
+    // files the write_allowlist doesn't cover
+    fn resolve_writable_path(&self, path: &Path) -> Result<PathBuf, FileServiceError> {
+        let resolved_path = self.resolve_path(path)?;
+        self.check_writable(&resolved_path)?;
+        Ok(resolved_path)
+    }
+
+    fn check_writable(&self, resolved_path: &Path) -> Result<(), FileServiceError> {
+        let allowlist = match &self.write_allowlist {
+            Some(allowlist) => allowlist,
+            None => return Ok(()),
+        };
+
+        // Resolved paths are canonical when the file exists, so the base
+        // directory may need canonicalizing to match
+        let relative = resolved_path.strip_prefix(&self.base_directory).ok().map(Path::to_path_buf).or_else(|| {
+            let canon_base = self.base_directory.canonicalize().ok()?;
+            resolved_path.strip_prefix(canon_base).ok().map(Path::to_path_buf)
+        });
+        match relative {
+            Some(relative) if allowlist.is_match(&relative) => Ok(()),
+            _ => Err(FileServiceError::PermissionDenied(format!(
+                "Not covered by project.write_allowlist: {}",
+                to_display_path(resolved_path)
+            ))),
+        }
+    }
+
+    // Where `path` resolves to and whether a write there is allowed,
+    // without touching the file
+    pub fn preview_path(&self, path: &Path) -> Result<(PathBuf, bool), FileServiceError> {
+        let resolved_path = self.resolve_path(path)?;
+        let writable = self.check_writable(&resolved_path).is_ok();
+        Ok((resolved_path, writable))
+    }
+
     // File Reading Operations
 
     pub async fn read_file(&self, path: &Path) -> anyhow::Result<String> {
         let resolved_path = self.resolve_path(path)?;
 
-        if !resolved_path.exists() {
+        let metadata = match tokio::fs::metadata(&resolved_path).await {
+            Ok(metadata) => metadata,
+            Err(_) => {
+                return Err(FileServiceError::FileNotFound(
+                    resolved_path.to_string_lossy().to_string(),
+                ).into());
+            }
+        };
+        let modified = metadata.modified()?;
+
+        if let Some(content) = self.read_cache.get(&resolved_path, modified, metadata.len()) {
+            self.remember_content(&resolved_path, &content);
+            return Ok(content);
+        }
+
+        if mime::is_probably_binary(&resolved_path).await {
+            return Err(FileServiceError::BinaryFile(
+                resolved_path.to_string_lossy().to_string(),
+            ).into());
+        }
+
+        let content = self.editor.read_file(&resolved_path).await?;
+        self.read_cache.insert(&resolved_path, modified, metadata.len(), &content);
+        self.remember_content(&resolved_path, &content);
+        Ok(content)
+    }
+
+    // Reads only the first line of a file, without its line terminator
+    pub async fn read_first_line(&self, path: &Path) -> anyhow::Result<Option<String>> {
+        let resolved_path = self.resolve_path(path)?;
+
+        if !resolved_path.is_file() {
+            return Ok(None);
+        }
+
+        let file = tokio::fs::File::open(&resolved_path).await?;
+        let mut line = String::new();
+        // Files that aren't valid UTF-8 simply have no readable first line
+        match BufReader::new(file).read_line(&mut line).await {
+            Ok(0) | Err(_) => Ok(None),
+            Ok(_) => Ok(Some(line.trim_end_matches(['\r', '\n']).to_string())),
+        }
+    }
+
+    // Reads 1-based line `line` of a file, without its line terminator,
+    // along with the number of lines in the file
+    pub async fn read_line(&self, path: &Path, line: usize) -> anyhow::Result<(String, usize)> {
+        let content = self.read_file(path).await?;
+        let line_count = content.lines().count();
+
+        match line.checked_sub(1).and_then(|index| content.lines().nth(index)) {
+            Some(text) => Ok((text.to_string(), line_count)),
+            None => Err(FileServiceError::LineBeyondEnd { line, line_count }.into()),
+        }
+    }
+
+    // Hashes a file in the project, returning the hex digest and the
+    // file's size in bytes
+    pub async fn file_hash(&self, path: &Path, algorithm: HashAlgorithm) -> anyhow::Result<(String, u64)> {
+        let resolved_path = self.resolve_path(path)?;
+
+        if !resolved_path.is_file() {
             return Err(FileServiceError::FileNotFound(
                 resolved_path.to_string_lossy().to_string(),
             ).into());
         }
 
-        self.editor.read_file(&resolved_path).await.map_err(|e| e.into())
+        hash_file(&resolved_path, algorithm).await
+    }
+
+    // Reads up to `len` bytes from the start of a file
+    pub async fn read_head(&self, path: &Path, len: usize) -> anyhow::Result<Vec<u8>> {
+        let resolved_path = self.resolve_path(path)?;
+
+        let file = tokio::fs::File::open(&resolved_path).await?;
+        let mut head = Vec::with_capacity(len);
+        file.take(len as u64).read_to_end(&mut head).await?;
+
+        Ok(head)
+    }
+
+    // Size and hit rate of the read cache
+    pub fn cache_stats(&self) -> serde_json::Value {
+        self.read_cache.stats()
+    }
+
+    // Where backups of files in the current directory are kept
+    pub fn backup_directory(&self) -> &Path {
+        self.backup_manager.backup_directory()
     }
 
     pub async fn file_exists(&self, path: &Path) -> bool {
@@ -148,21 +491,44 @@ impl FileService {
         Ok(resolved_path.is_dir())
     }
 
+    // Metadata of a path, or None if nothing exists there
+    pub async fn metadata(&self, path: &Path) -> anyhow::Result<Option<std::fs::Metadata>> {
+        let resolved_path = self.resolve_path(path)?;
+
+        match tokio::fs::metadata(&resolved_path).await {
+            Ok(metadata) => Ok(Some(metadata)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
     // File Writing Operations
 
-    pub async fn write_file(&self, path: &Path, content: &str) -> anyhow::Result<()> {
-        let resolved_path = self.resolve_path(path)?;
+    // Writes a file, creating its missing parent directories only when
+    // `create_dirs` is set
+    pub async fn write_file(
+        &self,
+        path: &Path,
+        content: &str,
+        create_dirs: bool,
+    ) -> anyhow::Result<ChangeSummary> {
+        let resolved_path = self.resolve_writable_path(path)?;
+        let before = self.content_or_empty(&resolved_path).await;
+        self.check_external_change(&resolved_path, &before).await?;
 
         // Create a backup before modifying
         if resolved_path.exists() {
-            self.backup_manager.create_backup(&resolved_path).await?;
+            self.backup_before_change(&resolved_path).await?;
         }
 
-        self.editor.write_file(&resolved_path, content).await.map_err(|e| e.into())
+        self.editor
+            .write_file_with_dirs(&resolved_path, content, create_dirs)
+            .await?;
+        self.summarize_change(&resolved_path, &before).await
     }
 
-    pub async fn append_to_file(&self, path: &Path, content: &str) -> anyhow::Result<()> {
-        let resolved_path = self.resolve_path(path)?;
+    pub async fn append_to_file(&self, path: &Path, content: &str) -> anyhow::Result<ChangeSummary> {
+        let resolved_path = self.resolve_writable_path(path)?;
 
         if !resolved_path.exists() {
             return Err(FileServiceError::FileNotFound(
@@ -170,14 +536,50 @@ impl FileService {
             ).into());
         }
 
+        let before = self.content_or_empty(&resolved_path).await;
+        self.check_external_change(&resolved_path, &before).await?;
+
         // Create a backup before modifying
-        self.backup_manager.create_backup(&resolved_path).await?;
+        self.backup_before_change(&resolved_path).await?;
 
-        self.editor.append_to_file(&resolved_path, content).await.map_err(|e| e.into())
+        self.editor.append_to_file(&resolved_path, content).await?;
+        self.summarize_change(&resolved_path, &before).await
     }
 
-    pub async fn create_file(&self, path: &Path, content: &str) -> anyhow::Result<()> {
-        let resolved_path = self.resolve_path(path)?;
+    // The path a new file is created at: `path` itself, or with the default
+    // extension added when enabled and the name has no extension. Dotfiles
+    // like .env are left alone
+    pub fn with_default_extension(&self, path: &Path) -> PathBuf {
+        let project = &self.config.project;
+        let extension = match (&project.default_extension, project.apply_default_extension) {
+            (Some(extension), Some(true)) => extension.trim_start_matches('.'),
+            _ => return path.to_path_buf(),
+        };
+
+        let is_bare_name = path
+            .file_name()
+            .map(|n| !n.to_string_lossy().starts_with('.'))
+            .unwrap_or(false)
+            && path.extension().is_none();
+
+        if is_bare_name && !extension.is_empty() {
+            path.with_extension(extension)
+        } else {
+            path.to_path_buf()
+        }
+    }
+
+    // Creates a new file, returning the path it was created at, which has
+    // the default extension added when that is enabled. Missing parent
+    // directories are created only when `create_dirs` is set
+    pub async fn create_file(
+        &self,
+        path: &Path,
+        content: &str,
+        create_dirs: bool,
+    ) -> anyhow::Result<(PathBuf, ChangeSummary)> {
+        let path = self.with_default_extension(path);
+        let resolved_path = self.resolve_writable_path(&path)?;
 
         if resolved_path.exists() {
             return Err(FileServiceError::FileAlreadyExists(
@@ -185,20 +587,17 @@ impl FileService {
             ).into());
         }
 
-        // Ensure parent directory exists
-        if let Some(parent) = resolved_path.parent() {
-            if !parent.exists() {
-                std::fs::create_dir_all(parent)?;
-            }
-        }
-
-        self.editor.write_file(&resolved_path, content).await.map_err(|e| e.into())
+        self.editor
+            .write_file_with_dirs(&resolved_path, content, create_dirs)
+            .await?;
+        let summary = self.summarize_change(&resolved_path, "").await?;
+        Ok((path, summary))
     }
 
     // Line-based editing operations
 
-    pub async fn insert_line(&self, path: &Path, line_num: usize, content: &str) -> anyhow::Result<()> {
-        let resolved_path = self.resolve_path(path)?;
+    pub async fn insert_line(&self, path: &Path, line_num: usize, content: &str) -> anyhow::Result<ChangeSummary> {
+        let resolved_path = self.resolve_writable_path(path)?;
 
         if !resolved_path.exists() {
             return Err(FileServiceError::FileNotFound(
@@ -206,14 +605,18 @@ impl FileService {
             ).into());
         }
 
+        let before = self.content_or_empty(&resolved_path).await;
+        self.check_external_change(&resolved_path, &before).await?;
+
         // Create a backup before modifying
-        self.backup_manager.create_backup(&resolved_path).await?;
+        self.backup_before_change(&resolved_path).await?;
 
-        self.editor.insert_line(&resolved_path, line_num, content).await.map_err(|e| e.into())
+        self.editor.insert_line(&resolved_path, line_num, content).await?;
+        self.summarize_change(&resolved_path, &before).await
     }
 
-    pub async fn replace_line(&self, path: &Path, line_num: usize, content: &str) -> anyhow::Result<()> {
-        let resolved_path = self.resolve_path(path)?;
+    pub async fn replace_line(&self, path: &Path, line_num: usize, content: &str) -> anyhow::Result<ChangeSummary> {
+        let resolved_path = self.resolve_writable_path(path)?;
 
         if !resolved_path.exists() {
             return Err(FileServiceError::FileNotFound(
@@ -221,14 +624,18 @@ impl FileService {
             ).into());
         }
 
+        let before = self.content_or_empty(&resolved_path).await;
+        self.check_external_change(&resolved_path, &before).await?;
+
         // Create a backup before modifying
-        self.backup_manager.create_backup(&resolved_path).await?;
+        self.backup_before_change(&resolved_path).await?;
 
-        self.editor.replace_line(&resolved_path, line_num, content).await.map_err(|e| e.into())
+        self.editor.replace_line(&resolved_path, line_num, content).await?;
+        self.summarize_change(&resolved_path, &before).await
     }
 
-    pub async fn delete_line(&self, path: &Path, line_num: usize) -> anyhow::Result<()> {
-        let resolved_path = self.resolve_path(path)?;
+    pub async fn delete_line(&self, path: &Path, line_num: usize) -> anyhow::Result<ChangeSummary> {
+        let resolved_path = self.resolve_writable_path(path)?;
 
         if !resolved_path.exists() {
             return Err(FileServiceError::FileNotFound(
@@ -236,10 +643,14 @@ impl FileService {
             ).into());
         }
 
+        let before = self.content_or_empty(&resolved_path).await;
+        self.check_external_change(&resolved_path, &before).await?;
+
         // Create a backup before modifying
-        self.backup_manager.create_backup(&resolved_path).await?;
+        self.backup_before_change(&resolved_path).await?;
 
-        self.editor.delete_line(&resolved_path, line_num).await.map_err(|e| e.into())
+        self.editor.delete_line(&resolved_path, line_num).await?;
+        self.summarize_change(&resolved_path, &before).await
     }
 
     pub async fn edit_region(
@@ -248,8 +659,8 @@ impl FileService {
         start_line: usize,
         end_line: usize,
         new_content: &str,
-    ) -> anyhow::Result<()> {
-        let resolved_path = self.resolve_path(path)?;
+    ) -> anyhow::Result<ChangeSummary> {
+        let resolved_path = self.resolve_writable_path(path)?;
 
         if !resolved_path.exists() {
             return Err(FileServiceError::FileNotFound(
@@ -257,18 +668,543 @@ impl FileService {
             ).into());
         }
 
+        let before = self.content_or_empty(&resolved_path).await;
+        self.check_external_change(&resolved_path, &before).await?;
+
         // Create a backup before modifying
-        self.backup_manager.create_backup(&resolved_path).await?;
+        self.backup_before_change(&resolved_path).await?;
+
+        self.editor.edit_region(&resolved_path, start_line, end_line, new_content).await?;
+        self.summarize_change(&resolved_path, &before).await
+    }
 
-        self.editor.edit_region(&resolved_path, start_line, end_line, new_content)
+    // Joins lines `start_line` through `end_line` (1-based, inclusive) of a
+    // file into one, with `separator` between them
+    pub async fn join_lines(
+        &self,
+        path: &Path,
+        start_line: usize,
+        end_line: usize,
+        separator: &str,
+    ) -> anyhow::Result<serde_json::Value> {
+        self.rewrite_lines(path, |content| {
+            join_line_range(content, start_line, end_line, separator)
+        })
+        .await
+    }
+
+    // Breaks 1-based line `line` of a file in two after its first `column`
+    // characters
+    pub async fn split_line(
+        &self,
+        path: &Path,
+        line: usize,
+        column: usize,
+    ) -> anyhow::Result<serde_json::Value> {
+        self.rewrite_lines(path, |content| split_line_at(content, line, column))
             .await
-            .map_err(|e| e.into())
+    }
+
+    // Backs up and rewrites a file with `change` applied to its content,
+    // reporting the change and its diff. Nothing is written if `change` fails
+    async fn rewrite_lines(
+        &self,
+        path: &Path,
+        change: impl FnOnce(&str) -> Result<String, EditorError>,
+    ) -> anyhow::Result<serde_json::Value> {
+        let resolved_path = self.resolve_writable_path(path)?;
+
+        if !resolved_path.exists() {
+            return Err(FileServiceError::FileNotFound(
+                resolved_path.to_string_lossy().to_string(),
+            ).into());
+        }
+
+        let before = self.editor.read_file(&resolved_path).await?;
+        self.check_external_change(&resolved_path, &before).await?;
+        let after = change(&before)?;
+
+        // Create a backup before modifying
+        self.backup_before_change(&resolved_path).await?;
+        self.editor.write_file(&resolved_path, &after).await?;
+
+        let summary = self.summarize_change(&resolved_path, &before).await?;
+        let written = tokio::fs::read_to_string(&resolved_path).await?;
+        let diff = DiffGenerator::generate_unified_diff(&before, &written)?;
+
+        let mut result = json!({
+            "success": true,
+            "path": to_display_path(path),
+            "diff": diff
+        });
+        if let (Some(result), serde_json::Value::Object(fields)) = (result.as_object_mut(), summary.to_json()) {
+            result.extend(fields);
+        }
+
+        Ok(result)
+    }
+
+    // Applies several region edits, all addressed against the file as it is
+    // now, in one read-modify-write with a single backup. Nothing is written
+    // if any edit is out of range or overlaps another
+    pub async fn edit_regions(
+        &self,
+        path: &Path,
+        edits: &[RegionEdit],
+    ) -> anyhow::Result<serde_json::Value> {
+        let resolved_path = self.resolve_writable_path(path)?;
+
+        if !resolved_path.exists() {
+            return Err(FileServiceError::FileNotFound(
+                resolved_path.to_string_lossy().to_string(),
+            ).into());
+        }
+
+        let before = self.editor.read_file(&resolved_path).await?;
+        self.check_external_change(&resolved_path, &before).await?;
+        let after = splice_regions(&before, edits)?;
+
+        // Create a backup before modifying
+        self.backup_before_change(&resolved_path).await?;
+        self.editor.write_file(&resolved_path, &after).await?;
+
+        let written = tokio::fs::read_to_string(&resolved_path).await?;
+        self.remember_content(&resolved_path, &written);
+        let diff = DiffGenerator::generate_unified_diff(&before, &written)?;
+
+        // Where each edit's content ends up, counting the growth or shrinkage
+        // of the edits above it. Reported lines are 1-based and inclusive
+        let line_count = before.lines().count();
+        let results: Vec<serde_json::Value> = edits
+            .iter()
+            .map(|edit| {
+                let end_line = edit.end_line.min(line_count);
+                let lines_added = edit.content.lines().count();
+                let shift: isize = edits
+                    .iter()
+                    .filter(|other| other.start_line < edit.start_line)
+                    .map(|other| {
+                        other.content.lines().count() as isize
+                            - (other.end_line.min(line_count) - other.start_line) as isize
+                    })
+                    .sum();
+                let new_start = (edit.start_line as isize + shift) as usize + 1;
+                // Edits that only delete leave no lines behind to point at
+                let new_range = (lines_added > 0).then(|| (new_start, new_start + lines_added - 1));
+
+                json!({
+                    "start": edit.start_line + 1,
+                    "end": end_line,
+                    "lines_removed": end_line - edit.start_line,
+                    "lines_added": lines_added,
+                    "new_start": new_range.map(|(start, _)| start),
+                    "new_end": new_range.map(|(_, end)| end),
+                    "status": "applied"
+                })
+            })
+            .collect();
+
+        let mut result = json!({
+            "success": true,
+            "path": to_display_path(path),
+            "edits": results,
+            "diff": diff
+        });
+        let summary = ChangeSummary::between(&before, &written);
+        if let (Some(result), serde_json::Value::Object(fields)) = (result.as_object_mut(), summary.to_json()) {
+            result.extend(fields);
+        }
+
+        Ok(result)
+    }
+
+    // Replaces the first (or every) span of the file matching `pattern` with
+    // `replacement`, which may refer to capture groups as `$1` or `${name}`.
+    // Reported line ranges are 1-based lines of the original content
+    pub async fn replace_matching_region(
+        &self,
+        path: &Path,
+        pattern: &Regex,
+        replacement: &str,
+        all: bool,
+    ) -> anyhow::Result<serde_json::Value> {
+        let resolved_path = self.resolve_writable_path(path)?;
+
+        if !resolved_path.exists() {
+            return Err(FileServiceError::FileNotFound(
+                resolved_path.to_string_lossy().to_string(),
+            ).into());
+        }
+
+        let before = self.editor.read_file(&resolved_path).await?;
+        self.check_external_change(&resolved_path, &before).await?;
+        let limit = if all { 0 } else { 1 };
+
+        let ranges: Vec<serde_json::Value> = pattern
+            .find_iter(&before)
+            .take(if all { usize::MAX } else { 1 })
+            .map(|m| {
+                let start_line = before[..m.start()].matches('\n').count() + 1;
+                // A match ending in a newline ends on the line that newline terminates
+                let last_byte = m.end().saturating_sub(1).max(m.start());
+                let end_line = start_line + before[m.start()..last_byte].matches('\n').count();
+                json!({ "start_line": start_line, "end_line": end_line })
+            })
+            .collect();
+
+        if ranges.is_empty() {
+            return Ok(json!({
+                "success": true,
+                "path": to_display_path(path),
+                "replacements": 0,
+                "ranges": ranges
+            }));
+        }
+
+        let after = pattern.replacen(&before, limit, replacement);
+
+        // Create a backup before modifying
+        self.backup_before_change(&resolved_path).await?;
+        self.editor.write_file(&resolved_path, &after).await?;
+
+        let mut result = json!({
+            "success": true,
+            "path": to_display_path(path),
+            "replacements": ranges.len(),
+            "ranges": ranges
+        });
+        let summary = self.summarize_change(&resolved_path, &before).await?;
+        if let (Some(result), serde_json::Value::Object(fields)) = (result.as_object_mut(), summary.to_json()) {
+            result.extend(fields);
+        }
+
+        Ok(result)
+    }
+
+    // Inserts content after (or before) the first line matching `pattern`,
+    // returning the 1-based line the content starts at
+    pub async fn insert_at_match(
+        &self,
+        path: &Path,
+        pattern: &Regex,
+        content: &str,
+        after: bool,
+        require_unique: bool,
+    ) -> anyhow::Result<(usize, ChangeSummary)> {
+        let resolved_path = self.resolve_writable_path(path)?;
+
+        if !resolved_path.exists() {
+            return Err(FileServiceError::FileNotFound(
+                resolved_path.to_string_lossy().to_string(),
+            ).into());
+        }
+
+        let before = self.content_or_empty(&resolved_path).await;
+        self.check_external_change(&resolved_path, &before).await?;
+
+        // Check the pattern before backing up, so a miss leaves no stray backup
+        find_matching_line(&before, pattern, require_unique)?;
+
+        // Create a backup before modifying
+        self.backup_before_change(&resolved_path).await?;
+
+        let line = self.editor
+            .insert_at_match(&resolved_path, pattern, content, after, require_unique)
+            .await?;
+        let summary = self.summarize_change(&resolved_path, &before).await?;
+
+        Ok((line, summary))
+    }
+
+    // Copies the content of `source` into `target` at `position`, backing up
+    // the target first. Returns the 1-based line the content starts at
+    pub async fn insert_file_contents(
+        &self,
+        target: &Path,
+        source: &Path,
+        position: &InsertPosition,
+    ) -> anyhow::Result<(usize, ChangeSummary)> {
+        let content = self.read_file(source).await?;
+
+        let line = match position {
+            InsertPosition::Anchor { pattern, after } => {
+                return self.insert_at_match(target, pattern, &content, *after, false).await;
+            }
+            InsertPosition::Line(line) => *line,
+        };
+
+        let resolved_path = self.resolve_writable_path(target)?;
+        if !resolved_path.exists() {
+            return Err(FileServiceError::FileNotFound(
+                resolved_path.to_string_lossy().to_string(),
+            ).into());
+        }
+
+        let before = self.content_or_empty(&resolved_path).await;
+        self.check_external_change(&resolved_path, &before).await?;
+
+        // Check the line before backing up, so a bad one leaves no stray backup
+        if line == 0 || line > before.lines().count() + 1 {
+            return Err(FileServiceError::LineNumberOutOfRange(line).into());
+        }
+
+        self.backup_before_change(&resolved_path).await?;
+
+        self.editor.insert_content(&resolved_path, line, &content).await?;
+        let summary = self.summarize_change(&resolved_path, &before).await?;
+
+        Ok((line, summary))
+    }
+
+    // Converts a file to the configured line ending, backing it up first.
+    // Returns whether the file changed, or would change with `dry_run`; None
+    // when no line ending is configured for it
+    pub async fn normalize_line_endings(&self, path: &Path, dry_run: bool) -> anyhow::Result<Option<bool>> {
+        let resolved_path = self.resolve_path(path)?;
+        let line_ending = match self.editor.settings_for(&resolved_path).line_ending {
+            Some(line_ending) => line_ending,
+            None => return Ok(None),
+        };
+
+        let before = tokio::fs::read_to_string(&resolved_path).await?;
+        let after = convert_line_endings(&before, line_ending);
+        if after == before || dry_run {
+            return Ok(Some(after != before));
+        }
+
+        self.check_writable(&resolved_path)?;
+        self.check_external_change(&resolved_path, &before).await?;
+        self.backup_before_change(&resolved_path).await?;
+
+        // Written as is, so only the line endings change
+        self.editor.write_raw(&resolved_path, after.as_bytes()).await?;
+        self.summarize_change(&resolved_path, &before).await?;
+
+        Ok(Some(true))
+    }
+
+    // Tidies the whitespace of a file, backing it up first. Returns a diff of
+    // the change, or of what would change with `dry_run`; None if the file
+    // is already clean
+    pub async fn cleanup_whitespace(
+        &self,
+        path: &Path,
+        cleanup: &WhitespaceCleanup,
+        dry_run: bool,
+    ) -> anyhow::Result<Option<String>> {
+        let settings = self.editor.settings_for(&self.resolve_path(path)?);
+        self.rewrite_file(path, |content| cleanup.apply(content, &settings), dry_run)
+            .await
+    }
+
+    // Rewrites a text file with `change` applied to its content, backing it
+    // up first. Returns a diff of the change, or of what would change with
+    // `dry_run`; None if `change` leaves the content as it is
+    pub async fn rewrite_file(
+        &self,
+        path: &Path,
+        change: impl FnOnce(&str) -> String,
+        dry_run: bool,
+    ) -> anyhow::Result<Option<String>> {
+        let resolved_path = self.resolve_path(path)?;
+
+        if mime::is_probably_binary(&resolved_path).await {
+            return Err(FileServiceError::BinaryFile(
+                resolved_path.to_string_lossy().to_string(),
+            ).into());
+        }
+
+        let before = tokio::fs::read_to_string(&resolved_path).await?;
+        let after = change(&before);
+        if after == before {
+            return Ok(None);
+        }
+
+        let diff = DiffGenerator::generate_unified_diff(&before, &after)?;
+        if dry_run {
+            return Ok(Some(diff));
+        }
+
+        self.check_writable(&resolved_path)?;
+        self.check_external_change(&resolved_path, &before).await?;
+        self.backup_before_change(&resolved_path).await?;
+
+        // Written as is, so nothing but the change itself is made
+        self.editor.write_raw(&resolved_path, after.as_bytes()).await?;
+        self.summarize_change(&resolved_path, &before).await?;
+
+        Ok(Some(diff))
+    }
+
+    // Backs up a file about to be changed, unless the backup policy skips it
+    async fn backup_before_change(&self, resolved_path: &Path) -> anyhow::Result<()> {
+        match self.backup_manager.skip_reason(resolved_path) {
+            Some(reason) => logging::info(&format!(
+                "Not backing up {}: {}",
+                resolved_path.display(),
+                reason
+            )),
+            None => {
+                self.backup_manager.create_backup(resolved_path).await?;
+            }
+        }
+        Ok(())
+    }
+
+    // Current content of a file, or empty if it doesn't exist yet
+    async fn content_or_empty(&self, resolved_path: &Path) -> String {
+        tokio::fs::read_to_string(resolved_path).await.unwrap_or_default()
+    }
+
+    // Notes the content of a file as mcedit last saw it
+    fn remember_content(&self, resolved_path: &Path, content: &str) {
+        if self.external_changes != ExternalChangePolicy::Ignore {
+            self.known_content
+                .lock()
+                .unwrap()
+                .insert(resolved_path.to_path_buf(), content_hash(content));
+        }
+    }
+
+    // Forgets what mcedit saw of a file that no longer exists
+    fn forget_content(&self, resolved_path: &Path) {
+        self.known_content.lock().unwrap().remove(resolved_path);
+    }
+
+    // Carries what mcedit saw of the files at or below `from` over to where
+    // a rename moved them, forgetting what was known of the destination
+    fn move_known_content(&self, from: &Path, to: &Path) {
+        let mut known_content = self.known_content.lock().unwrap();
+        known_content.retain(|path, _| !path.starts_with(to));
+        let moved: Vec<PathBuf> = known_content
+            .keys()
+            .filter(|path| path.starts_with(from))
+            .cloned()
+            .collect();
+        for path in moved {
+            if let (Some(hash), Ok(rest)) = (known_content.remove(&path), path.strip_prefix(from)) {
+                known_content.insert(to.join(rest), hash);
+            }
+        }
+    }
+
+    // Checks a file about to be written against the content mcedit last saw.
+    // Files mcedit hasn't read or written this session are not checked
+    async fn check_external_change(&self, resolved_path: &Path, current: &str) -> anyhow::Result<()> {
+        let changed = match self.known_content.lock().unwrap().get(resolved_path) {
+            Some(known) => *known != content_hash(current),
+            None => false,
+        };
+        if !changed {
+            return Ok(());
+        }
+
+        match self.external_changes {
+            ExternalChangePolicy::Ignore => Ok(()),
+            ExternalChangePolicy::Error => Err(FileServiceError::Conflict(
+                resolved_path.to_string_lossy().to_string(),
+            ).into()),
+            ExternalChangePolicy::Warn => {
+                let backup_path = self.backup_manager.create_backup(resolved_path).await?;
+                logging::warn(&format!(
+                    "{} changed on disk since it was last read, overwriting (changed content backed up at {})",
+                    resolved_path.display(),
+                    backup_path.display()
+                ));
+                Ok(())
+            }
+        }
+    }
+
+    // Compares a file's content after a change against what it was before
+    async fn summarize_change(&self, resolved_path: &Path, before: &str) -> anyhow::Result<ChangeSummary> {
+        self.read_cache.invalidate(resolved_path);
+        let after = tokio::fs::read_to_string(resolved_path).await?;
+        self.remember_content(resolved_path, &after);
+        Ok(ChangeSummary::between(before, &after))
+    }
+
+    // Creates the file empty if it is missing, then sets its modification
+    // time to `modified` (or now). Returns whether the file was created
+    pub async fn touch(&self, path: &Path, modified: Option<SystemTime>) -> anyhow::Result<bool> {
+        let resolved_path = self.resolve_writable_path(path)?;
+        let created = !resolved_path.exists();
+
+        let file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&resolved_path)
+            .await?
+            .into_std()
+            .await;
+        // tokio has no set_modified, so the blocking call gets its own thread
+        let modified = modified.unwrap_or_else(SystemTime::now);
+        tokio::task::spawn_blocking(move || file.set_modified(modified)).await??;
+
+        Ok(created)
+    }
+
+    pub async fn modified_time(&self, path: &Path) -> anyhow::Result<SystemTime> {
+        let resolved_path = self.resolve_path(path)?;
+        Ok(tokio::fs::metadata(&resolved_path).await?.modified()?)
+    }
+
+    // Permission operations
+
+    // Returns the permission bits of a file, e.g. 0o755
+    #[cfg(unix)]
+    pub async fn get_permissions(&self, path: &Path) -> anyhow::Result<u32> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let resolved_path = self.resolve_path(path)?;
+
+        if !resolved_path.exists() {
+            return Err(FileServiceError::FileNotFound(
+                resolved_path.to_string_lossy().to_string(),
+            ).into());
+        }
+
+        let metadata = tokio::fs::metadata(&resolved_path).await?;
+        Ok(metadata.permissions().mode() & 0o7777)
+    }
+
+    #[cfg(not(unix))]
+    pub async fn get_permissions(&self, _path: &Path) -> anyhow::Result<u32> {
+        Err(FileServiceError::Unsupported("file permissions are only available on Unix".to_string()).into())
+    }
+
+    // Sets the permission bits of a file. Content is unchanged, so no backup is made
+    #[cfg(unix)]
+    pub async fn set_permissions(&self, path: &Path, mode: u32) -> anyhow::Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let resolved_path = self.resolve_writable_path(path)?;
+
+        if !resolved_path.exists() {
+            return Err(FileServiceError::FileNotFound(
+                resolved_path.to_string_lossy().to_string(),
+            ).into());
+        }
+
+        logging::info(&format!(
+            "Setting permissions of {} to {:o}",
+            resolved_path.display(),
+            mode
+        ));
+
+        tokio::fs::set_permissions(&resolved_path, std::fs::Permissions::from_mode(mode)).await?;
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    pub async fn set_permissions(&self, _path: &Path, _mode: u32) -> anyhow::Result<()> {
+        Err(FileServiceError::Unsupported("file permissions are only available on Unix".to_string()).into())
     }
 
     // File management operations
 
     pub async fn delete_file(&self, path: &Path) -> anyhow::Result<()> {
-        let resolved_path = self.resolve_path(path)?;
+        let resolved_path = self.resolve_writable_path(path)?;
 
         if !resolved_path.exists() {
             return Err(FileServiceError::FileNotFound(
@@ -277,10 +1213,12 @@ impl FileService {
         }
 
         // Create a backup before deleting
-        self.backup_manager.create_backup(&resolved_path).await?;
+        self.backup_before_change(&resolved_path).await?;
 
         if resolved_path.is_file() {
             std::fs::remove_file(&resolved_path)?;
+            self.read_cache.invalidate(&resolved_path);
+            self.forget_content(&resolved_path);
             logging::info(&format!("Deleted file: {}", resolved_path.display()));
             Ok(())
         } else {
@@ -288,9 +1226,18 @@ impl FileService {
         }
     }
 
-    pub async fn rename_file(&self, from_path: &Path, to_path: &Path) -> anyhow::Result<()> {
-        let resolved_from = self.resolve_path(from_path)?;
-        let resolved_to = self.resolve_path(to_path)?;
+    // Moves a file like `mv`: a destination that is a directory receives
+    // the file under its own name. An existing destination file is an error
+    // unless `overwrite` is set, in which case it is backed up and replaced.
+    // Returns the path the file ended up at
+    pub async fn rename_file(
+        &self,
+        from_path: &Path,
+        to_path: &Path,
+        overwrite: bool,
+    ) -> anyhow::Result<PathBuf> {
+        let resolved_from = self.resolve_writable_path(from_path)?;
+        let mut resolved_to = self.resolve_path(to_path)?;
 
         if !resolved_from.exists() {
             return Err(FileServiceError::FileNotFound(
@@ -298,14 +1245,26 @@ impl FileService {
             ).into());
         }
 
+        if resolved_to.is_dir() {
+            let file_name = resolved_from.file_name().ok_or_else(|| {
+                FileServiceError::InvalidPath(resolved_from.to_string_lossy().to_string())
+            })?;
+            resolved_to.push(file_name);
+        }
+        self.check_writable(&resolved_to)?;
+
         if resolved_to.exists() {
-            return Err(FileServiceError::FileAlreadyExists(
-                resolved_to.to_string_lossy().to_string(),
-            ).into());
+            // Only files are replaced, never directories
+            if !overwrite || !resolved_to.is_file() || resolved_from.is_dir() {
+                return Err(FileServiceError::FileAlreadyExists(
+                    resolved_to.to_string_lossy().to_string(),
+                ).into());
+            }
+            self.backup_before_change(&resolved_to).await?;
         }
 
         // Create a backup before renaming
-        self.backup_manager.create_backup(&resolved_from).await?;
+        self.backup_before_change(&resolved_from).await?;
 
         // Ensure parent directory of target exists
         if let Some(parent) = resolved_to.parent() {
@@ -315,27 +1274,97 @@ impl FileService {
         }
 
         std::fs::rename(&resolved_from, &resolved_to)?;
+        self.read_cache.invalidate(&resolved_from);
+        self.read_cache.invalidate(&resolved_to);
+        self.move_known_content(&resolved_from, &resolved_to);
+
+        // The rename already happened, so a failure here only loses history
+        if let Err(e) = self.backup_manager.move_backups(&resolved_from, &resolved_to).await {
+            logging::warn(&format!(
+                "Failed to move backups of {} to {}: {}",
+                resolved_from.display(),
+                resolved_to.display(),
+                e
+            ));
+        }
         logging::info(&format!(
             "Renamed file from {} to {}",
             resolved_from.display(),
             resolved_to.display()
         ));
 
-        Ok(())
+        Ok(resolved_to)
     }
 
     // Backup and restore operations
 
-    pub async fn restore_backup(&self, path: &Path) -> anyhow::Result<()> {
+    // Backs up a file now rather than as part of an edit. Content identical
+    // to the latest backup reuses that backup
+    pub async fn create_backup(&self, path: &Path) -> anyhow::Result<PathBuf> {
         let resolved_path = self.resolve_path(path)?;
+        self.backup_manager.create_backup(&resolved_path).await.map_err(|e| e.into())
+    }
+
+    pub async fn restore_backup(&self, path: &Path) -> anyhow::Result<()> {
+        let resolved_path = self.resolve_writable_path(path)?;
+        self.read_cache.invalidate(&resolved_path);
         self.backup_manager.restore_latest_backup(&resolved_path).await.map_err(|e| e.into())
     }
 
+    // Deletes backups of a file, or of every file when no path is given
+    pub async fn purge_backups(
+        &self,
+        path: Option<&Path>,
+        older_than: Option<Duration>,
+        keep_latest: Option<usize>,
+    ) -> anyhow::Result<PurgeSummary> {
+        let resolved_path = path.map(|path| self.resolve_path(path)).transpose()?;
+        self.backup_manager
+            .purge(resolved_path.as_deref(), older_than, keep_latest)
+            .await
+            .map_err(|e| e.into())
+    }
+
     pub async fn list_backups(&self, path: &Path) -> anyhow::Result<Vec<PathBuf>> {
         let resolved_path = self.resolve_path(path)?;
         self.backup_manager.list_backups(&resolved_path).await.map_err(|e| e.into())
     }
 
+    // When each backup of a file was taken, newest first like list_backups
+    pub async fn backup_times(&self, path: &Path) -> anyhow::Result<Vec<SystemTime>> {
+        let resolved_path = self.resolve_path(path)?;
+        self.backup_manager.backup_times(&resolved_path).await.map_err(|e| e.into())
+    }
+
+    // Reads a backup of a file by index (0 is the most recent backup)
+    pub async fn read_backup(&self, path: &Path, index: usize) -> anyhow::Result<(PathBuf, String)> {
+        let resolved_path = self.resolve_path(path)?;
+        let backups = self.backup_manager.list_backups(&resolved_path).await?;
+
+        let backup_path = backups.get(index).ok_or_else(|| {
+            BackupError::NoBackupAvailable(format!(
+                "{} (index {}, {} backups available)",
+                resolved_path.display(),
+                index,
+                backups.len()
+            ))
+        })?;
+
+        let content = self.backup_manager.read_backup(backup_path).await?;
+        Ok((backup_path.clone(), content))
+    }
+
+    // Applies a unified diff to an already resolved file, returning the
+    // number of hunks applied. Nothing is written unless every hunk applies
+    pub async fn apply_unified_diff(&self, resolved_path: &Path, diff: &str) -> anyhow::Result<usize> {
+        let original = self.content_or_empty(resolved_path).await;
+        let patched = patch::apply_unified_diff(&original, diff)?;
+        self.editor.write_file(resolved_path, &patched).await?;
+        self.read_cache.invalidate(resolved_path);
+
+        Ok(diff.lines().filter(|line| line.starts_with("@@")).count())
+    }
+
     // Suggestion handling
 
     pub async fn apply_suggestion(
@@ -353,22 +1382,125 @@ impl FileService {
             .and_then(|v| v.as_str())
             .unwrap_or("unknown");
 
-        let resolved_path = self.resolve_path(path)?;
+        let resolved_path = self.resolve_writable_path(path)?;
+        let before = self.content_or_empty(&resolved_path).await;
+        self.check_external_change(&resolved_path, &before).await?;
 
         // Create backup before proceeding
         if resolved_path.exists() {
-            self.backup_manager.create_backup(&resolved_path).await?;
+            self.backup_before_change(&resolved_path).await?;
         }
 
+        let mut result = self.apply_parsed_suggestion(&resolved_path, edit_type, suggestion).await?;
+
+        // Report how much the suggestion changed the file
+        let summary = self.summarize_change(&resolved_path, &before).await?;
+        if let (Some(result), serde_json::Value::Object(fields)) = (result.as_object_mut(), summary.to_json()) {
+            result.extend(fields);
+        }
+
+        Ok(result)
+    }
+
+    // Applies a set of per-file suggestions as one change. If any of them
+    // fails, every file already changed is put back the way it was
+    pub async fn apply_multi_suggestion(
+        &self,
+        files: &[serde_json::Value],
+    ) -> anyhow::Result<serde_json::Value> {
+        // Original bytes of each file touched, None for files that didn't exist
+        let mut originals: Vec<(PathBuf, Option<Vec<u8>>)> = Vec::new();
+        let mut results = Vec::new();
+
+        for file in files {
+            let path_str = file
+                .get("path")
+                .and_then(|p| p.as_str())
+                .ok_or_else(|| anyhow::anyhow!("Missing 'path' field in multi suggestion"))?;
+            let path = Path::new(path_str);
+
+            let outcome = match self.resolve_path(path) {
+                Ok(resolved_path) => {
+                    if !originals.iter().any(|(p, _)| *p == resolved_path) {
+                        let original = tokio::fs::read(&resolved_path).await.ok();
+                        originals.push((resolved_path, original));
+                    }
+                    self.apply_suggestion(path, file).await
+                }
+                Err(err) => Err(err.into()),
+            };
+
+            // An edit the suggestion couldn't make is reported in its result
+            // rather than as an error, but fails the change all the same
+            let outcome = outcome.and_then(|result| match failed_edit(&result) {
+                Some(message) => Err(anyhow::anyhow!(message)),
+                None => Ok(result),
+            });
+
+            match outcome {
+                Ok(result) => results.push(result),
+                Err(err) => {
+                    let rolled_back = self.roll_back(&originals).await;
+                    return Err(anyhow::anyhow!(
+                        "{}: {} (rolled back {} of {} files)",
+                        path_str,
+                        err,
+                        rolled_back,
+                        originals.len()
+                    ));
+                }
+            }
+        }
+
+        Ok(json!({
+            "success": true,
+            "action": "multi",
+            "results": results
+        }))
+    }
+
+    // Restores files to captured content, removing those that didn't exist.
+    // Returns how many were restored
+    async fn roll_back(&self, originals: &[(PathBuf, Option<Vec<u8>>)]) -> usize {
+        let mut restored = 0;
+
+        for (path, original) in originals {
+            self.read_cache.invalidate(path);
+            let outcome = match original {
+                Some(content) => self.editor.write_raw(path, content).await.map_err(|e| e.to_string()),
+                None if path.exists() => tokio::fs::remove_file(path).await.map_err(|e| e.to_string()),
+                None => Ok(()),
+            };
+            // The file is back as it was, which is what mcedit last saw of it
+            match original.as_deref().map(std::str::from_utf8) {
+                Some(Ok(content)) if outcome.is_ok() => self.remember_content(path, content),
+                _ => self.forget_content(path),
+            }
+
+            match outcome {
+                Ok(()) => restored += 1,
+                Err(err) => logging::error(&format!("Failed to roll back {}: {}", path.display(), err)),
+            }
+        }
+
+        restored
+    }
+
+    async fn apply_parsed_suggestion(
+        &self,
+        resolved_path: &Path,
+        edit_type: &str,
+        suggestion: &serde_json::Value,
+    ) -> anyhow::Result<serde_json::Value> {
         match edit_type {
             "replace" => {
                 // Full file replacement
                 if let Some(content) = suggestion.get("content").and_then(|v| v.as_str()) {
-                    self.editor.write_file(&resolved_path, content).await?;
+                    self.editor.write_file(resolved_path, content).await?;
                     Ok(json!({
                         "success": true,
                         "action": "replace",
-                        "path": resolved_path.to_string_lossy()
+                        "path": to_display_path(resolved_path)
                     }))
                 } else {
                     Err(anyhow::anyhow!("Missing 'content' field in replace suggestion"))
@@ -387,7 +1519,7 @@ impl FileService {
                             "insert" => {
                                 let line = edit.get("line").and_then(|v| v.as_u64()).unwrap_or(0) as usize;
                                 let content = edit.get("content").and_then(|v| v.as_str()).unwrap_or("");
-                                self.editor.insert_line(&resolved_path, line, content).await?;
+                                self.editor.insert_line(resolved_path, line, content).await?;
                                 results.push(json!({
                                     "action": "insert",
                                     "line": line,
@@ -397,7 +1529,7 @@ impl FileService {
                             "replace" => {
                                 let line = edit.get("line").and_then(|v| v.as_u64()).unwrap_or(0) as usize;
                                 let content = edit.get("content").and_then(|v| v.as_str()).unwrap_or("");
-                                self.editor.replace_line(&resolved_path, line, content).await?;
+                                self.editor.replace_line(resolved_path, line, content).await?;
                                 results.push(json!({
                                     "action": "replace",
                                     "line": line,
@@ -406,7 +1538,7 @@ impl FileService {
                             },
                             "delete" => {
                                 let line = edit.get("line").and_then(|v| v.as_u64()).unwrap_or(0) as usize;
-                                self.editor.delete_line(&resolved_path, line).await?;
+                                self.editor.delete_line(resolved_path, line).await?;
                                 results.push(json!({
                                     "action": "delete",
                                     "line": line,
@@ -417,7 +1549,7 @@ impl FileService {
                                 let start = edit.get("start").and_then(|v| v.as_u64()).unwrap_or(0) as usize;
                                 let end = edit.get("end").and_then(|v| v.as_u64()).unwrap_or(0) as usize;
                                 let content = edit.get("content").and_then(|v| v.as_str()).unwrap_or("");
-                                self.editor.edit_region(&resolved_path, start, end, content).await?;
+                                self.editor.edit_region(resolved_path, start, end, content).await?;
                                 results.push(json!({
                                     "action": "region",
                                     "start": start,
@@ -438,13 +1570,27 @@ impl FileService {
                     Ok(json!({
                         "success": true,
                         "action": "edit",
-                        "path": resolved_path.to_string_lossy(),
+                        "path": to_display_path(resolved_path),
                         "results": results
                     }))
                 } else {
                     Err(anyhow::anyhow!("Missing or invalid 'edits' field in edit suggestion"))
                 }
             },
+            "patch" => {
+                // Unified diff against the current content
+                if let Some(diff) = suggestion.get("diff").and_then(|v| v.as_str()) {
+                    let hunks = self.apply_unified_diff(resolved_path, diff).await?;
+                    Ok(json!({
+                        "success": true,
+                        "action": "patch",
+                        "path": to_display_path(resolved_path),
+                        "hunks": hunks
+                    }))
+                } else {
+                    Err(anyhow::anyhow!("Missing 'diff' field in patch suggestion"))
+                }
+            },
             "create" => {
                 // Create a new file
                 if let Some(content) = suggestion.get("content").and_then(|v| v.as_str()) {
@@ -460,7 +1606,7 @@ impl FileService {
                         }
 
                         // Backup if we're going to overwrite
-                        self.backup_manager.create_backup(&resolved_path).await?;
+                        self.backup_before_change(resolved_path).await?;
                     }
 
                     // Ensure parent directories exist
@@ -470,12 +1616,12 @@ impl FileService {
                         }
                     }
 
-                    self.editor.write_file(&resolved_path, content).await?;
+                    self.editor.write_file(resolved_path, content).await?;
 
                     Ok(json!({
                         "success": true,
                         "action": "create",
-                        "path": resolved_path.to_string_lossy()
+                        "path": to_display_path(resolved_path)
                     }))
                 } else {
                     Err(anyhow::anyhow!("Missing 'content' field in create suggestion"))
@@ -485,3 +1631,193 @@ impl FileService {
         }
     }
 }
+
+// Hashes a file in chunks, without holding it in memory, returning the hex
+// digest and the file's size in bytes
+pub async fn hash_file(path: &Path, algorithm: HashAlgorithm) -> anyhow::Result<(String, u64)> {
+    let mut file = tokio::fs::File::open(path).await?;
+    let mut buffer = vec![0; 64 * 1024];
+    let mut size = 0;
+    let mut sha256 = Sha256::new();
+    let mut md5 = Md5::new();
+    let mut blake3 = blake3::Hasher::new();
+
+    loop {
+        let read = file.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        let chunk = &buffer[..read];
+        match algorithm {
+            HashAlgorithm::Sha256 => sha256.update(chunk),
+            HashAlgorithm::Md5 => md5.update(chunk),
+            HashAlgorithm::Blake3 => {
+                blake3.update(chunk);
+            }
+        }
+        size += read as u64;
+    }
+
+    let digest = match algorithm {
+        HashAlgorithm::Sha256 => format!("{:x}", sha256.finalize()),
+        HashAlgorithm::Md5 => format!("{:x}", md5.finalize()),
+        HashAlgorithm::Blake3 => blake3.finalize().to_hex().to_string(),
+    };
+
+    Ok((digest, size))
+}
+
+fn content_hash(content: &str) -> String {
+    format!("{:x}", Sha256::digest(content.as_bytes()))
+}
+
+// The editor used for files under `base_directory`
+fn editor_for(base_directory: &Path, config: &Config) -> FileEditor {
+    let editor = FileEditor::with_config(&config.editor);
+    if config.editor.use_editorconfig.unwrap_or(true) {
+        editor.with_editorconfig(base_directory)
+    } else {
+        editor
+    }
+}
+
+// The message of the first edit a suggestion result reports as failed
+fn failed_edit(result: &serde_json::Value) -> Option<String> {
+    let edits = result.get("results")?.as_array()?;
+    edits
+        .iter()
+        .find(|edit| edit.get("status").and_then(|s| s.as_str()) == Some("error"))
+        .map(|edit| {
+            let action = edit.get("action").and_then(|a| a.as_str()).unwrap_or("unknown");
+            let message = edit.get("message").and_then(|m| m.as_str()).unwrap_or("Edit failed");
+            format!("{} edit failed: {}", action, message)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config;
+
+    fn service(root: &Path) -> FileService {
+        FileService::new(&root.to_path_buf(), &config::default_config()).unwrap()
+    }
+
+    // A service refusing writes to files changed on disk since it saw them
+    fn detecting_service(root: &Path) -> FileService {
+        let mut config = config::default_config();
+        config.editor.detect_external_changes = Some("error".to_string());
+        FileService::new(&root.to_path_buf(), &config).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_delete_forgets_known_content() {
+        let root = tempfile::tempdir().unwrap();
+        let path = root.path().join("a.txt");
+        std::fs::write(&path, "one\n").unwrap();
+        let service = detecting_service(root.path());
+
+        service.read_file(&path).await.unwrap();
+        service.delete_file(&path).await.unwrap();
+        service.write_file(&path, "two\n", false).await.unwrap();
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "two\n");
+    }
+
+    #[tokio::test]
+    async fn test_rename_moves_known_content() {
+        let root = tempfile::tempdir().unwrap();
+        let from = root.path().join("a.txt");
+        let to = root.path().join("b.txt");
+        std::fs::write(&from, "one\n").unwrap();
+        let service = detecting_service(root.path());
+
+        service.read_file(&from).await.unwrap();
+        service.rename_file(&from, &to, false).await.unwrap();
+
+        // A new file at the old path is not mistaken for the renamed one
+        service.write_file(&from, "new\n", false).await.unwrap();
+        // The renamed file is still checked for changes made on disk
+        std::fs::write(&to, "changed elsewhere\n").unwrap();
+        assert!(service.write_file(&to, "two\n", false).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_roll_back_remembers_restored_content() {
+        let root = tempfile::tempdir().unwrap();
+        let path = root.path().join("a.txt");
+        std::fs::write(&path, "one\n").unwrap();
+        let service = detecting_service(root.path());
+
+        service.read_file(&path).await.unwrap();
+        service
+            .apply_multi_suggestion(&[
+                json!({"path": "a.txt", "type": "replace", "content": "changed\n"}),
+                json!({"path": "b.txt", "type": "edit", "edits": [{"action": "swap"}]}),
+            ])
+            .await
+            .unwrap_err();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "one\n");
+
+        service.write_file(&path, "two\n", false).await.unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "two\n");
+    }
+
+    #[tokio::test]
+    async fn test_edit_keeps_crlf_line_endings() {
+        let root = tempfile::tempdir().unwrap();
+        let path = root.path().join("a.txt");
+        std::fs::write(&path, "one\r\ntwo\r\nthree\r\n").unwrap();
+        let service = service(root.path());
+
+        service.edit_region(&path, 2, 2, "TWO").await.unwrap();
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "one\r\nTWO\r\nthree\r\n");
+    }
+
+    #[tokio::test]
+    async fn test_multi_suggestion_applies_every_file() {
+        let root = tempfile::tempdir().unwrap();
+        std::fs::write(root.path().join("a.txt"), "one\ntwo\n").unwrap();
+        let service = service(root.path());
+
+        let result = service
+            .apply_multi_suggestion(&[
+                json!({"path": "a.txt", "type": "edit", "edits": [
+                    {"action": "replace", "line": 1, "content": "TWO"}
+                ]}),
+                json!({"path": "b.txt", "type": "create", "content": "new\n"}),
+            ])
+            .await
+            .unwrap();
+
+        assert_eq!(result["results"].as_array().unwrap().len(), 2);
+        assert_eq!(std::fs::read_to_string(root.path().join("a.txt")).unwrap(), "one\nTWO\n");
+        assert_eq!(std::fs::read_to_string(root.path().join("b.txt")).unwrap(), "new\n");
+    }
+
+    #[tokio::test]
+    async fn test_multi_suggestion_rolls_back_failed_edit() {
+        let root = tempfile::tempdir().unwrap();
+        std::fs::write(root.path().join("a.txt"), "one\ntwo\n").unwrap();
+        std::fs::write(root.path().join("b.txt"), "three\n").unwrap();
+        let service = service(root.path());
+
+        let err = service
+            .apply_multi_suggestion(&[
+                json!({"path": "a.txt", "type": "replace", "content": "changed\n"}),
+                json!({"path": "c.txt", "type": "create", "content": "new\n"}),
+                json!({"path": "b.txt", "type": "edit", "edits": [
+                    {"action": "replace", "line": 0, "content": "THREE"},
+                    {"action": "swap", "line": 0}
+                ]}),
+            ])
+            .await
+            .unwrap_err();
+
+        assert!(err.to_string().contains("swap edit failed"));
+        assert_eq!(std::fs::read_to_string(root.path().join("a.txt")).unwrap(), "one\ntwo\n");
+        assert_eq!(std::fs::read_to_string(root.path().join("b.txt")).unwrap(), "three\n");
+        assert!(!root.path().join("c.txt").exists());
+    }
+}
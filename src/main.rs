@@ -9,10 +9,16 @@ mod project;
 mod shared;
 mod suggestions;
 
-use clap::{arg, command, Parser, Subcommand};
+use clap::{arg, command, Parser, Subcommand, ValueEnum};
+use config::TransportConfig;
+use core::doctor::{self, Check, CheckStatus};
 use core::mcedit::McEdit;
+use mcp::handler::McpHandler;
+use project::analyzer::{AnalysisSections, FileSort, SearchOptions, SearchQuery};
 use shared::logging;
+use std::net::SocketAddr;
 use std::path::PathBuf;
+use tokio::io::AsyncReadExt;
 
 const APP_VERSION: &str = env!("CARGO_PKG_VERSION");
 
@@ -30,6 +36,7 @@ pub struct Cli {
     #[arg(
         long,
         short = 'c',
+        global = true,
         value_name = "PATH",
         help = "Path to the configuration file"
     )]
@@ -43,22 +50,75 @@ pub struct Cli {
     )]
     pub dir: Option<String>,
 
+    #[arg(long, help = "Create the project directory if it does not exist")]
+    pub create_dir: bool,
+
+    #[arg(
+        long,
+        value_enum,
+        global = true,
+        default_value = "text",
+        help = "Format of command output"
+    )]
+    pub output: OutputFormat,
+
+    #[arg(
+        long,
+        short = 'q',
+        global = true,
+        help = "Only log warnings and errors, and skip confirmation messages"
+    )]
+    pub quiet: bool,
+
     #[arg(long, short = 'V', help = "Print version")]
     pub version: bool,
 }
 
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum OutputFormat {
+    /// Plain lines meant for people and line-oriented tools
+    Text,
+    /// A single JSON document
+    Json,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum TransportKind {
+    /// JSON-RPC messages over stdin/stdout
+    Stdio,
+    /// JSON-RPC over HTTP POST with Server-Sent Events for server messages
+    Http,
+}
+
 #[derive(Subcommand)]
 enum Commands {
     #[command(name = "mcp", about = "Launch mcedit as an MCP server")]
-    Mcp,
+    Mcp {
+        #[arg(
+            long,
+            value_enum,
+            help = "Transport to serve MCP over [default: mcp.transport.kind from the config, or stdio]"
+        )]
+        transport: Option<TransportKind>,
+
+        #[arg(
+            long,
+            value_name = "ADDR",
+            help = "Address to listen on for the http transport [default: mcp.transport.address from the config, or 127.0.0.1:3000]"
+        )]
+        listen: Option<SocketAddr>,
+    },
 
     #[command(name = "edit", about = "Edit a file with the given content")]
     Edit {
         #[arg(help = "Path to the file to edit")]
         path: String,
 
-        #[arg(help = "Content to write to the file")]
+        #[arg(help = "Content to write to the file, or - to read it from stdin")]
         content: Option<String>,
+
+        #[arg(long, help = "Read the content to write from stdin")]
+        stdin: bool,
     },
 
     #[command(name = "list", about = "List files in the project")]
@@ -75,6 +135,15 @@ enum Commands {
         #[arg(help = "Text to search for")]
         query: String,
     },
+
+    #[command(name = "validate-config", about = "Check the configuration file for problems")]
+    ValidateConfig,
+
+    #[command(name = "clear-state", about = "Forget the session state kept for the project")]
+    ClearState,
+
+    #[command(name = "doctor", about = "Check the setup for problems and print a report")]
+    Doctor,
 }
 
 #[tokio::main]
@@ -86,13 +155,24 @@ async fn main() {
         std::process::exit(0);
     }
 
+    logging::init(cli.quiet);
+    let json_output = cli.output == OutputFormat::Json;
+
     match &cli.command {
         Some(cmd) => match cmd {
-            Commands::Mcp => {
+            Commands::Mcp { transport, listen } => {
                 logging::info("Starting mcedit in MCP server mode");
                 match init_mcedit(&cli).await {
                     Ok(mut mcedit) => {
-                        if let Err(err) = mcedit.launch_mcp().await {
+                        let requested = TransportConfig {
+                            kind: transport.map(|kind| match kind {
+                                TransportKind::Stdio => "stdio".to_string(),
+                                TransportKind::Http => "http".to_string(),
+                            }),
+                            address: listen.map(|addr| addr.to_string()),
+                        };
+                        let result = mcedit.launch_mcp(&requested).await;
+                        if let Err(err) = result {
                             logging::error(&format!("Error launching MCP server: {:?}", err));
                             std::process::exit(1);
                         }
@@ -103,22 +183,59 @@ async fn main() {
                     }
                 }
             }
-            Commands::Edit { path, content } => {
+            Commands::Edit {
+                path,
+                content,
+                stdin,
+            } => {
                 logging::info(&format!("Editing file: {}", path));
                 match init_mcedit(&cli).await {
                     Ok(mcedit) => {
                         let file_path = PathBuf::from(path);
 
-                        if let Some(content_str) = content {
+                        let content = if *stdin || content.as_deref() == Some("-") {
+                            match read_stdin().await {
+                                Ok(content) => Some(content),
+                                Err(err) => {
+                                    logging::error(&format!("Error reading stdin: {:?}", err));
+                                    std::process::exit(1);
+                                }
+                            }
+                        } else {
+                            content.clone()
+                        };
+
+                        if let Some(content_str) = &content {
+                            // New files may get the default extension
+                            let file_path = mcedit.path_for_write(&file_path).await;
+                            let path = file_path.to_string_lossy();
+
                             // Write content to file
-                            if let Err(err) = mcedit.write_file(&file_path, content_str).await {
-                                logging::error(&format!("Error writing to file: {:?}", err));
-                                std::process::exit(1);
+                            match mcedit.write_file(&file_path, content_str, true).await {
+                                Ok(summary) if json_output => print_json(&serde_json::json!({
+                                    "success": true,
+                                    "path": path,
+                                    "lines_before": summary.lines_before,
+                                    "lines_after": summary.lines_after,
+                                    "bytes_changed": summary.bytes_changed
+                                })),
+                                Ok(_) => {
+                                    if !cli.quiet {
+                                        println!("File {} updated successfully", path);
+                                    }
+                                }
+                                Err(err) => {
+                                    logging::error(&format!("Error writing to file: {:?}", err));
+                                    std::process::exit(1);
+                                }
                             }
-                            println!("File {} updated successfully", path);
                         } else {
                             // Read and display file content
                             match mcedit.read_file(&file_path).await {
+                                Ok(content) if json_output => print_json(&serde_json::json!({
+                                    "path": path,
+                                    "content": content
+                                })),
                                 Ok(content) => {
                                     println!("{}", content);
                                 }
@@ -139,8 +256,9 @@ async fn main() {
                 logging::info("Listing files in project");
                 match init_mcedit(&cli).await {
                     Ok(mcedit) => {
-                        match mcedit.list_files(pattern.as_deref()).await {
-                            Ok(files) => {
+                        match mcedit.list_files(pattern.as_deref(), FileSort::Name, false, None, None).await {
+                            Ok((files, _)) if json_output => print_json(&serde_json::json!(files)),
+                            Ok((files, _)) => {
                                 for file in files {
                                     println!("{}", file.display());
                                 }
@@ -161,10 +279,9 @@ async fn main() {
                 logging::info("Analyzing project structure");
                 match init_mcedit(&cli).await {
                     Ok(mcedit) => {
-                        match mcedit.analyze_project().await {
-                            Ok(analysis) => {
-                                println!("{}", serde_json::to_string_pretty(&analysis).unwrap());
-                            }
+                        match mcedit.analyze_project(None, &AnalysisSections::default(), None, false, None).await {
+                            Ok(analysis) if json_output => print_json(&analysis),
+                            Ok(analysis) => print_analysis(&analysis),
                             Err(err) => {
                                 logging::error(&format!("Error analyzing project: {:?}", err));
                                 std::process::exit(1);
@@ -181,10 +298,16 @@ async fn main() {
                 logging::info(&format!("Searching for: {}", query));
                 match init_mcedit(&cli).await {
                     Ok(mcedit) => {
-                        match mcedit.search_files(query).await {
-                            Ok(results) => {
-                                println!("{}", serde_json::to_string_pretty(&results).unwrap());
+                        let search = match SearchQuery::single(query) {
+                            Ok(search) => search,
+                            Err(err) => {
+                                logging::error(&format!("Error searching files: {}", err));
+                                std::process::exit(1);
                             }
+                        };
+                        match mcedit.search_files(&search, &SearchOptions::default(), None).await {
+                            Ok(results) if json_output => print_json(&results),
+                            Ok(results) => print_search_results(&results),
                             Err(err) => {
                                 logging::error(&format!("Error searching files: {:?}", err));
                                 std::process::exit(1);
@@ -197,6 +320,75 @@ async fn main() {
                     }
                 }
             }
+            Commands::ValidateConfig => {
+                let config_path = cli.config.clone().or_else(config::default_config_path);
+                let problems = config::validate::load_and_validate(
+                    config_path.as_deref(),
+                    &McpHandler::tool_names(),
+                );
+                let source = config_path.as_deref().unwrap_or("built-in defaults");
+
+                if json_output {
+                    print_json(&serde_json::json!({
+                        "config": source,
+                        "valid": problems.is_empty(),
+                        "problems": problems
+                    }));
+                } else if problems.is_empty() {
+                    if !cli.quiet {
+                        println!("Config {} is valid", source);
+                    }
+                } else {
+                    println!("Config {} has {} problem(s):", source, problems.len());
+                    for problem in &problems {
+                        println!("  - {}", problem);
+                    }
+                }
+
+                if !problems.is_empty() {
+                    std::process::exit(1);
+                }
+            }
+            Commands::Doctor => {
+                let checks = doctor::run(cli.config.clone(), cli.dir.clone()).await;
+                let healthy = checks.iter().all(|check| check.status != CheckStatus::Fail);
+
+                if json_output {
+                    print_json(&serde_json::json!({
+                        "healthy": healthy,
+                        "checks": checks.iter().map(Check::to_json).collect::<Vec<_>>()
+                    }));
+                } else {
+                    for check in &checks {
+                        println!("{:<6} {}: {}", check.status.label(), check.name, check.detail);
+                    }
+                }
+
+                if !healthy {
+                    std::process::exit(1);
+                }
+            }
+            Commands::ClearState => match init_mcedit(&cli).await {
+                Ok(mcedit) => match mcedit.clear_state() {
+                    Ok(result) if json_output => print_json(&result),
+                    Ok(result) => {
+                        if !cli.quiet {
+                            println!(
+                                "Cleared session state in {}",
+                                result["state_directory"].as_str().unwrap_or_default()
+                            );
+                        }
+                    }
+                    Err(err) => {
+                        logging::error(&format!("Error clearing state: {:?}", err));
+                        std::process::exit(1);
+                    }
+                },
+                Err(e) => {
+                    logging::error(&format!("Failed to initialize mcedit: {}", e));
+                    std::process::exit(1);
+                }
+            },
         },
         None => {
             // Default behavior if no command is specified
@@ -205,6 +397,62 @@ async fn main() {
     };
 }
 
+fn print_json(value: &serde_json::Value) {
+    println!("{}", serde_json::to_string_pretty(value).unwrap());
+}
+
+fn print_analysis(analysis: &serde_json::Value) {
+    let project_types: Vec<&str> = analysis["project_type"]
+        .as_array()
+        .map(|types| types.iter().filter_map(|t| t.as_str()).collect())
+        .unwrap_or_default();
+
+    println!(
+        "Project directory: {}",
+        analysis["project_directory"].as_str().unwrap_or_default()
+    );
+    println!("Project type: {}", project_types.join(", "));
+    println!("Files: {}", analysis["stats"]["total_files"]);
+    println!("Directories: {}", analysis["stats"]["total_directories"]);
+    println!("Size: {} bytes", analysis["stats"]["total_size_bytes"]);
+
+    println!("Languages:");
+    for language in analysis["languages"].as_array().into_iter().flatten() {
+        println!(
+            "  {} ({}): {}",
+            language["extension"].as_str().unwrap_or_default(),
+            language["language"].as_str().unwrap_or_default(),
+            language["count"]
+        );
+    }
+
+    println!("Key files:");
+    for key_file in analysis["key_files"].as_array().into_iter().flatten() {
+        println!("  {}", key_file["file"].as_str().unwrap_or_default());
+    }
+}
+
+// One `file:line: text` line per match, like grep -n
+fn print_search_results(results: &serde_json::Value) {
+    for file in results["results"].as_array().into_iter().flatten() {
+        let name = file["file"].as_str().unwrap_or_default();
+        for line_match in file["matches"].as_array().into_iter().flatten() {
+            println!(
+                "{}:{}: {}",
+                name,
+                line_match["line_number"],
+                line_match["line"].as_str().unwrap_or_default()
+            );
+        }
+    }
+}
+
+async fn read_stdin() -> std::io::Result<String> {
+    let mut content = String::new();
+    tokio::io::stdin().read_to_string(&mut content).await?;
+    Ok(content)
+}
+
 async fn init_mcedit(cli: &Cli) -> anyhow::Result<McEdit> {
     let config_path = cli.config.clone();
     let dir_path = cli.dir.clone();
@@ -213,5 +461,5 @@ async fn init_mcedit(cli: &Cli) -> anyhow::Result<McEdit> {
         "Initializing mcedit with config: {:?}, dir: {:?}",
         config_path, dir_path
     ));
-    McEdit::new(config_path, dir_path)
+    McEdit::new(config_path, dir_path, cli.create_dir)
 }
@@ -1,618 +1,5685 @@
 use crate::core::mcedit::{JsonRpcErrorCode, McEdit};
-use crate::mcp::stdio::{Message, StdioTransport, Transport};
+use crate::diff::generator::{DiffFormat, WhitespaceMode};
+use crate::editor::file_editor::RegionEdit;
+use crate::file_service::service::{HashAlgorithm, InsertPosition};
+use crate::mcp::heartbeat::{next_beat, Heartbeat};
+use crate::mcp::rate_limit::RateLimiter;
+use crate::mcp::signals::{shutdown_signal, HangupListener};
+use crate::mcp::stdio::{Message, Transport};
+use crate::mcp::tools::{ToolDef, ToolRegistry};
+use crate::project::analyzer::{
+    into_grep_output, AnalysisSections, FileSort, MatchMode, SearchOptions, SearchQuery,
+    DEFAULT_LIST_MAX_RESULTS,
+};
+use crate::project::tasks::TaskOutput;
+use crate::project::tree::{DEFAULT_TREE_DEPTH, DEFAULT_TREE_ENTRIES};
 use crate::shared::logging;
+use crate::shared::utils::path::{path_from_file_uri, to_display_path};
 use futures::StreamExt;
+use regex::{Regex, RegexBuilder};
 use serde_json::{json, Value};
 use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tokio::sync::mpsc;
 
-const TOOLS_JSON: &str = r#"{
-  "tools": [
-    {
-      "name": "read_file",
-      "description": "Read the content of a file",
-      "inputSchema": {
-        "type": "object",
-        "properties": {
-          "path": {
-            "type": "string",
-            "description": "Path to the file to read"
-          }
+// Every tool the server provides, in the order tools/list reports them
+fn tool_registry() -> ToolRegistry {
+    let mut tools = ToolRegistry::new();
+    tools.register(ToolDef {
+        name: "read_file",
+        description: "Read the content of a file",
+        input_schema: json!({
+          "type": "object",
+          "properties": {
+            "cwd": {
+              "type": "string",
+              "description": "Directory inside the project to resolve path against, for this call only"
+            },
+            "path": {
+              "type": "string",
+              "description": "Path to the file to read"
+            },
+            "budget_tokens": {
+              "type": "integer",
+              "description": "Optional token budget. Files that don't fit are returned as their outline plus as much of the start of the file as fits"
+            },
+            "expand_tabs": {
+              "type": "boolean",
+              "description": "Replace tabs in the indentation of each line with spaces in the returned content; the file on disk is unchanged (default false)"
+            },
+            "expand_all_tabs": {
+              "type": "boolean",
+              "description": "Like expand_tabs, but replace every tab, not only indentation (default false)"
+            },
+            "tab_size": {
+              "type": "integer",
+              "description": "Columns per tab when expanding tabs (default: editor.tab_size from the config)"
+            }
+          },
+          "required": ["path"]
+        }),
+        output_schema: Some(json!({
+          "type": "object",
+          "properties": {
+            "content": {
+              "type": "string",
+              "description": "Content of the file, or its start when elided"
+            },
+            "path": {
+              "type": "string",
+              "description": "Path to the file that was read"
+            },
+            "outline": {
+              "type": "array",
+              "description": "Outline of the file, when it didn't fit in budget_tokens"
+            },
+            "budget_used": {
+              "type": "integer",
+              "description": "Estimated tokens returned, when budget_tokens is given"
+            },
+            "elided": {
+              "type": ["object", "null"],
+              "description": "Lines returned and left out, when the file didn't fit in budget_tokens"
+            }
+          },
+          "required": ["content", "path"]
+        })),
+        handler: |handler, transport, id, params| {
+            Box::pin(handler.handle_read_file(transport, id, params))
         },
-        "required": ["path"]
-      },
-      "outputSchema": {
-        "type": "object",
-        "properties": {
-          "content": {
-            "type": "string",
-            "description": "Content of the file"
-          },
-          "path": {
-            "type": "string",
-            "description": "Path to the file that was read"
-          }
+    });
+    tools.register(ToolDef {
+        name: "get_line",
+        description: "Read a single line of a file by its 1-based number, for example to confirm a line before editing it",
+        input_schema: json!({
+          "type": "object",
+          "properties": {
+            "path": {
+              "type": "string",
+              "description": "Path to the file to read"
+            },
+            "line": {
+              "type": "integer",
+              "description": "Line to read (1-based)"
+            }
+          },
+          "required": ["path", "line"]
+        }),
+        output_schema: Some(json!({
+          "type": "object",
+          "properties": {
+            "path": {
+              "type": "string",
+              "description": "Path to the file that was read"
+            },
+            "line": {
+              "type": "integer",
+              "description": "Number of the line returned"
+            },
+            "content": {
+              "type": "string",
+              "description": "Content of the line, without its line terminator"
+            },
+            "line_count": {
+              "type": "integer",
+              "description": "Number of lines in the file"
+            }
+          },
+          "required": ["path", "line", "content", "line_count"]
+        })),
+        handler: |handler, transport, id, params| {
+            Box::pin(handler.handle_get_line(transport, id, params))
         },
-        "required": ["content", "path"]
-      }
-    },
-    {
-      "name": "write_file",
-      "description": "Write content to a file",
-      "inputSchema": {
-        "type": "object",
-        "properties": {
-          "path": {
-            "type": "string",
-            "description": "Path to the file to write"
-          },
-          "content": {
-            "type": "string",
-            "description": "Content to write to the file"
-          }
+    });
+    tools.register(ToolDef {
+        name: "resolve_path",
+        description: "Show where the server would take a path to be, without touching the file: expands a leading ~, resolves it against cwd and the project directory, and either returns the resulting absolute path (canonical when it exists) or explains why the path is rejected",
+        input_schema: json!({
+          "type": "object",
+          "properties": {
+            "path": {
+              "type": "string",
+              "description": "Path to resolve, relative, absolute or starting with ~"
+            },
+            "cwd": {
+              "type": "string",
+              "description": "Directory inside the project to resolve a relative path against (default: the project root)"
+            }
+          },
+          "required": ["path"]
+        }),
+        output_schema: Some(json!({
+          "type": "object",
+          "properties": {
+            "path": {
+              "type": "string",
+              "description": "The path as given"
+            },
+            "resolved": {
+              "type": "string",
+              "description": "Absolute path the server would use"
+            },
+            "relative": {
+              "type": ["string", "null"],
+              "description": "The resolved path relative to the project directory"
+            },
+            "exists": { "type": "boolean" },
+            "is_dir": { "type": "boolean" },
+            "writable": {
+              "type": "boolean",
+              "description": "Whether project.write_allowlist allows writing there"
+            }
+          },
+          "required": ["path", "resolved", "relative", "exists", "is_dir", "writable"]
+        })),
+        handler: |handler, transport, id, params| {
+            Box::pin(handler.handle_resolve_path(transport, id, params))
         },
-        "required": ["path", "content"]
-      },
-      "outputSchema": {
-        "type": "object",
-        "properties": {
-          "success": {
-            "type": "boolean",
-            "description": "Whether the write operation was successful"
-          },
-          "path": {
-            "type": "string",
-            "description": "Path to the file that was written"
-          }
+    });
+    tools.register(ToolDef {
+        name: "file_hash",
+        description: "Compute the hash of a file without returning its content, for change detection or comparing files",
+        input_schema: json!({
+          "type": "object",
+          "properties": {
+            "path": {
+              "type": "string",
+              "description": "Path to the file to hash"
+            },
+            "algorithm": {
+              "type": "string",
+              "enum": ["sha256", "md5", "blake3"],
+              "description": "Hash algorithm (default: sha256)"
+            }
+          },
+          "required": ["path"]
+        }),
+        output_schema: Some(json!({
+          "type": "object",
+          "properties": {
+            "path": {
+              "type": "string",
+              "description": "Path to the file that was hashed"
+            },
+            "algorithm": {
+              "type": "string",
+              "description": "Hash algorithm used"
+            },
+            "hash": {
+              "type": "string",
+              "description": "Hex digest of the file's content"
+            },
+            "size": {
+              "type": "integer",
+              "description": "Size of the file in bytes"
+            }
+          },
+          "required": ["path", "algorithm", "hash", "size"]
+        })),
+        handler: |handler, transport, id, params| {
+            Box::pin(handler.handle_file_hash(transport, id, params))
         },
-        "required": ["success", "path"]
-      }
-    },
-    {
-      "name": "list_files",
-      "description": "List files in the project directory that match a pattern",
-      "inputSchema": {
-        "type": "object",
-        "properties": {
-          "pattern": {
-            "type": "string",
-            "description": "Pattern to match files against (regex)"
-          }
-        }
-      },
-      "outputSchema": {
-        "type": "object",
-        "properties": {
-          "files": {
-            "type": "array",
-            "items": {
-              "type": "string"
+    });
+    tools.register(ToolDef {
+        name: "read_many",
+        description: "Read several files in one call, given as a list of paths or a glob. Content is capped in total: the file that reaches the cap is cut short and later files are returned empty, each flagged truncated",
+        input_schema: json!({
+          "type": "object",
+          "properties": {
+            "paths": {
+              "type": "array",
+              "items": {
+                "type": "string"
+              },
+              "description": "Paths of the files to read, in the order to return them"
             },
-            "description": "List of file paths matching the pattern"
+            "glob": {
+              "type": "string",
+              "description": "Glob matched against paths relative to the project root, such as src/**/*.rs; used when paths is not given"
+            },
+            "max_total_bytes": {
+              "type": "integer",
+              "description": "Maximum bytes of content returned across all files (default 262144)"
+            }
           }
+        }),
+        output_schema: Some(json!({
+          "type": "object",
+          "properties": {
+            "files": {
+              "type": "array",
+              "items": {
+                "type": "object",
+                "properties": {
+                  "path": {
+                    "type": "string",
+                    "description": "Path of the file"
+                  },
+                  "content": {
+                    "type": "string",
+                    "description": "Content of the file, possibly cut short"
+                  },
+                  "size": {
+                    "type": "integer",
+                    "description": "Full size of the file in bytes"
+                  },
+                  "truncated": {
+                    "type": "boolean",
+                    "description": "Whether content was cut short by max_total_bytes"
+                  },
+                  "error": {
+                    "type": "string",
+                    "description": "Why the file could not be read, in place of content"
+                  }
+                }
+              },
+              "description": "The files, in the order requested"
+            },
+            "total_bytes": {
+              "type": "integer",
+              "description": "Bytes of content returned across all files"
+            },
+            "truncated": {
+              "type": "boolean",
+              "description": "Whether any file was cut short"
+            }
+          },
+          "required": ["files", "total_bytes", "truncated"]
+        })),
+        handler: |handler, transport, id, params| {
+            Box::pin(handler.handle_read_many(transport, id, params))
         },
-        "required": ["files"]
-      }
-    },
-    {
-      "name": "search_files",
-      "description": "Search for text in files in the project",
-      "inputSchema": {
-        "type": "object",
-        "properties": {
-          "query": {
-            "type": "string",
-            "description": "Text to search for"
+    });
+    tools.register(ToolDef {
+        name: "write_file",
+        description: "Write content to a file",
+        input_schema: json!({
+          "type": "object",
+          "properties": {
+            "path": {
+              "type": "string",
+              "description": "Path to the file to write"
+            },
+            "content": {
+              "type": "string",
+              "description": "Content to write to the file"
+            },
+            "create_dirs": {
+              "type": "boolean",
+              "description": "Create missing parent directories (default false, so a mistyped directory is an error)"
+            }
+          },
+          "required": ["path", "content"]
+        }),
+        output_schema: Some(json!({
+          "type": "object",
+          "properties": {
+            "success": {
+              "type": "boolean",
+              "description": "Whether the write operation was successful"
+            },
+            "path": {
+              "type": "string",
+              "description": "Path to the file that was written"
+            }
+          },
+          "required": ["success", "path"]
+        })),
+        handler: |handler, transport, id, params| {
+            Box::pin(handler.handle_write_file(transport, id, params))
+        },
+    });
+    tools.register(ToolDef {
+        name: "list_files",
+        description: "List files in the project directory that match a pattern",
+        input_schema: json!({
+          "type": "object",
+          "properties": {
+            "cwd": {
+              "type": "string",
+              "description": "Directory inside the project to list instead of the whole project, for this call only"
+            },
+            "pattern": {
+              "type": "string",
+              "description": "Pattern to match files against (regex)"
+            },
+            "with_metadata": {
+              "type": "boolean",
+              "description": "Return objects with path, size, modified, and is_dir instead of plain paths (default false)"
+            },
+            "sort": {
+              "type": "string",
+              "enum": ["name", "mtime", "size"],
+              "description": "Order of the files: by path (default), by modification time oldest first, or by size smallest first"
+            },
+            "reverse": {
+              "type": "boolean",
+              "description": "Reverse the order, e.g. with sort mtime to list the most recently changed files first (default false)"
+            },
+            "max_results": {
+              "type": "integer",
+              "description": "Most files to return (default 10000). The listing stops at the first max_results files found, which are then sorted, so narrow it with pattern or cwd to see the rest"
+            }
           }
+        }),
+        output_schema: Some(json!({
+          "type": "object",
+          "properties": {
+            "files": {
+              "type": "array",
+              "items": {
+                "type": ["string", "object"]
+              },
+              "description": "List of file paths matching the pattern, or objects with path, size, modified, and is_dir when with_metadata is set"
+            },
+            "truncated": {
+              "type": "boolean",
+              "description": "Whether more files matched than max_results"
+            }
+          },
+          "required": ["files"]
+        })),
+        handler: |handler, transport, id, params| {
+            Box::pin(handler.handle_list_files(transport, id, params))
         },
-        "required": ["query"]
-      },
-      "outputSchema": {
-        "type": "object",
-        "properties": {
-          "results": {
-            "type": "array",
-            "items": {
+    });
+    tools.register(ToolDef {
+        name: "directory_tree",
+        description: "Show the project as a tree, like the tree command, a few levels deep. Directories at the depth limit show how many files they hold",
+        input_schema: json!({
+          "type": "object",
+          "properties": {
+            "cwd": {
+              "type": "string",
+              "description": "Directory inside the project to show instead of the whole project, for this call only"
+            },
+            "depth": {
+              "type": "integer",
+              "description": "Levels of the tree to show below its root (default 3)"
+            },
+            "dirs_only": {
+              "type": "boolean",
+              "description": "Show only directories (default false)"
+            },
+            "max_entries": {
+              "type": "integer",
+              "description": "Most entries to show, beyond which the tree is cut short and marked truncated (default 500)"
+            }
+          }
+        }),
+        output_schema: Some(json!({
+          "type": "object",
+          "properties": {
+            "root": {
+              "type": "string",
+              "description": "Directory the tree starts from"
+            },
+            "tree": {
+              "type": "string",
+              "description": "The tree rendered as text, with directories ending in /"
+            },
+            "structure": {
               "type": "object",
-              "properties": {
-                "file": {
-                  "type": "string",
-                  "description": "File path where match was found"
-                },
-                "matches": {
-                  "type": "array",
-                  "items": {
-                    "type": "object",
-                    "properties": {
-                      "line_number": {
-                        "type": "integer",
-                        "description": "Line number where match was found"
-                      },
-                      "line": {
-                        "type": "string",
-                        "description": "Content of the line containing the match"
+              "description": "The same tree as nested objects with name, type (directory or file), and either children or, at the depth limit, file_count"
+            },
+            "entries": {
+              "type": "integer",
+              "description": "Number of entries shown"
+            },
+            "truncated": {
+              "type": "boolean",
+              "description": "Whether entries were left out to stay within max_entries"
+            }
+          },
+          "required": ["tree", "structure", "truncated"]
+        })),
+        handler: |handler, transport, id, params| {
+            Box::pin(handler.handle_directory_tree(transport, id, params))
+        },
+    });
+    tools.register(ToolDef {
+        name: "search_files",
+        description: "Search for text in files in the project. Give a single regex as query, or several as patterns combined by mode, optionally ruling out lines that match any exclude pattern",
+        input_schema: json!({
+          "type": "object",
+          "properties": {
+            "cwd": {
+              "type": "string",
+              "description": "Directory inside the project to search instead of the whole project, for this call only; result paths are relative to it"
+            },
+            "path": {
+              "type": "string",
+              "description": "Subdirectory to search, such as src; result paths stay relative to the project (or cwd)"
+            },
+            "path_glob": {
+              "type": "string",
+              "description": "Only search files whose path relative to the project (or cwd) matches this glob, such as **/*.rs"
+            },
+            "query": {
+              "type": "string",
+              "description": "Regex to search for"
+            },
+            "patterns": {
+              "type": "array",
+              "items": {
+                "type": "string"
+              },
+              "description": "Regexes to search for, combined by mode; used together with query if both are given"
+            },
+            "mode": {
+              "type": "string",
+              "enum": ["all", "any"],
+              "description": "Whether a line must match all of the patterns or any one of them (default all)"
+            },
+            "exclude": {
+              "type": "array",
+              "items": {
+                "type": "string"
+              },
+              "description": "Regexes ruling out a line if any of them matches it"
+            },
+            "max_line_preview": {
+              "type": "integer",
+              "description": "Maximum characters of each matching line to return, kept centered on the match (default 200, 0 for no limit)"
+            },
+            "budget_tokens": {
+              "type": "integer",
+              "description": "Optional token budget; matches beyond it are left out and counted in elided"
+            },
+            "max_matches_per_file": {
+              "type": "integer",
+              "description": "Maximum matches returned for any one file; files with more are flagged truncated_in_file"
+            },
+            "max_results": {
+              "type": "integer",
+              "description": "Maximum matches returned in total; the search stops there and sets truncated"
+            },
+            "output": {
+              "type": "string",
+              "enum": ["json", "grep"],
+              "description": "json (default) returns results grouped by file; grep returns lines instead, a flat list of file:line:text strings like grep -n"
+            }
+          }
+        }),
+        output_schema: Some(json!({
+          "type": "object",
+          "properties": {
+            "results": {
+              "type": "array",
+              "items": {
+                "type": "object",
+                "properties": {
+                  "file": {
+                    "type": "string",
+                    "description": "File path where match was found"
+                  },
+                  "matches": {
+                    "type": "array",
+                    "items": {
+                      "type": "object",
+                      "properties": {
+                        "line_number": {
+                          "type": "integer",
+                          "description": "Line number where match was found"
+                        },
+                        "line": {
+                          "type": "string",
+                          "description": "Content of the line containing the match, trimmed with … around the match when longer than max_line_preview"
+                        },
+                        "line_length": {
+                          "type": "integer",
+                          "description": "Length of the full line in characters"
+                        },
+                        "column": {
+                          "type": "integer",
+                          "description": "1-based column of the first match of any pattern, counted in characters (not bytes)"
+                        }
                       }
                     }
+                  },
+                  "skipped": {
+                    "type": "string",
+                    "description": "Set to \"binary\" for files named like text whose content is binary; they are not searched and have no matches"
+                  },
+                  "truncated_in_file": {
+                    "type": "boolean",
+                    "description": "Present and true when the file had more matches than max_matches_per_file"
+                  },
+                  "total_in_file": {
+                    "type": "integer",
+                    "description": "Number of matches in the file, when truncated_in_file is set"
                   }
                 }
-              }
+              },
+              "description": "List of matches found"
             },
-            "description": "List of matches found"
-          }
-        },
-        "required": ["results"]
-      }
-    },
-    {
-      "name": "analyze_project",
-      "description": "Analyze the structure of the project",
-      "inputSchema": {
-        "type": "object",
-        "properties": {}
-      },
-      "outputSchema": {
-        "type": "object",
-        "properties": {
-          "project_directory": {
-            "type": "string",
-            "description": "Base directory of the project"
-          },
-          "project_type": {
-            "type": "array",
-            "items": {
-              "type": "string"
-            },
-            "description": "Detected project types"
-          },
-          "stats": {
-            "type": "object",
-            "description": "Project statistics"
-          },
-          "languages": {
-            "type": "array",
-            "description": "Programming languages used in the project"
-          },
-          "key_files": {
-            "type": "array",
-            "description": "Important files in the project"
+            "lines": {
+              "type": "array",
+              "items": {
+                "type": "string"
+              },
+              "description": "Matches as file:line:text strings, in place of results when output is grep"
+            },
+            "truncated": {
+              "type": "boolean",
+              "description": "Whether the search stopped early at max_results"
+            },
+            "depth_limited": {
+              "type": "boolean",
+              "description": "Whether directories deeper than project.max_depth were left out of the search"
+            },
+            "files_searched": {
+              "type": "integer",
+              "description": "Number of text files read and searched"
+            },
+            "files_skipped": {
+              "type": "object",
+              "description": "Files passed over, by reason: binary, unreadable, outside_path_glob, and excluded (entries left out by ignore files or project.exclude_patterns; an excluded directory counts once)"
+            },
+            "elapsed_ms": {
+              "type": "integer",
+              "description": "Time the search took in milliseconds"
+            }
           }
+        })),
+        handler: |handler, transport, id, params| {
+            Box::pin(handler.handle_search_files(transport, id, params))
         },
-        "required": ["project_directory", "project_type"]
-      }
-    },
-    {
-      "name": "apply_suggestion",
-      "description": "Apply suggested changes to a file",
-      "inputSchema": {
-        "type": "object",
-        "properties": {
-          "path": {
-            "type": "string",
-            "description": "Path to the file to modify"
-          },
-          "suggestion": {
-            "type": "string",
-            "description": "Suggestion text describing the changes"
-          }
+    });
+    tools.register(ToolDef {
+        name: "replace_in_files",
+        description: "Replace text across the files search_files would search, on the lines it would match. Takes the same query, patterns, mode, and exclude as search_files, so case-insensitive (?i) or whole-word \\b patterns and path_glob narrow the replacement exactly as they narrow a search. Changed files are backed up first",
+        input_schema: json!({
+          "type": "object",
+          "properties": {
+            "cwd": {
+              "type": "string",
+              "description": "Directory inside the project to work in instead of the whole project, for this call only"
+            },
+            "path": {
+              "type": "string",
+              "description": "Subdirectory to replace in, such as src"
+            },
+            "path_glob": {
+              "type": "string",
+              "description": "Only change files whose path relative to the project (or cwd) matches this glob, such as **/*.rs"
+            },
+            "query": {
+              "type": "string",
+              "description": "Regex whose matches are replaced"
+            },
+            "patterns": {
+              "type": "array",
+              "items": {
+                "type": "string"
+              },
+              "description": "Regexes whose matches are replaced on lines matching them as combined by mode; used together with query if both are given"
+            },
+            "mode": {
+              "type": "string",
+              "enum": ["all", "any"],
+              "description": "Whether a line must match all of the patterns or any one of them to be changed (default all)"
+            },
+            "exclude": {
+              "type": "array",
+              "items": {
+                "type": "string"
+              },
+              "description": "Regexes leaving a line unchanged if any of them matches it"
+            },
+            "replacement": {
+              "type": "string",
+              "description": "Text to put in place of each match; $1 or ${name} insert capture groups"
+            },
+            "dry_run": {
+              "type": "boolean",
+              "description": "Return the diffs without changing any file (default false)"
+            }
+          },
+          "required": ["replacement"]
+        }),
+        output_schema: Some(json!({
+          "type": "object",
+          "properties": {
+            "dry_run": {
+              "type": "boolean"
+            },
+            "files_searched": {
+              "type": "integer",
+              "description": "Number of files the search covered"
+            },
+            "files_changed": {
+              "type": "integer",
+              "description": "Number of files changed, or that would change with dry_run"
+            },
+            "lines_changed": {
+              "type": "integer",
+              "description": "Number of lines changed across all files"
+            },
+            "files": {
+              "type": "array",
+              "items": {
+                "type": "object",
+                "properties": {
+                  "path": {
+                    "type": "string"
+                  },
+                  "lines_changed": {
+                    "type": "integer"
+                  },
+                  "diff": {
+                    "type": "string",
+                    "description": "Unified diff of the change to the file"
+                  }
+                }
+              }
+            },
+            "errors": {
+              "type": "array",
+              "items": {
+                "type": "object",
+                "properties": {
+                  "path": {
+                    "type": "string"
+                  },
+                  "error": {
+                    "type": "string"
+                  }
+                }
+              },
+              "description": "Files that could not be changed, with the reason"
+            }
+          },
+          "required": ["dry_run", "files_changed", "files", "errors"]
+        })),
+        handler: |handler, transport, id, params| {
+            Box::pin(handler.handle_replace_in_files(transport, id, params))
         },
-        "required": ["path", "suggestion"]
-      },
-      "outputSchema": {
-        "type": "object",
-        "properties": {
-          "success": {
-            "type": "boolean",
-            "description": "Whether the suggestion was applied successfully"
-          },
-          "action": {
-            "type": "string",
-            "description": "Type of action performed"
-          },
-          "path": {
-            "type": "string",
-            "description": "Path to the file that was modified"
+    });
+    tools.register(ToolDef {
+        name: "count_matches",
+        description: "Count the lines matching a search without returning them, to gauge how big a full search_files result would be. Takes the same query, patterns, mode and exclude arguments as search_files",
+        input_schema: json!({
+          "type": "object",
+          "properties": {
+            "query": {
+              "type": "string",
+              "description": "Regex to search for"
+            },
+            "patterns": {
+              "type": "array",
+              "items": {
+                "type": "string"
+              },
+              "description": "Regexes to search for, combined by mode; used together with query if both are given"
+            },
+            "mode": {
+              "type": "string",
+              "enum": ["all", "any"],
+              "description": "Whether a line must match all of the patterns or any one of them (default all)"
+            },
+            "exclude": {
+              "type": "array",
+              "items": {
+                "type": "string"
+              },
+              "description": "Regexes ruling out a line if any of them matches it"
+            }
           }
+        }),
+        output_schema: Some(json!({
+          "type": "object",
+          "properties": {
+            "total": {
+              "type": "integer",
+              "description": "Number of matching lines across the project"
+            },
+            "files_matched": {
+              "type": "integer",
+              "description": "Number of files with at least one matching line"
+            },
+            "per_file": {
+              "type": "array",
+              "items": {
+                "type": "object",
+                "properties": {
+                  "file": {
+                    "type": "string",
+                    "description": "File path relative to the project root"
+                  },
+                  "count": {
+                    "type": "integer",
+                    "description": "Number of matching lines in the file"
+                  }
+                }
+              },
+              "description": "Files with matches and how many each has"
+            }
+          },
+          "required": ["total", "files_matched", "per_file"]
+        })),
+        handler: |handler, transport, id, params| {
+            Box::pin(handler.handle_count_matches(transport, id, params))
         },
-        "required": ["success", "action", "path"]
-      }
-    },
-    {
-      "name": "generate_diff",
-      "description": "Generate diff between original and modified text",
-      "inputSchema": {
-        "type": "object",
-        "properties": {
-          "original": {
-            "type": "string",
-            "description": "Original text"
-          },
-          "modified": {
-            "type": "string",
-            "description": "Modified text"
-          }
+    });
+    tools.register(ToolDef {
+        name: "find_references",
+        description: "Find uses of an identifier across the project using whole-word matching, marking each as a likely definition or a usage",
+        input_schema: json!({
+          "type": "object",
+          "properties": {
+            "identifier": {
+              "type": "string",
+              "description": "Identifier to look for, e.g. a function or type name"
+            }
+          },
+          "required": ["identifier"]
+        }),
+        output_schema: Some(json!({
+          "type": "object",
+          "properties": {
+            "identifier": {
+              "type": "string",
+              "description": "Identifier that was searched for"
+            },
+            "total": {
+              "type": "integer",
+              "description": "Number of lines referencing the identifier"
+            },
+            "files_matched": {
+              "type": "integer",
+              "description": "Number of files referencing the identifier"
+            },
+            "references": {
+              "type": "array",
+              "items": {
+                "type": "object",
+                "properties": {
+                  "file": {
+                    "type": "string",
+                    "description": "File containing the reference"
+                  },
+                  "line": {
+                    "type": "integer",
+                    "description": "Line number (1-based)"
+                  },
+                  "column": {
+                    "type": "integer",
+                    "description": "Character column of the first reference on the line (1-based)"
+                  },
+                  "kind": {
+                    "type": "string",
+                    "description": "definition or usage, guessed from the preceding keyword"
+                  },
+                  "text": {
+                    "type": "string",
+                    "description": "The referencing line"
+                  }
+                }
+              },
+              "description": "References grouped by file, in line order"
+            }
+          },
+          "required": ["identifier", "total", "references"]
+        })),
+        handler: |handler, transport, id, params| {
+            Box::pin(handler.handle_find_references(transport, id, params))
         },
-        "required": ["original", "modified"]
-      },
-      "outputSchema": {
-        "type": "object",
-        "properties": {
-          "diff": {
-            "type": "string",
-            "description": "Unified diff between original and modified text"
+    });
+    tools.register(ToolDef {
+        name: "analyze_project",
+        description: "Analyze the structure of the project. The project type and key files need no walk of the project, so asking only for those sections is fast; given a progressToken, they are also sent as a progress notification before the walk for stats and languages",
+        input_schema: json!({
+          "type": "object",
+          "properties": {
+            "cwd": {
+              "type": "string",
+              "description": "Directory inside the project that path is relative to, for this call only"
+            },
+            "path": {
+              "type": "string",
+              "description": "Optional subdirectory of the project to analyze instead of the whole project"
+            },
+            "max_age_seconds": {
+              "type": "integer",
+              "description": "Reuse the last analysis of the same directory, kept across server restarts, if it is at most this many seconds old. Without it the project is always analyzed afresh"
+            },
+            "sections": {
+              "type": "array",
+              "items": {
+                "type": "string",
+                "enum": ["stats", "languages", "key_files", "project_type"]
+              },
+              "description": "Parts of the analysis to compute (default: all). Leaving out stats and languages skips walking the whole directory"
+            },
+            "include_dependencies": {
+              "type": "boolean",
+              "description": "Also summarize the dependencies declared in Cargo.toml, package.json, go.mod and requirements.txt at the top of the analyzed directory (default: false)"
+            }
           }
+        }),
+        output_schema: Some(json!({
+          "type": "object",
+          "properties": {
+            "project_directory": {
+              "type": "string",
+              "description": "Directory that was analyzed: the project root, or the subdirectory given as path relative to it"
+            },
+            "project_type": {
+              "type": "array",
+              "items": {
+                "type": "string"
+              },
+              "description": "Detected project types"
+            },
+            "stats": {
+              "type": "object",
+              "description": "Project statistics"
+            },
+            "languages": {
+              "type": "array",
+              "description": "Programming languages used in the project"
+            },
+            "depth_limited": {
+              "type": "boolean",
+              "description": "Whether directories deeper than project.max_depth were left out of stats and languages"
+            },
+            "key_files": {
+              "type": "array",
+              "description": "Important files in the project"
+            },
+            "dependencies": {
+              "type": "object",
+              "description": "Dependencies per manifest, present only when include_dependencies was set"
+            },
+            "cached_at": {
+              "type": "string",
+              "description": "When the analysis was made, present only when a cached analysis was returned"
+            }
+          },
+          "required": ["project_directory"]
+        })),
+        handler: |handler, transport, id, params| {
+            Box::pin(handler.handle_analyze_project(transport, id, params))
         },
-        "required": ["diff"]
-      }
-    },
-    {
-      "name": "change_directory",
-      "description": "Change the current working directory",
-      "inputSchema": {
-        "type": "object",
-        "properties": {
-          "directory": {
-            "type": "string",
-            "description": "New directory path"
-          }
+    });
+    tools.register(ToolDef {
+        name: "apply_suggestion",
+        description: "Apply suggested changes to a file, or to several files at once",
+        input_schema: json!({
+          "type": "object",
+          "properties": {
+            "path": {
+              "type": "string",
+              "description": "Path to the file to modify. Not needed for multi-file suggestions"
+            },
+            "suggestion": {
+              "type": "string",
+              "description": "Suggestion text describing the changes. May be a JSON suggestion such as {\"type\": \"patch\", \"diff\": \"<unified diff>\"} to apply a unified diff, or {\"type\": \"multi\", \"files\": [{\"path\": \"...\", \"type\": \"replace\", \"content\": \"...\"}]} to change several files together; if one of them fails, all are rolled back"
+            }
+          },
+          "required": ["suggestion"]
+        }),
+        output_schema: Some(json!({
+          "type": "object",
+          "properties": {
+            "success": {
+              "type": "boolean",
+              "description": "Whether the suggestion was applied successfully"
+            },
+            "action": {
+              "type": "string",
+              "description": "Type of action performed"
+            },
+            "path": {
+              "type": "string",
+              "description": "Path to the file that was modified"
+            },
+            "results": {
+              "type": "array",
+              "description": "Result for each file, for multi-file suggestions"
+            }
+          },
+          "required": ["success", "action"]
+        })),
+        handler: |handler, transport, id, params| {
+            Box::pin(handler.handle_apply_suggestion(transport, id, params))
         },
-        "required": ["directory"]
-      },
-      "outputSchema": {
-        "type": "object",
-        "properties": {
-          "success": {
-            "type": "boolean",
-            "description": "Whether the directory change was successful"
-          },
-          "directory": {
-            "type": "string",
-            "description": "New current directory"
-          }
+    });
+    tools.register(ToolDef {
+        name: "generate_diff",
+        description: "Generate diff between original and modified text",
+        input_schema: json!({
+          "type": "object",
+          "properties": {
+            "original": {
+              "type": "string",
+              "description": "Original text"
+            },
+            "modified": {
+              "type": "string",
+              "description": "Modified text"
+            },
+            "format": {
+              "type": "string",
+              "enum": ["unified", "side_by_side"],
+              "description": "unified (default), or side_by_side for the old and new text in two columns"
+            },
+            "width": {
+              "type": "integer",
+              "description": "Total line width of a side_by_side diff; longer lines wrap (default: 160)"
+            },
+            "inline": {
+              "type": "boolean",
+              "description": "In a unified diff, mark the words that changed within each replaced line as [-removed-] and {+added+} (default false)"
+            },
+            "ignore_whitespace": {
+              "type": "string",
+              "enum": ["none", "trailing", "all"],
+              "description": "Whitespace differences to leave out of the diff: none (default), trailing whitespace, or all changes in the amount of whitespace including indentation. Lines are shown normalized"
+            }
+          },
+          "required": ["original", "modified"]
+        }),
+        output_schema: Some(json!({
+          "type": "object",
+          "properties": {
+            "diff": {
+              "type": "string",
+              "description": "Diff between original and modified text, in the requested format"
+            }
+          },
+          "required": ["diff"]
+        })),
+        handler: |handler, transport, id, params| {
+            Box::pin(handler.handle_generate_diff(transport, id, params))
         },
-        "required": ["success", "directory"]
-      }
-    },
-    {
-      "name": "create_file",
-      "description": "Create a new file with the specified content",
-      "inputSchema": {
-        "type": "object",
-        "properties": {
-          "path": {
-            "type": "string",
-            "description": "Path to the file to create"
-          },
-          "content": {
-            "type": "string",
-            "description": "Content to write to the file"
-          }
+    });
+    tools.register(ToolDef {
+        name: "diff_against_backup",
+        description: "Show a diff between a backup of a file and its current content",
+        input_schema: json!({
+          "type": "object",
+          "properties": {
+            "path": {
+              "type": "string",
+              "description": "Path to the file to compare"
+            },
+            "index": {
+              "type": "integer",
+              "description": "Backup to compare against, 0 being the most recent (default: 0)"
+            },
+            "format": {
+              "type": "string",
+              "enum": ["unified", "side_by_side"],
+              "description": "unified (default), or side_by_side for the old and new text in two columns"
+            },
+            "width": {
+              "type": "integer",
+              "description": "Total line width of a side_by_side diff; longer lines wrap (default: 160)"
+            },
+            "inline": {
+              "type": "boolean",
+              "description": "In a unified diff, mark the words that changed within each replaced line as [-removed-] and {+added+} (default false)"
+            },
+            "ignore_whitespace": {
+              "type": "string",
+              "enum": ["none", "trailing", "all"],
+              "description": "Whitespace differences to leave out of the diff: none (default), trailing whitespace, or all changes in the amount of whitespace including indentation. Lines are shown normalized"
+            }
+          },
+          "required": ["path"]
+        }),
+        output_schema: Some(json!({
+          "type": "object",
+          "properties": {
+            "path": {
+              "type": "string",
+              "description": "Path to the compared file"
+            },
+            "backup": {
+              "type": "string",
+              "description": "Path to the backup that was compared"
+            },
+            "backup_count": {
+              "type": "integer",
+              "description": "Number of backups available for the file"
+            },
+            "diff": {
+              "type": "string",
+              "description": "Diff from the backup to the current content, in the requested format"
+            },
+            "message": {
+              "type": "string",
+              "description": "Explanation when no backups are available"
+            }
+          },
+          "required": ["path", "backup_count"]
+        })),
+        handler: |handler, transport, id, params| {
+            Box::pin(handler.handle_diff_against_backup(transport, id, params))
         },
-        "required": ["path", "content"]
-      },
-      "outputSchema": {
-        "type": "object",
-        "properties": {
-          "success": {
-            "type": "boolean",
-            "description": "Whether the file was created successfully"
-          },
-          "path": {
-            "type": "string",
-            "description": "Path to the created file"
-          }
+    });
+    tools.register(ToolDef {
+        name: "read_backup",
+        description: "Read the content of a backup of a file without restoring it, to see an earlier version while keeping the current one",
+        input_schema: json!({
+          "type": "object",
+          "properties": {
+            "path": {
+              "type": "string",
+              "description": "Path to the file whose backup to read"
+            },
+            "index": {
+              "type": "integer",
+              "description": "Backup to read, 0 being the most recent (default: 0)"
+            },
+            "timestamp": {
+              "type": "string",
+              "description": "Read the newest backup taken at or before this RFC 3339 time instead, e.g. 2024-05-01T12:00:00Z"
+            }
+          },
+          "required": ["path"]
+        }),
+        output_schema: Some(json!({
+          "type": "object",
+          "properties": {
+            "path": {
+              "type": "string",
+              "description": "Path to the file"
+            },
+            "backup": {
+              "type": "string",
+              "description": "Path to the backup that was read"
+            },
+            "backup_index": {
+              "type": "integer",
+              "description": "Index of the backup, 0 being the most recent"
+            },
+            "backup_count": {
+              "type": "integer",
+              "description": "Number of backups available for the file"
+            },
+            "timestamp": {
+              "type": "string",
+              "description": "When the backup was taken, in RFC 3339 format"
+            },
+            "content": {
+              "type": "string",
+              "description": "Content of the file as the backup holds it"
+            }
+          },
+          "required": ["path", "backup", "backup_index", "backup_count", "content"]
+        })),
+        handler: |handler, transport, id, params| {
+            Box::pin(handler.handle_read_backup(transport, id, params))
         },
-        "required": ["success", "path"]
-      }
-    },
-    {
-      "name": "rename_file",
-      "description": "Rename or move a file",
-      "inputSchema": {
-        "type": "object",
-        "properties": {
-          "from_path": {
-            "type": "string",
-            "description": "Original path of the file"
-          },
-          "to_path": {
-            "type": "string",
-            "description": "New path for the file"
+    });
+    tools.register(ToolDef {
+        name: "cleanup_whitespace",
+        description: "Tidy up whitespace in a file, or every file matching a glob: collapse runs of blank lines, trim trailing whitespace, and end the file with a newline. Changed files are backed up first. Trimming and the final newline follow the editor config and .editorconfig unless set here",
+        input_schema: json!({
+          "type": "object",
+          "properties": {
+            "path": {
+              "type": "string",
+              "description": "Path of the file to clean up"
+            },
+            "glob": {
+              "type": "string",
+              "description": "Glob matched against paths relative to the project root, such as src/**/*.rs, to clean up every matching file; used when path is not given"
+            },
+            "max_blank_lines": {
+              "type": "integer",
+              "description": "Longest run of blank lines to keep (default editor.max_blank_lines, 1)"
+            },
+            "trim_trailing_whitespace": {
+              "type": "boolean",
+              "description": "Remove whitespace at the end of lines"
+            },
+            "ensure_final_newline": {
+              "type": "boolean",
+              "description": "End the file with a newline"
+            },
+            "dry_run": {
+              "type": "boolean",
+              "description": "Only report what would change, without writing (default false)"
+            }
           }
+        }),
+        output_schema: Some(json!({
+          "type": "object",
+          "properties": {
+            "dry_run": {
+              "type": "boolean"
+            },
+            "files_checked": {
+              "type": "integer"
+            },
+            "files_changed": {
+              "type": "integer",
+              "description": "Number of files cleaned up, or that would be with dry_run"
+            },
+            "files": {
+              "type": "array",
+              "items": {
+                "type": "object"
+              },
+              "description": "Each file changed, with its path and a unified diff of the change"
+            },
+            "errors": {
+              "type": "array",
+              "items": {
+                "type": "object"
+              },
+              "description": "Files that couldn't be cleaned up, with path and error"
+            }
+          },
+          "required": ["files_checked", "files_changed", "files"]
+        })),
+        handler: |handler, transport, id, params| {
+            Box::pin(handler.handle_cleanup_whitespace(transport, id, params))
         },
-        "required": ["from_path", "to_path"]
-      },
-      "outputSchema": {
-        "type": "object",
-        "properties": {
-          "success": {
-            "type": "boolean",
-            "description": "Whether the file was renamed successfully"
-          },
-          "from_path": {
-            "type": "string",
-            "description": "Original path of the file"
-          },
-          "to_path": {
-            "type": "string",
-            "description": "New path of the file"
+    });
+    tools.register(ToolDef {
+        name: "normalize_project_line_endings",
+        description: "Convert every text file in the project to the configured line_endings (or the one .editorconfig sets for it), backing up each file changed. Excluded files are left alone",
+        input_schema: json!({
+          "type": "object",
+          "properties": {
+            "dry_run": {
+              "type": "boolean",
+              "description": "Only report which files would change, without writing (default false)"
+            }
           }
+        }),
+        output_schema: Some(json!({
+          "type": "object",
+          "properties": {
+            "line_endings": {
+              "type": ["string", "null"],
+              "description": "The configured line_endings"
+            },
+            "dry_run": {
+              "type": "boolean"
+            },
+            "files_checked": {
+              "type": "integer",
+              "description": "Number of text files looked at"
+            },
+            "files_changed": {
+              "type": "integer",
+              "description": "Number of files converted, or that would be with dry_run"
+            },
+            "files_skipped": {
+              "type": "integer",
+              "description": "Number of files with no line ending configured"
+            },
+            "changed": {
+              "type": "array",
+              "items": {
+                "type": "string"
+              },
+              "description": "Paths of the files converted, or that would be with dry_run"
+            },
+            "errors": {
+              "type": "array",
+              "items": {
+                "type": "object"
+              },
+              "description": "Files that couldn't be converted, with path and error"
+            }
+          },
+          "required": ["files_checked", "files_changed", "changed"]
+        })),
+        handler: |handler, transport, id, params| {
+            Box::pin(handler.handle_normalize_project_line_endings(transport, id, params))
         },
-        "required": ["success", "from_path", "to_path"]
-      }
-    },
-    {
-      "name": "delete_file",
-      "description": "Delete a file",
-      "inputSchema": {
-        "type": "object",
-        "properties": {
-          "path": {
-            "type": "string",
-            "description": "Path to the file to delete"
+    });
+    tools.register(ToolDef {
+        name: "purge_backups",
+        description: "Delete backups to reclaim space, for one file or every file: all of them, only those older than some days, or all but the latest few per file. Options combine, e.g. keep_latest 3 with older_than_days 30 keeps each file's 3 newest backups and deletes the rest if older than 30 days",
+        input_schema: json!({
+          "type": "object",
+          "properties": {
+            "path": {
+              "type": "string",
+              "description": "Path of the file whose backups to delete; every file's when not given"
+            },
+            "older_than_days": {
+              "type": "integer",
+              "description": "Only delete backups older than this many days"
+            },
+            "keep_latest": {
+              "type": "integer",
+              "description": "Keep this many of the newest backups of each file"
+            },
+            "all": {
+              "type": "boolean",
+              "description": "Delete every backup of the files in scope; required when neither older_than_days nor keep_latest is given"
+            }
           }
+        }),
+        output_schema: Some(json!({
+          "type": "object",
+          "properties": {
+            "path": {
+              "type": ["string", "null"],
+              "description": "The file whose backups were purged, or null for every file"
+            },
+            "removed": {
+              "type": "integer",
+              "description": "Number of backups deleted"
+            },
+            "bytes_freed": {
+              "type": "integer",
+              "description": "Total size of the backups deleted"
+            },
+            "files_affected": {
+              "type": "integer",
+              "description": "Number of files that lost backups"
+            }
+          },
+          "required": ["removed", "bytes_freed"]
+        })),
+        handler: |handler, transport, id, params| {
+            Box::pin(handler.handle_purge_backups(transport, id, params))
         },
-        "required": ["path"]
-      },
-      "outputSchema": {
-        "type": "object",
-        "properties": {
-          "success": {
-            "type": "boolean",
-            "description": "Whether the file was deleted successfully"
-          },
-          "path": {
-            "type": "string",
-            "description": "Path to the deleted file"
+    });
+    tools.register(ToolDef {
+        name: "create_backup",
+        description: "Back up files now, as a save point before a series of edits that can later be restored. A file unchanged since its latest backup keeps that backup instead of getting a duplicate",
+        input_schema: json!({
+          "type": "object",
+          "properties": {
+            "path": {
+              "type": "string",
+              "description": "Path of the file to back up"
+            },
+            "glob": {
+              "type": "string",
+              "description": "Glob matched against paths relative to the project root, such as src/**/*.rs, to back up every matching file; used when path is not given"
+            }
           }
+        }),
+        output_schema: Some(json!({
+          "type": "object",
+          "properties": {
+            "backups": {
+              "type": "array",
+              "items": {
+                "type": "object",
+                "properties": {
+                  "path": {
+                    "type": "string",
+                    "description": "Path of the backed up file"
+                  },
+                  "backup": {
+                    "type": "string",
+                    "description": "Path of the backup"
+                  },
+                  "created_at": {
+                    "type": "string",
+                    "description": "When the backup was written (RFC 3339)"
+                  },
+                  "error": {
+                    "type": "string",
+                    "description": "Why the file could not be backed up, in place of backup"
+                  }
+                }
+              },
+              "description": "One entry per file"
+            }
+          },
+          "required": ["backups"]
+        })),
+        handler: |handler, transport, id, params| {
+            Box::pin(handler.handle_create_backup(transport, id, params))
         },
-        "required": ["success", "path"]
-      }
+    });
+    tools.register(ToolDef {
+        name: "detect_language",
+        description: "Detect the language of a file from its name, extension, and shebang line",
+        input_schema: json!({
+          "type": "object",
+          "properties": {
+            "path": {
+              "type": "string",
+              "description": "Path to the file"
+            }
+          },
+          "required": ["path"]
+        }),
+        output_schema: Some(json!({
+          "type": "object",
+          "properties": {
+            "path": {
+              "type": "string",
+              "description": "Path to the file"
+            },
+            "mime_type": {
+              "type": "string",
+              "description": "MIME type implied by the file name"
+            },
+            "language": {
+              "type": ["string", "null"],
+              "description": "Detected language, or null if unknown"
+            },
+            "confidence": {
+              "type": ["string", "null"],
+              "description": "Confidence of the detection (high or medium)"
+            },
+            "source": {
+              "type": ["string", "null"],
+              "description": "What the detection was based on (filename, extension, or shebang)"
+            }
+          },
+          "required": ["path", "language"]
+        })),
+        handler: |handler, transport, id, params| {
+            Box::pin(handler.handle_detect_language(transport, id, params))
+        },
+    });
+    tools.register(ToolDef {
+        name: "test_replacement",
+        description: "Preview a regex replacement against sample text without touching any file. Uses the same matching rules as replace_matching_region, so a pattern and replacement can be checked before running them on real files",
+        input_schema: json!({
+          "type": "object",
+          "properties": {
+            "pattern": {
+              "type": "string",
+              "description": "Regular expression to match, in multi-line mode with . matching newlines"
+            },
+            "replacement": {
+              "type": "string",
+              "description": "Text to replace each match with, may contain $1-style backreferences"
+            },
+            "text": {
+              "type": "string",
+              "description": "Sample text to apply the replacement to"
+            },
+            "all": {
+              "type": "boolean",
+              "description": "Replace every match instead of only the first (default false)"
+            }
+          },
+          "required": ["pattern", "replacement", "text"]
+        }),
+        output_schema: Some(json!({
+          "type": "object",
+          "properties": {
+            "result": {
+              "type": "string",
+              "description": "The sample text after replacement"
+            },
+            "replacements": {
+              "type": "integer",
+              "description": "Number of matches replaced"
+            },
+            "matches": {
+              "type": "array",
+              "description": "Each replaced match with the text it expands to",
+              "items": {
+                "type": "object",
+                "properties": {
+                  "match": {
+                    "type": "string",
+                    "description": "Text matched by the pattern"
+                  },
+                  "expansion": {
+                    "type": "string",
+                    "description": "Replacement with backreferences filled in"
+                  }
+                }
+              }
+            }
+          },
+          "required": ["result", "replacements", "matches"]
+        })),
+        handler: |handler, transport, id, params| {
+            Box::pin(handler.handle_test_replacement(transport, id, params))
+        },
+    });
+    tools.register(ToolDef {
+        name: "insert_file_contents",
+        description: "Insert the content of one file into another, at a line or next to the first line matching an anchor. Both paths must be within the project. The target is backed up first",
+        input_schema: json!({
+          "type": "object",
+          "properties": {
+            "target": {
+              "type": "string",
+              "description": "Path to the file to insert into"
+            },
+            "source": {
+              "type": "string",
+              "description": "Path to the file whose content is inserted"
+            },
+            "line": {
+              "type": "integer",
+              "description": "1-based line to insert before; one past the last line appends. Used when no anchor is given"
+            },
+            "anchor": {
+              "type": "string",
+              "description": "Text to look for in each line of the target; the content goes after the first matching line"
+            },
+            "regex": {
+              "type": "boolean",
+              "description": "Treat anchor as a regular expression instead of literal text (default false)"
+            },
+            "before": {
+              "type": "boolean",
+              "description": "Insert before the anchor line instead of after it (default false)"
+            }
+          },
+          "required": ["target", "source"]
+        }),
+        output_schema: Some(json!({
+          "type": "object",
+          "properties": {
+            "success": {
+              "type": "boolean",
+              "description": "Whether the operation was successful"
+            },
+            "path": {
+              "type": "string",
+              "description": "Path to the edited file"
+            },
+            "line": {
+              "type": "integer",
+              "description": "1-based line number where the inserted content starts"
+            },
+            "lines_before": {
+              "type": "integer",
+              "description": "Number of lines before the edit"
+            },
+            "lines_after": {
+              "type": "integer",
+              "description": "Number of lines after the edit"
+            }
+          },
+          "required": ["success", "path", "line", "lines_after"]
+        })),
+        handler: |handler, transport, id, params| {
+            Box::pin(handler.handle_insert_file_contents(transport, id, params))
+        },
+    });
+    tools.register(ToolDef {
+        name: "insert_after_match",
+        description: "Insert content after the first line matching a pattern. Content without leading indentation takes on the indentation of the matched line. A backup is created first",
+        input_schema: json!({
+          "type": "object",
+          "properties": {
+            "path": {
+              "type": "string",
+              "description": "Path to the file to edit"
+            },
+            "pattern": {
+              "type": "string",
+              "description": "Text to look for in each line (a regular expression when regex is true)"
+            },
+            "content": {
+              "type": "string",
+              "description": "Content to insert"
+            },
+            "regex": {
+              "type": "boolean",
+              "description": "Treat pattern as a regular expression instead of literal text (default false)"
+            },
+            "unique": {
+              "type": "boolean",
+              "description": "Fail if the pattern matches more than one line (default false)"
+            }
+          },
+          "required": ["path", "pattern", "content"]
+        }),
+        output_schema: Some(json!({
+          "type": "object",
+          "properties": {
+            "success": {
+              "type": "boolean",
+              "description": "Whether the operation was successful"
+            },
+            "path": {
+              "type": "string",
+              "description": "Path to the edited file"
+            },
+            "line": {
+              "type": "integer",
+              "description": "1-based line number where the inserted content starts"
+            },
+            "lines_before": {
+              "type": "integer",
+              "description": "Number of lines before the edit"
+            },
+            "lines_after": {
+              "type": "integer",
+              "description": "Number of lines after the edit"
+            },
+            "bytes_changed": {
+              "type": "integer",
+              "description": "Size in bytes of the changed span"
+            }
+          },
+          "required": ["success", "path", "line"]
+        })),
+        handler: |handler, transport, id, params| {
+            Box::pin(handler.handle_insert_at_match(transport, id, params, true))
+        },
+    });
+    tools.register(ToolDef {
+        name: "insert_before_match",
+        description: "Insert content before the first line matching a pattern. Content without leading indentation takes on the indentation of the matched line. A backup is created first",
+        input_schema: json!({
+          "type": "object",
+          "properties": {
+            "path": {
+              "type": "string",
+              "description": "Path to the file to edit"
+            },
+            "pattern": {
+              "type": "string",
+              "description": "Text to look for in each line (a regular expression when regex is true)"
+            },
+            "content": {
+              "type": "string",
+              "description": "Content to insert"
+            },
+            "regex": {
+              "type": "boolean",
+              "description": "Treat pattern as a regular expression instead of literal text (default false)"
+            },
+            "unique": {
+              "type": "boolean",
+              "description": "Fail if the pattern matches more than one line (default false)"
+            }
+          },
+          "required": ["path", "pattern", "content"]
+        }),
+        output_schema: Some(json!({
+          "type": "object",
+          "properties": {
+            "success": {
+              "type": "boolean",
+              "description": "Whether the operation was successful"
+            },
+            "path": {
+              "type": "string",
+              "description": "Path to the edited file"
+            },
+            "line": {
+              "type": "integer",
+              "description": "1-based line number where the inserted content starts"
+            },
+            "lines_before": {
+              "type": "integer",
+              "description": "Number of lines before the edit"
+            },
+            "lines_after": {
+              "type": "integer",
+              "description": "Number of lines after the edit"
+            },
+            "bytes_changed": {
+              "type": "integer",
+              "description": "Size in bytes of the changed span"
+            }
+          },
+          "required": ["success", "path", "line"]
+        })),
+        handler: |handler, transport, id, params| {
+            Box::pin(handler.handle_insert_at_match(transport, id, params, false))
+        },
+    });
+    tools.register(ToolDef {
+        name: "replace_matching_region",
+        description: "Replace the first (or every) region of a file matching a regular expression. The pattern runs in multi-line mode with . matching newlines, so it can span lines; the replacement may refer to capture groups as $1 or ${name}",
+        input_schema: json!({
+          "type": "object",
+          "properties": {
+            "path": {
+              "type": "string",
+              "description": "Path to the file to edit"
+            },
+            "pattern": {
+              "type": "string",
+              "description": "Regular expression matching the region to replace"
+            },
+            "replacement": {
+              "type": "string",
+              "description": "Text to replace each match with, may contain $1-style backreferences"
+            },
+            "all": {
+              "type": "boolean",
+              "description": "Replace every match instead of only the first (default false)"
+            }
+          },
+          "required": ["path", "pattern", "replacement"]
+        }),
+        output_schema: Some(json!({
+          "type": "object",
+          "properties": {
+            "success": {
+              "type": "boolean",
+              "description": "Whether the operation was successful"
+            },
+            "path": {
+              "type": "string",
+              "description": "Path to the edited file"
+            },
+            "replacements": {
+              "type": "integer",
+              "description": "Number of regions replaced"
+            },
+            "ranges": {
+              "type": "array",
+              "items": {
+                "type": "object",
+                "properties": {
+                  "start_line": {
+                    "type": "integer",
+                    "description": "First line of the replaced region in the original file (1-based)"
+                  },
+                  "end_line": {
+                    "type": "integer",
+                    "description": "Last line of the replaced region in the original file (1-based)"
+                  }
+                }
+              },
+              "description": "Line ranges of the replaced regions"
+            }
+          },
+          "required": ["success", "path", "replacements", "ranges"]
+        })),
+        handler: |handler, transport, id, params| {
+            Box::pin(handler.handle_replace_matching_region(transport, id, params))
+        },
+    });
+    tools.register(ToolDef {
+        name: "edit_region",
+        description: "Replace a range of lines of a file: start_line through end_line, 1-based and inclusive, so lines 3 to 5 replaces exactly those three lines. Empty content deletes them. The file is backed up first",
+        input_schema: json!({
+          "type": "object",
+          "properties": {
+            "path": {
+              "type": "string",
+              "description": "Path to the file to edit"
+            },
+            "start_line": {
+              "type": "integer",
+              "description": "First line to replace (1-based)"
+            },
+            "end_line": {
+              "type": "integer",
+              "description": "Last line to replace (1-based, inclusive); past the end of the file means up to the last line"
+            },
+            "content": {
+              "type": "string",
+              "description": "Text to put in place of the lines, empty to delete them"
+            }
+          },
+          "required": ["path", "start_line", "end_line", "content"]
+        }),
+        output_schema: Some(json!({
+          "type": "object",
+          "properties": {
+            "success": {
+              "type": "boolean",
+              "description": "Whether the operation was successful"
+            },
+            "path": {
+              "type": "string",
+              "description": "Path to the edited file"
+            },
+            "lines_before": {
+              "type": "integer",
+              "description": "Number of lines in the file before the edit"
+            },
+            "lines_after": {
+              "type": "integer",
+              "description": "Number of lines in the file after the edit"
+            }
+          },
+          "required": ["success", "path"]
+        })),
+        handler: |handler, transport, id, params| {
+            Box::pin(handler.handle_edit_region(transport, id, params))
+        },
+    });
+    tools.register(ToolDef {
+        name: "join_lines",
+        description: "Join a range of lines of a file into one line: start_line through end_line, 1-based and inclusive. Lines after the first lose their leading indentation. The file is backed up first",
+        input_schema: json!({
+          "type": "object",
+          "properties": {
+            "path": {
+              "type": "string",
+              "description": "Path to the file to edit"
+            },
+            "start_line": {
+              "type": "integer",
+              "description": "First line to join (1-based)"
+            },
+            "end_line": {
+              "type": "integer",
+              "description": "Last line to join (1-based, inclusive)"
+            },
+            "separator": {
+              "type": "string",
+              "description": "Text put between the joined lines (default: a single space)"
+            }
+          },
+          "required": ["path", "start_line", "end_line"]
+        }),
+        output_schema: Some(json!({
+          "type": "object",
+          "properties": {
+            "success": {
+              "type": "boolean",
+              "description": "Whether the operation was successful"
+            },
+            "path": {
+              "type": "string",
+              "description": "Path to the edited file"
+            },
+            "diff": {
+              "type": "string",
+              "description": "Unified diff of the change"
+            }
+          },
+          "required": ["success", "path", "diff"]
+        })),
+        handler: |handler, transport, id, params| {
+            Box::pin(handler.handle_join_lines(transport, id, params))
+        },
+    });
+    tools.register(ToolDef {
+        name: "split_line",
+        description: "Split one line of a file in two: the first column characters stay on the line and the rest move to a new line below it. The file is backed up first",
+        input_schema: json!({
+          "type": "object",
+          "properties": {
+            "path": {
+              "type": "string",
+              "description": "Path to the file to edit"
+            },
+            "line": {
+              "type": "integer",
+              "description": "Line to split (1-based)"
+            },
+            "column": {
+              "type": "integer",
+              "description": "Number of characters kept on the line, from 0 up to its length"
+            }
+          },
+          "required": ["path", "line", "column"]
+        }),
+        output_schema: Some(json!({
+          "type": "object",
+          "properties": {
+            "success": {
+              "type": "boolean",
+              "description": "Whether the operation was successful"
+            },
+            "path": {
+              "type": "string",
+              "description": "Path to the edited file"
+            },
+            "diff": {
+              "type": "string",
+              "description": "Unified diff of the change"
+            }
+          },
+          "required": ["success", "path", "diff"]
+        })),
+        handler: |handler, transport, id, params| {
+            Box::pin(handler.handle_split_line(transport, id, params))
+        },
+    });
+    tools.register(ToolDef {
+        name: "multi_region_edit",
+        description: "Replace several line ranges of a file in one atomic edit. Every range refers to the file as it is before the call, so line numbers don't shift between edits; ranges may not overlap. The file is backed up once, and nothing is written if any edit is invalid",
+        input_schema: json!({
+          "type": "object",
+          "properties": {
+            "path": {
+              "type": "string",
+              "description": "Path to the file to edit"
+            },
+            "edits": {
+              "type": "array",
+              "items": {
+                "type": "object",
+                "properties": {
+                  "start": {
+                    "type": "integer",
+                    "description": "First line to replace (1-based)"
+                  },
+                  "end": {
+                    "type": "integer",
+                    "description": "Last line to replace (1-based, inclusive)"
+                  },
+                  "content": {
+                    "type": "string",
+                    "description": "Text to put in place of the lines, empty to delete them"
+                  }
+                },
+                "required": ["start", "end", "content"]
+              },
+              "description": "The regions to replace"
+            }
+          },
+          "required": ["path", "edits"]
+        }),
+        output_schema: Some(json!({
+          "type": "object",
+          "properties": {
+            "success": {
+              "type": "boolean",
+              "description": "Whether the operation was successful"
+            },
+            "path": {
+              "type": "string",
+              "description": "Path to the edited file"
+            },
+            "edits": {
+              "type": "array",
+              "items": {
+                "type": "object",
+                "properties": {
+                  "start": {
+                    "type": "integer",
+                    "description": "First replaced line in the original file (1-based)"
+                  },
+                  "end": {
+                    "type": "integer",
+                    "description": "Last replaced line in the original file (1-based)"
+                  },
+                  "lines_removed": {
+                    "type": "integer",
+                    "description": "Number of original lines replaced"
+                  },
+                  "lines_added": {
+                    "type": "integer",
+                    "description": "Number of lines the content added"
+                  },
+                  "new_start": {
+                    "type": "integer",
+                    "description": "Line the content starts at in the edited file (1-based), null if the edit only deleted lines"
+                  },
+                  "new_end": {
+                    "type": "integer",
+                    "description": "Line the content ends at in the edited file (1-based), null if the edit only deleted lines"
+                  },
+                  "status": {
+                    "type": "string",
+                    "description": "applied"
+                  }
+                }
+              },
+              "description": "Outcome of each edit, in the order given"
+            },
+            "diff": {
+              "type": "string",
+              "description": "Unified diff of the whole change"
+            }
+          },
+          "required": ["success", "path", "edits", "diff"]
+        })),
+        handler: |handler, transport, id, params| {
+            Box::pin(handler.handle_multi_region_edit(transport, id, params))
+        },
+    });
+    tools.register(ToolDef {
+        name: "summarize_file",
+        description: "Get a compact outline of a file without reading it whole: Markdown headings, top-level declarations for code, or the first non-blank lines for other files",
+        input_schema: json!({
+          "type": "object",
+          "properties": {
+            "path": {
+              "type": "string",
+              "description": "Path to the file to summarize"
+            }
+          },
+          "required": ["path"]
+        }),
+        output_schema: Some(json!({
+          "type": "object",
+          "properties": {
+            "path": {
+              "type": "string",
+              "description": "Path to the file"
+            },
+            "language": {
+              "type": ["string", "null"],
+              "description": "Detected language of the file"
+            },
+            "total_lines": {
+              "type": "integer",
+              "description": "Number of lines in the file"
+            },
+            "entries": {
+              "type": "array",
+              "items": {
+                "type": "object",
+                "properties": {
+                  "kind": {
+                    "type": "string",
+                    "description": "Kind of entry: heading, a declaration keyword such as fn or class, or line"
+                  },
+                  "title": {
+                    "type": "string",
+                    "description": "Heading text or declaration line"
+                  },
+                  "line": {
+                    "type": "integer",
+                    "description": "Line number (1-based)"
+                  },
+                  "level": {
+                    "type": "integer",
+                    "description": "Heading level, for Markdown headings"
+                  }
+                }
+              },
+              "description": "Outline entries in file order"
+            }
+          },
+          "required": ["path", "entries"]
+        })),
+        handler: |handler, transport, id, params| {
+            Box::pin(handler.handle_summarize_file(transport, id, params))
+        },
+    });
+    tools.register(ToolDef {
+        name: "path_info",
+        description: "Check whether a path exists and whether it is a file or directory, with its size and modification time",
+        input_schema: json!({
+          "type": "object",
+          "properties": {
+            "path": {
+              "type": "string",
+              "description": "Path to check"
+            }
+          },
+          "required": ["path"]
+        }),
+        output_schema: Some(json!({
+          "type": "object",
+          "properties": {
+            "path": {
+              "type": "string",
+              "description": "Path that was checked"
+            },
+            "exists": {
+              "type": "boolean",
+              "description": "Whether anything exists at the path"
+            },
+            "is_file": {
+              "type": "boolean",
+              "description": "Whether the path is a file"
+            },
+            "is_dir": {
+              "type": "boolean",
+              "description": "Whether the path is a directory"
+            },
+            "size": {
+              "type": "integer",
+              "description": "Size in bytes, if the path exists"
+            },
+            "modified": {
+              "type": ["string", "null"],
+              "description": "Last modification time, if the path exists"
+            },
+            "mime_type": {
+              "type": ["string", "null"],
+              "description": "MIME type of the file, if the path is a file"
+            }
+          },
+          "required": ["path", "exists", "is_file", "is_dir"]
+        })),
+        handler: |handler, transport, id, params| {
+            Box::pin(handler.handle_path_info(transport, id, params))
+        },
+    });
+    tools.register(ToolDef {
+        name: "touch",
+        description: "Update a file's modification time, creating it empty if it doesn't exist",
+        input_schema: json!({
+          "type": "object",
+          "properties": {
+            "path": {
+              "type": "string",
+              "description": "Path to the file"
+            },
+            "timestamp": {
+              "type": "string",
+              "description": "Optional RFC 3339 / ISO 8601 time to set, e.g. 2024-01-31T12:00:00Z (defaults to now)"
+            }
+          },
+          "required": ["path"]
+        }),
+        output_schema: Some(json!({
+          "type": "object",
+          "properties": {
+            "path": {
+              "type": "string",
+              "description": "Path to the file"
+            },
+            "created": {
+              "type": "boolean",
+              "description": "Whether the file was created"
+            },
+            "modified": {
+              "type": "string",
+              "description": "Modification time of the file after the update"
+            }
+          },
+          "required": ["path", "created", "modified"]
+        })),
+        handler: |handler, transport, id, params| {
+            Box::pin(handler.handle_touch(transport, id, params))
+        },
+    });
+    tools.register(ToolDef {
+        name: "get_permissions",
+        description: "Get the permission mode of a file (Unix only)",
+        input_schema: json!({
+          "type": "object",
+          "properties": {
+            "path": {
+              "type": "string",
+              "description": "Path to the file"
+            }
+          },
+          "required": ["path"]
+        }),
+        output_schema: Some(json!({
+          "type": "object",
+          "properties": {
+            "path": {
+              "type": "string",
+              "description": "Path to the file"
+            },
+            "mode": {
+              "type": "string",
+              "description": "Permission mode as an octal string, e.g. 755"
+            },
+            "executable": {
+              "type": "boolean",
+              "description": "Whether any execute bit is set"
+            }
+          },
+          "required": ["path", "mode", "executable"]
+        })),
+        handler: |handler, transport, id, params| {
+            Box::pin(handler.handle_get_permissions(transport, id, params))
+        },
+    });
+    tools.register(ToolDef {
+        name: "set_permissions",
+        description:
+            "Set the permission mode of a file (Unix only), e.g. to make a script executable",
+        input_schema: json!({
+          "type": "object",
+          "properties": {
+            "path": {
+              "type": "string",
+              "description": "Path to the file"
+            },
+            "mode": {
+              "type": "string",
+              "description": "Permission mode as an octal string, e.g. 755; setuid, setgid and sticky bits are refused"
+            }
+          },
+          "required": ["path", "mode"]
+        }),
+        output_schema: Some(json!({
+          "type": "object",
+          "properties": {
+            "path": {
+              "type": "string",
+              "description": "Path to the file"
+            },
+            "mode": {
+              "type": "string",
+              "description": "Permission mode after the change"
+            },
+            "executable": {
+              "type": "boolean",
+              "description": "Whether any execute bit is set"
+            }
+          },
+          "required": ["path", "mode", "executable"]
+        })),
+        handler: |handler, transport, id, params| {
+            Box::pin(handler.handle_set_permissions(transport, id, params))
+        },
+    });
+    tools.register(ToolDef {
+        name: "run_task",
+        description: "Run one of the project's configured tasks, such as its build or tests, in the project directory. Only task names listed in the tasks section of the mcedit config can run; arbitrary commands cannot. Output beyond the configured size keeps its beginning and end around a truncation marker. Given a progressToken, output is also streamed as progress notifications while the task runs",
+        input_schema: json!({
+          "type": "object",
+          "properties": {
+            "name": {
+              "type": "string",
+              "description": "Name of the task to run, e.g. test"
+            }
+          },
+          "required": ["name"]
+        }),
+        output_schema: Some(json!({
+          "type": "object",
+          "properties": {
+            "task": {
+              "type": "string",
+              "description": "Name of the task"
+            },
+            "command": {
+              "type": "string",
+              "description": "Command the task ran"
+            },
+            "success": {
+              "type": "boolean",
+              "description": "Whether the command exited with status 0"
+            },
+            "timed_out": {
+              "type": "boolean",
+              "description": "Whether the command ran past the timeout and was killed, rather than exiting"
+            },
+            "truncated": {
+              "type": "boolean",
+              "description": "Whether stdout or stderr was cut to the configured max_output_bytes"
+            },
+            "exit_code": {
+              "type": "integer",
+              "description": "Exit status of the command, null if it timed out or was killed by a signal"
+            },
+            "duration_ms": {
+              "type": "integer",
+              "description": "How long the command ran, in milliseconds"
+            },
+            "stdout": {
+              "type": "string",
+              "description": "Standard output of the command"
+            },
+            "stderr": {
+              "type": "string",
+              "description": "Standard error of the command"
+            }
+          },
+          "required": ["task", "success", "timed_out"]
+        })),
+        handler: |handler, transport, id, params| {
+            Box::pin(handler.handle_run_task(transport, id, params))
+        },
+    });
+    tools.register(ToolDef {
+        name: "capabilities",
+        description: "Report what this server can do as configured and run: transport, authentication, rate limiting, whether git is available, configured tasks, backup policy, and size limits",
+        input_schema: json!({
+          "type": "object",
+          "properties": {}
+        }),
+        output_schema: Some(json!({
+          "type": "object",
+          "properties": {
+            "server": {
+              "type": "object",
+              "description": "Server name and version"
+            },
+            "transport": {
+              "type": "string",
+              "enum": ["stdio", "http"],
+              "description": "Transport this client is connected through"
+            },
+            "read_only": {
+              "type": "boolean",
+              "description": "Whether tools that write are unavailable"
+            },
+            "auth_required": {
+              "type": "boolean",
+              "description": "Whether requests need a bearer token"
+            },
+            "rate_limit": {
+              "type": ["object", "null"],
+              "description": "Requests allowed per second and in a burst, or null when unlimited"
+            },
+            "git": {
+              "type": "object",
+              "description": "Whether git was found on PATH, and its version"
+            },
+            "formatters": {
+              "type": "object",
+              "description": "Whether external formatters are configured"
+            },
+            "tasks": {
+              "type": "array",
+              "items": {
+                "type": "string"
+              },
+              "description": "Names run_task accepts"
+            },
+            "external_changes": {
+              "type": "string",
+              "description": "How writes treat files changed on disk since they were read: off, error or warn"
+            },
+            "backups": {
+              "type": "object",
+              "description": "Backup policy: whether edits are backed up, where, and how many are kept for how long"
+            },
+            "limits": {
+              "type": "object",
+              "description": "Size and depth limits, in bytes and directory levels"
+            }
+          },
+          "required": ["server", "transport", "read_only", "limits"]
+        })),
+        handler: |handler, transport, id, _| {
+            Box::pin(handler.handle_capabilities(transport, id))
+        },
+    });
+    tools.register(ToolDef {
+        name: "get_config",
+        description: "Get the configuration the server is running with, after combining command line arguments, environment variables, the config file, and defaults. The auth token, if any, is redacted",
+        input_schema: json!({
+          "type": "object",
+          "properties": {}
+        }),
+        output_schema: Some(json!({
+          "type": "object",
+          "properties": {
+            "project": {
+              "type": "object",
+              "description": "Project settings, with directory set to the current project directory"
+            },
+            "editor": {
+              "type": "object",
+              "description": "Editor settings"
+            },
+            "backups": {
+              "type": "object",
+              "description": "Backup policy, with backup_directory set to where backups are kept"
+            },
+            "mcp": {
+              "type": "object",
+              "description": "Enabled tools and transport settings"
+            }
+          },
+          "required": ["project", "editor", "backups", "mcp"]
+        })),
+        handler: |handler, transport, id, _| {
+            Box::pin(handler.handle_get_config(transport, id))
+        },
+    });
+    tools.register(ToolDef {
+        name: "cache_stats",
+        description: "Show how the in-memory cache of read files is doing: its size and how many reads it served",
+        input_schema: json!({
+          "type": "object",
+          "properties": {}
+        }),
+        output_schema: Some(json!({
+          "type": "object",
+          "properties": {
+            "enabled": {
+              "type": "boolean",
+              "description": "Whether the cache is on (editor.read_cache_bytes above 0)"
+            },
+            "entries": {
+              "type": "integer",
+              "description": "Number of files cached"
+            },
+            "bytes": {
+              "type": "integer",
+              "description": "Bytes of content cached"
+            },
+            "max_bytes": {
+              "type": "integer",
+              "description": "Most bytes the cache holds before dropping the least recently read files"
+            },
+            "hits": {
+              "type": "integer",
+              "description": "Reads served from the cache"
+            },
+            "misses": {
+              "type": "integer",
+              "description": "Reads that went to disk because the file wasn't cached or had changed"
+            },
+            "evictions": {
+              "type": "integer",
+              "description": "Files dropped to make room for others"
+            }
+          },
+          "required": ["enabled", "entries", "bytes", "hits", "misses"]
+        })),
+        handler: |handler, transport, id, _| {
+            Box::pin(handler.handle_cache_stats(transport, id))
+        },
+    });
+    tools.register(ToolDef {
+        name: "create_checkpoint",
+        description: "Snapshot the project's files by content hash under a name, kept in the state directory, so get_file_tree_diff can later list what changed since. A checkpoint named session-start is taken when the server starts if project.checkpoint_session_start is set",
+        input_schema: json!({
+          "type": "object",
+          "properties": {
+            "name": {
+              "type": "string",
+              "description": "Name of the checkpoint (letters, digits, '-', '_' and '.'); an existing checkpoint of this name is replaced"
+            }
+          },
+          "required": ["name"]
+        }),
+        output_schema: Some(json!({
+          "type": "object",
+          "properties": {
+            "name": { "type": "string" },
+            "created_at": { "type": "string" },
+            "files": {
+              "type": "integer",
+              "description": "Number of files recorded"
+            }
+          },
+          "required": ["name", "created_at", "files"]
+        })),
+        handler: |handler, transport, id, params| {
+            Box::pin(handler.handle_create_checkpoint(transport, id, params))
+        },
+    });
+    tools.register(ToolDef {
+        name: "get_file_tree_diff",
+        description: "List the files added, modified and deleted since a checkpoint, by default the one taken when the session started. Works without git",
+        input_schema: json!({
+          "type": "object",
+          "properties": {
+            "checkpoint": {
+              "type": "string",
+              "description": "Checkpoint to compare against (default: session-start)"
+            }
+          }
+        }),
+        output_schema: Some(json!({
+          "type": "object",
+          "properties": {
+            "checkpoint": { "type": "string" },
+            "created_at": { "type": "string" },
+            "added": { "type": "array", "items": { "type": "string" } },
+            "modified": { "type": "array", "items": { "type": "string" } },
+            "deleted": { "type": "array", "items": { "type": "string" } }
+          },
+          "required": ["checkpoint", "created_at", "added", "modified", "deleted"]
+        })),
+        handler: |handler, transport, id, params| {
+            Box::pin(handler.handle_get_file_tree_diff(transport, id, params))
+        },
+    });
+    tools.register(ToolDef {
+        name: "clear_state",
+        description: "Forget the session state kept in the project's state directory (.mcedit by default): cached analyses and the audit log of tool calls",
+        input_schema: json!({
+          "type": "object",
+          "properties": {}
+        }),
+        output_schema: Some(json!({
+          "type": "object",
+          "properties": {
+            "state_directory": {
+              "type": "string",
+              "description": "Directory the state was kept in"
+            },
+            "cleared": {
+              "type": "object",
+              "description": "Number of analyses and audit entries that were cleared"
+            }
+          },
+          "required": ["state_directory", "cleared"]
+        })),
+        handler: |handler, transport, id, _| {
+            Box::pin(handler.handle_clear_state(transport, id))
+        },
+    });
+    tools.register(ToolDef {
+        name: "change_directory",
+        description: "Change the current working directory, creating it if missing. Fails if the directory can't be read, and reports whether it can be written to",
+        input_schema: json!({
+          "type": "object",
+          "properties": {
+            "directory": {
+              "type": "string",
+              "description": "New directory path"
+            }
+          },
+          "required": ["directory"]
+        }),
+        output_schema: Some(json!({
+          "type": "object",
+          "properties": {
+            "success": {
+              "type": "boolean",
+              "description": "Whether the directory change was successful"
+            },
+            "directory": {
+              "type": "string",
+              "description": "New current directory"
+            },
+            "writable": {
+              "type": "boolean",
+              "description": "Whether files can be created in the new directory"
+            }
+          },
+          "required": ["success", "directory", "writable"]
+        })),
+        handler: |handler, transport, id, params| {
+            Box::pin(handler.handle_change_directory(transport, id, params))
+        },
+    });
+    tools.register(ToolDef {
+        name: "create_file",
+        description: "Create a new file with the specified content",
+        input_schema: json!({
+          "type": "object",
+          "properties": {
+            "path": {
+              "type": "string",
+              "description": "Path to the file to create. If project.apply_default_extension is enabled and the name has no extension, project.default_extension is added"
+            },
+            "content": {
+              "type": "string",
+              "description": "Content to write to the file"
+            },
+            "create_dirs": {
+              "type": "boolean",
+              "description": "Create missing parent directories (default true)"
+            }
+          },
+          "required": ["path", "content"]
+        }),
+        output_schema: Some(json!({
+          "type": "object",
+          "properties": {
+            "success": {
+              "type": "boolean",
+              "description": "Whether the file was created successfully"
+            },
+            "path": {
+              "type": "string",
+              "description": "Path to the created file, including the default extension when one was added"
+            }
+          },
+          "required": ["success", "path"]
+        })),
+        handler: |handler, transport, id, params| {
+            Box::pin(handler.handle_create_file(transport, id, params))
+        },
+    });
+    tools.register(ToolDef {
+        name: "rename_file",
+        description: "Rename or move a file. Moving onto an existing directory keeps the file's name inside it",
+        input_schema: json!({
+          "type": "object",
+          "properties": {
+            "from_path": {
+              "type": "string",
+              "description": "Original path of the file"
+            },
+            "to_path": {
+              "type": "string",
+              "description": "New path for the file, or a directory to move it into"
+            },
+            "overwrite": {
+              "type": "boolean",
+              "description": "Replace an existing destination file, backing it up first; without it an existing destination is an error (default false)"
+            }
+          },
+          "required": ["from_path", "to_path"]
+        }),
+        output_schema: Some(json!({
+          "type": "object",
+          "properties": {
+            "success": {
+              "type": "boolean",
+              "description": "Whether the file was renamed successfully"
+            },
+            "from_path": {
+              "type": "string",
+              "description": "Original path of the file"
+            },
+            "to_path": {
+              "type": "string",
+              "description": "Path the file ended up at, inside to_path when it was a directory"
+            }
+          },
+          "required": ["success", "from_path", "to_path"]
+        })),
+        handler: |handler, transport, id, params| {
+            Box::pin(handler.handle_rename_file(transport, id, params))
+        },
+    });
+    tools.register(ToolDef {
+        name: "delete_file",
+        description: "Delete a file",
+        input_schema: json!({
+          "type": "object",
+          "properties": {
+            "path": {
+              "type": "string",
+              "description": "Path to the file to delete"
+            }
+          },
+          "required": ["path"]
+        }),
+        output_schema: Some(json!({
+          "type": "object",
+          "properties": {
+            "success": {
+              "type": "boolean",
+              "description": "Whether the file was deleted successfully"
+            },
+            "path": {
+              "type": "string",
+              "description": "Path to the deleted file"
+            }
+          },
+          "required": ["success", "path"]
+        })),
+        handler: |handler, transport, id, params| {
+            Box::pin(handler.handle_delete_file(transport, id, params))
+        },
+    });
+
+    tools
+}
+
+// Compiles a pattern for region replacement: multi-line mode with . matching
+// newlines, so a pattern can span lines
+fn region_regex(pattern: &str) -> Result<Regex, regex::Error> {
+    RegexBuilder::new(pattern)
+        .multi_line(true)
+        .dot_matches_new_line(true)
+        .build()
+}
+
+// The search described by a tool call's query, patterns, mode and exclude
+// arguments
+fn search_query_arg(params_val: &serde_json::Value) -> Result<SearchQuery, String> {
+    let args = params_val.get("arguments");
+    let string_list = |name: &str| -> Vec<String> {
+        args.and_then(|args| args.get(name))
+            .and_then(|v| v.as_array())
+            .map(|items| {
+                items
+                    .iter()
+                    .filter_map(|item| item.as_str().map(String::from))
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+
+    // Either a single query or a list of patterns combined by mode
+    let mut patterns = string_list("patterns");
+    if let Some(query) = args
+        .and_then(|args| args.get("query"))
+        .and_then(|q| q.as_str())
+    {
+        patterns.insert(0, query.to_string());
+    }
+    if patterns.is_empty() {
+        return Err("Missing required parameter: query or patterns".to_string());
+    }
+
+    let mode = match args
+        .and_then(|args| args.get("mode"))
+        .and_then(|m| m.as_str())
+    {
+        None | Some("all") => MatchMode::All,
+        Some("any") => MatchMode::Any,
+        Some(other) => {
+            return Err(format!(
+                "Invalid mode: {} (expected \"all\" or \"any\")",
+                other
+            ));
+        }
+    };
+
+    SearchQuery::new(&patterns, mode, &string_list("exclude")).map_err(|err| err.to_string())
+}
+
+// The cwd argument scoping a single call to a directory of the project
+fn cwd_arg(params_val: &serde_json::Value) -> Option<PathBuf> {
+    params_val
+        .get("arguments")
+        .and_then(|args| args.get("cwd"))
+        .and_then(|c| c.as_str())
+        .map(PathBuf::from)
+}
+
+// The ignore_whitespace argument of the diff tools
+fn whitespace_arg(args: &serde_json::Value) -> Result<WhitespaceMode, String> {
+    match args.get("ignore_whitespace").and_then(|w| w.as_str()) {
+        None => Ok(WhitespaceMode::None),
+        Some(value) => WhitespaceMode::parse(value).ok_or_else(|| {
+            format!(
+                "Invalid ignore_whitespace: {} (expected none, trailing or all)",
+                value
+            )
+        }),
+    }
+}
+
+// The format and width arguments of the diff tools
+fn diff_format_arg(args: &serde_json::Value) -> Result<DiffFormat, String> {
+    let width = args
+        .get("width")
+        .and_then(|w| w.as_u64())
+        .map(|w| w as usize);
+    let inline = args
+        .get("inline")
+        .and_then(|i| i.as_bool())
+        .unwrap_or(false);
+    match args.get("format").and_then(|f| f.as_str()) {
+        None => Ok(DiffFormat::Unified { inline }),
+        Some(value) => DiffFormat::parse(value, width, inline).ok_or_else(|| {
+            format!(
+                "Invalid format: {} (expected unified or side_by_side)",
+                value
+            )
+        }),
+    }
+}
+
+// The edits argument of multi_region_edit, converted from 1-based inclusive
+// line ranges to the editor's 0-based exclusive ones
+fn region_edits_arg(args: &serde_json::Value) -> Result<Vec<RegionEdit>, String> {
+    let edits = args
+        .get("edits")
+        .and_then(|e| e.as_array())
+        .ok_or_else(|| "Missing required parameter: edits".to_string())?;
+    if edits.is_empty() {
+        return Err("No edits given".to_string());
+    }
+
+    edits
+        .iter()
+        .enumerate()
+        .map(|(i, edit)| {
+            let line = |key: &str| {
+                edit.get(key)
+                    .and_then(|v| v.as_u64())
+                    .map(|v| v as usize)
+                    .ok_or_else(|| format!("Edit {} is missing {}", i + 1, key))
+            };
+            let start = line("start")?;
+            let end = line("end")?;
+            let content = edit
+                .get("content")
+                .and_then(|c| c.as_str())
+                .ok_or_else(|| format!("Edit {} is missing content", i + 1))?;
+
+            if start == 0 || end < start {
+                return Err(format!(
+                    "Edit {} has an invalid range: {}-{}",
+                    i + 1,
+                    start,
+                    end
+                ));
+            }
+
+            Ok(RegionEdit {
+                start_line: start - 1,
+                end_line: end,
+                content: content.to_string(),
+            })
+        })
+        .collect()
+}
+
+// Content read_many returns across all files when no cap is given
+const DEFAULT_READ_MANY_BYTES: usize = 256 * 1024;
+
+// Maximum number of items returned in a single page of a list response
+const LIST_PAGE_SIZE: usize = 50;
+
+// Returns the page of `items` starting at `cursor`, along with the cursor for
+// the next page when more items remain. Cursors are opaque to clients; we
+// encode them as the offset of the first item of the page.
+fn paginate(
+    items: Vec<Value>,
+    cursor: Option<&str>,
+) -> Result<(Vec<Value>, Option<String>), String> {
+    let offset = match cursor {
+        Some(c) => c
+            .parse::<usize>()
+            .map_err(|_| format!("Invalid cursor: {}", c))?,
+        None => 0,
+    };
+
+    if offset > items.len() {
+        return Err(format!("Invalid cursor: {}", offset));
+    }
+
+    let end = (offset + LIST_PAGE_SIZE).min(items.len());
+    let next_cursor = if end < items.len() {
+        Some(end.to_string())
+    } else {
+        None
+    };
+
+    let page = items.into_iter().skip(offset).take(end - offset).collect();
+    Ok((page, next_cursor))
+}
+
+pub struct McpHandler<'a> {
+    mcedit: &'a mut McEdit,
+    initialized: bool,
+    rate_limiter: Option<RateLimiter>,
+    heartbeat: Option<Heartbeat>,
+    tools: ToolRegistry,
+    // Whether the client offered roots/list when it initialized
+    client_lists_roots: bool,
+    // Id of the next request the server sends the client
+    next_request_id: u64,
+    // Id of the roots/list request awaiting the client's response
+    pending_roots_request: Option<u64>,
+}
+
+impl<'a> McpHandler<'a> {
+    pub fn new(mcedit: &'a mut McEdit) -> Self {
+        Self {
+            mcedit,
+            initialized: false,
+            rate_limiter: None,
+            heartbeat: None,
+            tools: tool_registry(),
+            client_lists_roots: false,
+            next_request_id: 1,
+            pending_roots_request: None,
+        }
+    }
+
+    // Limits how fast the connected client may send requests
+    pub fn with_rate_limiter(mut self, rate_limiter: Option<RateLimiter>) -> Self {
+        self.rate_limiter = rate_limiter;
+        self
+    }
+
+    // Logs a heartbeat at its interval while the server runs
+    pub fn with_heartbeat(mut self, heartbeat: Option<Heartbeat>) -> Self {
+        self.heartbeat = heartbeat;
+        self
+    }
+
+    pub async fn launch_mcp(&mut self, transport: &dyn Transport) -> anyhow::Result<()> {
+        let mut stream = transport.receive();
+
+        logging::info("MCP stdio transport server started. Waiting for JSON messages on stdin...");
+        logging::send_log_message(
+            transport,
+            logging::LogLevel::Info,
+            "McEdit server initialized and ready",
+        )
+        .await?;
+
+        let mut hangup = HangupListener::new();
+        let shutdown = shutdown_signal();
+        tokio::pin!(shutdown);
+
+        loop {
+            let msg_result = tokio::select! {
+                msg_result = stream.next() => match msg_result {
+                    Some(msg_result) => msg_result,
+                    None => break,
+                },
+                _ = &mut shutdown => {
+                    logging::info("Shutdown requested, stopping MCP server");
+                    break;
+                }
+                _ = hangup.recv() => {
+                    self.handle_reload(transport).await?;
+                    continue;
+                }
+                idle = next_beat(self.heartbeat.as_mut()) => {
+                    self.beat(transport, idle).await?;
+                    continue;
+                }
+            };
+
+            if let Some(heartbeat) = self.heartbeat.as_mut() {
+                heartbeat.message_received();
+            }
+
+            match msg_result {
+                Ok(Message::Request {
+                    id, method, params, ..
+                }) => {
+                    logging::log_both(
+                        transport,
+                        logging::LogLevel::Debug,
+                        &format!(
+                            "Got Request: id={}, method={}, params={:?}",
+                            id, method, params
+                        ),
+                    )
+                    .await?;
+
+                    if let Some(limiter) = self.rate_limiter.as_mut() {
+                        if !limiter.try_acquire() {
+                            logging::warn(&format!(
+                                "Rate limited request: id={}, method={}",
+                                id, method
+                            ));
+                            self.send_error_response(
+                                transport,
+                                id,
+                                JsonRpcErrorCode::RateLimited,
+                                "Rate limited: too many requests, slow down and retry".to_string(),
+                            )
+                            .await?;
+                            continue;
+                        }
+                    }
+
+                    // Handle initialization request first
+                    if method == "initialize" {
+                        self.client_lists_roots = params
+                            .as_ref()
+                            .and_then(|p| p.get("capabilities"))
+                            .and_then(|c| c.get("roots"))
+                            .is_some();
+                        if let Err(err) = self.handle_initialize(transport, id).await {
+                            logging::error(&format!("Error handling initialize request: {}", err));
+                        }
+                        self.initialized = true;
+                        continue;
+                    }
+
+                    // For all other requests, ensure we're initialized. Pings
+                    // may come at any time
+                    if !self.initialized && method != "ping" {
+                        self.send_error_response(
+                            transport,
+                            id,
+                            JsonRpcErrorCode::InvalidRequest,
+                            "Server not initialized. Send 'initialize' request first.".to_string(),
+                        )
+                        .await?;
+                        continue;
+                    }
+
+                    if let Err(err) = self.handle_request(transport, id, method, params).await {
+                        logging::error(&format!("Error handling request: {:?}", err));
+                        self.send_error_response(
+                            transport,
+                            id,
+                            JsonRpcErrorCode::InternalError,
+                            format!("Failed to handle request: {}", err),
+                        )
+                        .await?;
+                    }
+                }
+                Ok(Message::Notification { method, params, .. }) => {
+                    logging::log_both(
+                        transport,
+                        logging::LogLevel::Debug,
+                        &format!("Got Notification: method={}, params={:?}", method, params),
+                    )
+                    .await?;
+
+                    // Ask for the client's roots once it is ready, and again
+                    // whenever it says they changed
+                    let roots_may_have_changed = matches!(
+                        &*method,
+                        "notifications/initialized" | "notifications/roots/list_changed"
+                    );
+                    if roots_may_have_changed && self.client_lists_roots {
+                        self.request_roots(transport).await?;
+                    }
+                }
+                Ok(Message::Response {
+                    id, result, error, ..
+                }) => {
+                    logging::log_both(
+                        transport,
+                        logging::LogLevel::Debug,
+                        &format!(
+                            "Got Response: id={}, result={:?}, error={:?}",
+                            id, result, error
+                        ),
+                    )
+                    .await?;
+
+                    if self.pending_roots_request == Some(id) {
+                        self.pending_roots_request = None;
+                        self.apply_roots(result, error);
+                    }
+                }
+                Err(e) => {
+                    logging::error(&format!("Error receiving message: {:?}", e));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Logs that the server is alive and how long it has waited for a message
+    async fn beat(&self, transport: &dyn Transport, idle: Duration) -> anyhow::Result<()> {
+        let message = format!(
+            "Heartbeat: server running, no message for {}s",
+            idle.as_secs()
+        );
+        logging::info(&message);
+        if self.heartbeat.as_ref().is_some_and(|h| h.notify_client()) {
+            logging::send_log_message(transport, logging::LogLevel::Info, &message).await?;
+        }
+        Ok(())
+    }
+
+    // Sends the client a roots/list request; its response is matched up in
+    // the message loop
+    async fn request_roots(&mut self, transport: &dyn Transport) -> anyhow::Result<()> {
+        let id = self.next_request_id;
+        self.next_request_id += 1;
+        self.pending_roots_request = Some(id);
+
+        transport
+            .send(Message::Request {
+                jsonrpc: "2.0".to_string(),
+                method: "roots/list".to_string(),
+                id,
+                params: None,
+            })
+            .await?;
+        Ok(())
+    }
+
+    // Limits file access to the roots the client listed. Roots other than
+    // file:// URIs are ignored; a failed request leaves access as it was
+    fn apply_roots(&mut self, result: Option<Value>, error: Option<Value>) {
+        let roots = match result
+            .as_ref()
+            .and_then(|r| r.get("roots"))
+            .and_then(|r| r.as_array())
+        {
+            Some(roots) => roots,
+            None => {
+                logging::warn(&format!(
+                    "Client did not list its roots, file access is unchanged: {:?}",
+                    error
+                ));
+                return;
+            }
+        };
+
+        let roots = roots
+            .iter()
+            .filter_map(|root| root.get("uri").and_then(|u| u.as_str()))
+            .filter_map(path_from_file_uri)
+            .map(PathBuf::from)
+            .collect();
+        self.mcedit.set_client_roots(Some(roots));
+    }
+
+    // Re-reads the config file on SIGHUP, keeping the current settings if
+    // the new file can't be loaded
+    async fn handle_reload(&mut self, transport: &dyn Transport) -> anyhow::Result<()> {
+        logging::info("Received SIGHUP, reloading config");
+        match self.mcedit.reload_config() {
+            Ok(()) => {
+                logging::log_both(transport, logging::LogLevel::Info, "Config reloaded").await?;
+            }
+            Err(err) => {
+                logging::log_both(
+                    transport,
+                    logging::LogLevel::Error,
+                    &format!("Failed to reload config, keeping current settings: {}", err),
+                )
+                .await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn handle_request(
+        &mut self,
+        transport: &dyn Transport,
+        id: u64,
+        method: String,
+        params: Option<serde_json::Value>,
+    ) -> anyhow::Result<()> {
+        match &*method {
+            "initialize" => self.handle_initialize(transport, id).await?,
+            // Liveness check, answered with an empty result
+            "ping" => {
+                transport
+                    .send(Message::Response {
+                        jsonrpc: "2.0".to_string(),
+                        id,
+                        result: Some(json!({})),
+                        error: None,
+                    })
+                    .await?;
+            }
+            "tools/list" => self.handle_tools_list(transport, id, params).await?,
+            "tools/call" => {
+                if let Some(params_val) = params {
+                    self.handle_tools_call(transport, id, params_val).await?;
+                }
+            }
+            "resources/list" => self.handle_resources_list(transport, id, params).await?,
+            "resources/templates/list" => {
+                self.handle_resource_templates_list(transport, id).await?
+            }
+            "resources/read" => self.handle_resources_read(transport, id, params).await?,
+            "prompts/list" => self.handle_prompts_list(transport, id).await?,
+            _ => {
+                self.send_error_response(
+                    transport,
+                    id,
+                    JsonRpcErrorCode::MethodNotFound,
+                    format!("Method not found: {}", method),
+                )
+                .await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn handle_initialize(&self, transport: &dyn Transport, id: u64) -> anyhow::Result<()> {
+        logging::info("Handling initialize request");
+
+        // Create a properly structured capabilities response
+        let response = Message::Response {
+            jsonrpc: "2.0".to_string(),
+            id,
+            result: Some(json!({
+                "capabilities": Self::server_capabilities(),
+                "protocolVersion": "2024-11-05",
+                "serverInfo": {
+                    "name": "mcedit",
+                    "version": env!("CARGO_PKG_VERSION")
+                }
+            })),
+            error: None,
+        };
+
+        // Log the response for debugging
+        if let Ok(json_str) = serde_json::to_string_pretty(&response) {
+            logging::debug(&format!("Sending initialize response: {}", json_str));
+        }
+
+        // Send the response
+        match transport.send(response).await {
+            Ok(_) => {
+                logging::info("Initialize response sent successfully");
+                Ok(())
+            }
+            Err(e) => {
+                logging::error(&format!("Failed to send initialize response: {}", e));
+                Err(anyhow::anyhow!("Failed to send initialize response: {}", e))
+            }
+        }
+    }
+
+    // Capabilities advertised to clients, limited to what is implemented so
+    // strict clients don't attempt unsupported methods. Files are offered as
+    // resources through a template rather than listed, there are no prompts
+    // (prompts/list still answers, with an empty list), logging/setLevel
+    // isn't handled, and the tool list is fixed for the life of the server
+    fn server_capabilities() -> Value {
+        json!({
+            "resources": { "listChanged": false, "subscribe": false },
+            "tools": { "listChanged": false }
+        })
+    }
+
+    // Names of every tool the server provides
+    pub fn tool_names() -> Vec<String> {
+        tool_registry().names()
+    }
+
+    async fn handle_tools_list(
+        &self,
+        transport: &dyn Transport,
+        id: u64,
+        params: Option<serde_json::Value>,
+    ) -> anyhow::Result<()> {
+        let tools = self.tools.definitions();
+
+        let cursor = params
+            .as_ref()
+            .and_then(|p| p.get("cursor"))
+            .and_then(|c| c.as_str());
+
+        let (page, next_cursor) = match paginate(tools, cursor) {
+            Ok(result) => result,
+            Err(message) => {
+                return self
+                    .send_error_response(transport, id, JsonRpcErrorCode::InvalidParams, message)
+                    .await;
+            }
+        };
+
+        let mut result = json!({ "tools": page });
+        if let Some(next_cursor) = next_cursor {
+            result["nextCursor"] = json!(next_cursor);
+        }
+
+        let response = Message::Response {
+            jsonrpc: "2.0".to_string(),
+            id,
+            result: Some(result),
+            error: None,
+        };
+
+        transport.send(response).await?;
+        Ok(())
+    }
+
+    async fn handle_tools_call(
+        &mut self,
+        transport: &dyn Transport,
+        id: u64,
+        params_val: serde_json::Value,
+    ) -> anyhow::Result<()> {
+        let name = params_val
+            .get("name")
+            .and_then(|v| v.as_str())
+            .unwrap_or("");
+
+        logging::info(&format!("Handling tools/call for tool: {}", name));
+
+        let path = params_val
+            .get("arguments")
+            .and_then(|args| args.get("path"))
+            .and_then(|p| p.as_str());
+        self.mcedit.record_tool_call(name, path);
+
+        match self.tools.get(name).map(|tool| tool.handler) {
+            Some(handler) => handler(self, transport, id, &params_val).await?,
+            None => {
+                self.send_error_response(
+                    transport,
+                    id,
+                    JsonRpcErrorCode::MethodNotFound,
+                    format!("Tool not found: {}", name),
+                )
+                .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn handle_resolve_path(
+        &self,
+        transport: &dyn Transport,
+        id: u64,
+        params_val: &serde_json::Value,
+    ) -> anyhow::Result<()> {
+        let path = match params_val
+            .get("arguments")
+            .and_then(|args| args.get("path"))
+            .and_then(|p| p.as_str())
+        {
+            Some(p) => p,
+            None => {
+                return self
+                    .send_error_response(
+                        transport,
+                        id,
+                        JsonRpcErrorCode::InvalidParams,
+                        "Missing required parameter: path".to_string(),
+                    )
+                    .await;
+            }
+        };
+
+        match self
+            .mcedit
+            .resolve_path(path, cwd_arg(params_val).as_deref())
+        {
+            Ok(result) => {
+                let obj_as_str = serde_json::to_string(&result)?;
+                self.send_text_response(transport, id, &obj_as_str).await?;
+            }
+            Err(err) => {
+                self.send_error_response(
+                    transport,
+                    id,
+                    JsonRpcErrorCode::InvalidPath,
+                    format!("Path rejected: {}", err),
+                )
+                .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn handle_read_file(
+        &self,
+        transport: &dyn Transport,
+        id: u64,
+        params_val: &serde_json::Value,
+    ) -> anyhow::Result<()> {
+        // Get path parameter
+        let path_str = match params_val
+            .get("arguments")
+            .and_then(|args| args.get("path"))
+            .and_then(|p| p.as_str())
+        {
+            Some(p) => p,
+            None => {
+                return self
+                    .send_error_response(
+                        transport,
+                        id,
+                        JsonRpcErrorCode::InvalidParams,
+                        "Missing required parameter: path".to_string(),
+                    )
+                    .await;
+            }
+        };
+
+        let path = match self
+            .mcedit
+            .path_in(cwd_arg(params_val).as_deref(), Path::new(path_str))
+        {
+            Ok(path) => path,
+            Err(err) => {
+                return self
+                    .send_error_response(
+                        transport,
+                        id,
+                        JsonRpcErrorCode::InvalidParams,
+                        format!("Invalid cwd: {}", err),
+                    )
+                    .await;
+            }
+        };
+
+        let budget_tokens = params_val
+            .get("arguments")
+            .and_then(|args| args.get("budget_tokens"))
+            .and_then(|b| b.as_u64());
+
+        // Tab expansion only changes the returned content, never the file
+        let arg_flag = |name: &str| {
+            params_val
+                .get("arguments")
+                .and_then(|args| args.get(name))
+                .and_then(|v| v.as_bool())
+                .unwrap_or(false)
+        };
+        let expand_all_tabs = arg_flag("expand_all_tabs");
+        let expand_tabs = expand_all_tabs || arg_flag("expand_tabs");
+        let tab_size = params_val
+            .get("arguments")
+            .and_then(|args| args.get("tab_size"))
+            .and_then(|t| t.as_u64())
+            .map(|t| t as usize);
+        let display = |content: &str| {
+            if expand_tabs {
+                self.mcedit.expand_tabs(content, tab_size, expand_all_tabs)
+            } else {
+                content.to_string()
+            }
+        };
+
+        if let Some(budget_tokens) = budget_tokens {
+            match self
+                .mcedit
+                .read_file_within_budget(&path, budget_tokens as usize)
+                .await
+            {
+                Ok(mut result) => {
+                    if let Some(content) = result.get("content").and_then(|c| c.as_str()) {
+                        result["content"] = json!(display(content));
+                    }
+                    let obj_as_str = serde_json::to_string(&result)?;
+                    self.send_text_response(transport, id, &obj_as_str).await?;
+                }
+                Err(err) => {
+                    self.send_error_response(
+                        transport,
+                        id,
+                        JsonRpcErrorCode::InternalError,
+                        format!("Failed to read file: {}", err),
+                    )
+                    .await?;
+                }
+            }
+            return Ok(());
+        }
+
+        // Read the file
+        match self.mcedit.read_file(&path).await {
+            Ok(content) => {
+                let result_json = json!({
+                    "content": display(&content),
+                    "path": to_display_path(&path)
+                });
+                let obj_as_str = serde_json::to_string(&result_json)?;
+                self.send_text_response(transport, id, &obj_as_str).await?;
+            }
+            Err(err) => {
+                self.send_error_response(
+                    transport,
+                    id,
+                    JsonRpcErrorCode::InternalError,
+                    format!("Failed to read file: {}", err),
+                )
+                .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn handle_get_line(
+        &self,
+        transport: &dyn Transport,
+        id: u64,
+        params_val: &serde_json::Value,
+    ) -> anyhow::Result<()> {
+        let args = params_val.get("arguments");
+
+        let path_str = match args
+            .and_then(|args| args.get("path"))
+            .and_then(|p| p.as_str())
+        {
+            Some(p) => p,
+            None => {
+                return self
+                    .send_error_response(
+                        transport,
+                        id,
+                        JsonRpcErrorCode::InvalidParams,
+                        "Missing required parameter: path".to_string(),
+                    )
+                    .await;
+            }
+        };
+
+        let line = match args
+            .and_then(|args| args.get("line"))
+            .and_then(|l| l.as_u64())
+        {
+            Some(l) => l as usize,
+            None => {
+                return self
+                    .send_error_response(
+                        transport,
+                        id,
+                        JsonRpcErrorCode::InvalidParams,
+                        "Missing required parameter: line".to_string(),
+                    )
+                    .await;
+            }
+        };
+
+        let path = PathBuf::from(path_str);
+
+        match self.mcedit.read_line(&path, line).await {
+            Ok((content, line_count)) => {
+                let result_json = json!({
+                    "path": to_display_path(&path),
+                    "line": line,
+                    "content": content,
+                    "line_count": line_count
+                });
+                let obj_as_str = serde_json::to_string(&result_json)?;
+                self.send_text_response(transport, id, &obj_as_str).await?;
+            }
+            Err(err) => {
+                self.send_error_response(
+                    transport,
+                    id,
+                    JsonRpcErrorCode::InternalError,
+                    format!("Failed to read line: {}", err),
+                )
+                .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn handle_file_hash(
+        &self,
+        transport: &dyn Transport,
+        id: u64,
+        params_val: &serde_json::Value,
+    ) -> anyhow::Result<()> {
+        let args = params_val.get("arguments");
+
+        let path_str = match args
+            .and_then(|args| args.get("path"))
+            .and_then(|p| p.as_str())
+        {
+            Some(p) => p,
+            None => {
+                return self
+                    .send_error_response(
+                        transport,
+                        id,
+                        JsonRpcErrorCode::InvalidParams,
+                        "Missing required parameter: path".to_string(),
+                    )
+                    .await;
+            }
+        };
+
+        let algorithm = match args
+            .and_then(|args| args.get("algorithm"))
+            .and_then(|a| a.as_str())
+        {
+            Some(name) => match HashAlgorithm::parse(name) {
+                Some(algorithm) => algorithm,
+                None => {
+                    return self
+                        .send_error_response(
+                            transport,
+                            id,
+                            JsonRpcErrorCode::InvalidParams,
+                            format!(
+                                "Unknown algorithm: {} (expected sha256, md5 or blake3)",
+                                name
+                            ),
+                        )
+                        .await;
+                }
+            },
+            None => HashAlgorithm::default(),
+        };
+
+        let path = PathBuf::from(path_str);
+
+        match self.mcedit.file_hash(&path, algorithm).await {
+            Ok((hash, size)) => {
+                let result_json = json!({
+                    "path": to_display_path(&path),
+                    "algorithm": algorithm.name(),
+                    "hash": hash,
+                    "size": size
+                });
+                let obj_as_str = serde_json::to_string(&result_json)?;
+                self.send_text_response(transport, id, &obj_as_str).await?;
+            }
+            Err(err) => {
+                self.send_error_response(
+                    transport,
+                    id,
+                    JsonRpcErrorCode::InternalError,
+                    format!("Failed to hash file: {}", err),
+                )
+                .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn handle_read_many(
+        &self,
+        transport: &dyn Transport,
+        id: u64,
+        params_val: &serde_json::Value,
+    ) -> anyhow::Result<()> {
+        let args = params_val.get("arguments");
+        let paths = args
+            .and_then(|args| args.get("paths"))
+            .and_then(|p| p.as_array())
+            .map(|paths| {
+                paths
+                    .iter()
+                    .filter_map(|p| p.as_str().map(PathBuf::from))
+                    .collect::<Vec<_>>()
+            });
+        let glob = args
+            .and_then(|args| args.get("glob"))
+            .and_then(|g| g.as_str());
+
+        let paths = match (paths, glob) {
+            (Some(paths), _) => paths,
+            (None, Some(glob)) => match self.mcedit.list_files_matching_glob(glob).await {
+                Ok(paths) => paths,
+                Err(err) => {
+                    return self
+                        .send_error_response(
+                            transport,
+                            id,
+                            JsonRpcErrorCode::InvalidParams,
+                            err.to_string(),
+                        )
+                        .await;
+                }
+            },
+            (None, None) => {
+                return self
+                    .send_error_response(
+                        transport,
+                        id,
+                        JsonRpcErrorCode::InvalidParams,
+                        "Missing required parameter: paths or glob".to_string(),
+                    )
+                    .await;
+            }
+        };
+
+        let max_total_bytes = args
+            .and_then(|args| args.get("max_total_bytes"))
+            .and_then(|m| m.as_u64())
+            .map(|m| m as usize)
+            .unwrap_or(DEFAULT_READ_MANY_BYTES);
+
+        let result = self.mcedit.read_many(&paths, max_total_bytes).await;
+        let obj_as_str = serde_json::to_string(&result)?;
+        self.send_text_response(transport, id, &obj_as_str).await?;
+
+        Ok(())
+    }
+
+    async fn handle_write_file(
+        &self,
+        transport: &dyn Transport,
+        id: u64,
+        params_val: &serde_json::Value,
+    ) -> anyhow::Result<()> {
+        // Get path and content parameters
+        let args = match params_val.get("arguments") {
+            Some(a) => a,
+            None => {
+                return self
+                    .send_error_response(
+                        transport,
+                        id,
+                        JsonRpcErrorCode::InvalidParams,
+                        "Missing required arguments".to_string(),
+                    )
+                    .await;
+            }
+        };
+
+        let path_str = match args.get("path").and_then(|p| p.as_str()) {
+            Some(p) => p,
+            None => {
+                return self
+                    .send_error_response(
+                        transport,
+                        id,
+                        JsonRpcErrorCode::InvalidParams,
+                        "Missing required parameter: path".to_string(),
+                    )
+                    .await;
+            }
+        };
+
+        let content = match args.get("content").and_then(|c| c.as_str()) {
+            Some(c) => c,
+            None => {
+                return self
+                    .send_error_response(
+                        transport,
+                        id,
+                        JsonRpcErrorCode::InvalidParams,
+                        "Missing required parameter: content".to_string(),
+                    )
+                    .await;
+            }
+        };
+
+        let create_dirs = args
+            .get("create_dirs")
+            .and_then(|c| c.as_bool())
+            .unwrap_or(false);
+
+        let path = PathBuf::from(path_str);
+
+        // Write to the file
+        match self.mcedit.write_file(&path, content, create_dirs).await {
+            Ok(summary) => {
+                let result_json = json!({
+                    "success": true,
+                    "path": to_display_path(&path),
+                    "lines_before": summary.lines_before,
+                    "lines_after": summary.lines_after,
+                    "bytes_changed": summary.bytes_changed
+                });
+                let obj_as_str = serde_json::to_string(&result_json)?;
+                self.send_text_response(transport, id, &obj_as_str).await?;
+            }
+            Err(err) => {
+                self.send_error_response(
+                    transport,
+                    id,
+                    JsonRpcErrorCode::InternalError,
+                    format!("Failed to write file: {}", err),
+                )
+                .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn handle_list_files(
+        &self,
+        transport: &dyn Transport,
+        id: u64,
+        params_val: &serde_json::Value,
+    ) -> anyhow::Result<()> {
+        // Get optional pattern parameter
+        let pattern = params_val
+            .get("arguments")
+            .and_then(|args| args.get("pattern"))
+            .and_then(|p| p.as_str());
+
+        let with_metadata = params_val
+            .get("arguments")
+            .and_then(|args| args.get("with_metadata"))
+            .and_then(|w| w.as_bool())
+            .unwrap_or(false);
+
+        let sort = match params_val
+            .get("arguments")
+            .and_then(|args| args.get("sort"))
+            .and_then(|s| s.as_str())
+        {
+            None => FileSort::Name,
+            Some(value) => match FileSort::parse(value) {
+                Some(sort) => sort,
+                None => {
+                    return self
+                        .send_error_response(
+                            transport,
+                            id,
+                            JsonRpcErrorCode::InvalidParams,
+                            format!("Invalid sort: {} (expected name, mtime or size)", value),
+                        )
+                        .await;
+                }
+            },
+        };
+
+        let reverse = params_val
+            .get("arguments")
+            .and_then(|args| args.get("reverse"))
+            .and_then(|r| r.as_bool())
+            .unwrap_or(false);
+
+        let max_results = params_val
+            .get("arguments")
+            .and_then(|args| args.get("max_results"))
+            .and_then(|m| m.as_u64())
+            .map(|m| m as usize)
+            .unwrap_or(DEFAULT_LIST_MAX_RESULTS);
+
+        let cwd = cwd_arg(params_val);
+
+        if with_metadata {
+            match self
+                .mcedit
+                .list_files_with_metadata(pattern, sort, reverse, cwd.as_deref(), Some(max_results))
+                .await
+            {
+                Ok((files, truncated)) => {
+                    let result_json = json!({ "files": files, "truncated": truncated });
+                    let obj_as_str = serde_json::to_string(&result_json)?;
+                    self.send_text_response(transport, id, &obj_as_str).await?;
+                }
+                Err(err) => {
+                    self.send_error_response(
+                        transport,
+                        id,
+                        JsonRpcErrorCode::InternalError,
+                        format!("Failed to list files: {}", err),
+                    )
+                    .await?;
+                }
+            }
+            return Ok(());
+        }
+
+        // List files
+        match self
+            .mcedit
+            .list_files(pattern, sort, reverse, cwd.as_deref(), Some(max_results))
+            .await
+        {
+            Ok((files, truncated)) => {
+                // Convert file paths to strings
+                let file_strings: Vec<String> = files.iter().map(|p| to_display_path(p)).collect();
+
+                let result_json = json!({ "files": file_strings, "truncated": truncated });
+                let obj_as_str = serde_json::to_string(&result_json)?;
+                self.send_text_response(transport, id, &obj_as_str).await?;
+            }
+            Err(err) => {
+                self.send_error_response(
+                    transport,
+                    id,
+                    JsonRpcErrorCode::InternalError,
+                    format!("Failed to list files: {}", err),
+                )
+                .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn handle_directory_tree(
+        &self,
+        transport: &dyn Transport,
+        id: u64,
+        params_val: &serde_json::Value,
+    ) -> anyhow::Result<()> {
+        let depth = params_val
+            .get("arguments")
+            .and_then(|args| args.get("depth"))
+            .and_then(|d| d.as_u64())
+            .map(|d| d as usize)
+            .unwrap_or(DEFAULT_TREE_DEPTH);
+
+        let dirs_only = params_val
+            .get("arguments")
+            .and_then(|args| args.get("dirs_only"))
+            .and_then(|d| d.as_bool())
+            .unwrap_or(false);
+
+        let max_entries = params_val
+            .get("arguments")
+            .and_then(|args| args.get("max_entries"))
+            .and_then(|m| m.as_u64())
+            .map(|m| m as usize)
+            .unwrap_or(DEFAULT_TREE_ENTRIES);
+
+        let cwd = cwd_arg(params_val);
+
+        match self
+            .mcedit
+            .directory_tree(depth, dirs_only, max_entries, cwd.as_deref())
+            .await
+        {
+            Ok(tree) => {
+                let obj_as_str = serde_json::to_string(&tree)?;
+                self.send_text_response(transport, id, &obj_as_str).await?;
+            }
+            Err(err) => {
+                self.send_error_response(
+                    transport,
+                    id,
+                    JsonRpcErrorCode::InternalError,
+                    format!("Failed to build directory tree: {}", err),
+                )
+                .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn handle_search_files(
+        &self,
+        transport: &dyn Transport,
+        id: u64,
+        params_val: &serde_json::Value,
+    ) -> anyhow::Result<()> {
+        let query = match search_query_arg(params_val) {
+            Ok(query) => query,
+            Err(message) => {
+                return self
+                    .send_error_response(transport, id, JsonRpcErrorCode::InvalidParams, message)
+                    .await;
+            }
+        };
+
+        let usize_arg = |name: &str| {
+            params_val
+                .get("arguments")
+                .and_then(|args| args.get(name))
+                .and_then(|v| v.as_u64())
+                .map(|v| v as usize)
+        };
+        let str_arg = |name: &str| {
+            params_val
+                .get("arguments")
+                .and_then(|args| args.get(name))
+                .and_then(|v| v.as_str())
+        };
+        let options = SearchOptions {
+            path: str_arg("path").map(PathBuf::from),
+            path_glob: str_arg("path_glob").map(str::to_string),
+            max_line_preview: usize_arg("max_line_preview"),
+            max_matches_per_file: usize_arg("max_matches_per_file"),
+            max_results: usize_arg("max_results"),
+        };
+
+        let budget_tokens = usize_arg("budget_tokens");
+
+        let grep_output = match params_val
+            .get("arguments")
+            .and_then(|args| args.get("output"))
+            .and_then(|o| o.as_str())
+        {
+            None | Some("json") => false,
+            Some("grep") => true,
+            Some(other) => {
+                return self
+                    .send_error_response(
+                        transport,
+                        id,
+                        JsonRpcErrorCode::InvalidParams,
+                        format!("Invalid output: {} (expected json or grep)", other),
+                    )
+                    .await;
+            }
+        };
+
+        let cwd = cwd_arg(params_val);
+
+        // Search files
+        let search = match budget_tokens {
+            Some(budget_tokens) => {
+                self.mcedit
+                    .search_files_within_budget(&query, &options, budget_tokens, cwd.as_deref())
+                    .await
+            }
+            None => {
+                self.mcedit
+                    .search_files(&query, &options, cwd.as_deref())
+                    .await
+            }
+        };
+
+        match search {
+            Ok(results) => {
+                let results = if grep_output {
+                    into_grep_output(results)
+                } else {
+                    results
+                };
+                let obj_as_str = serde_json::to_string(&results)?;
+                self.send_text_response(transport, id, &obj_as_str).await?;
+            }
+            Err(err) => {
+                self.send_error_response(
+                    transport,
+                    id,
+                    JsonRpcErrorCode::InternalError,
+                    format!("Failed to search files: {}", err),
+                )
+                .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn handle_replace_in_files(
+        &self,
+        transport: &dyn Transport,
+        id: u64,
+        params_val: &serde_json::Value,
+    ) -> anyhow::Result<()> {
+        let query = match search_query_arg(params_val) {
+            Ok(query) => query,
+            Err(message) => {
+                return self
+                    .send_error_response(transport, id, JsonRpcErrorCode::InvalidParams, message)
+                    .await;
+            }
+        };
+
+        let args = params_val.get("arguments");
+        let str_arg = |name: &str| {
+            args.and_then(|args| args.get(name))
+                .and_then(|v| v.as_str())
+        };
+
+        let replacement = match str_arg("replacement") {
+            Some(replacement) => replacement,
+            None => {
+                return self
+                    .send_error_response(
+                        transport,
+                        id,
+                        JsonRpcErrorCode::InvalidParams,
+                        "Missing required parameter: replacement".to_string(),
+                    )
+                    .await;
+            }
+        };
+
+        let options = SearchOptions {
+            path: str_arg("path").map(PathBuf::from),
+            path_glob: str_arg("path_glob").map(str::to_string),
+            ..SearchOptions::default()
+        };
+
+        let dry_run = args
+            .and_then(|args| args.get("dry_run"))
+            .and_then(|d| d.as_bool())
+            .unwrap_or(false);
+
+        let cwd = cwd_arg(params_val);
+
+        match self
+            .mcedit
+            .replace_in_files(&query, &options, replacement, dry_run, cwd.as_deref())
+            .await
+        {
+            Ok(result) => {
+                let obj_as_str = serde_json::to_string(&result)?;
+                self.send_text_response(transport, id, &obj_as_str).await?;
+            }
+            Err(err) => {
+                self.send_error_response(
+                    transport,
+                    id,
+                    JsonRpcErrorCode::InternalError,
+                    format!("Failed to replace in files: {}", err),
+                )
+                .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn handle_count_matches(
+        &self,
+        transport: &dyn Transport,
+        id: u64,
+        params_val: &serde_json::Value,
+    ) -> anyhow::Result<()> {
+        let query = match search_query_arg(params_val) {
+            Ok(query) => query,
+            Err(message) => {
+                return self
+                    .send_error_response(transport, id, JsonRpcErrorCode::InvalidParams, message)
+                    .await;
+            }
+        };
+
+        match self.mcedit.count_matches(&query).await {
+            Ok(counts) => {
+                let obj_as_str = serde_json::to_string(&counts)?;
+                self.send_text_response(transport, id, &obj_as_str).await?;
+            }
+            Err(err) => {
+                self.send_error_response(
+                    transport,
+                    id,
+                    JsonRpcErrorCode::InternalError,
+                    format!("Failed to count matches: {}", err),
+                )
+                .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn handle_find_references(
+        &self,
+        transport: &dyn Transport,
+        id: u64,
+        params_val: &serde_json::Value,
+    ) -> anyhow::Result<()> {
+        // Get identifier parameter
+        let identifier = match params_val
+            .get("arguments")
+            .and_then(|args| args.get("identifier"))
+            .and_then(|i| i.as_str())
+        {
+            Some(i) => i,
+            None => {
+                return self
+                    .send_error_response(
+                        transport,
+                        id,
+                        JsonRpcErrorCode::InvalidParams,
+                        "Missing required parameter: identifier".to_string(),
+                    )
+                    .await;
+            }
+        };
+
+        // Word boundaries only make sense around word characters
+        if identifier.is_empty() || !identifier.chars().all(|c| c.is_alphanumeric() || c == '_') {
+            return self
+                .send_error_response(
+                    transport,
+                    id,
+                    JsonRpcErrorCode::InvalidParams,
+                    format!(
+                        "Invalid identifier '{}': only letters, digits, and underscores are allowed",
+                        identifier
+                    ),
+                )
+                .await;
+        }
+
+        match self.mcedit.find_references(identifier).await {
+            Ok(result) => {
+                let obj_as_str = serde_json::to_string(&result)?;
+                self.send_text_response(transport, id, &obj_as_str).await?;
+            }
+            Err(err) => {
+                self.send_error_response(
+                    transport,
+                    id,
+                    JsonRpcErrorCode::InternalError,
+                    format!("Failed to find references: {}", err),
+                )
+                .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn handle_analyze_project(
+        &self,
+        transport: &dyn Transport,
+        id: u64,
+        params_val: &serde_json::Value,
+    ) -> anyhow::Result<()> {
+        // Get optional subdirectory parameter
+        let subdirectory = params_val
+            .get("arguments")
+            .and_then(|args| args.get("path"))
+            .and_then(|p| p.as_str())
+            .map(PathBuf::from);
+
+        // A cwd only changes what the subdirectory is relative to
+        let subdirectory = match (cwd_arg(params_val), subdirectory) {
+            (Some(cwd), Some(subdirectory)) => Some(cwd.join(subdirectory)),
+            (cwd, subdirectory) => cwd.or(subdirectory),
+        };
+
+        let max_age = params_val
+            .get("arguments")
+            .and_then(|args| args.get("max_age_seconds"))
+            .and_then(|m| m.as_u64())
+            .map(Duration::from_secs);
+
+        let include_dependencies = params_val
+            .get("arguments")
+            .and_then(|args| args.get("include_dependencies"))
+            .and_then(|d| d.as_bool())
+            .unwrap_or(false);
+
+        let sections = match params_val
+            .get("arguments")
+            .and_then(|args| args.get("sections"))
+            .and_then(|s| s.as_array())
+        {
+            Some(names) => {
+                let names = names.iter().filter_map(|name| name.as_str());
+                match AnalysisSections::parse(names) {
+                    Ok(sections) => sections,
+                    Err(err) => {
+                        return self
+                            .send_error_response(
+                                transport,
+                                id,
+                                JsonRpcErrorCode::InvalidParams,
+                                err.to_string(),
+                            )
+                            .await;
+                    }
+                }
+            }
+            None => AnalysisSections::default(),
+        };
+
+        // Clients asking for progress get the project type and key files
+        // as soon as they are known, ahead of the walk for the rest
+        let progress_token = params_val
+            .get("_meta")
+            .and_then(|meta| meta.get("progressToken"))
+            .cloned();
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let analysis = self.mcedit.analyze_project(
+            subdirectory.as_deref(),
+            &sections,
+            max_age,
+            include_dependencies,
+            progress_token.as_ref().map(|_| sender),
+        );
+        tokio::pin!(analysis);
+
+        let outcome = loop {
+            tokio::select! {
+                outcome = &mut analysis => break outcome,
+                Some(early) = receiver.recv() => {
+                    if let Some(token) = &progress_token {
+                        self.send_analysis_progress(transport, token, early).await;
+                    }
+                }
+            }
+        };
+
+        match outcome {
+            Ok(analysis) => {
+                let obj_as_str = serde_json::to_string(&analysis)?;
+                self.send_text_response(transport, id, &obj_as_str).await?;
+            }
+            Err(err) => {
+                self.send_error_response(
+                    transport,
+                    id,
+                    JsonRpcErrorCode::InternalError,
+                    format!("Failed to analyze project: {}", err),
+                )
+                .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Sends the part of an analysis that is ready before the walk
+    async fn send_analysis_progress(&self, transport: &dyn Transport, token: &Value, early: Value) {
+        let notification = Message::Notification {
+            jsonrpc: "2.0".to_string(),
+            method: "notifications/progress".to_string(),
+            params: Some(json!({
+                "progressToken": token,
+                "progress": 1,
+                "total": 2,
+                "message": "Detected project type and key files, walking the project",
+                "analysis": early
+            })),
+        };
+
+        if let Err(e) = transport.send(notification).await {
+            logging::warn(&format!("Failed to send progress notification: {}", e));
+        }
+    }
+
+    async fn handle_apply_suggestion(
+        &self,
+        transport: &dyn Transport,
+        id: u64,
+        params_val: &serde_json::Value,
+    ) -> anyhow::Result<()> {
+        // Get path and suggestion parameters
+        let args = match params_val.get("arguments") {
+            Some(a) => a,
+            None => {
+                return self
+                    .send_error_response(
+                        transport,
+                        id,
+                        JsonRpcErrorCode::InvalidParams,
+                        "Missing required arguments".to_string(),
+                    )
+                    .await;
+            }
+        };
+
+        // Multi-file suggestions carry their own paths
+        let path_str = args.get("path").and_then(|p| p.as_str());
+
+        let suggestion = match args.get("suggestion").and_then(|s| s.as_str()) {
+            Some(s) => s,
+            None => {
+                return self
+                    .send_error_response(
+                        transport,
+                        id,
+                        JsonRpcErrorCode::InvalidParams,
+                        "Missing required parameter: suggestion".to_string(),
+                    )
+                    .await;
+            }
+        };
+
+        // Parse suggestion and apply it
+        match self.mcedit.parse_suggestion(suggestion).await {
+            Ok(parsed_suggestion) => {
+                let is_multi =
+                    parsed_suggestion.get("type").and_then(|t| t.as_str()) == Some("multi");
+                let path = match path_str {
+                    Some(p) => PathBuf::from(p),
+                    None if is_multi => PathBuf::new(),
+                    None => {
+                        return self
+                            .send_error_response(
+                                transport,
+                                id,
+                                JsonRpcErrorCode::InvalidParams,
+                                "Missing required parameter: path".to_string(),
+                            )
+                            .await;
+                    }
+                };
+
+                match self.mcedit.apply_suggestion(&path, suggestion).await {
+                    Ok(result) => {
+                        let obj_as_str = serde_json::to_string(&result)?;
+                        self.send_text_response(transport, id, &obj_as_str).await?;
+                    }
+                    Err(err) => {
+                        self.send_error_response(
+                            transport,
+                            id,
+                            JsonRpcErrorCode::InternalError,
+                            format!("Failed to apply suggestion: {}", err),
+                        )
+                        .await?;
+                    }
+                }
+            }
+            Err(err) => {
+                self.send_error_response(
+                    transport,
+                    id,
+                    JsonRpcErrorCode::InvalidParams,
+                    format!("Failed to parse suggestion: {}", err),
+                )
+                .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn handle_generate_diff(
+        &self,
+        transport: &dyn Transport,
+        id: u64,
+        params_val: &serde_json::Value,
+    ) -> anyhow::Result<()> {
+        // Get original and modified parameters
+        let args = match params_val.get("arguments") {
+            Some(a) => a,
+            None => {
+                return self
+                    .send_error_response(
+                        transport,
+                        id,
+                        JsonRpcErrorCode::InvalidParams,
+                        "Missing required arguments".to_string(),
+                    )
+                    .await;
+            }
+        };
+
+        let original = match args.get("original").and_then(|o| o.as_str()) {
+            Some(o) => o,
+            None => {
+                return self
+                    .send_error_response(
+                        transport,
+                        id,
+                        JsonRpcErrorCode::InvalidParams,
+                        "Missing required parameter: original".to_string(),
+                    )
+                    .await;
+            }
+        };
+
+        let modified = match args.get("modified").and_then(|m| m.as_str()) {
+            Some(m) => m,
+            None => {
+                return self
+                    .send_error_response(
+                        transport,
+                        id,
+                        JsonRpcErrorCode::InvalidParams,
+                        "Missing required parameter: modified".to_string(),
+                    )
+                    .await;
+            }
+        };
+
+        let whitespace = match whitespace_arg(args) {
+            Ok(whitespace) => whitespace,
+            Err(message) => {
+                return self
+                    .send_error_response(transport, id, JsonRpcErrorCode::InvalidParams, message)
+                    .await;
+            }
+        };
+
+        let format = match diff_format_arg(args) {
+            Ok(format) => format,
+            Err(message) => {
+                return self
+                    .send_error_response(transport, id, JsonRpcErrorCode::InvalidParams, message)
+                    .await;
+            }
+        };
+
+        // Generate diff
+        match self
+            .mcedit
+            .generate_diff(original, modified, whitespace, format)
+            .await
+        {
+            Ok(diff) => {
+                let result_json = json!({ "diff": diff });
+                let obj_as_str = serde_json::to_string(&result_json)?;
+                self.send_text_response(transport, id, &obj_as_str).await?;
+            }
+            Err(err) => {
+                self.send_error_response(
+                    transport,
+                    id,
+                    JsonRpcErrorCode::InternalError,
+                    format!("Failed to generate diff: {}", err),
+                )
+                .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn handle_read_backup(
+        &self,
+        transport: &dyn Transport,
+        id: u64,
+        params_val: &serde_json::Value,
+    ) -> anyhow::Result<()> {
+        let args = params_val.get("arguments");
+
+        let path_str = match args
+            .and_then(|args| args.get("path"))
+            .and_then(|p| p.as_str())
+        {
+            Some(p) => p,
+            None => {
+                return self
+                    .send_error_response(
+                        transport,
+                        id,
+                        JsonRpcErrorCode::InvalidParams,
+                        "Missing required parameter: path".to_string(),
+                    )
+                    .await;
+            }
+        };
+
+        let index = args
+            .and_then(|args| args.get("index"))
+            .and_then(|i| i.as_u64())
+            .map(|i| i as usize);
+
+        let taken_at = match args
+            .and_then(|args| args.get("timestamp"))
+            .and_then(|t| t.as_str())
+        {
+            Some(_) if index.is_some() => {
+                return self
+                    .send_error_response(
+                        transport,
+                        id,
+                        JsonRpcErrorCode::InvalidParams,
+                        "Pass either index or timestamp, not both".to_string(),
+                    )
+                    .await;
+            }
+            Some(timestamp) => match chrono::DateTime::parse_from_rfc3339(timestamp) {
+                Ok(time) => Some(SystemTime::from(time)),
+                Err(err) => {
+                    return self
+                        .send_error_response(
+                            transport,
+                            id,
+                            JsonRpcErrorCode::InvalidParams,
+                            format!("Invalid timestamp '{}': {}", timestamp, err),
+                        )
+                        .await;
+                }
+            },
+            None => None,
+        };
+
+        let path = PathBuf::from(path_str);
+
+        match self.mcedit.read_backup(&path, index, taken_at).await {
+            Ok(result) => {
+                let obj_as_str = serde_json::to_string(&result)?;
+                self.send_text_response(transport, id, &obj_as_str).await?;
+            }
+            Err(err) => {
+                self.send_error_response(
+                    transport,
+                    id,
+                    JsonRpcErrorCode::InternalError,
+                    format!("Failed to read backup: {}", err),
+                )
+                .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn handle_diff_against_backup(
+        &self,
+        transport: &dyn Transport,
+        id: u64,
+        params_val: &serde_json::Value,
+    ) -> anyhow::Result<()> {
+        // Get path and optional index parameters
+        let args = match params_val.get("arguments") {
+            Some(a) => a,
+            None => {
+                return self
+                    .send_error_response(
+                        transport,
+                        id,
+                        JsonRpcErrorCode::InvalidParams,
+                        "Missing required arguments".to_string(),
+                    )
+                    .await;
+            }
+        };
+
+        let path_str = match args.get("path").and_then(|p| p.as_str()) {
+            Some(p) => p,
+            None => {
+                return self
+                    .send_error_response(
+                        transport,
+                        id,
+                        JsonRpcErrorCode::InvalidParams,
+                        "Missing required parameter: path".to_string(),
+                    )
+                    .await;
+            }
+        };
+
+        let index = args.get("index").and_then(|i| i.as_u64()).unwrap_or(0) as usize;
+
+        let whitespace = match whitespace_arg(args) {
+            Ok(whitespace) => whitespace,
+            Err(message) => {
+                return self
+                    .send_error_response(transport, id, JsonRpcErrorCode::InvalidParams, message)
+                    .await;
+            }
+        };
+
+        let format = match diff_format_arg(args) {
+            Ok(format) => format,
+            Err(message) => {
+                return self
+                    .send_error_response(transport, id, JsonRpcErrorCode::InvalidParams, message)
+                    .await;
+            }
+        };
+
+        let path = PathBuf::from(path_str);
+
+        // Diff the current file against the backup
+        match self
+            .mcedit
+            .diff_against_backup(&path, index, whitespace, format)
+            .await
+        {
+            Ok(result) => {
+                let obj_as_str = serde_json::to_string(&result)?;
+                self.send_text_response(transport, id, &obj_as_str).await?;
+            }
+            Err(err) => {
+                self.send_error_response(
+                    transport,
+                    id,
+                    JsonRpcErrorCode::InternalError,
+                    format!("Failed to diff against backup: {}", err),
+                )
+                .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn handle_cleanup_whitespace(
+        &self,
+        transport: &dyn Transport,
+        id: u64,
+        params_val: &serde_json::Value,
+    ) -> anyhow::Result<()> {
+        let args = params_val.get("arguments");
+        let path = args
+            .and_then(|args| args.get("path"))
+            .and_then(|p| p.as_str());
+        let glob = args
+            .and_then(|args| args.get("glob"))
+            .and_then(|g| g.as_str());
+
+        let paths = match (path, glob) {
+            (Some(path), _) => vec![PathBuf::from(path)],
+            (None, Some(glob)) => match self.mcedit.list_files_matching_glob(glob).await {
+                Ok(paths) => paths,
+                Err(err) => {
+                    return self
+                        .send_error_response(
+                            transport,
+                            id,
+                            JsonRpcErrorCode::InvalidParams,
+                            err.to_string(),
+                        )
+                        .await;
+                }
+            },
+            (None, None) => {
+                return self
+                    .send_error_response(
+                        transport,
+                        id,
+                        JsonRpcErrorCode::InvalidParams,
+                        "Missing required parameter: path or glob".to_string(),
+                    )
+                    .await;
+            }
+        };
+
+        let max_blank_lines = args
+            .and_then(|args| args.get("max_blank_lines"))
+            .and_then(|m| m.as_u64())
+            .map(|m| m as usize);
+        let trim_trailing_whitespace = args
+            .and_then(|args| args.get("trim_trailing_whitespace"))
+            .and_then(|t| t.as_bool());
+        let ensure_final_newline = args
+            .and_then(|args| args.get("ensure_final_newline"))
+            .and_then(|e| e.as_bool());
+        let dry_run = args
+            .and_then(|args| args.get("dry_run"))
+            .and_then(|d| d.as_bool())
+            .unwrap_or(false);
+
+        let result = self
+            .mcedit
+            .cleanup_whitespace(
+                &paths,
+                max_blank_lines,
+                trim_trailing_whitespace,
+                ensure_final_newline,
+                dry_run,
+            )
+            .await;
+        let obj_as_str = serde_json::to_string(&result)?;
+        self.send_text_response(transport, id, &obj_as_str).await?;
+
+        Ok(())
+    }
+
+    async fn handle_normalize_project_line_endings(
+        &self,
+        transport: &dyn Transport,
+        id: u64,
+        params_val: &serde_json::Value,
+    ) -> anyhow::Result<()> {
+        let dry_run = params_val
+            .get("arguments")
+            .and_then(|args| args.get("dry_run"))
+            .and_then(|d| d.as_bool())
+            .unwrap_or(false);
+
+        match self.mcedit.normalize_project_line_endings(dry_run).await {
+            Ok(result) => {
+                let obj_as_str = serde_json::to_string(&result)?;
+                self.send_text_response(transport, id, &obj_as_str).await?;
+            }
+            Err(err) => {
+                self.send_error_response(
+                    transport,
+                    id,
+                    JsonRpcErrorCode::InternalError,
+                    format!("Failed to normalize line endings: {}", err),
+                )
+                .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn handle_purge_backups(
+        &self,
+        transport: &dyn Transport,
+        id: u64,
+        params_val: &serde_json::Value,
+    ) -> anyhow::Result<()> {
+        let args = params_val.get("arguments");
+        let path = args
+            .and_then(|args| args.get("path"))
+            .and_then(|p| p.as_str())
+            .map(PathBuf::from);
+        let older_than = args
+            .and_then(|args| args.get("older_than_days"))
+            .and_then(|d| d.as_u64())
+            .map(|days| Duration::from_secs(days * 24 * 60 * 60));
+        let keep_latest = args
+            .and_then(|args| args.get("keep_latest"))
+            .and_then(|k| k.as_u64())
+            .map(|k| k as usize);
+        let all = args
+            .and_then(|args| args.get("all"))
+            .and_then(|a| a.as_bool())
+            .unwrap_or(false);
+
+        // Deleting everything must be asked for, not the result of a typo
+        if older_than.is_none() && keep_latest.is_none() && !all {
+            return self
+                .send_error_response(
+                    transport,
+                    id,
+                    JsonRpcErrorCode::InvalidParams,
+                    "Specify older_than_days, keep_latest, or all".to_string(),
+                )
+                .await;
+        }
+
+        match self
+            .mcedit
+            .purge_backups(path.as_deref(), older_than, keep_latest)
+            .await
+        {
+            Ok(result) => {
+                let obj_as_str = serde_json::to_string(&result)?;
+                self.send_text_response(transport, id, &obj_as_str).await?;
+            }
+            Err(err) => {
+                self.send_error_response(
+                    transport,
+                    id,
+                    JsonRpcErrorCode::InternalError,
+                    format!("Failed to purge backups: {}", err),
+                )
+                .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn handle_create_backup(
+        &self,
+        transport: &dyn Transport,
+        id: u64,
+        params_val: &serde_json::Value,
+    ) -> anyhow::Result<()> {
+        let args = params_val.get("arguments");
+        let path = args
+            .and_then(|args| args.get("path"))
+            .and_then(|p| p.as_str());
+        let glob = args
+            .and_then(|args| args.get("glob"))
+            .and_then(|g| g.as_str());
+
+        let paths = match (path, glob) {
+            (Some(path), _) => vec![PathBuf::from(path)],
+            (None, Some(glob)) => match self.mcedit.list_files_matching_glob(glob).await {
+                Ok(paths) => paths,
+                Err(err) => {
+                    return self
+                        .send_error_response(
+                            transport,
+                            id,
+                            JsonRpcErrorCode::InvalidParams,
+                            err.to_string(),
+                        )
+                        .await;
+                }
+            },
+            (None, None) => {
+                return self
+                    .send_error_response(
+                        transport,
+                        id,
+                        JsonRpcErrorCode::InvalidParams,
+                        "Missing required parameter: path or glob".to_string(),
+                    )
+                    .await;
+            }
+        };
+
+        let result = self.mcedit.create_backups(&paths).await;
+        let obj_as_str = serde_json::to_string(&result)?;
+        self.send_text_response(transport, id, &obj_as_str).await?;
+
+        Ok(())
+    }
+
+    async fn handle_detect_language(
+        &self,
+        transport: &dyn Transport,
+        id: u64,
+        params_val: &serde_json::Value,
+    ) -> anyhow::Result<()> {
+        // Get path parameter
+        let path_str = match params_val
+            .get("arguments")
+            .and_then(|args| args.get("path"))
+            .and_then(|p| p.as_str())
+        {
+            Some(p) => p,
+            None => {
+                return self
+                    .send_error_response(
+                        transport,
+                        id,
+                        JsonRpcErrorCode::InvalidParams,
+                        "Missing required parameter: path".to_string(),
+                    )
+                    .await;
+            }
+        };
+
+        let path = PathBuf::from(path_str);
+
+        // Detect the language
+        match self.mcedit.detect_language(&path).await {
+            Ok(result) => {
+                let obj_as_str = serde_json::to_string(&result)?;
+                self.send_text_response(transport, id, &obj_as_str).await?;
+            }
+            Err(err) => {
+                self.send_error_response(
+                    transport,
+                    id,
+                    JsonRpcErrorCode::InternalError,
+                    format!("Failed to detect language: {}", err),
+                )
+                .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn handle_test_replacement(
+        &self,
+        transport: &dyn Transport,
+        id: u64,
+        params_val: &serde_json::Value,
+    ) -> anyhow::Result<()> {
+        // Get pattern, replacement, and text parameters
+        let args = match params_val.get("arguments") {
+            Some(a) => a,
+            None => {
+                return self
+                    .send_error_response(
+                        transport,
+                        id,
+                        JsonRpcErrorCode::InvalidParams,
+                        "Missing required arguments".to_string(),
+                    )
+                    .await;
+            }
+        };
+
+        let pattern_str = match args.get("pattern").and_then(|p| p.as_str()) {
+            Some(p) => p,
+            None => {
+                return self
+                    .send_error_response(
+                        transport,
+                        id,
+                        JsonRpcErrorCode::InvalidParams,
+                        "Missing required parameter: pattern".to_string(),
+                    )
+                    .await;
+            }
+        };
+
+        let replacement = match args.get("replacement").and_then(|r| r.as_str()) {
+            Some(r) => r,
+            None => {
+                return self
+                    .send_error_response(
+                        transport,
+                        id,
+                        JsonRpcErrorCode::InvalidParams,
+                        "Missing required parameter: replacement".to_string(),
+                    )
+                    .await;
+            }
+        };
+
+        let text = match args.get("text").and_then(|t| t.as_str()) {
+            Some(t) => t,
+            None => {
+                return self
+                    .send_error_response(
+                        transport,
+                        id,
+                        JsonRpcErrorCode::InvalidParams,
+                        "Missing required parameter: text".to_string(),
+                    )
+                    .await;
+            }
+        };
+
+        let all = args.get("all").and_then(|a| a.as_bool()).unwrap_or(false);
+
+        let pattern = match region_regex(pattern_str) {
+            Ok(pattern) => pattern,
+            Err(err) => {
+                return self
+                    .send_error_response(
+                        transport,
+                        id,
+                        JsonRpcErrorCode::InvalidParams,
+                        format!("Invalid pattern: {}", err),
+                    )
+                    .await;
+            }
+        };
+
+        let result = self
+            .mcedit
+            .test_replacement(&pattern, replacement, text, all);
+        let obj_as_str = serde_json::to_string(&result)?;
+        self.send_text_response(transport, id, &obj_as_str).await?;
+
+        Ok(())
+    }
+
+    async fn handle_insert_file_contents(
+        &self,
+        transport: &dyn Transport,
+        id: u64,
+        params_val: &serde_json::Value,
+    ) -> anyhow::Result<()> {
+        let args = match params_val.get("arguments") {
+            Some(a) => a,
+            None => {
+                return self
+                    .send_error_response(
+                        transport,
+                        id,
+                        JsonRpcErrorCode::InvalidParams,
+                        "Missing required arguments".to_string(),
+                    )
+                    .await;
+            }
+        };
+
+        let (target_str, source_str) = match (
+            args.get("target").and_then(|t| t.as_str()),
+            args.get("source").and_then(|s| s.as_str()),
+        ) {
+            (Some(target), Some(source)) => (target, source),
+            _ => {
+                return self
+                    .send_error_response(
+                        transport,
+                        id,
+                        JsonRpcErrorCode::InvalidParams,
+                        "Missing required parameter: target or source".to_string(),
+                    )
+                    .await;
+            }
+        };
+
+        let position = match (
+            args.get("anchor").and_then(|a| a.as_str()),
+            args.get("line").and_then(|l| l.as_u64()),
+        ) {
+            (Some(anchor), _) => {
+                let is_regex = args.get("regex").and_then(|r| r.as_bool()).unwrap_or(false);
+                let source = if is_regex {
+                    anchor.to_string()
+                } else {
+                    regex::escape(anchor)
+                };
+                match Regex::new(&source) {
+                    Ok(pattern) => InsertPosition::Anchor {
+                        pattern,
+                        after: !args
+                            .get("before")
+                            .and_then(|b| b.as_bool())
+                            .unwrap_or(false),
+                    },
+                    Err(err) => {
+                        return self
+                            .send_error_response(
+                                transport,
+                                id,
+                                JsonRpcErrorCode::InvalidParams,
+                                format!("Invalid anchor: {}", err),
+                            )
+                            .await;
+                    }
+                }
+            }
+            (None, Some(line)) => InsertPosition::Line(line as usize),
+            (None, None) => {
+                return self
+                    .send_error_response(
+                        transport,
+                        id,
+                        JsonRpcErrorCode::InvalidParams,
+                        "Missing required parameter: line or anchor".to_string(),
+                    )
+                    .await;
+            }
+        };
+
+        let target = PathBuf::from(target_str);
+        let source = PathBuf::from(source_str);
+
+        match self
+            .mcedit
+            .insert_file_contents(&target, &source, &position)
+            .await
+        {
+            Ok((line, summary)) => {
+                let result_json = json!({
+                    "success": true,
+                    "path": to_display_path(&target),
+                    "line": line,
+                    "lines_before": summary.lines_before,
+                    "lines_after": summary.lines_after
+                });
+                let obj_as_str = serde_json::to_string(&result_json)?;
+                self.send_text_response(transport, id, &obj_as_str).await?;
+            }
+            Err(err) => {
+                self.send_error_response(
+                    transport,
+                    id,
+                    JsonRpcErrorCode::InternalError,
+                    format!("Failed to insert file contents: {}", err),
+                )
+                .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn handle_insert_at_match(
+        &self,
+        transport: &dyn Transport,
+        id: u64,
+        params_val: &serde_json::Value,
+        after: bool,
+    ) -> anyhow::Result<()> {
+        // Get path, pattern, and content parameters
+        let args = match params_val.get("arguments") {
+            Some(a) => a,
+            None => {
+                return self
+                    .send_error_response(
+                        transport,
+                        id,
+                        JsonRpcErrorCode::InvalidParams,
+                        "Missing required arguments".to_string(),
+                    )
+                    .await;
+            }
+        };
+
+        let path_str = match args.get("path").and_then(|p| p.as_str()) {
+            Some(p) => p,
+            None => {
+                return self
+                    .send_error_response(
+                        transport,
+                        id,
+                        JsonRpcErrorCode::InvalidParams,
+                        "Missing required parameter: path".to_string(),
+                    )
+                    .await;
+            }
+        };
+
+        let pattern_str = match args.get("pattern").and_then(|p| p.as_str()) {
+            Some(p) => p,
+            None => {
+                return self
+                    .send_error_response(
+                        transport,
+                        id,
+                        JsonRpcErrorCode::InvalidParams,
+                        "Missing required parameter: pattern".to_string(),
+                    )
+                    .await;
+            }
+        };
+
+        let content = match args.get("content").and_then(|c| c.as_str()) {
+            Some(c) => c,
+            None => {
+                return self
+                    .send_error_response(
+                        transport,
+                        id,
+                        JsonRpcErrorCode::InvalidParams,
+                        "Missing required parameter: content".to_string(),
+                    )
+                    .await;
+            }
+        };
+
+        let is_regex = args.get("regex").and_then(|r| r.as_bool()).unwrap_or(false);
+        let unique = args
+            .get("unique")
+            .and_then(|u| u.as_bool())
+            .unwrap_or(false);
+
+        let pattern_source = if is_regex {
+            pattern_str.to_string()
+        } else {
+            regex::escape(pattern_str)
+        };
+        let pattern = match Regex::new(&pattern_source) {
+            Ok(pattern) => pattern,
+            Err(err) => {
+                return self
+                    .send_error_response(
+                        transport,
+                        id,
+                        JsonRpcErrorCode::InvalidParams,
+                        format!("Invalid pattern: {}", err),
+                    )
+                    .await;
+            }
+        };
+
+        let path = PathBuf::from(path_str);
+
+        match self
+            .mcedit
+            .insert_at_match(&path, &pattern, content, after, unique)
+            .await
+        {
+            Ok((line, summary)) => {
+                let result_json = json!({
+                    "success": true,
+                    "path": to_display_path(&path),
+                    "line": line,
+                    "lines_before": summary.lines_before,
+                    "lines_after": summary.lines_after,
+                    "bytes_changed": summary.bytes_changed
+                });
+                let obj_as_str = serde_json::to_string(&result_json)?;
+                self.send_text_response(transport, id, &obj_as_str).await?;
+            }
+            Err(err) => {
+                self.send_error_response(
+                    transport,
+                    id,
+                    JsonRpcErrorCode::InternalError,
+                    format!("Failed to insert content: {}", err),
+                )
+                .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn handle_replace_matching_region(
+        &self,
+        transport: &dyn Transport,
+        id: u64,
+        params_val: &serde_json::Value,
+    ) -> anyhow::Result<()> {
+        // Get path, pattern, and replacement parameters
+        let args = match params_val.get("arguments") {
+            Some(a) => a,
+            None => {
+                return self
+                    .send_error_response(
+                        transport,
+                        id,
+                        JsonRpcErrorCode::InvalidParams,
+                        "Missing required arguments".to_string(),
+                    )
+                    .await;
+            }
+        };
+
+        let path_str = match args.get("path").and_then(|p| p.as_str()) {
+            Some(p) => p,
+            None => {
+                return self
+                    .send_error_response(
+                        transport,
+                        id,
+                        JsonRpcErrorCode::InvalidParams,
+                        "Missing required parameter: path".to_string(),
+                    )
+                    .await;
+            }
+        };
+
+        let pattern_str = match args.get("pattern").and_then(|p| p.as_str()) {
+            Some(p) => p,
+            None => {
+                return self
+                    .send_error_response(
+                        transport,
+                        id,
+                        JsonRpcErrorCode::InvalidParams,
+                        "Missing required parameter: pattern".to_string(),
+                    )
+                    .await;
+            }
+        };
+
+        let replacement = match args.get("replacement").and_then(|r| r.as_str()) {
+            Some(r) => r,
+            None => {
+                return self
+                    .send_error_response(
+                        transport,
+                        id,
+                        JsonRpcErrorCode::InvalidParams,
+                        "Missing required parameter: replacement".to_string(),
+                    )
+                    .await;
+            }
+        };
+
+        let all = args.get("all").and_then(|a| a.as_bool()).unwrap_or(false);
+
+        let pattern = match region_regex(pattern_str) {
+            Ok(pattern) => pattern,
+            Err(err) => {
+                return self
+                    .send_error_response(
+                        transport,
+                        id,
+                        JsonRpcErrorCode::InvalidParams,
+                        format!("Invalid pattern: {}", err),
+                    )
+                    .await;
+            }
+        };
+
+        let path = PathBuf::from(path_str);
+
+        match self
+            .mcedit
+            .replace_matching_region(&path, &pattern, replacement, all)
+            .await
+        {
+            Ok(result) => {
+                let obj_as_str = serde_json::to_string(&result)?;
+                self.send_text_response(transport, id, &obj_as_str).await?;
+            }
+            Err(err) => {
+                self.send_error_response(
+                    transport,
+                    id,
+                    JsonRpcErrorCode::InternalError,
+                    format!("Failed to replace matching region: {}", err),
+                )
+                .await?;
+            }
+        }
+
+        Ok(())
     }
-  ]
-}"#;
 
-pub struct McpHandler<'a> {
-    mcedit: &'a mut McEdit,
-    initialized: bool,
-}
+    async fn handle_edit_region(
+        &self,
+        transport: &dyn Transport,
+        id: u64,
+        params_val: &serde_json::Value,
+    ) -> anyhow::Result<()> {
+        let args = params_val.get("arguments");
 
-impl<'a> McpHandler<'a> {
-    pub fn new(mcedit: &'a mut McEdit) -> Self {
-        Self {
-            mcedit,
-            initialized: false,
+        let path_str = match args
+            .and_then(|args| args.get("path"))
+            .and_then(|p| p.as_str())
+        {
+            Some(p) => p,
+            None => {
+                return self
+                    .send_error_response(
+                        transport,
+                        id,
+                        JsonRpcErrorCode::InvalidParams,
+                        "Missing required parameter: path".to_string(),
+                    )
+                    .await;
+            }
+        };
+
+        let line = |key: &str| {
+            args.and_then(|args| args.get(key))
+                .and_then(|l| l.as_u64())
+                .map(|l| l as usize)
+        };
+        let (start_line, end_line) = match (line("start_line"), line("end_line")) {
+            (Some(start), Some(end)) => (start, end),
+            _ => {
+                return self
+                    .send_error_response(
+                        transport,
+                        id,
+                        JsonRpcErrorCode::InvalidParams,
+                        "Missing required parameter: start_line or end_line".to_string(),
+                    )
+                    .await;
+            }
+        };
+
+        let content = match args
+            .and_then(|args| args.get("content"))
+            .and_then(|c| c.as_str())
+        {
+            Some(c) => c,
+            None => {
+                return self
+                    .send_error_response(
+                        transport,
+                        id,
+                        JsonRpcErrorCode::InvalidParams,
+                        "Missing required parameter: content".to_string(),
+                    )
+                    .await;
+            }
+        };
+
+        let path = PathBuf::from(path_str);
+
+        match self
+            .mcedit
+            .edit_file_region(&path, start_line, end_line, content)
+            .await
+        {
+            Ok(summary) => {
+                let result_json = json!({
+                    "success": true,
+                    "path": to_display_path(&path),
+                    "lines_before": summary.lines_before,
+                    "lines_after": summary.lines_after,
+                    "bytes_changed": summary.bytes_changed
+                });
+                let obj_as_str = serde_json::to_string(&result_json)?;
+                self.send_text_response(transport, id, &obj_as_str).await?;
+            }
+            Err(err) => {
+                self.send_error_response(
+                    transport,
+                    id,
+                    JsonRpcErrorCode::InternalError,
+                    format!("Failed to edit region: {}", err),
+                )
+                .await?;
+            }
         }
+
+        Ok(())
     }
 
-    pub async fn launch_mcp(&mut self, transport: &StdioTransport) -> anyhow::Result<()> {
-        let mut stream = transport.receive();
+    async fn handle_join_lines(
+        &self,
+        transport: &dyn Transport,
+        id: u64,
+        params_val: &serde_json::Value,
+    ) -> anyhow::Result<()> {
+        let args = params_val.get("arguments");
 
-        logging::info("MCP stdio transport server started. Waiting for JSON messages on stdin...");
-        logging::send_log_message(
-            transport,
-            logging::LogLevel::Info,
-            "McEdit server initialized and ready",
-        )
-        .await?;
+        let path_str = match args
+            .and_then(|args| args.get("path"))
+            .and_then(|p| p.as_str())
+        {
+            Some(p) => p,
+            None => {
+                return self
+                    .send_error_response(
+                        transport,
+                        id,
+                        JsonRpcErrorCode::InvalidParams,
+                        "Missing required parameter: path".to_string(),
+                    )
+                    .await;
+            }
+        };
 
-        while let Some(msg_result) = stream.next().await {
-            match msg_result {
-                Ok(Message::Request {
-                    id, method, params, ..
-                }) => {
-                    logging::log_both(
+        let line = |key: &str| {
+            args.and_then(|args| args.get(key))
+                .and_then(|l| l.as_u64())
+                .map(|l| l as usize)
+        };
+        let (start_line, end_line) = match (line("start_line"), line("end_line")) {
+            (Some(start), Some(end)) => (start, end),
+            _ => {
+                return self
+                    .send_error_response(
                         transport,
-                        logging::LogLevel::Debug,
-                        &format!(
-                            "Got Request: id={}, method={}, params={:?}",
-                            id, method, params
-                        ),
+                        id,
+                        JsonRpcErrorCode::InvalidParams,
+                        "Missing required parameter: start_line or end_line".to_string(),
                     )
-                    .await?;
+                    .await;
+            }
+        };
 
-                    // Handle initialization request first
-                    if method == "initialize" {
-                        if let Err(err) = self.handle_initialize(transport, id).await {
-                            logging::error(&format!("Error handling initialize request: {}", err));
-                        }
-                        self.initialized = true;
-                        continue;
-                    }
+        let separator = args
+            .and_then(|args| args.get("separator"))
+            .and_then(|s| s.as_str())
+            .unwrap_or(" ");
 
-                    // For all other requests, ensure we're initialized
-                    if !self.initialized {
-                        self.send_error_response(
-                            transport,
-                            id,
-                            JsonRpcErrorCode::InvalidRequest,
-                            "Server not initialized. Send 'initialize' request first.".to_string(),
-                        )
-                        .await?;
-                        continue;
-                    }
+        let path = PathBuf::from(path_str);
 
-                    if let Err(err) = self.handle_request(transport, id, method, params).await {
-                        logging::error(&format!("Error handling request: {:?}", err));
-                        self.send_error_response(
-                            transport,
-                            id,
-                            JsonRpcErrorCode::InternalError,
-                            format!("Failed to handle request: {}", err),
-                        )
-                        .await?;
-                    }
-                }
-                Ok(Message::Notification { method, params, .. }) => {
-                    logging::log_both(
+        match self
+            .mcedit
+            .join_lines(&path, start_line, end_line, separator)
+            .await
+        {
+            Ok(result) => {
+                let obj_as_str = serde_json::to_string(&result)?;
+                self.send_text_response(transport, id, &obj_as_str).await?;
+            }
+            Err(err) => {
+                self.send_error_response(
+                    transport,
+                    id,
+                    JsonRpcErrorCode::InternalError,
+                    format!("Failed to join lines: {}", err),
+                )
+                .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn handle_split_line(
+        &self,
+        transport: &dyn Transport,
+        id: u64,
+        params_val: &serde_json::Value,
+    ) -> anyhow::Result<()> {
+        let args = params_val.get("arguments");
+
+        let path_str = match args
+            .and_then(|args| args.get("path"))
+            .and_then(|p| p.as_str())
+        {
+            Some(p) => p,
+            None => {
+                return self
+                    .send_error_response(
                         transport,
-                        logging::LogLevel::Debug,
-                        &format!("Got Notification: method={}, params={:?}", method, params),
+                        id,
+                        JsonRpcErrorCode::InvalidParams,
+                        "Missing required parameter: path".to_string(),
                     )
-                    .await?;
-                }
-                Ok(Message::Response {
-                    id, result, error, ..
-                }) => {
-                    logging::log_both(
+                    .await;
+            }
+        };
+
+        let number = |key: &str| {
+            args.and_then(|args| args.get(key))
+                .and_then(|n| n.as_u64())
+                .map(|n| n as usize)
+        };
+        let (line, column) = match (number("line"), number("column")) {
+            (Some(line), Some(column)) => (line, column),
+            _ => {
+                return self
+                    .send_error_response(
                         transport,
-                        logging::LogLevel::Debug,
-                        &format!(
-                            "Got Response: id={}, result={:?}, error={:?}",
-                            id, result, error
-                        ),
+                        id,
+                        JsonRpcErrorCode::InvalidParams,
+                        "Missing required parameter: line or column".to_string(),
                     )
-                    .await?;
-                }
-                Err(e) => {
-                    logging::error(&format!("Error receiving message: {:?}", e));
-                }
+                    .await;
+            }
+        };
+
+        let path = PathBuf::from(path_str);
+
+        match self.mcedit.split_line(&path, line, column).await {
+            Ok(result) => {
+                let obj_as_str = serde_json::to_string(&result)?;
+                self.send_text_response(transport, id, &obj_as_str).await?;
+            }
+            Err(err) => {
+                self.send_error_response(
+                    transport,
+                    id,
+                    JsonRpcErrorCode::InternalError,
+                    format!("Failed to split line: {}", err),
+                )
+                .await?;
             }
         }
 
         Ok(())
     }
 
-    async fn handle_request(
-        &mut self,
-        transport: &StdioTransport,
+    async fn handle_multi_region_edit(
+        &self,
+        transport: &dyn Transport,
         id: u64,
-        method: String,
-        params: Option<serde_json::Value>,
+        params_val: &serde_json::Value,
     ) -> anyhow::Result<()> {
-        match &*method {
-            "initialize" => self.handle_initialize(transport, id).await?,
-            "tools/list" => self.handle_tools_list(transport, id).await?,
-            "tools/call" => {
-                if let Some(params_val) = params {
-                    self.handle_tools_call(transport, id, params_val).await?;
-                }
+        // Get path and edits parameters
+        let args = match params_val.get("arguments") {
+            Some(a) => a,
+            None => {
+                return self
+                    .send_error_response(
+                        transport,
+                        id,
+                        JsonRpcErrorCode::InvalidParams,
+                        "Missing required arguments".to_string(),
+                    )
+                    .await;
             }
-            "resources/list" => self.handle_resources_list(transport, id).await?,
-            "prompts/list" => self.handle_prompts_list(transport, id).await?,
-            _ => {
+        };
+
+        let path_str = match args.get("path").and_then(|p| p.as_str()) {
+            Some(p) => p,
+            None => {
+                return self
+                    .send_error_response(
+                        transport,
+                        id,
+                        JsonRpcErrorCode::InvalidParams,
+                        "Missing required parameter: path".to_string(),
+                    )
+                    .await;
+            }
+        };
+
+        let edits = match region_edits_arg(args) {
+            Ok(edits) => edits,
+            Err(message) => {
+                return self
+                    .send_error_response(transport, id, JsonRpcErrorCode::InvalidParams, message)
+                    .await;
+            }
+        };
+
+        let path = PathBuf::from(path_str);
+
+        match self.mcedit.multi_region_edit(&path, &edits).await {
+            Ok(result) => {
+                let obj_as_str = serde_json::to_string(&result)?;
+                self.send_text_response(transport, id, &obj_as_str).await?;
+            }
+            Err(err) => {
                 self.send_error_response(
                     transport,
                     id,
-                    JsonRpcErrorCode::MethodNotFound,
-                    format!("Method not found: {}", method),
+                    JsonRpcErrorCode::InternalError,
+                    format!("Failed to edit regions: {}", err),
                 )
                 .await?;
             }
         }
+
         Ok(())
     }
 
-    async fn handle_initialize(&self, transport: &StdioTransport, id: u64) -> anyhow::Result<()> {
-        logging::info("Handling initialize request");
-
-        // Create a properly structured capabilities response
-        let response = Message::Response {
-            jsonrpc: "2.0".to_string(),
-            id,
-            result: Some(json!({
-                "capabilities": {
-                    "experimental": {},
-                    "prompts": { "listChanged": false },
-                    "resources": { "listChanged": false, "subscribe": false },
-                    "tools": { "listChanged": false }
-                },
-                "protocolVersion": "2024-11-05",
-                "serverInfo": {
-                    "name": "mcedit",
-                    "version": "0.1.0"
-                }
-            })),
-            error: None,
+    async fn handle_summarize_file(
+        &self,
+        transport: &dyn Transport,
+        id: u64,
+        params_val: &serde_json::Value,
+    ) -> anyhow::Result<()> {
+        // Get path parameter
+        let path_str = match params_val
+            .get("arguments")
+            .and_then(|args| args.get("path"))
+            .and_then(|p| p.as_str())
+        {
+            Some(p) => p,
+            None => {
+                return self
+                    .send_error_response(
+                        transport,
+                        id,
+                        JsonRpcErrorCode::InvalidParams,
+                        "Missing required parameter: path".to_string(),
+                    )
+                    .await;
+            }
         };
 
-        // Log the response for debugging
-        if let Ok(json_str) = serde_json::to_string_pretty(&response) {
-            logging::debug(&format!("Sending initialize response: {}", json_str));
-        }
+        let path = PathBuf::from(path_str);
 
-        // Send the response
-        match transport.send(response).await {
-            Ok(_) => {
-                logging::info("Initialize response sent successfully");
-                Ok(())
+        match self.mcedit.summarize_file(&path).await {
+            Ok(result) => {
+                let obj_as_str = serde_json::to_string(&result)?;
+                self.send_text_response(transport, id, &obj_as_str).await?;
             }
-            Err(e) => {
-                logging::error(&format!("Failed to send initialize response: {}", e));
-                Err(anyhow::anyhow!("Failed to send initialize response: {}", e))
+            Err(err) => {
+                self.send_error_response(
+                    transport,
+                    id,
+                    JsonRpcErrorCode::InternalError,
+                    format!("Failed to summarize file: {}", err),
+                )
+                .await?;
             }
         }
-    }
-
-    async fn handle_tools_list(&self, transport: &StdioTransport, id: u64) -> anyhow::Result<()> {
-        let tools_value: serde_json::Value =
-            serde_json::from_str(TOOLS_JSON).expect("tools.json must be valid JSON");
-
-        let response = Message::Response {
-            jsonrpc: "2.0".to_string(),
-            id,
-            result: Some(tools_value),
-            error: None,
-        };
 
-        transport.send(response).await?;
         Ok(())
     }
 
-    async fn handle_tools_call(
-        &mut self,
-        transport: &StdioTransport,
+    async fn handle_path_info(
+        &self,
+        transport: &dyn Transport,
         id: u64,
-        params_val: serde_json::Value,
+        params_val: &serde_json::Value,
     ) -> anyhow::Result<()> {
-        let name = params_val
-            .get("name")
-            .and_then(|v| v.as_str())
-            .unwrap_or("");
+        // Get path parameter
+        let path_str = match params_val
+            .get("arguments")
+            .and_then(|args| args.get("path"))
+            .and_then(|p| p.as_str())
+        {
+            Some(p) => p,
+            None => {
+                return self
+                    .send_error_response(
+                        transport,
+                        id,
+                        JsonRpcErrorCode::InvalidParams,
+                        "Missing required parameter: path".to_string(),
+                    )
+                    .await;
+            }
+        };
 
-        logging::info(&format!("Handling tools/call for tool: {}", name));
+        let path = PathBuf::from(path_str);
 
-        match name {
-            "read_file" => {
-                self.handle_read_file(transport, id, &params_val).await?;
-            }
-            "write_file" => {
-                self.handle_write_file(transport, id, &params_val).await?;
-            }
-            "list_files" => {
-                self.handle_list_files(transport, id, &params_val).await?;
-            }
-            "search_files" => {
-                self.handle_search_files(transport, id, &params_val).await?;
-            }
-            "analyze_project" => {
-                self.handle_analyze_project(transport, id).await?;
-            }
-            "apply_suggestion" => {
-                self.handle_apply_suggestion(transport, id, &params_val)
-                    .await?;
-            }
-            "generate_diff" => {
-                self.handle_generate_diff(transport, id, &params_val)
-                    .await?;
+        match self.mcedit.path_info(&path).await {
+            Ok(result) => {
+                let obj_as_str = serde_json::to_string(&result)?;
+                self.send_text_response(transport, id, &obj_as_str).await?;
             }
-            "change_directory" => {
-                self.handle_change_directory(transport, id, &params_val)
-                    .await?;
+            Err(err) => {
+                self.send_error_response(
+                    transport,
+                    id,
+                    JsonRpcErrorCode::InternalError,
+                    format!("Failed to get path info: {}", err),
+                )
+                .await?;
             }
-            "create_file" => {
-                self.handle_create_file(transport, id, &params_val).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn handle_touch(
+        &self,
+        transport: &dyn Transport,
+        id: u64,
+        params_val: &serde_json::Value,
+    ) -> anyhow::Result<()> {
+        // Get path and optional timestamp parameters
+        let args = match params_val.get("arguments") {
+            Some(a) => a,
+            None => {
+                return self
+                    .send_error_response(
+                        transport,
+                        id,
+                        JsonRpcErrorCode::InvalidParams,
+                        "Missing required arguments".to_string(),
+                    )
+                    .await;
             }
-            "rename_file" => {
-                self.handle_rename_file(transport, id, &params_val).await?;
+        };
+
+        let path_str = match args.get("path").and_then(|p| p.as_str()) {
+            Some(p) => p,
+            None => {
+                return self
+                    .send_error_response(
+                        transport,
+                        id,
+                        JsonRpcErrorCode::InvalidParams,
+                        "Missing required parameter: path".to_string(),
+                    )
+                    .await;
             }
-            "delete_file" => {
-                self.handle_delete_file(transport, id, &params_val).await?;
+        };
+
+        let modified = match args.get("timestamp").and_then(|t| t.as_str()) {
+            Some(timestamp) => match chrono::DateTime::parse_from_rfc3339(timestamp) {
+                Ok(time) => Some(SystemTime::from(time)),
+                Err(err) => {
+                    return self
+                        .send_error_response(
+                            transport,
+                            id,
+                            JsonRpcErrorCode::InvalidParams,
+                            format!("Invalid timestamp '{}': {}", timestamp, err),
+                        )
+                        .await;
+                }
+            },
+            None => None,
+        };
+
+        let path = PathBuf::from(path_str);
+
+        match self.mcedit.touch(&path, modified).await {
+            Ok(result) => {
+                let obj_as_str = serde_json::to_string(&result)?;
+                self.send_text_response(transport, id, &obj_as_str).await?;
             }
-            _ => {
+            Err(err) => {
                 self.send_error_response(
                     transport,
                     id,
-                    JsonRpcErrorCode::MethodNotFound,
-                    format!("Tool not found: {}", name),
+                    JsonRpcErrorCode::InternalError,
+                    format!("Failed to touch file: {}", err),
                 )
                 .await?;
             }
@@ -621,9 +5688,9 @@ impl<'a> McpHandler<'a> {
         Ok(())
     }
 
-    async fn handle_read_file(
+    async fn handle_get_permissions(
         &self,
-        transport: &StdioTransport,
+        transport: &dyn Transport,
         id: u64,
         params_val: &serde_json::Value,
     ) -> anyhow::Result<()> {
@@ -648,14 +5715,9 @@ impl<'a> McpHandler<'a> {
 
         let path = PathBuf::from(path_str);
 
-        // Read the file
-        match self.mcedit.read_file(&path).await {
-            Ok(content) => {
-                let result_json = json!({
-                    "content": content,
-                    "path": path.to_string_lossy()
-                });
-                let obj_as_str = serde_json::to_string(&result_json)?;
+        match self.mcedit.get_permissions(&path).await {
+            Ok(result) => {
+                let obj_as_str = serde_json::to_string(&result)?;
                 self.send_text_response(transport, id, &obj_as_str).await?;
             }
             Err(err) => {
@@ -663,7 +5725,7 @@ impl<'a> McpHandler<'a> {
                     transport,
                     id,
                     JsonRpcErrorCode::InternalError,
-                    format!("Failed to read file: {}", err),
+                    format!("Failed to get permissions: {}", err),
                 )
                 .await?;
             }
@@ -672,13 +5734,13 @@ impl<'a> McpHandler<'a> {
         Ok(())
     }
 
-    async fn handle_write_file(
+    async fn handle_set_permissions(
         &self,
-        transport: &StdioTransport,
+        transport: &dyn Transport,
         id: u64,
         params_val: &serde_json::Value,
     ) -> anyhow::Result<()> {
-        // Get path and content parameters
+        // Get path and mode parameters
         let args = match params_val.get("arguments") {
             Some(a) => a,
             None => {
@@ -687,35 +5749,66 @@ impl<'a> McpHandler<'a> {
                         transport,
                         id,
                         JsonRpcErrorCode::InvalidParams,
-                        "Missing required arguments".to_string(),
+                        "Missing required arguments".to_string(),
+                    )
+                    .await;
+            }
+        };
+
+        let path_str = match args.get("path").and_then(|p| p.as_str()) {
+            Some(p) => p,
+            None => {
+                return self
+                    .send_error_response(
+                        transport,
+                        id,
+                        JsonRpcErrorCode::InvalidParams,
+                        "Missing required parameter: path".to_string(),
+                    )
+                    .await;
+            }
+        };
+
+        let mode_str = match args.get("mode").and_then(|m| m.as_str()) {
+            Some(m) => m,
+            None => {
+                return self
+                    .send_error_response(
+                        transport,
+                        id,
+                        JsonRpcErrorCode::InvalidParams,
+                        "Missing required parameter: mode".to_string(),
                     )
                     .await;
             }
         };
 
-        let path_str = match args.get("path").and_then(|p| p.as_str()) {
-            Some(p) => p,
-            None => {
+        let mode = match u32::from_str_radix(mode_str, 8) {
+            Ok(mode) if mode <= 0o777 => mode,
+            // An editing tool has no business making setuid files
+            Ok(mode) if mode <= 0o7777 => {
                 return self
                     .send_error_response(
                         transport,
                         id,
                         JsonRpcErrorCode::InvalidParams,
-                        "Missing required parameter: path".to_string(),
+                        format!(
+                            "Invalid mode '{}': setuid, setgid and sticky bits can't be set",
+                            mode_str
+                        ),
                     )
                     .await;
             }
-        };
-
-        let content = match args.get("content").and_then(|c| c.as_str()) {
-            Some(c) => c,
-            None => {
+            _ => {
                 return self
                     .send_error_response(
                         transport,
                         id,
                         JsonRpcErrorCode::InvalidParams,
-                        "Missing required parameter: content".to_string(),
+                        format!(
+                            "Invalid mode '{}': expected an octal string such as \"755\"",
+                            mode_str
+                        ),
                     )
                     .await;
             }
@@ -723,14 +5816,9 @@ impl<'a> McpHandler<'a> {
 
         let path = PathBuf::from(path_str);
 
-        // Write to the file
-        match self.mcedit.write_file(&path, content).await {
-            Ok(()) => {
-                let result_json = json!({
-                    "success": true,
-                    "path": path.to_string_lossy()
-                });
-                let obj_as_str = serde_json::to_string(&result_json)?;
+        match self.mcedit.set_permissions(&path, mode).await {
+            Ok(result) => {
+                let obj_as_str = serde_json::to_string(&result)?;
                 self.send_text_response(transport, id, &obj_as_str).await?;
             }
             Err(err) => {
@@ -738,7 +5826,7 @@ impl<'a> McpHandler<'a> {
                     transport,
                     id,
                     JsonRpcErrorCode::InternalError,
-                    format!("Failed to write file: {}", err),
+                    format!("Failed to set permissions: {}", err),
                 )
                 .await?;
             }
@@ -747,29 +5835,64 @@ impl<'a> McpHandler<'a> {
         Ok(())
     }
 
-    async fn handle_list_files(
+    async fn handle_run_task(
         &self,
-        transport: &StdioTransport,
+        transport: &dyn Transport,
         id: u64,
         params_val: &serde_json::Value,
     ) -> anyhow::Result<()> {
-        // Get optional pattern parameter
-        let pattern = params_val
+        // Get name parameter
+        let name = match params_val
             .get("arguments")
-            .and_then(|args| args.get("pattern"))
-            .and_then(|p| p.as_str());
+            .and_then(|args| args.get("name"))
+            .and_then(|n| n.as_str())
+        {
+            Some(n) => n,
+            None => {
+                return self
+                    .send_error_response(
+                        transport,
+                        id,
+                        JsonRpcErrorCode::InvalidParams,
+                        "Missing required parameter: name".to_string(),
+                    )
+                    .await;
+            }
+        };
 
-        // List files
-        match self.mcedit.list_files(pattern).await {
-            Ok(files) => {
-                // Convert file paths to strings
-                let file_strings: Vec<String> = files
-                    .iter()
-                    .map(|p| p.to_string_lossy().to_string())
-                    .collect();
+        // Clients asking for progress get the output as it is produced
+        let progress_token = params_val
+            .get("_meta")
+            .and_then(|meta| meta.get("progressToken"))
+            .cloned();
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let run = self
+            .mcedit
+            .run_task(name, progress_token.as_ref().map(|_| sender));
+        tokio::pin!(run);
 
-                let result_json = json!({ "files": file_strings });
-                let obj_as_str = serde_json::to_string(&result_json)?;
+        let mut chunks = 0;
+        let outcome = loop {
+            tokio::select! {
+                outcome = &mut run => break outcome,
+                Some(output) = receiver.recv() => {
+                    chunks += 1;
+                    if let Some(token) = &progress_token {
+                        self.send_progress(transport, token, chunks, &output).await;
+                    }
+                }
+            }
+        };
+        while let Ok(output) = receiver.try_recv() {
+            chunks += 1;
+            if let Some(token) = &progress_token {
+                self.send_progress(transport, token, chunks, &output).await;
+            }
+        }
+
+        match outcome {
+            Ok(result) => {
+                let obj_as_str = serde_json::to_string(&result)?;
                 self.send_text_response(transport, id, &obj_as_str).await?;
             }
             Err(err) => {
@@ -777,7 +5900,7 @@ impl<'a> McpHandler<'a> {
                     transport,
                     id,
                     JsonRpcErrorCode::InternalError,
-                    format!("Failed to list files: {}", err),
+                    format!("Failed to run task: {}", err),
                 )
                 .await?;
             }
@@ -786,35 +5909,42 @@ impl<'a> McpHandler<'a> {
         Ok(())
     }
 
-    async fn handle_search_files(
+    // Sends a piece of a running task's output as a progress notification
+    async fn send_progress(
         &self,
-        transport: &StdioTransport,
-        id: u64,
-        params_val: &serde_json::Value,
-    ) -> anyhow::Result<()> {
-        // Get query parameter
-        let query = match params_val
-            .get("arguments")
-            .and_then(|args| args.get("query"))
-            .and_then(|q| q.as_str())
-        {
-            Some(q) => q,
-            None => {
-                return self
-                    .send_error_response(
-                        transport,
-                        id,
-                        JsonRpcErrorCode::InvalidParams,
-                        "Missing required parameter: query".to_string(),
-                    )
-                    .await;
-            }
+        transport: &dyn Transport,
+        token: &Value,
+        progress: u64,
+        output: &TaskOutput,
+    ) {
+        let notification = Message::Notification {
+            jsonrpc: "2.0".to_string(),
+            method: "notifications/progress".to_string(),
+            params: Some(json!({
+                "progressToken": token,
+                "progress": progress,
+                "message": output.text,
+                "stream": output.stream
+            })),
         };
 
-        // Search files
-        match self.mcedit.search_files(query).await {
-            Ok(results) => {
-                let obj_as_str = serde_json::to_string(&results)?;
+        if let Err(e) = transport.send(notification).await {
+            logging::warn(&format!("Failed to send progress notification: {}", e));
+        }
+    }
+
+    async fn handle_capabilities(&self, transport: &dyn Transport, id: u64) -> anyhow::Result<()> {
+        let mut capabilities = self.mcedit.capabilities(transport.name()).await;
+        capabilities["limits"]["read_many_default_bytes"] = json!(DEFAULT_READ_MANY_BYTES);
+
+        let obj_as_str = serde_json::to_string(&capabilities)?;
+        self.send_text_response(transport, id, &obj_as_str).await
+    }
+
+    async fn handle_get_config(&self, transport: &dyn Transport, id: u64) -> anyhow::Result<()> {
+        match self.mcedit.effective_config() {
+            Ok(config) => {
+                let obj_as_str = serde_json::to_string(&config)?;
                 self.send_text_response(transport, id, &obj_as_str).await?;
             }
             Err(err) => {
@@ -822,7 +5952,7 @@ impl<'a> McpHandler<'a> {
                     transport,
                     id,
                     JsonRpcErrorCode::InternalError,
-                    format!("Failed to search files: {}", err),
+                    format!("Failed to get config: {}", err),
                 )
                 .await?;
             }
@@ -831,15 +5961,16 @@ impl<'a> McpHandler<'a> {
         Ok(())
     }
 
-    async fn handle_analyze_project(
-        &self,
-        transport: &StdioTransport,
-        id: u64,
-    ) -> anyhow::Result<()> {
-        // Analyze project
-        match self.mcedit.analyze_project().await {
-            Ok(analysis) => {
-                let obj_as_str = serde_json::to_string(&analysis)?;
+    async fn handle_cache_stats(&self, transport: &dyn Transport, id: u64) -> anyhow::Result<()> {
+        let stats = self.mcedit.cache_stats();
+        let obj_as_str = serde_json::to_string(&stats)?;
+        self.send_text_response(transport, id, &obj_as_str).await
+    }
+
+    async fn handle_clear_state(&self, transport: &dyn Transport, id: u64) -> anyhow::Result<()> {
+        match self.mcedit.clear_state() {
+            Ok(result) => {
+                let obj_as_str = serde_json::to_string(&result)?;
                 self.send_text_response(transport, id, &obj_as_str).await?;
             }
             Err(err) => {
@@ -847,7 +5978,7 @@ impl<'a> McpHandler<'a> {
                     transport,
                     id,
                     JsonRpcErrorCode::InternalError,
-                    format!("Failed to analyze project: {}", err),
+                    format!("Failed to clear state: {}", err),
                 )
                 .await?;
             }
@@ -856,80 +5987,41 @@ impl<'a> McpHandler<'a> {
         Ok(())
     }
 
-    async fn handle_apply_suggestion(
+    async fn handle_create_checkpoint(
         &self,
-        transport: &StdioTransport,
+        transport: &dyn Transport,
         id: u64,
         params_val: &serde_json::Value,
     ) -> anyhow::Result<()> {
-        // Get path and suggestion parameters
-        let args = match params_val.get("arguments") {
-            Some(a) => a,
-            None => {
-                return self
-                    .send_error_response(
-                        transport,
-                        id,
-                        JsonRpcErrorCode::InvalidParams,
-                        "Missing required arguments".to_string(),
-                    )
-                    .await;
-            }
-        };
-
-        let path_str = match args.get("path").and_then(|p| p.as_str()) {
-            Some(p) => p,
+        let name = match params_val
+            .get("arguments")
+            .and_then(|args| args.get("name"))
+            .and_then(|n| n.as_str())
+        {
+            Some(n) => n,
             None => {
                 return self
                     .send_error_response(
                         transport,
                         id,
                         JsonRpcErrorCode::InvalidParams,
-                        "Missing required parameter: path".to_string(),
+                        "Missing required parameter: name".to_string(),
                     )
                     .await;
             }
         };
 
-        let suggestion = match args.get("suggestion").and_then(|s| s.as_str()) {
-            Some(s) => s,
-            None => {
-                return self
-                    .send_error_response(
-                        transport,
-                        id,
-                        JsonRpcErrorCode::InvalidParams,
-                        "Missing required parameter: suggestion".to_string(),
-                    )
-                    .await;
+        match self.mcedit.create_checkpoint(name).await {
+            Ok(result) => {
+                let obj_as_str = serde_json::to_string(&result)?;
+                self.send_text_response(transport, id, &obj_as_str).await?;
             }
-        };
-
-        let path = PathBuf::from(path_str);
-
-        // Parse suggestion and apply it
-        match self.mcedit.parse_suggestion(suggestion).await {
-            Ok(parsed_suggestion) => match self.mcedit.apply_suggestion(&path, suggestion).await {
-                Ok(result) => {
-                    let obj_as_str = serde_json::to_string(&result)?;
-                    self.send_text_response(transport, id, &obj_as_str).await?;
-                }
-                Err(err) => {
-                    self.send_error_response(
-                        transport,
-                        id,
-                        JsonRpcErrorCode::InternalError,
-                        format!("Failed to apply suggestion: {}", err),
-                    )
-                    .await?;
-                }
-            },
             Err(err) => {
                 self.send_error_response(
                     transport,
                     id,
-                    JsonRpcErrorCode::InvalidParams,
-                    format!("Failed to parse suggestion: {}", err),
+                    JsonRpcErrorCode::InternalError,
+                    format!("Failed to create checkpoint: {}", err),
                 )
                 .await?;
             }
@@ -938,60 +6030,20 @@ impl<'a> McpHandler<'a> {
         Ok(())
     }
 
-    async fn handle_generate_diff(
+    async fn handle_get_file_tree_diff(
         &self,
-        transport: &StdioTransport,
+        transport: &dyn Transport,
         id: u64,
         params_val: &serde_json::Value,
     ) -> anyhow::Result<()> {
-        // Get original and modified parameters
-        let args = match params_val.get("arguments") {
-            Some(a) => a,
-            None => {
-                return self
-                    .send_error_response(
-                        transport,
-                        id,
-                        JsonRpcErrorCode::InvalidParams,
-                        "Missing required arguments".to_string(),
-                    )
-                    .await;
-            }
-        };
-
-        let original = match args.get("original").and_then(|o| o.as_str()) {
-            Some(o) => o,
-            None => {
-                return self
-                    .send_error_response(
-                        transport,
-                        id,
-                        JsonRpcErrorCode::InvalidParams,
-                        "Missing required parameter: original".to_string(),
-                    )
-                    .await;
-            }
-        };
-
-        let modified = match args.get("modified").and_then(|m| m.as_str()) {
-            Some(m) => m,
-            None => {
-                return self
-                    .send_error_response(
-                        transport,
-                        id,
-                        JsonRpcErrorCode::InvalidParams,
-                        "Missing required parameter: modified".to_string(),
-                    )
-                    .await;
-            }
-        };
+        let checkpoint = params_val
+            .get("arguments")
+            .and_then(|args| args.get("checkpoint"))
+            .and_then(|c| c.as_str());
 
-        // Generate diff
-        match self.mcedit.generate_diff(original, modified).await {
-            Ok(diff) => {
-                let result_json = json!({ "diff": diff });
-                let obj_as_str = serde_json::to_string(&result_json)?;
+        match self.mcedit.file_tree_diff(checkpoint).await {
+            Ok(result) => {
+                let obj_as_str = serde_json::to_string(&result)?;
                 self.send_text_response(transport, id, &obj_as_str).await?;
             }
             Err(err) => {
@@ -999,7 +6051,7 @@ impl<'a> McpHandler<'a> {
                     transport,
                     id,
                     JsonRpcErrorCode::InternalError,
-                    format!("Failed to generate diff: {}", err),
+                    format!("Failed to diff file tree: {}", err),
                 )
                 .await?;
             }
@@ -1010,7 +6062,7 @@ impl<'a> McpHandler<'a> {
 
     async fn handle_change_directory(
         &mut self,
-        transport: &StdioTransport,
+        transport: &dyn Transport,
         id: u64,
         params_val: &serde_json::Value,
     ) -> anyhow::Result<()> {
@@ -1035,11 +6087,12 @@ impl<'a> McpHandler<'a> {
 
         // Change directory
         match self.mcedit.change_current_directory(directory.to_string()) {
-            Ok(()) => {
+            Ok(writable) => {
                 let current_dir = self.mcedit.get_current_directory();
                 let result_json = json!({
                     "success": true,
-                    "directory": current_dir.to_string_lossy()
+                    "directory": to_display_path(&current_dir),
+                    "writable": writable
                 });
                 let obj_as_str = serde_json::to_string(&result_json)?;
                 self.send_text_response(transport, id, &obj_as_str).await?;
@@ -1060,7 +6113,7 @@ impl<'a> McpHandler<'a> {
 
     async fn handle_create_file(
         &self,
-        transport: &StdioTransport,
+        transport: &dyn Transport,
         id: u64,
         params_val: &serde_json::Value,
     ) -> anyhow::Result<()> {
@@ -1107,14 +6160,22 @@ impl<'a> McpHandler<'a> {
             }
         };
 
+        let create_dirs = args
+            .get("create_dirs")
+            .and_then(|c| c.as_bool())
+            .unwrap_or(true);
+
         let path = PathBuf::from(path_str);
 
         // Create the file
-        match self.mcedit.create_file(&path, content).await {
-            Ok(()) => {
+        match self.mcedit.create_file(&path, content, create_dirs).await {
+            Ok((created_path, summary)) => {
                 let result_json = json!({
                     "success": true,
-                    "path": path.to_string_lossy()
+                    "path": to_display_path(&created_path),
+                    "lines_before": summary.lines_before,
+                    "lines_after": summary.lines_after,
+                    "bytes_changed": summary.bytes_changed
                 });
                 let obj_as_str = serde_json::to_string(&result_json)?;
                 self.send_text_response(transport, id, &obj_as_str).await?;
@@ -1135,7 +6196,7 @@ impl<'a> McpHandler<'a> {
 
     async fn handle_rename_file(
         &self,
-        transport: &StdioTransport,
+        transport: &dyn Transport,
         id: u64,
         params_val: &serde_json::Value,
     ) -> anyhow::Result<()> {
@@ -1182,16 +6243,27 @@ impl<'a> McpHandler<'a> {
             }
         };
 
+        let overwrite = args
+            .get("overwrite")
+            .and_then(|o| o.as_bool())
+            .unwrap_or(false);
+
         let from_path = PathBuf::from(from_path_str);
         let to_path = PathBuf::from(to_path_str);
 
         // Rename the file
-        match self.mcedit.rename_file(&from_path, &to_path).await {
-            Ok(()) => {
+        match self
+            .mcedit
+            .rename_file(&from_path, &to_path, overwrite)
+            .await
+        {
+            Ok(destination) => {
+                let project = self.mcedit.get_current_directory();
+                let destination = destination.strip_prefix(&project).unwrap_or(&destination);
                 let result_json = json!({
                     "success": true,
-                    "from_path": from_path.to_string_lossy(),
-                    "to_path": to_path.to_string_lossy()
+                    "from_path": to_display_path(&from_path),
+                    "to_path": to_display_path(destination)
                 });
                 let obj_as_str = serde_json::to_string(&result_json)?;
                 self.send_text_response(transport, id, &obj_as_str).await?;
@@ -1212,7 +6284,7 @@ impl<'a> McpHandler<'a> {
 
     async fn handle_delete_file(
         &self,
-        transport: &StdioTransport,
+        transport: &dyn Transport,
         id: u64,
         params_val: &serde_json::Value,
     ) -> anyhow::Result<()> {
@@ -1242,7 +6314,7 @@ impl<'a> McpHandler<'a> {
             Ok(()) => {
                 let result_json = json!({
                     "success": true,
-                    "path": path.to_string_lossy()
+                    "path": to_display_path(&path)
                 });
                 let obj_as_str = serde_json::to_string(&result_json)?;
                 self.send_text_response(transport, id, &obj_as_str).await?;
@@ -1263,18 +6335,39 @@ impl<'a> McpHandler<'a> {
 
     async fn handle_resources_list(
         &self,
-        transport: &StdioTransport,
+        transport: &dyn Transport,
         id: u64,
+        params: Option<serde_json::Value>,
     ) -> anyhow::Result<()> {
         logging::info("Handling resources/list request");
 
-        // Create a response with an empty resources list
+        // No resources are exposed yet, but page through them the same way as
+        // tools so large resource lists stay bounded once they exist
+        let resources: Vec<Value> = Vec::new();
+
+        let cursor = params
+            .as_ref()
+            .and_then(|p| p.get("cursor"))
+            .and_then(|c| c.as_str());
+
+        let (page, next_cursor) = match paginate(resources, cursor) {
+            Ok(result) => result,
+            Err(message) => {
+                return self
+                    .send_error_response(transport, id, JsonRpcErrorCode::InvalidParams, message)
+                    .await;
+            }
+        };
+
+        let mut result = json!({ "resources": page });
+        if let Some(next_cursor) = next_cursor {
+            result["nextCursor"] = json!(next_cursor);
+        }
+
         let response = Message::Response {
             jsonrpc: "2.0".to_string(),
             id,
-            result: Some(json!({
-                "resources": []
-            })),
+            result: Some(result),
             error: None,
         };
 
@@ -1296,7 +6389,83 @@ impl<'a> McpHandler<'a> {
         }
     }
 
-    async fn handle_prompts_list(&self, transport: &StdioTransport, id: u64) -> anyhow::Result<()> {
+    async fn handle_resource_templates_list(
+        &self,
+        transport: &dyn Transport,
+        id: u64,
+    ) -> anyhow::Result<()> {
+        logging::info("Handling resources/templates/list request");
+
+        // Any project file can be read on demand, which scales to large
+        // projects where listing every file would not
+        let response = Message::Response {
+            jsonrpc: "2.0".to_string(),
+            id,
+            result: Some(json!({
+                "resourceTemplates": [{
+                    "uriTemplate": "file:///{path}",
+                    "name": "Project file",
+                    "description": "A text file in the project, by its path relative to the project root"
+                }]
+            })),
+            error: None,
+        };
+
+        transport.send(response).await?;
+        Ok(())
+    }
+
+    async fn handle_resources_read(
+        &self,
+        transport: &dyn Transport,
+        id: u64,
+        params: Option<serde_json::Value>,
+    ) -> anyhow::Result<()> {
+        logging::info("Handling resources/read request");
+
+        let uri = match params
+            .as_ref()
+            .and_then(|p| p.get("uri"))
+            .and_then(|u| u.as_str())
+        {
+            Some(uri) => uri,
+            None => {
+                return self
+                    .send_error_response(
+                        transport,
+                        id,
+                        JsonRpcErrorCode::InvalidParams,
+                        "Missing required parameter: uri".to_string(),
+                    )
+                    .await;
+            }
+        };
+
+        match self.mcedit.read_resource(uri).await {
+            Ok(result) => {
+                let response = Message::Response {
+                    jsonrpc: "2.0".to_string(),
+                    id,
+                    result: Some(result),
+                    error: None,
+                };
+                transport.send(response).await?;
+            }
+            Err(err) => {
+                self.send_error_response(
+                    transport,
+                    id,
+                    JsonRpcErrorCode::InternalError,
+                    format!("Failed to read resource: {}", err),
+                )
+                .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn handle_prompts_list(&self, transport: &dyn Transport, id: u64) -> anyhow::Result<()> {
         logging::info("Handling prompts/list request");
 
         // Create a response with an empty prompts list
@@ -1329,7 +6498,7 @@ impl<'a> McpHandler<'a> {
 
     async fn send_text_response(
         &self,
-        transport: &StdioTransport,
+        transport: &dyn Transport,
         id: u64,
         text: &str,
     ) -> anyhow::Result<()> {
@@ -1368,7 +6537,7 @@ impl<'a> McpHandler<'a> {
 
     async fn send_error_response(
         &self,
-        transport: &StdioTransport,
+        transport: &dyn Transport,
         id: u64,
         code: JsonRpcErrorCode,
         message: String,
@@ -0,0 +1,62 @@
+use std::time::Duration;
+use tokio::time::{interval_at, Instant, Interval, MissedTickBehavior};
+
+/// Beats at a fixed interval while the server runs, so a log shows the
+/// server is alive while it waits for messages, and how long it has idled
+pub struct Heartbeat {
+    interval: Interval,
+    last_message: Instant,
+    // Whether beats are also sent to the client as $/log notifications
+    notify_client: bool,
+}
+
+impl Heartbeat {
+    pub fn new(period: Duration, notify_client: bool) -> Self {
+        // The first beat comes one period in, not right away
+        let mut interval = interval_at(Instant::now() + period, period);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        Self {
+            interval,
+            last_message: Instant::now(),
+            notify_client,
+        }
+    }
+
+    pub fn notify_client(&self) -> bool {
+        self.notify_client
+    }
+
+    /// Notes that a message arrived, restarting the idle time
+    pub fn message_received(&mut self) {
+        self.last_message = Instant::now();
+    }
+
+    /// Waits for the next beat and returns how long no message has arrived
+    pub async fn tick(&mut self) -> Duration {
+        self.interval.tick().await;
+        self.last_message.elapsed()
+    }
+}
+
+/// Waits for the next beat of `heartbeat`, or forever without one
+pub async fn next_beat(heartbeat: Option<&mut Heartbeat>) -> Duration {
+    match heartbeat {
+        Some(heartbeat) => heartbeat.tick().await,
+        None => std::future::pending().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_beats_report_idle_time() {
+        let mut heartbeat = Heartbeat::new(Duration::from_secs(30), false);
+
+        assert_eq!(heartbeat.tick().await, Duration::from_secs(30));
+        tokio::time::advance(Duration::from_secs(10)).await;
+        heartbeat.message_received();
+        assert_eq!(heartbeat.tick().await, Duration::from_secs(20));
+    }
+}
@@ -0,0 +1,381 @@
+use async_trait::async_trait;
+use axum::extract::{Query, Request, State};
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::Router;
+use futures::Stream;
+use serde::Deserialize;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use tokio::sync::{broadcast, mpsc};
+
+use crate::mcp::stdio::{Error, Message, Transport};
+use crate::shared::logging;
+
+// Header carrying the session id on client requests and SSE responses
+pub const SESSION_HEADER: &str = "Mcp-Session-Id";
+
+// Path of the SSE stream carrying server-to-client messages
+const SSE_PATH: &str = "/sse";
+
+// Path clients POST JSON-RPC messages to
+const MESSAGE_PATH: &str = "/message";
+
+// The SSE stream of the connected client
+struct Session {
+    id: String,
+    outgoing: mpsc::UnboundedSender<Message>,
+}
+
+struct SharedState {
+    incoming: broadcast::Sender<Result<Message, Error>>,
+    // Bearer token every request must present, if configured
+    auth_token: Option<String>,
+    // The handler serves a single client, so only one session is active at a
+    // time; a new SSE connection replaces the previous one
+    session: Mutex<Option<Session>>,
+}
+
+// Address the http transport listens on when neither the command line nor
+// the config says
+pub const DEFAULT_HTTP_ADDRESS: &str = "127.0.0.1:3000";
+
+/// MCP transport over HTTP with Server-Sent Events: clients open an SSE stream
+/// at `/sse` to receive server messages and POST JSON-RPC messages to
+/// `/message`, identifying their session with the `sessionId` query parameter
+/// or the `Mcp-Session-Id` header. When an auth token is configured, every
+/// request must carry it as `Authorization: Bearer <token>`
+pub struct HttpSseTransport {
+    state: Arc<SharedState>,
+    // Subscribed when binding, so messages posted before the first
+    // receive() are kept for it; later calls subscribe afresh
+    receiver: Mutex<Option<broadcast::Receiver<Result<Message, Error>>>>,
+}
+
+#[derive(Deserialize)]
+struct SessionQuery {
+    #[serde(rename = "sessionId")]
+    session_id: Option<String>,
+}
+
+impl HttpSseTransport {
+    pub async fn bind(
+        addr: SocketAddr,
+        auth_token: Option<String>,
+    ) -> Result<(Self, broadcast::Sender<Result<Message, Error>>), Error> {
+        if auth_token.is_none() && !addr.ip().is_loopback() {
+            logging::warn(&format!(
+                "Serving MCP on non-loopback address {} without an auth token; anyone who can reach it can edit files",
+                addr
+            ));
+        }
+
+        let (sender, receiver) = broadcast::channel(100);
+        let state = Arc::new(SharedState {
+            incoming: sender.clone(),
+            auth_token,
+            session: Mutex::new(None),
+        });
+
+        let app = Router::new()
+            .route(SSE_PATH, get(handle_sse))
+            .route(MESSAGE_PATH, post(handle_message))
+            .layer(middleware::from_fn_with_state(state.clone(), require_auth))
+            .with_state(state.clone());
+
+        let listener = tokio::net::TcpListener::bind(addr)
+            .await
+            .map_err(|e| Error::Io(format!("Failed to bind {}: {}", addr, e)))?;
+
+        logging::info(&format!(
+            "MCP HTTP transport listening on http://{}{}",
+            addr, SSE_PATH
+        ));
+
+        tokio::spawn(async move {
+            if let Err(e) = axum::serve(listener, app).await {
+                logging::error(&format!("HTTP server error: {}", e));
+            }
+        });
+
+        let receiver = Mutex::new(Some(receiver));
+        Ok((Self { state, receiver }, sender))
+    }
+}
+
+// Compares two tokens in time independent of where they differ
+fn tokens_match(expected: &str, provided: &str) -> bool {
+    let expected = expected.as_bytes();
+    let provided = provided.as_bytes();
+
+    if expected.len() != provided.len() {
+        return false;
+    }
+
+    expected
+        .iter()
+        .zip(provided)
+        .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+        == 0
+}
+
+// Rejects requests that don't carry the configured bearer token
+async fn require_auth(
+    State(state): State<Arc<SharedState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let expected = match &state.auth_token {
+        Some(token) => token,
+        None => return next.run(request).await,
+    };
+
+    let provided = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+
+    match provided {
+        Some(token) if tokens_match(expected, token) => next.run(request).await,
+        _ => {
+            logging::warn(&format!(
+                "Rejected unauthenticated request to {}",
+                request.uri().path()
+            ));
+            (
+                StatusCode::UNAUTHORIZED,
+                [(header::WWW_AUTHENTICATE, "Bearer")],
+                "Unauthorized",
+            )
+                .into_response()
+        }
+    }
+}
+
+async fn handle_sse(State(state): State<Arc<SharedState>>) -> Response {
+    let session_id = uuid::Uuid::new_v4().to_string();
+    let (outgoing, rx) = mpsc::unbounded_channel();
+
+    {
+        let mut session = match state.session.lock() {
+            Ok(session) => session,
+            Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        };
+        if let Some(previous) = session.as_ref() {
+            logging::info(&format!("Replacing SSE session {}", previous.id));
+        }
+        *session = Some(Session {
+            id: session_id.clone(),
+            outgoing,
+        });
+    }
+
+    logging::info(&format!("Opened SSE session {}", session_id));
+
+    // The first event tells the client where to POST its messages
+    let endpoint = Event::default()
+        .event("endpoint")
+        .data(format!("{}?sessionId={}", MESSAGE_PATH, session_id));
+
+    let messages = futures::stream::unfold(rx, |mut rx| async move {
+        let message = rx.recv().await?;
+        let event = match serde_json::to_string(&message) {
+            Ok(json) => Event::default().event("message").data(json),
+            Err(e) => Event::default()
+                .event("error")
+                .data(format!("JSON serialization error: {}", e)),
+        };
+        Some((Ok::<_, std::convert::Infallible>(event), rx))
+    });
+
+    let stream =
+        futures::StreamExt::chain(futures::stream::once(async move { Ok(endpoint) }), messages);
+
+    let mut response = Sse::new(stream)
+        .keep_alive(KeepAlive::default())
+        .into_response();
+    if let Ok(value) = HeaderValue::from_str(&session_id) {
+        response.headers_mut().insert(SESSION_HEADER, value);
+    }
+    response
+}
+
+async fn handle_message(
+    State(state): State<Arc<SharedState>>,
+    Query(query): Query<SessionQuery>,
+    headers: HeaderMap,
+    body: String,
+) -> Response {
+    let session_id = query.session_id.or_else(|| {
+        headers
+            .get(SESSION_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.to_string())
+    });
+
+    let session_id = match session_id {
+        Some(id) => id,
+        None => {
+            return (StatusCode::BAD_REQUEST, "Missing session id").into_response();
+        }
+    };
+
+    let is_active = state
+        .session
+        .lock()
+        .map(|session| {
+            session
+                .as_ref()
+                .map(|s| s.id == session_id)
+                .unwrap_or(false)
+        })
+        .unwrap_or(false);
+    if !is_active {
+        return (StatusCode::NOT_FOUND, "Unknown session").into_response();
+    }
+
+    let message = match serde_json::from_str::<Message>(&body) {
+        Ok(message) => message,
+        Err(e) => {
+            logging::warn(&format!("Rejected invalid JSON-RPC message: {}", e));
+            return (StatusCode::BAD_REQUEST, format!("Invalid message: {}", e)).into_response();
+        }
+    };
+
+    if state.incoming.send(Ok(message)).is_err() {
+        return (StatusCode::SERVICE_UNAVAILABLE, "Server is shutting down").into_response();
+    }
+
+    // Responses are delivered over the SSE stream
+    StatusCode::ACCEPTED.into_response()
+}
+
+#[async_trait]
+impl Transport for HttpSseTransport {
+    fn name(&self) -> &'static str {
+        "http"
+    }
+
+    async fn send(&self, message: Message) -> Result<(), Error> {
+        let mut session = self
+            .state
+            .session
+            .lock()
+            .map_err(|_| Error::Other("Failed to lock session".into()))?;
+
+        // A client going away must not take the server down with it, so
+        // messages without a connected session are dropped rather than failing
+        let active = match session.as_ref() {
+            Some(active) => active,
+            None => {
+                logging::debug("Dropping message, no SSE session is connected");
+                return Ok(());
+            }
+        };
+
+        if active.outgoing.send(message).is_err() {
+            logging::warn(&format!(
+                "SSE session {} disconnected, dropping message",
+                active.id
+            ));
+            *session = None;
+        }
+
+        Ok(())
+    }
+
+    fn receive(&self) -> Pin<Box<dyn Stream<Item = Result<Message, Error>> + Send>> {
+        let rx = self
+            .receiver
+            .lock()
+            .ok()
+            .and_then(|mut receiver| receiver.take())
+            .unwrap_or_else(|| self.state.incoming.subscribe());
+        Box::pin(futures::stream::unfold(rx, |mut rx| async move {
+            loop {
+                match rx.recv().await {
+                    Ok(msg) => return Some((msg, rx)),
+                    // A burst of POSTs can overflow the channel while a slow
+                    // tool call runs; that loses messages, not the transport
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        logging::warn(&format!(
+                            "Dropped {} incoming messages that arrived faster than they were handled",
+                            skipped
+                        ));
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        }))
+    }
+
+    async fn close(&self) -> Result<(), Error> {
+        if let Ok(mut session) = self.state.session.lock() {
+            *session = None;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+
+    fn notification(method: String) -> Result<Message, Error> {
+        Ok(Message::Notification {
+            jsonrpc: "2.0".to_string(),
+            method,
+            params: None,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_message_before_receive_is_kept() {
+        let (transport, sender) = HttpSseTransport::bind("127.0.0.1:0".parse().unwrap(), None)
+            .await
+            .unwrap();
+
+        // Posted while the server is still setting up, before it receives
+        sender.send(notification("initialize".to_string())).unwrap();
+
+        assert!(matches!(
+            transport.receive().next().await,
+            Some(Ok(Message::Notification { method, .. })) if method == "initialize"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_receive_survives_channel_overflow() {
+        let (transport, sender) = HttpSseTransport::bind("127.0.0.1:0".parse().unwrap(), None)
+            .await
+            .unwrap();
+        let mut messages = transport.receive();
+
+        for i in 0..150 {
+            sender.send(notification(format!("m{}", i))).unwrap();
+        }
+
+        // The oldest messages are lost to the overflow, but the stream goes on
+        let mut received = Vec::new();
+        while received.last().map(String::as_str) != Some("m149") {
+            match messages.next().await {
+                Some(Ok(Message::Notification { method, .. })) => received.push(method),
+                other => panic!("unexpected item: {:?}", other),
+            }
+        }
+        assert_ne!(received[0], "m0");
+        assert!(received.len() >= 100);
+
+        sender.send(notification("after".to_string())).unwrap();
+        assert!(matches!(
+            messages.next().await,
+            Some(Ok(Message::Notification { method, .. })) if method == "after"
+        ));
+    }
+}
@@ -0,0 +1,340 @@
+use crate::mcp::stdio::{Error, Message, Transport};
+use async_trait::async_trait;
+use futures::Stream;
+use std::pin::Pin;
+use std::sync::Mutex;
+
+// Transport for tests that delivers a fixed list of messages and then ends,
+// keeping everything the server sends
+pub struct MemoryTransport {
+    incoming: Mutex<Vec<Message>>,
+    sent: Mutex<Vec<Message>>,
+}
+
+impl MemoryTransport {
+    pub fn new(incoming: Vec<Message>) -> Self {
+        Self {
+            incoming: Mutex::new(incoming),
+            sent: Mutex::new(Vec::new()),
+        }
+    }
+
+    // The result, or else the error, the server sent in reply to request `id`
+    pub fn response(&self, id: u64) -> Option<serde_json::Value> {
+        self.sent
+            .lock()
+            .unwrap()
+            .iter()
+            .find_map(|message| match message {
+                Message::Response {
+                    id: response_id,
+                    result,
+                    error,
+                    ..
+                } if *response_id == id => result.clone().or_else(|| error.clone()),
+                _ => None,
+            })
+    }
+}
+
+#[async_trait]
+impl Transport for MemoryTransport {
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+
+    async fn send(&self, message: Message) -> Result<(), Error> {
+        self.sent.lock().unwrap().push(message);
+        Ok(())
+    }
+
+    fn receive(&self) -> Pin<Box<dyn Stream<Item = Result<Message, Error>> + Send>> {
+        let incoming = std::mem::take(&mut *self.incoming.lock().unwrap());
+        Box::pin(futures::stream::iter(incoming.into_iter().map(Ok)))
+    }
+
+    async fn close(&self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::mcedit::McEdit;
+    use serde_json::json;
+
+    fn request(id: u64, method: &str, params: serde_json::Value) -> Message {
+        Message::Request {
+            jsonrpc: "2.0".to_string(),
+            method: method.to_string(),
+            id,
+            params: Some(params),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_serves_requests_over_memory_transport() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        std::fs::write(temp_dir.path().join("hello.txt"), "hello\n").unwrap();
+        let dir = temp_dir.path().to_string_lossy().to_string();
+        let mut mcedit = McEdit::new(None, Some(dir), false).unwrap();
+
+        let transport = MemoryTransport::new(vec![
+            request(0, "ping", json!({})),
+            request(1, "initialize", json!({})),
+            request(
+                2,
+                "tools/call",
+                json!({ "name": "read_file", "arguments": { "path": "hello.txt" } }),
+            ),
+            request(3, "no/such_method", json!({})),
+            request(4, "resources/read", json!({ "uri": "file:///hello.txt" })),
+        ]);
+        mcedit.serve(&transport).await.unwrap();
+
+        assert_eq!(transport.response(0).unwrap(), json!({}));
+        let capabilities = &transport.response(1).unwrap()["capabilities"];
+        assert_eq!(capabilities["tools"]["listChanged"], false);
+        assert_eq!(capabilities["resources"]["subscribe"], false);
+        let read = transport.response(2).unwrap();
+        assert!(read["content"][0]["text"]
+            .as_str()
+            .unwrap()
+            .contains("hello"));
+        assert_eq!(transport.response(3).unwrap()["code"], -32601);
+        let resource = transport.response(4).unwrap();
+        assert_eq!(resource["contents"][0]["text"], "hello\n");
+        assert_eq!(resource["contents"][0]["mimeType"], "text/plain");
+    }
+
+    #[tokio::test]
+    async fn test_client_roots_limit_file_access() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let shared = temp_dir.path().join("shared");
+        std::fs::create_dir(&shared).unwrap();
+        std::fs::write(shared.join("open.txt"), "open\n").unwrap();
+        std::fs::write(temp_dir.path().join("private.txt"), "private\n").unwrap();
+        let dir = temp_dir.path().to_string_lossy().to_string();
+        let mut mcedit = McEdit::new(None, Some(dir), false).unwrap();
+
+        let read = |id, path: &str| {
+            request(
+                id,
+                "tools/call",
+                json!({ "name": "read_file", "arguments": { "path": path } }),
+            )
+        };
+        let transport = MemoryTransport::new(vec![
+            request(1, "initialize", json!({ "capabilities": { "roots": {} } })),
+            Message::Notification {
+                jsonrpc: "2.0".to_string(),
+                method: "notifications/initialized".to_string(),
+                params: None,
+            },
+            // Reply to the server's roots/list, the first request it sends
+            Message::Response {
+                jsonrpc: "2.0".to_string(),
+                id: 1,
+                result: Some(json!({
+                    "roots": [{ "uri": format!("file://{}", shared.display()) }]
+                })),
+                error: None,
+            },
+            read(2, "shared/open.txt"),
+            read(3, "private.txt"),
+        ]);
+        mcedit.serve(&transport).await.unwrap();
+
+        assert!(transport.response(2).unwrap()["content"][0]["text"]
+            .as_str()
+            .unwrap()
+            .contains("open"));
+        let denied = transport.response(3).unwrap();
+        assert!(denied["message"]
+            .as_str()
+            .unwrap()
+            .contains("outside the roots"));
+    }
+
+    #[tokio::test]
+    async fn test_line_edits_report_the_path_as_given() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        std::fs::write(temp_dir.path().join("notes.txt"), "one two\nthree\n").unwrap();
+        let dir = temp_dir.path().to_string_lossy().to_string();
+        let mut mcedit = McEdit::new(None, Some(dir), false).unwrap();
+
+        let call = |id, name: &str, arguments: serde_json::Value| {
+            request(
+                id,
+                "tools/call",
+                json!({ "name": name, "arguments": arguments }),
+            )
+        };
+        let transport = MemoryTransport::new(vec![
+            request(0, "initialize", json!({})),
+            call(
+                1,
+                "split_line",
+                json!({ "path": "notes.txt", "line": 1, "column": 4 }),
+            ),
+            call(
+                2,
+                "replace_matching_region",
+                json!({ "path": "notes.txt", "pattern": "three", "replacement": "four" }),
+            ),
+            call(
+                3,
+                "multi_region_edit",
+                json!({
+                    "path": "notes.txt",
+                    "edits": [{ "start": 1, "end": 1, "content": "zero" }]
+                }),
+            ),
+        ]);
+        mcedit.serve(&transport).await.unwrap();
+
+        for id in 1..=3 {
+            let text = transport.response(id).unwrap()["content"][0]["text"]
+                .as_str()
+                .unwrap()
+                .to_string();
+            let result: serde_json::Value = serde_json::from_str(&text).unwrap();
+            assert_eq!(result["path"], "notes.txt", "{}", text);
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_set_permissions_refuses_special_bits() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let script = temp_dir.path().join("run.sh");
+        std::fs::write(&script, "#!/bin/sh\n").unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o644)).unwrap();
+        let dir = temp_dir.path().to_string_lossy().to_string();
+        let mut mcedit = McEdit::new(None, Some(dir), false).unwrap();
+
+        let chmod = |id, mode: &str| {
+            request(
+                id,
+                "tools/call",
+                json!({ "name": "set_permissions", "arguments": { "path": "run.sh", "mode": mode } }),
+            )
+        };
+        let transport = MemoryTransport::new(vec![
+            request(0, "initialize", json!({})),
+            chmod(1, "7777"),
+            chmod(2, "4755"),
+            chmod(3, "755"),
+        ]);
+        mcedit.serve(&transport).await.unwrap();
+
+        assert_eq!(transport.response(1).unwrap()["code"], -32602);
+        assert_eq!(transport.response(2).unwrap()["code"], -32602);
+        assert!(transport.response(3).unwrap()["content"].is_array());
+        let mode = std::fs::metadata(&script).unwrap().permissions().mode();
+        assert_eq!(mode & 0o7777, 0o755);
+    }
+
+    // The text `tool` returns when the client's only root is `shared`, in a
+    // project where private.txt lies outside it
+    async fn output_within_shared_root(tool: &str, arguments: serde_json::Value) -> String {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let shared = temp_dir.path().join("shared");
+        std::fs::create_dir(&shared).unwrap();
+        std::fs::write(shared.join("open.txt"), "needle open\n").unwrap();
+        std::fs::write(temp_dir.path().join("private.txt"), "needle secret\n").unwrap();
+        let dir = temp_dir.path().to_string_lossy().to_string();
+        let mut mcedit = McEdit::new(None, Some(dir), false).unwrap();
+
+        let transport = MemoryTransport::new(vec![
+            request(1, "initialize", json!({ "capabilities": { "roots": {} } })),
+            Message::Notification {
+                jsonrpc: "2.0".to_string(),
+                method: "notifications/initialized".to_string(),
+                params: None,
+            },
+            Message::Response {
+                jsonrpc: "2.0".to_string(),
+                id: 1,
+                result: Some(json!({
+                    "roots": [{ "uri": format!("file://{}", shared.display()) }]
+                })),
+                error: None,
+            },
+            request(
+                2,
+                "tools/call",
+                json!({ "name": tool, "arguments": arguments }),
+            ),
+        ]);
+        mcedit.serve(&transport).await.unwrap();
+
+        let output = transport.response(2).unwrap()["content"][0]["text"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        assert_eq!(
+            std::fs::read_to_string(temp_dir.path().join("private.txt")).unwrap(),
+            "needle secret\n"
+        );
+        output
+    }
+
+    #[tokio::test]
+    async fn test_client_roots_limit_search_files() {
+        let output = output_within_shared_root("search_files", json!({ "query": "needle" })).await;
+        assert!(output.contains("open.txt"));
+        assert!(!output.contains("private") && !output.contains("secret"));
+    }
+
+    #[tokio::test]
+    async fn test_client_roots_limit_count_matches() {
+        let output = output_within_shared_root("count_matches", json!({ "query": "needle" })).await;
+        let counts: serde_json::Value = serde_json::from_str(&output).unwrap();
+        assert_eq!(counts["total"], 1);
+        assert!(!output.contains("private"));
+    }
+
+    #[tokio::test]
+    async fn test_client_roots_limit_find_references() {
+        let output =
+            output_within_shared_root("find_references", json!({ "identifier": "needle" })).await;
+        assert!(output.contains("open.txt"));
+        assert!(!output.contains("private"));
+    }
+
+    #[tokio::test]
+    async fn test_client_roots_limit_replace_in_files() {
+        let output = output_within_shared_root(
+            "replace_in_files",
+            json!({ "query": "needle", "replacement": "pin" }),
+        )
+        .await;
+        assert!(output.contains("open.txt"));
+        assert!(!output.contains("private"));
+    }
+
+    #[tokio::test]
+    async fn test_client_roots_limit_list_files() {
+        let output = output_within_shared_root("list_files", json!({})).await;
+        assert!(output.contains("open.txt"));
+        assert!(!output.contains("private"));
+    }
+
+    #[tokio::test]
+    async fn test_client_roots_limit_directory_tree() {
+        let output = output_within_shared_root("directory_tree", json!({})).await;
+        assert!(output.contains("open.txt"));
+        assert!(!output.contains("private"));
+    }
+
+    #[tokio::test]
+    async fn test_client_roots_limit_analyze_project() {
+        let output = output_within_shared_root("analyze_project", json!({})).await;
+        let analysis: serde_json::Value = serde_json::from_str(&output).unwrap();
+        assert_eq!(analysis["stats"]["total_files"], 1);
+    }
+}
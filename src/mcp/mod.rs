@@ -1,2 +1,9 @@
 pub mod handler;
+pub mod heartbeat;
+pub mod http;
+#[cfg(test)]
+pub mod memory;
+pub mod rate_limit;
+pub mod signals;
 pub mod stdio;
+pub mod tools;
@@ -0,0 +1,76 @@
+use std::time::Instant;
+
+// Burst size used when only a rate is configured
+pub const DEFAULT_RATE_LIMIT_BURST: u32 = 10;
+
+/// Token bucket limiting how many requests a connection may make per second.
+/// The bucket holds up to `burst` tokens and refills at `rate` tokens per
+/// second; each request consumes one token
+pub struct RateLimiter {
+    rate: f64,
+    burst: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(requests_per_second: f64, burst: u32) -> Self {
+        // Always allow at least one request to get through
+        let burst = f64::from(burst.max(1));
+        Self {
+            rate: requests_per_second,
+            burst,
+            tokens: burst,
+            last_refill: Instant::now(),
+        }
+    }
+
+    /// Takes a token for a request, returning false if the bucket is empty
+    pub fn try_acquire(&mut self) -> bool {
+        self.try_acquire_at(Instant::now())
+    }
+
+    fn try_acquire_at(&mut self, now: Instant) -> bool {
+        let elapsed = now
+            .saturating_duration_since(self.last_refill)
+            .as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.burst);
+        self.last_refill = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_limits_bursts_and_refills_over_time() {
+        let mut limiter = RateLimiter::new(2.0, 3);
+        let start = limiter.last_refill;
+
+        assert!(limiter.try_acquire_at(start));
+        assert!(limiter.try_acquire_at(start));
+        assert!(limiter.try_acquire_at(start));
+        assert!(!limiter.try_acquire_at(start));
+
+        // Half a second at 2 requests per second refills one token
+        let later = start + Duration::from_millis(500);
+        assert!(limiter.try_acquire_at(later));
+        assert!(!limiter.try_acquire_at(later));
+
+        // Refill is capped at the burst size
+        let much_later = later + Duration::from_secs(60);
+        for _ in 0..3 {
+            assert!(limiter.try_acquire_at(much_later));
+        }
+        assert!(!limiter.try_acquire_at(much_later));
+    }
+}
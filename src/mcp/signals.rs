@@ -0,0 +1,68 @@
+#[cfg(unix)]
+use tokio::signal::unix::{signal, Signal, SignalKind};
+
+/// Listens for SIGHUP, the conventional request for a long-running server
+/// to reload its configuration. On platforms without it, or if the handler
+/// cannot be installed, it simply never fires
+pub struct HangupListener {
+    #[cfg(unix)]
+    signal: Option<Signal>,
+}
+
+impl HangupListener {
+    pub fn new() -> Self {
+        #[cfg(unix)]
+        {
+            let signal = match signal(SignalKind::hangup()) {
+                Ok(signal) => Some(signal),
+                Err(err) => {
+                    crate::shared::logging::warn(&format!(
+                        "Failed to install SIGHUP handler, config reload disabled: {}",
+                        err
+                    ));
+                    None
+                }
+            };
+            Self { signal }
+        }
+
+        #[cfg(not(unix))]
+        Self {}
+    }
+
+    /// Waits for the next SIGHUP
+    pub async fn recv(&mut self) {
+        #[cfg(unix)]
+        if let Some(signal) = self.signal.as_mut() {
+            if signal.recv().await.is_some() {
+                return;
+            }
+            // The signal stream has closed, so no more will arrive
+            self.signal = None;
+        }
+
+        std::future::pending::<()>().await
+    }
+}
+
+/// Resolves when the process is asked to stop with Ctrl-C, or SIGTERM on
+/// Unix, so the server can shut down cleanly
+pub async fn shutdown_signal() {
+    #[cfg(unix)]
+    let terminate = async {
+        match signal(SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(_) => std::future::pending::<()>().await,
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = terminate => {}
+    }
+}
@@ -10,6 +10,11 @@ use tokio::sync::broadcast;
 
 use tokio::io::AsyncBufReadExt;
 
+use crate::shared::logging::{self, LogLevel};
+
+// Bytes of a message logged before the rest is cut
+const MAX_LOGGED_PAYLOAD: usize = 500;
+
 #[derive(thiserror::Error, Debug, Clone)]
 pub enum Error {
     #[error("IO error: {0}")]
@@ -69,25 +74,40 @@ pub enum Message {
 #[async_trait]
 pub trait Transport: Send + Sync {
     async fn send(&self, message: Message) -> Result<(), Error>;
+    // Incoming messages. Those that arrive after the transport opens but
+    // before the first call are kept for it, so setup may run in between
     fn receive(&self) -> Pin<Box<dyn Stream<Item = Result<Message, Error>> + Send>>;
     async fn close(&self) -> Result<(), Error>;
+    // Short name of the transport, as reported to clients
+    fn name(&self) -> &'static str;
 }
 
 pub struct StdioTransport {
     stdout: Arc<Mutex<std::io::Stdout>>,
-    receiver: broadcast::Receiver<Result<Message, Error>>,
+    // Subscribed before reading starts, so lines read before the first
+    // receive() are kept for it; later calls subscribe afresh
+    receiver: Mutex<Option<broadcast::Receiver<Result<Message, Error>>>>,
+    sender: broadcast::Sender<Result<Message, Error>>,
 }
 
 impl StdioTransport {
     pub fn new() -> (Self, broadcast::Sender<Result<Message, Error>>) {
+        Self::from_reader(tokio::io::stdin())
+    }
+
+    // Reads messages from `input`, one per line, instead of stdin
+    fn from_reader<R>(input: R) -> (Self, broadcast::Sender<Result<Message, Error>>)
+    where
+        R: tokio::io::AsyncRead + Unpin + Send + 'static,
+    {
         let (sender, receiver) = broadcast::channel(100);
         let transport = Self {
             stdout: Arc::new(Mutex::new(std::io::stdout())),
-            receiver,
+            receiver: Mutex::new(Some(receiver)),
+            sender: sender.clone(),
         };
 
-        let stdin = tokio::io::stdin();
-        let mut reader = tokio::io::BufReader::new(stdin);
+        let mut reader = tokio::io::BufReader::new(input);
         let sender_clone = sender.clone();
 
         tokio::spawn(async move {
@@ -101,18 +121,23 @@ impl StdioTransport {
                         let trimmed_line = line.trim();
 
                         // Debug log the received JSON
-                        eprintln!("[DEBUG] Received JSON: {}", trimmed_line);
+                        if logging::enabled(LogLevel::Debug) {
+                            logging::debug(&format!(
+                                "Received JSON: {}",
+                                truncate_payload(trimmed_line)
+                            ));
+                        }
 
                         // Use the helper function for more robust parsing
                         let parsed = parse_json_message(trimmed_line);
 
                         if sender_clone.send(parsed).is_err() {
-                            eprintln!("[ERROR] Failed to send parsed message to channel");
+                            logging::error("Failed to send parsed message to channel");
                             break;
                         }
                     }
                     Err(e) => {
-                        eprintln!("[ERROR] Error reading from stdin: {}", e);
+                        logging::error(&format!("Error reading from stdin: {}", e));
                         let _ = sender_clone
                             .send(Err(Error::Io(format!("Error reading from stdin: {}", e))));
                         break;
@@ -127,6 +152,10 @@ impl StdioTransport {
 
 #[async_trait]
 impl Transport for StdioTransport {
+    fn name(&self) -> &'static str {
+        "stdio"
+    }
+
     async fn send(&self, message: Message) -> Result<(), Error> {
         let mut stdout = self
             .stdout
@@ -145,12 +174,9 @@ impl Transport for StdioTransport {
         };
 
         // Debug log the JSON being sent (truncated if very long)
-        let truncated_json = if json.len() > 500 {
-            format!("{}... (truncated)", &json[0..500])
-        } else {
-            json.clone()
-        };
-        eprintln!("[DEBUG] Sending JSON: {}", truncated_json);
+        if logging::enabled(LogLevel::Debug) {
+            logging::debug(&format!("Sending JSON: {}", truncate_payload(&json)));
+        }
 
         // Write the JSON string followed by a newline and flush
         if let Err(e) = writeln!(stdout, "{}", json) {
@@ -165,11 +191,22 @@ impl Transport for StdioTransport {
     }
 
     fn receive(&self) -> Pin<Box<dyn Stream<Item = Result<Message, Error>> + Send>> {
-        let rx = self.receiver.resubscribe();
+        let rx = self
+            .receiver
+            .lock()
+            .ok()
+            .and_then(|mut receiver| receiver.take())
+            .unwrap_or_else(|| self.sender.subscribe());
         Box::pin(futures::stream::unfold(rx, |mut rx| async move {
-            match rx.recv().await {
-                Ok(msg) => Some((msg, rx)),
-                Err(_) => None,
+            loop {
+                match rx.recv().await {
+                    Ok(msg) => return Some((msg, rx)),
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => logging::warn(&format!(
+                        "Dropped {} incoming messages that arrived faster than they were handled",
+                        skipped
+                    )),
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
             }
         }))
     }
@@ -186,41 +223,84 @@ fn parse_json_message(json_string: &str) -> Result<Message, Error> {
         return Err(Error::Serialization("Empty JSON string".into()));
     }
 
-    // Try to fix common JSON issues
-    let mut processed_json = json_string.to_string();
+    serde_json::from_str::<Message>(json_string).map_err(|e| {
+        logging::error(&format!(
+            "JSON parse error: {}. Input: {}",
+            e,
+            truncate_payload(json_string)
+        ));
+        Error::Serialization(format!("JSON parse error: {}", e))
+    })
+}
 
-    // Remove problematic whitespace characters
-    processed_json = processed_json.replace(['\n', '\r', '\t'], " ");
+// A message cut to MAX_LOGGED_PAYLOAD bytes for the log, on a character
+// boundary, noting how long it was
+fn truncate_payload(payload: &str) -> std::borrow::Cow<'_, str> {
+    if payload.len() <= MAX_LOGGED_PAYLOAD {
+        return std::borrow::Cow::Borrowed(payload);
+    }
 
-    // Handle unescaped backslashes and quotes if needed
-    if processed_json.contains("\\\\") || processed_json.contains("\\\"") {
-        processed_json = processed_json.replace("\\\\", "\\").replace("\\\"", "\"");
+    let mut end = MAX_LOGGED_PAYLOAD;
+    while !payload.is_char_boundary(end) {
+        end -= 1;
     }
+    std::borrow::Cow::Owned(format!(
+        "{}... ({} bytes, truncated)",
+        &payload[..end],
+        payload.len()
+    ))
+}
 
-    // Attempt parsing with modified string
-    let parse_result = serde_json::from_str::<Message>(&processed_json);
-
-    match parse_result {
-        Ok(msg) => Ok(msg),
-        Err(e) => {
-            eprintln!("[ERROR] JSON parse error: {}. Input: {}", e, processed_json);
-
-            // Provide additional diagnostics
-            if let Ok(value) = serde_json::from_str::<serde_json::Value>(&processed_json) {
-                eprintln!("[DEBUG] JSON parsed as generic value: {:?}", value);
-            } else {
-                eprintln!("[ERROR] Could not parse JSON even as generic value");
-
-                // Try to fix more aggressively
-                if let Ok(msg) = serde_json::from_str::<Message>(
-                    "{\"jsonrpc\":\"2.0\",\"method\":\"unknown\",\"id\":0}}",
-                ) {
-                    eprintln!("[DEBUG] Returning fallback message");
-                    return Ok(msg);
-                }
-            }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+    use tokio::io::AsyncWriteExt;
+
+    #[tokio::test]
+    async fn test_message_sent_before_receive_is_kept() {
+        let (mut client, server) = tokio::io::duplex(1024);
+        let (transport, _sender) = StdioTransport::from_reader(server);
+
+        // The client writes as soon as the server starts, before the server
+        // has got round to receiving
+        client
+            .write_all(b"{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"initialize\",\"params\":{}}\n")
+            .await
+            .unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+
+        let mut stream = transport.receive();
+        let received = tokio::time::timeout(std::time::Duration::from_secs(1), stream.next())
+            .await
+            .expect("initialize was lost");
+        assert!(matches!(
+            received,
+            Some(Ok(Message::Request { id: 1, ref method, .. })) if method == "initialize"
+        ));
+    }
 
-            Err(Error::Serialization(format!("JSON parse error: {}", e)))
-        }
+    #[test]
+    fn test_parse_keeps_escaped_backslashes_and_quotes() {
+        let line = r#"{"jsonrpc":"2.0","id":2,"method":"tools/call","params":{"name":"search_files","arguments":{"pattern":"\\w+","replacement":"say \"hi\""}}}"#;
+        let Message::Request {
+            params: Some(params),
+            ..
+        } = parse_json_message(line).unwrap()
+        else {
+            panic!("not a request: {}", line);
+        };
+        assert_eq!(params["arguments"]["pattern"], r"\w+");
+        assert_eq!(params["arguments"]["replacement"], r#"say "hi""#);
+    }
+
+    #[test]
+    fn test_truncate_payload_on_char_boundary() {
+        assert_eq!(truncate_payload("{}"), "{}");
+
+        let payload = format!("{}é{}", "a".repeat(MAX_LOGGED_PAYLOAD - 1), "b".repeat(10));
+        let truncated = truncate_payload(&payload);
+        assert!(truncated.starts_with(&"a".repeat(MAX_LOGGED_PAYLOAD - 1)));
+        assert!(truncated.ends_with(&format!("... ({} bytes, truncated)", payload.len())));
     }
 }
@@ -0,0 +1,99 @@
+use crate::mcp::handler::McpHandler;
+use crate::mcp::stdio::Transport;
+use serde_json::{json, Value};
+use std::future::Future;
+use std::pin::Pin;
+
+pub type ToolFuture<'h> = Pin<Box<dyn Future<Output = anyhow::Result<()>> + 'h>>;
+
+/// Handles a tools/call of one tool: the handler, the transport to answer
+/// on, the request id, and the call's params
+pub type ToolHandler =
+    for<'h, 'm> fn(&'h mut McpHandler<'m>, &'h dyn Transport, u64, &'h Value) -> ToolFuture<'h>;
+
+/// A tool the server provides: what tools/list reports about it and the
+/// handler tools/call dispatches to
+pub struct ToolDef {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub input_schema: Value,
+    pub output_schema: Option<Value>,
+    pub handler: ToolHandler,
+}
+
+impl ToolDef {
+    // The tool as tools/list describes it
+    fn definition(&self) -> Value {
+        let mut definition = json!({
+            "name": self.name,
+            "description": self.description,
+            "inputSchema": self.input_schema
+        });
+        if let Some(output_schema) = &self.output_schema {
+            definition["outputSchema"] = output_schema.clone();
+        }
+        definition
+    }
+}
+
+/// The tools the server provides, kept in the order tools/list reports them
+#[derive(Default)]
+pub struct ToolRegistry {
+    tools: Vec<ToolDef>,
+}
+
+impl ToolRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a tool, replacing any registered under the same name
+    pub fn register(&mut self, tool: ToolDef) {
+        match self.tools.iter_mut().find(|t| t.name == tool.name) {
+            Some(existing) => *existing = tool,
+            None => self.tools.push(tool),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ToolDef> {
+        self.tools.iter().find(|t| t.name == name)
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.tools.iter().map(|t| t.name.to_string()).collect()
+    }
+
+    pub fn definitions(&self) -> Vec<Value> {
+        self.tools.iter().map(ToolDef::definition).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tool(name: &'static str, description: &'static str) -> ToolDef {
+        ToolDef {
+            name,
+            description,
+            input_schema: json!({ "type": "object" }),
+            output_schema: None,
+            handler: |_, _, _, _| Box::pin(async { Ok(()) }),
+        }
+    }
+
+    #[test]
+    fn test_register_replaces_tool_of_same_name() {
+        let mut registry = ToolRegistry::new();
+        registry.register(tool("read", "old"));
+        registry.register(tool("write", "write"));
+        registry.register(tool("read", "new"));
+
+        assert_eq!(registry.names(), vec!["read", "write"]);
+        assert_eq!(registry.get("read").unwrap().description, "new");
+        assert!(registry.get("delete").is_none());
+        let definitions = registry.definitions();
+        assert_eq!(definitions[0]["inputSchema"]["type"], "object");
+        assert!(definitions[0].get("outputSchema").is_none());
+    }
+}
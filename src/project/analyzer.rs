@@ -1,168 +1,578 @@
+use crate::config::ProjectConfig;
+use crate::project::manifest;
+use crate::project::tree::DirectoryTree;
+use crate::project::walker::{Walk, WalkOptions};
+use crate::shared::language::{detect_language, Language};
 use crate::shared::logging;
+use crate::shared::mime;
+use crate::shared::utils::path::to_display_path;
+use globset::{GlobBuilder, GlobMatcher};
 use regex::Regex;
 use serde_json::{json, Value};
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Instant;
 use tokio::fs;
-use tokio::io::AsyncReadExt;
+use tokio::sync::mpsc::UnboundedSender;
+
+// Characters of a matching line returned by search when no limit is given
+const DEFAULT_MAX_LINE_PREVIEW: usize = 200;
+
+// Files larger than this are skipped when finding references
+pub const MAX_REFERENCE_FILE_SIZE: u64 = 1024 * 1024;
+
+// Files list_files returns when the caller doesn't say, so listing a huge
+// tree doesn't produce hundreds of thousands of paths
+pub const DEFAULT_LIST_MAX_RESULTS: usize = 10_000;
+
+// Keywords that introduce a definition of the name following them
+const DEFINITION_KEYWORDS: &[&str] = &[
+    "fn", "def", "class", "struct", "enum", "trait", "type", "interface", "let", "const",
+    "var", "func", "mod", "function", "static", "module", "macro_rules!", "union",
+];
+
+// Totals gathered while walking the project for analysis
+#[derive(Default)]
+struct DirectoryStats {
+    extension_counts: std::collections::HashMap<String, usize>,
+    total_files: usize,
+    total_dirs: usize,
+    total_size: u64,
+    // Files whose content is binary, left out of the language counts
+    binary_files: usize,
+    // Whether directories deeper than max_depth were left out
+    depth_limited: bool,
+}
+
+// Files gathered for searching, split by content
+struct TextFiles {
+    text: Vec<PathBuf>,
+    // Files named like text (by a known language) whose content is binary
+    binary: Vec<PathBuf>,
+    // Whether directories deeper than max_depth were left out
+    depth_limited: bool,
+    // Files passed over: binary ones of any name, those outside the glob,
+    // and entries excluded by ignore files or exclude_patterns
+    binary_count: usize,
+    outside_glob: usize,
+    excluded: usize,
+}
+
+// Parts of analyze_project's result to compute. Stats and languages need a
+// walk of the whole tree; key files and the project type mostly check a
+// few paths
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AnalysisSections {
+    pub stats: bool,
+    pub languages: bool,
+    pub key_files: bool,
+    pub project_type: bool,
+}
+
+impl Default for AnalysisSections {
+    fn default() -> Self {
+        Self {
+            stats: true,
+            languages: true,
+            key_files: true,
+            project_type: true,
+        }
+    }
+}
+
+impl AnalysisSections {
+    // Only the named sections
+    pub fn parse<'a>(names: impl IntoIterator<Item = &'a str>) -> anyhow::Result<Self> {
+        let mut sections = Self {
+            stats: false,
+            languages: false,
+            key_files: false,
+            project_type: false,
+        };
+        for name in names {
+            match name {
+                "stats" => sections.stats = true,
+                "languages" => sections.languages = true,
+                "key_files" => sections.key_files = true,
+                "project_type" => sections.project_type = true,
+                _ => {
+                    return Err(anyhow::anyhow!(
+                        "Unknown section: {} (expected stats, languages, key_files or project_type)",
+                        name
+                    ))
+                }
+            }
+        }
+        Ok(sections)
+    }
+}
+
+// Which files a search covers, and limits on what it returns
+#[derive(Debug, Clone, Default)]
+pub struct SearchOptions {
+    // Subdirectory to search instead of the whole scope; result paths stay
+    // relative to the scope
+    pub path: Option<PathBuf>,
+    // Glob a file's path relative to the scope must match, like `src/**/*.rs`
+    pub path_glob: Option<String>,
+    // Characters of each matching line returned, centered on the match
+    pub max_line_preview: Option<usize>,
+    // Matches returned for any single file; the rest are only counted
+    pub max_matches_per_file: Option<usize>,
+    // Matches returned across all files, after which the search stops
+    pub max_results: Option<usize>,
+}
+
+// Order of files in a listing
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum FileSort {
+    #[default]
+    Name,
+    // Modification time, oldest first
+    Modified,
+    // Size in bytes, smallest first
+    Size,
+}
+
+impl FileSort {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "name" => Some(Self::Name),
+            "mtime" => Some(Self::Modified),
+            "size" => Some(Self::Size),
+            _ => None,
+        }
+    }
+}
+
+// Compiles a glob matched against relative paths, where `*` stays within one
+// directory and `**` crosses them
+fn compile_glob(glob: &str) -> anyhow::Result<GlobMatcher> {
+    Ok(GlobBuilder::new(glob)
+        .literal_separator(true)
+        .build()
+        .map_err(|e| anyhow::anyhow!("Invalid glob: {}", e))?
+        .compile_matcher())
+}
+
+// Rewrites search_files results as grep -n style `file:line:text` lines,
+// replacing `results` with `lines`. Files skipped as binary are left out
+pub fn into_grep_output(mut search: Value) -> Value {
+    let results = match search.get_mut("results").and_then(|r| r.as_array_mut()) {
+        Some(results) => std::mem::take(results),
+        None => return search,
+    };
+
+    let lines: Vec<String> = results
+        .iter()
+        .flat_map(|file| {
+            let name = file["file"].as_str().unwrap_or_default();
+            file["matches"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .map(move |m| {
+                    format!(
+                        "{}:{}:{}",
+                        name,
+                        m["line_number"],
+                        m["line"].as_str().unwrap_or_default()
+                    )
+                })
+        })
+        .collect();
+
+    if let Some(fields) = search.as_object_mut() {
+        fields.remove("results");
+        fields.insert("lines".to_string(), json!(lines));
+    }
+    search
+}
+
+// Sorts listed files using the metadata gathered during the walk. Ties, and
+// files whose modification time is unknown, fall back to path order
+pub fn sort_files(files: &mut [(PathBuf, std::fs::Metadata)], sort: FileSort, reverse: bool) {
+    files.sort_by(|(a_path, a), (b_path, b)| {
+        let order = match sort {
+            FileSort::Name => std::cmp::Ordering::Equal,
+            FileSort::Modified => a.modified().ok().cmp(&b.modified().ok()),
+            FileSort::Size => a.len().cmp(&b.len()),
+        };
+        order.then_with(|| a_path.cmp(b_path))
+    });
+
+    if reverse {
+        files.reverse();
+    }
+}
+
+// Whether a line has to match every search pattern or just one of them
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MatchMode {
+    All,
+    Any,
+}
+
+// What a search looks for: lines matching `patterns` as combined by `mode`
+// and none of the `exclude` patterns
+pub struct SearchQuery {
+    patterns: Vec<Regex>,
+    mode: MatchMode,
+    exclude: Vec<Regex>,
+}
+
+impl SearchQuery {
+    pub fn new(patterns: &[String], mode: MatchMode, exclude: &[String]) -> anyhow::Result<Self> {
+        if patterns.is_empty() {
+            return Err(anyhow::anyhow!("At least one search pattern is required"));
+        }
+
+        let compile = |pattern: &String| {
+            Regex::new(pattern).map_err(|e| anyhow::anyhow!("Invalid search pattern: {}", e))
+        };
+        Ok(Self {
+            patterns: patterns.iter().map(compile).collect::<anyhow::Result<_>>()?,
+            mode,
+            exclude: exclude.iter().map(compile).collect::<anyhow::Result<_>>()?,
+        })
+    }
+
+    // A plain search for one pattern
+    pub fn single(query: &str) -> anyhow::Result<Self> {
+        Self::new(&[query.to_string()], MatchMode::All, &[])
+    }
+
+    // The byte span of the earliest pattern match if the line matches the
+    // query as a whole
+    fn find(&self, line: &str) -> Option<(usize, usize)> {
+        if self.exclude.iter().any(|regex| regex.is_match(line)) {
+            return None;
+        }
+
+        let found = self
+            .patterns
+            .iter()
+            .map(|regex| regex.find(line).map(|m| (m.start(), m.end())));
+        let spans: Vec<(usize, usize)> = match self.mode {
+            MatchMode::All => found.collect::<Option<_>>()?,
+            MatchMode::Any => found.flatten().collect(),
+        };
+        spans.into_iter().min()
+    }
+
+    // Replaces, in every line of `content` matching the query, what each of
+    // its patterns matches, expanding `$1`-style groups in `replacement`.
+    // Returns the new content and the number of lines changed
+    pub fn replace_in(&self, content: &str, replacement: &str) -> (String, usize) {
+        let mut replaced = String::with_capacity(content.len());
+        let mut lines_changed = 0;
+
+        for line in content.split_inclusive('\n') {
+            let text = line.trim_end_matches(['\n', '\r']);
+            if self.find(text).is_none() {
+                replaced.push_str(line);
+                continue;
+            }
+
+            let mut new_text = text.to_string();
+            for regex in &self.patterns {
+                new_text = regex.replace_all(&new_text, replacement).into_owned();
+            }
+            if new_text != text {
+                lines_changed += 1;
+            }
+            replaced.push_str(&new_text);
+            replaced.push_str(&line[text.len()..]);
+        }
+
+        (replaced, lines_changed)
+    }
+
+    // The query as reported back in search results: the pattern itself for
+    // a plain search
+    fn describe(&self) -> Value {
+        if self.patterns.len() == 1 && self.exclude.is_empty() {
+            return json!(self.patterns[0].as_str());
+        }
+
+        let sources = |regexes: &[Regex]| -> Vec<String> {
+            regexes.iter().map(|r| r.as_str().to_string()).collect()
+        };
+        json!({
+            "patterns": sources(&self.patterns),
+            "mode": if self.mode == MatchMode::All { "all" } else { "any" },
+            "exclude": sources(&self.exclude)
+        })
+    }
+}
 
+#[derive(Clone)]
 pub struct ProjectAnalyzer {
     base_directory: PathBuf,
+    // Canonical project root, used to keep followed symlinks inside the project
+    canonical_root: PathBuf,
+    // Directory walks start from, which reported paths are relative to: the
+    // project root unless narrowed by `scoped`
+    scope: PathBuf,
+    // Ignore rules, symlink policy and depth limit shared by every walk
+    walk_options: Arc<WalkOptions>,
 }
 
 impl ProjectAnalyzer {
-    pub fn new(base_directory: PathBuf) -> Self {
-        Self { base_directory }
-    }
-
-    // Analyze an entire project directory
-    pub async fn analyze_project(&self) -> anyhow::Result<Value> {
-        logging::info(&format!(
-            "Analyzing project in: {}",
-            self.base_directory.display()
-        ));
-
-        // Count files by extension
-        let mut extension_counts = std::collections::HashMap::new();
-        let mut total_files = 0;
-        let mut total_dirs = 0;
-        let mut total_size = 0;
-
-        // File types to detect (extension, language name)
-        let known_types = [
-            ("rs", "Rust"),
-            ("go", "Go"),
-            ("js", "JavaScript"),
-            ("ts", "TypeScript"),
-            ("py", "Python"),
-            ("java", "Java"),
-            ("c", "C"),
-            ("cpp", "C++"),
-            ("h", "C/C++ Header"),
-            ("hpp", "C++ Header"),
-            ("cs", "C#"),
-            ("rb", "Ruby"),
-            ("php", "PHP"),
-            ("html", "HTML"),
-            ("css", "CSS"),
-            ("json", "JSON"),
-            ("md", "Markdown"),
-            ("yml", "YAML"),
-            ("yaml", "YAML"),
-            ("toml", "TOML"),
-            ("xml", "XML"),
-            ("txt", "Text"),
-            ("sh", "Shell"),
-            ("bat", "Batch"),
-            ("ps1", "PowerShell"),
-            ("tf", "Terraform"),
-            ("sql", "SQL"),
-        ];
+    pub fn new(base_directory: PathBuf, config: &ProjectConfig) -> Self {
+        let canonical_root =
+            std::fs::canonicalize(&base_directory).unwrap_or_else(|_| base_directory.clone());
+
+        Self {
+            scope: base_directory.clone(),
+            base_directory,
+            canonical_root,
+            walk_options: Arc::new(WalkOptions::from_config(config)),
+        }
+    }
+
+    // Leaves files outside `roots` out of every walk, or lifts the limit with
+    // None. Roots that don't exist can hold nothing and are dropped
+    pub fn set_client_roots(&mut self, roots: Option<&[PathBuf]>) {
+        let mut walk_options = (*self.walk_options).clone();
+        walk_options.client_roots = roots.map(|roots| {
+            roots
+                .iter()
+                .filter_map(|root| root.canonicalize().ok())
+                .collect()
+        });
+        self.walk_options = Arc::new(walk_options);
+    }
 
-        // Recursively process directory
-        self.process_directory(
+    // Starts a walk of `root`, a directory inside the project
+    async fn walk(&self, root: &Path) -> anyhow::Result<Walk> {
+        Walk::new(
+            self.walk_options.clone(),
             &self.base_directory,
-            &mut extension_counts,
-            &mut total_files,
-            &mut total_dirs,
-            &mut total_size,
+            &self.canonical_root,
+            root,
         )
-        .await?;
+        .await
+    }
 
-        // Build result JSON
-        let mut languages = Vec::new();
-        for (ext, count) in &extension_counts {
-            // Find language name for this extension
-            let language = known_types
-                .iter()
-                .find(|(e, _)| e == &ext.as_str())
-                .map(|(_, lang)| *lang)
-                .unwrap_or("Unknown");
-
-            languages.push(json!({
-                "extension": ext,
-                "language": language,
-                "count": count
-            }));
+    // The same analyzer working as if `subdirectory` were the project root,
+    // while still applying the project's ignore rules
+    pub fn scoped(&self, subdirectory: &Path) -> anyhow::Result<Self> {
+        Ok(Self {
+            scope: self.resolve_subdirectory(subdirectory)?,
+            ..self.clone()
+        })
+    }
+
+    // Resolves a subdirectory of the project to analyze, which must stay
+    // inside the project
+    pub fn resolve_subdirectory(&self, subdirectory: &Path) -> anyhow::Result<PathBuf> {
+        let root = self.scope.join(subdirectory);
+        let canonical = std::fs::canonicalize(&root)
+            .map_err(|_| anyhow::anyhow!("Directory not found: {}", subdirectory.display()))?;
+
+        if !canonical.starts_with(&self.canonical_root) {
+            return Err(anyhow::anyhow!(
+                "Path escapes the project directory: {}",
+                subdirectory.display()
+            ));
+        }
+        if !canonical.is_dir() {
+            return Err(anyhow::anyhow!("Not a directory: {}", subdirectory.display()));
+        }
+
+        Ok(root)
+    }
+
+    // `directory`, inside the scope, as a path relative to it, or "." for
+    // the scope itself
+    fn scope_relative(&self, directory: &Path) -> String {
+        let relative = match (directory.canonicalize(), self.scope.canonicalize()) {
+            (Ok(directory), Ok(scope)) => directory.strip_prefix(&scope).map(Path::to_path_buf).ok(),
+            _ => None,
+        };
+        match relative {
+            Some(relative) if relative.as_os_str().is_empty() => ".".to_string(),
+            Some(relative) => to_display_path(&relative),
+            None => to_display_path(directory.strip_prefix(&self.scope).unwrap_or(directory)),
         }
+    }
 
-        // Detect key files
-        let key_files = self.detect_key_files().await?;
+    // Analyze an entire project directory, or only the given subdirectory of
+    // it, computing only the requested sections. `include_dependencies` adds
+    // a summary of the dependencies declared by the manifests at the top of
+    // the analyzed directory. The key files and project type only take a few
+    // checks at the top, so they come first; given `early`, they are sent on
+    // it before the slow walk for the stats and languages starts
+    pub async fn analyze_project(
+        &self,
+        subdirectory: Option<&Path>,
+        sections: &AnalysisSections,
+        include_dependencies: bool,
+        early: Option<UnboundedSender<Value>>,
+    ) -> anyhow::Result<Value> {
+        // A subdirectory is reported as its path within the project
+        let (root, project_directory) = match subdirectory {
+            Some(subdirectory) => {
+                let root = self.resolve_subdirectory(subdirectory)?;
+                let relative = self.scope_relative(&root);
+                (root, relative)
+            }
+            None => (self.scope.clone(), to_display_path(&self.scope)),
+        };
 
-        // Detect project type
-        let project_type = self.detect_project_type(&key_files).await?;
+        logging::info(&format!("Analyzing project in: {}", root.display()));
 
-        let result = json!({
-            "project_directory": self.base_directory.to_string_lossy(),
-            "project_type": project_type,
-            "stats": {
-                "total_files": total_files,
-                "total_directories": total_dirs,
-                "total_size_bytes": total_size
-            },
-            "languages": languages,
-            "key_files": key_files
+        let mut result = json!({
+            "project_directory": project_directory
         });
 
+        if sections.key_files || sections.project_type {
+            // Detect key files
+            let key_files = self.detect_key_files(&root).await?;
+
+            // Detect project type
+            if sections.project_type {
+                result["project_type"] = self.detect_project_type(&root, &key_files).await?;
+            }
+            if sections.key_files {
+                result["key_files"] = key_files;
+            }
+        }
+
+        // Recursively process directory, only when a section needs the counts
+        if sections.stats || sections.languages {
+            if let Some(early) = early {
+                let _ = early.send(result.clone());
+            }
+
+            let mut stats = DirectoryStats::default();
+            self.process_directory(&root, &mut stats).await?;
+            result["depth_limited"] = json!(stats.depth_limited);
+
+            if sections.stats {
+                result["stats"] = json!({
+                    "total_files": stats.total_files,
+                    "total_directories": stats.total_dirs,
+                    "total_size_bytes": stats.total_size,
+                    "binary_files": stats.binary_files
+                });
+            }
+
+            if sections.languages {
+                let mut languages = Vec::new();
+                for (ext, count) in &stats.extension_counts {
+                    // Find language name for this extension
+                    let language = Language::from_extension(ext)
+                        .map(|lang| lang.name())
+                        .unwrap_or("Unknown");
+
+                    languages.push(json!({
+                        "extension": ext,
+                        "language": language,
+                        "count": count
+                    }));
+                }
+                result["languages"] = json!(languages);
+            }
+        }
+
+        if include_dependencies {
+            result["dependencies"] = manifest::dependencies(&root).await;
+        }
+
         Ok(result)
     }
 
     // Process a directory recursively
-    async fn process_directory(
-        &self,
-        dir: &Path,
-        extension_counts: &mut std::collections::HashMap<String, usize>,
-        total_files: &mut usize,
-        total_dirs: &mut usize,
-        total_size: &mut u64,
-    ) -> anyhow::Result<()> {
-        let mut entries = fs::read_dir(dir).await?;
-
-        *total_dirs += 1;
-
-        while let Some(entry) = entries.next_entry().await? {
-            let path = entry.path();
-
-            // Skip hidden files and directories
-            if path
-                .file_name()
-                .map(|n| n.to_string_lossy().starts_with('.'))
-                .unwrap_or(false)
-            {
-                continue;
-            }
+    async fn process_directory(&self, dir: &Path, stats: &mut DirectoryStats) -> anyhow::Result<()> {
+        let mut walk = self.walk(dir).await?;
 
-            if path.is_dir() {
-                // Recursively process subdirectory
-                Box::pin(self.process_directory(
-                    &path,
-                    extension_counts,
-                    total_files,
-                    total_dirs,
-                    total_size,
-                ))
-                .await?;
-            } else if path.is_file() {
+        stats.total_dirs += 1;
+
+        while let Some(entry) = walk.next_entry().await? {
+            if entry.is_dir() {
+                stats.total_dirs += 1;
+            } else {
                 // Process file
-                *total_files += 1;
+                stats.total_files += 1;
+                stats.total_size += entry.metadata.len();
 
-                // Get file size
-                if let Ok(metadata) = fs::metadata(&path).await {
-                    *total_size += metadata.len();
+                if mime::is_probably_binary(&entry.path).await {
+                    stats.binary_files += 1;
+                    continue;
                 }
 
                 // Count by extension
-                if let Some(ext) = path.extension() {
+                if let Some(ext) = entry.path.extension() {
                     let ext_str = ext.to_string_lossy().to_lowercase();
-                    *extension_counts.entry(ext_str.to_string()).or_insert(0) += 1;
+                    *stats.extension_counts.entry(ext_str.to_string()).or_insert(0) += 1;
                 }
             }
         }
 
+        stats.depth_limited = walk.depth_limited();
         Ok(())
     }
 
     // List files in the project that match a pattern
     pub async fn list_files(&self, pattern: Option<&str>) -> anyhow::Result<Vec<PathBuf>> {
+        let files = self.list_files_with_metadata(pattern, None).await?;
+        Ok(files.into_iter().map(|(path, _)| path).collect())
+    }
+
+    // Files in the project whose path relative to the project root matches
+    // a glob such as `src/**/*.rs`, as relative paths
+    pub async fn list_files_matching_glob(&self, glob: &str) -> anyhow::Result<Vec<PathBuf>> {
+        let matcher = compile_glob(glob)?;
+
+        let files = self.list_files(None).await?;
+        Ok(files
+            .iter()
+            .filter_map(|path| path.strip_prefix(&self.scope).ok())
+            .filter(|relative| matcher.is_match(relative))
+            .map(Path::to_path_buf)
+            .collect())
+    }
+
+    // The project as a `tree`-style listing, `depth` levels deep, along with
+    // the same tree as nested JSON
+    pub async fn directory_tree(
+        &self,
+        depth: usize,
+        dirs_only: bool,
+        max_entries: usize,
+    ) -> anyhow::Result<Value> {
+        let mut tree = DirectoryTree::new();
+
+        // The whole project is walked so directories at the depth limit can
+        // report how many files they hold
+        let mut walk = self.walk(&self.scope).await?;
+        while let Some(entry) = walk.next_entry().await? {
+            if let Ok(relative) = entry.path.strip_prefix(&self.scope) {
+                tree.insert(relative, entry.is_dir());
+            }
+        }
+
+        let root_name = self
+            .scope
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| ".".to_string());
+        let mut result = tree.render(&root_name, depth, dirs_only, max_entries);
+        result["root"] = json!(to_display_path(&self.scope));
+        result["depth_limited"] = json!(walk.depth_limited());
+        Ok(result)
+    }
+
+    // List files in the project that match a pattern, with the metadata
+    // gathered while walking. With a `limit` the walk stops once that many
+    // files are found, so the result is the first ones in walk order
+    pub async fn list_files_with_metadata(
+        &self,
+        pattern: Option<&str>,
+        limit: Option<usize>,
+    ) -> anyhow::Result<Vec<(PathBuf, std::fs::Metadata)>> {
         let mut results = Vec::new();
 
         // Compile regex if pattern is provided
@@ -173,187 +583,331 @@ impl ProjectAnalyzer {
             None => None,
         };
 
-        // Recursively find files
-        self.find_files_recursive(&self.base_directory, &regex, &mut results)
-            .await?;
-
-        Ok(results)
-    }
-
-    // Recursively find files matching a pattern
-    async fn find_files_recursive(
-        &self,
-        dir: &Path,
-        regex: &Option<Regex>,
-        results: &mut Vec<PathBuf>,
-    ) -> anyhow::Result<()> {
-        let mut entries = fs::read_dir(dir).await?;
-
-        while let Some(entry) = entries.next_entry().await? {
-            let path = entry.path();
-
-            // Skip hidden files and directories
-            if path
-                .file_name()
-                .map(|n| n.to_string_lossy().starts_with('.'))
-                .unwrap_or(false)
-            {
+        let mut walk = self.walk(&self.scope).await?;
+        while let Some(entry) = walk.next_entry().await? {
+            if !entry.is_file() {
                 continue;
             }
 
-            if path.is_dir() {
-                // Recursively process subdirectory
-                Box::pin(self.find_files_recursive(&path, regex, results)).await?;            } else if path.is_file() {
-                // Check if file matches pattern
-                let file_name = path
+            // Check if file matches pattern
+            let include = match &regex {
+                Some(re) => entry
+                    .path
                     .file_name()
-                    .map(|n| n.to_string_lossy().to_string())
-                    .unwrap_or_default();
-
-                let include = match regex {
-                    Some(re) => re.is_match(&file_name),
-                    None => true, // No pattern means include all files
-                };
+                    .map(|n| re.is_match(&n.to_string_lossy()))
+                    .unwrap_or(false),
+                None => true, // No pattern means include all files
+            };
 
-                if include {
-                    results.push(path);
+            if include {
+                results.push((entry.path, entry.metadata));
+                if limit.is_some_and(|limit| results.len() >= limit) {
+                    break;
                 }
             }
         }
 
-        Ok(())
+        Ok(results)
     }
 
     // Search for text in files
-    pub async fn search_files(&self, query: &str) -> anyhow::Result<Value> {
-        logging::info(&format!("Searching for '{}' in project", query));
+    pub async fn search_files(
+        &self,
+        query: &SearchQuery,
+        options: &SearchOptions,
+    ) -> anyhow::Result<Value> {
+        let description = query.describe();
+        logging::info(&format!("Searching for {} in project", description));
+
+        let max_line_preview = options.max_line_preview.unwrap_or(DEFAULT_MAX_LINE_PREVIEW);
+        let max_matches_per_file = options.max_matches_per_file.unwrap_or(usize::MAX);
+        let max_results = options.max_results.unwrap_or(usize::MAX);
+        let mut total_results = 0;
+        let mut truncated = false;
+        let started = Instant::now();
+        let mut files_searched = 0;
+        let mut unreadable = 0;
 
         let mut results = Vec::new();
-        let search_regex =
-            Regex::new(query).map_err(|e| anyhow::anyhow!("Invalid search pattern: {}", e))?;
-
-        // Find all text files
-        let text_extensions = [
-            "txt", "md", "rs", "go", "js", "ts", "py", "java", "c", "cpp", "h", "hpp", "cs", "rb",
-            "php", "html", "css", "json", "yml", "yaml", "toml", "xml", "sh", "bat", "ps1", "tf",
-            "sql",
-        ];
 
-        let mut files_to_search = Vec::new();
+        // First, gather the text files to search
+        let files = self.files_to_search(options).await?;
 
-        // First, gather all text files
-        let mut entries = fs::read_dir(&self.base_directory).await?;
-        while let Some(entry) = entries.next_entry().await? {
-            let path = entry.path();
-
-            // Skip hidden files and directories
-            if path
-                .file_name()
-                .map(|n| n.to_string_lossy().starts_with('.'))
-                .unwrap_or(false)
-            {
-                continue;
+        // Now search through each file
+        for file_path in files.text {
+            if total_results >= max_results {
+                truncated = true;
+                break;
             }
 
-            if path.is_dir() {
-                // Recursively gather files from subdirectory
-                self.gather_text_files(&path, &text_extensions, &mut files_to_search)
-                    .await?;
-            } else if path.is_file() {
-                // Check if it's a text file
-                if let Some(ext) = path.extension() {
-                    let ext_str = ext.to_string_lossy().to_lowercase();
-                    if text_extensions.contains(&ext_str.as_ref()) {
-                        files_to_search.push(path);
-                    }
+            let content = match fs::read_to_string(&file_path).await {
+                Ok(content) => content,
+                Err(_) => {
+                    // Skip files we can't read as text
+                    unreadable += 1;
+                    continue;
                 }
-            }
-        }
-
-        // Now search through each file
-        for file_path in files_to_search {
-            let mut file = match fs::File::open(&file_path).await {
-                Ok(f) => f,
-                Err(_) => continue, // Skip files we can't open
             };
-
-            let mut content = String::new();
-            if file.read_to_string(&mut content).await.is_err() {
-                continue; // Skip files we can't read as text
-            }
+            files_searched += 1;
 
             let mut line_matches = Vec::new();
+            let mut total_in_file = 0;
 
             // Search line by line
             for (i, line) in content.lines().enumerate() {
-                if search_regex.is_match(line) {
+                if let Some((start, end)) = query.find(line) {
+                    total_in_file += 1;
+                    if line_matches.len() >= max_matches_per_file {
+                        // Keep counting so the file reports its real total
+                        continue;
+                    }
+                    if total_results >= max_results {
+                        truncated = true;
+                        break;
+                    }
+                    total_results += 1;
                     line_matches.push(json!({
                         "line_number": i + 1,
-                        "line": line
+                        "line": preview_line(line, start, end, max_line_preview),
+                        "line_length": line.chars().count(),
+                        "column": char_column(line, start)
                     }));
                 }
             }
 
             if !line_matches.is_empty() {
                 // Convert path to relative to base directory
-                let rel_path = file_path
-                    .strip_prefix(&self.base_directory)
-                    .unwrap_or(&file_path)
-                    .to_string_lossy();
+                let rel_path = to_display_path(
+                    file_path
+                        .strip_prefix(&self.scope)
+                        .unwrap_or(&file_path),
+                );
 
-                results.push(json!({
+                let mut file_result = json!({
                     "file": rel_path,
                     "matches": line_matches
+                });
+                if total_in_file > max_matches_per_file {
+                    file_result["truncated_in_file"] = json!(true);
+                    file_result["total_in_file"] = json!(total_in_file);
+                }
+                results.push(file_result);
+            }
+        }
+
+        for file_path in files.binary {
+            let rel_path = to_display_path(
+                file_path
+                    .strip_prefix(&self.scope)
+                    .unwrap_or(&file_path),
+            );
+            results.push(json!({
+                "file": rel_path,
+                "skipped": "binary"
+            }));
+        }
+
+        // Lets an empty result be told apart from an empty or excluded tree
+        Ok(json!({
+            "query": description,
+            "results": results,
+            "truncated": truncated,
+            "depth_limited": files.depth_limited,
+            "files_searched": files_searched,
+            "files_skipped": {
+                "binary": files.binary_count,
+                "unreadable": unreadable,
+                "outside_path_glob": files.outside_glob,
+                "excluded": files.excluded
+            },
+            "elapsed_ms": started.elapsed().as_millis() as u64
+        }))
+    }
+
+    // Counts the lines matching a search, per file, without building any
+    // match previews
+    pub async fn count_matches(&self, query: &SearchQuery) -> anyhow::Result<Value> {
+        logging::info(&format!("Counting matches for {} in project", query.describe()));
+
+        let files = self.gather_text_files().await?;
+        let mut total = 0;
+        let mut per_file = Vec::new();
+
+        for file_path in &files.text {
+            let content = match fs::read_to_string(&file_path).await {
+                Ok(content) => content,
+                Err(_) => continue, // Skip files we can't read as text
+            };
+
+            let count = content.lines().filter(|line| query.find(line).is_some()).count();
+            if count > 0 {
+                total += count;
+                let rel_path = to_display_path(
+                    file_path
+                        .strip_prefix(&self.scope)
+                        .unwrap_or(file_path),
+                );
+                per_file.push(json!({
+                    "file": rel_path,
+                    "count": count
                 }));
             }
         }
 
         Ok(json!({
-            "query": query,
-            "results": results
+            "total": total,
+            "files_matched": per_file.len(),
+            "per_file": per_file,
+            "depth_limited": files.depth_limited
         }))
     }
 
-    // Helper to gather text files recursively
-    async fn gather_text_files(
+    // Find uses of an identifier across the project, matched on word
+    // boundaries and classified as a likely definition or a usage
+    pub async fn find_references(&self, identifier: &str) -> anyhow::Result<Value> {
+        logging::info(&format!("Finding references to '{}' in project", identifier));
+
+        let identifier_regex = Regex::new(&format!(r"\b{}\b", regex::escape(identifier)))
+            .map_err(|e| anyhow::anyhow!("Invalid identifier: {}", e))?;
+
+        let files = self.gather_text_files().await?;
+
+        let mut references = Vec::new();
+        let mut files_matched = 0;
+
+        for file_path in &files.text {
+            // Skip very large files, which are rarely hand-written source
+            match fs::metadata(&file_path).await {
+                Ok(metadata) if metadata.len() <= MAX_REFERENCE_FILE_SIZE => {}
+                _ => continue,
+            }
+
+            let content = match fs::read_to_string(&file_path).await {
+                Ok(content) => content,
+                Err(_) => continue, // Skip files we can't read as text
+            };
+
+            let rel_path = to_display_path(
+                file_path
+                    .strip_prefix(&self.scope)
+                    .unwrap_or(file_path),
+            );
+            let before = references.len();
+
+            for (i, line) in content.lines().enumerate() {
+                let matches: Vec<_> = identifier_regex.find_iter(line).collect();
+                let first = match matches.first() {
+                    Some(first) => first,
+                    None => continue,
+                };
+
+                let is_definition = matches
+                    .iter()
+                    .any(|m| is_definition_site(&line[..m.start()]));
+
+                references.push(json!({
+                    "file": rel_path,
+                    "line": i + 1,
+                    "kind": if is_definition { "definition" } else { "usage" },
+                    "text": preview_line(line, first.start(), first.end(), DEFAULT_MAX_LINE_PREVIEW)
+                        .trim(),
+                    "column": char_column(line, first.start())
+                }));
+            }
+
+            if references.len() > before {
+                files_matched += 1;
+            }
+        }
+
+        Ok(json!({
+            "identifier": identifier,
+            "total": references.len(),
+            "files_matched": files_matched,
+            "references": references,
+            "depth_limited": files.depth_limited
+        }))
+    }
+
+    // Every text file in the project, decided by content, as relative paths
+    pub async fn list_text_files(&self) -> anyhow::Result<Vec<PathBuf>> {
+        let files = self.gather_text_files().await?;
+        Ok(files
+            .text
+            .iter()
+            .filter_map(|path| path.strip_prefix(&self.scope).ok())
+            .map(Path::to_path_buf)
+            .collect())
+    }
+
+    // Helper to gather text files recursively, decided by content
+    async fn gather_text_files(&self) -> anyhow::Result<TextFiles> {
+        self.gather_text_files_in(&self.scope, None).await
+    }
+
+    // The text files a search with `options` covers, as absolute paths
+    pub async fn search_targets(&self, options: &SearchOptions) -> anyhow::Result<Vec<PathBuf>> {
+        Ok(self.files_to_search(options).await?.text)
+    }
+
+    async fn files_to_search(&self, options: &SearchOptions) -> anyhow::Result<TextFiles> {
+        let root = match &options.path {
+            Some(path) => self.resolve_subdirectory(path)?,
+            None => self.scope.clone(),
+        };
+        let glob = options.path_glob.as_deref().map(compile_glob).transpose()?;
+        self.gather_text_files_in(&root, glob.as_ref()).await
+    }
+
+    // Like gather_text_files, for the files below `root` whose path relative
+    // to the scope matches `glob`, if given
+    async fn gather_text_files_in(
         &self,
-        dir: &Path,
-        text_extensions: &[&str],
-        files: &mut Vec<PathBuf>,
-    ) -> anyhow::Result<()> {
-        let mut entries = fs::read_dir(dir).await?;
-
-        while let Some(entry) = entries.next_entry().await? {
-            let path = entry.path();
-
-            // Skip hidden files and directories
-            if path
-                .file_name()
-                .map(|n| n.to_string_lossy().starts_with('.'))
-                .unwrap_or(false)
-            {
+        root: &Path,
+        glob: Option<&GlobMatcher>,
+    ) -> anyhow::Result<TextFiles> {
+        let mut files = Vec::new();
+        let mut binary_files = Vec::new();
+        let mut binary_count = 0;
+        let mut outside_glob = 0;
+        let mut walk = self.walk(root).await?;
+
+        while let Some(entry) = walk.next_entry().await? {
+            if !entry.is_file() {
                 continue;
             }
+            if let Some(glob) = glob {
+                let relative = entry.path.strip_prefix(&self.scope).unwrap_or(&entry.path);
+                if !glob.is_match(relative) {
+                    outside_glob += 1;
+                    continue;
+                }
+            }
 
-            if path.is_dir() {
-                // Recursively process subdirectory
-                Box::pin(self.gather_text_files(&path, text_extensions, files)).await?;
-            } else if path.is_file() {
-                // Check if it's a text file
-                if let Some(ext) = path.extension() {
-                    let ext_str = ext.to_string_lossy().to_lowercase();
-                    if text_extensions.contains(&ext_str.as_ref()) {
-                        files.push(path);
-                    }
+            // Decide by content, so text files with an unusual extension are
+            // searched and binary files with a text extension are not
+            if mime::is_probably_binary(&entry.path).await {
+                binary_count += 1;
+                if detect_language(&entry.path, None).is_some() {
+                    binary_files.push(entry.path);
                 }
+            } else {
+                files.push(entry.path);
             }
         }
 
-        Ok(())
+        Ok(TextFiles {
+            text: files,
+            binary: binary_files,
+            depth_limited: walk.depth_limited(),
+            binary_count,
+            outside_glob,
+            excluded: walk.excluded(),
+        })
     }
 
     // Detect key files in the project
-    async fn detect_key_files(&self) -> anyhow::Result<Value> {
+    async fn detect_key_files(&self, root: &Path) -> anyhow::Result<Value> {
         // Define key files to look for
         let key_files = [
             // Version control
@@ -389,12 +943,13 @@ impl ProjectAnalyzer {
         let mut found_files = Vec::new();
 
         for &file in &key_files {
-            let file_path = self.base_directory.join(file);
+            let file_path = root.join(file);
             if file_path.exists() {
-                let rel_path = file_path
-                    .strip_prefix(&self.base_directory)
-                    .unwrap_or(&file_path)
-                    .to_string_lossy();
+                let rel_path = to_display_path(
+                    file_path
+                        .strip_prefix(root)
+                        .unwrap_or(&file_path),
+                );
 
                 found_files.push(json!({
                     "file": rel_path,
@@ -407,7 +962,7 @@ impl ProjectAnalyzer {
     }
 
     // Detect project type based on key files
-    async fn detect_project_type(&self, key_files: &Value) -> anyhow::Result<Value> {
+    async fn detect_project_type(&self, root: &Path, key_files: &Value) -> anyhow::Result<Value> {
         // Convert key_files to a vector of strings for easier checking
         let empty_list = Vec::new();
         let key_files_array = key_files.as_array().unwrap_or(&empty_list);
@@ -443,9 +998,9 @@ impl ProjectAnalyzer {
         // If no type detected, check for common files
         if detected_types.is_empty() {
             // Count files by extension
-            let mut extension_counts = std::collections::HashMap::new();
-            self.count_extensions(&self.base_directory, &mut extension_counts)
-                .await?;
+            let mut stats = DirectoryStats::default();
+            self.process_directory(root, &mut stats).await?;
+            let extension_counts = stats.extension_counts;
 
             // Detect based on file extensions
             if extension_counts.get("rs").unwrap_or(&0) > &0 {
@@ -473,39 +1028,324 @@ impl ProjectAnalyzer {
 
         Ok(json!(detected_types))
     }
+}
 
-    // Helper to count file extensions
-    async fn count_extensions(
-        &self,
-        dir: &Path,
-        counts: &mut std::collections::HashMap<String, usize>,
-    ) -> anyhow::Result<()> {
-        let mut entries = fs::read_dir(dir).await?;
-
-        while let Some(entry) = entries.next_entry().await? {
-            let path = entry.path();
-
-            // Skip hidden files and directories
-            if path
-                .file_name()
-                .map(|n| n.to_string_lossy().starts_with('.'))
-                .unwrap_or(false)
-            {
-                continue;
-            }
+// Whether the text preceding an identifier marks it as being defined there,
+// e.g. `pub fn ` or `let mut `
+fn is_definition_site(prefix: &str) -> bool {
+    let prefix = prefix.trim_end();
+    let prefix = prefix.strip_suffix("mut").map(str::trim_end).unwrap_or(prefix);
+
+    DEFINITION_KEYWORDS.iter().any(|keyword| {
+        prefix
+            .strip_suffix(keyword)
+            .map(|rest| rest.is_empty() || rest.ends_with(|c: char| !c.is_alphanumeric() && c != '_'))
+            .unwrap_or(false)
+    })
+}
 
-            if path.is_dir() {
-                // Recursively process subdirectory
-                Box::pin(self.count_extensions(&path, counts)).await?;
-            } else if path.is_file() {
-                // Count by extension
-                if let Some(ext) = path.extension() {
-                    let ext_str = ext.to_string_lossy().to_lowercase();
-                    *counts.entry(ext_str.to_string()).or_insert(0) += 1;
-                }
+// Trims a long line to at most `max_chars` characters, keeping the match at
+// byte range `start..end` centered and marking cut ends with an ellipsis.
+// A `max_chars` of zero leaves the line untouched
+fn preview_line(line: &str, start: usize, end: usize, max_chars: usize) -> String {
+    let total = line.chars().count();
+    if max_chars == 0 || total <= max_chars {
+        return line.to_string();
+    }
+
+    // Work in characters so the cut never splits a multibyte character
+    let start = floor_char_boundary(line, start);
+    let end = floor_char_boundary(line, end).max(start);
+    let match_start = line[..start].chars().count();
+    let match_len = line[start..end].chars().count();
+
+    let context = max_chars.saturating_sub(match_len) / 2;
+    let first = match_start.saturating_sub(context);
+    let last = (first + max_chars).min(total);
+    let first = last.saturating_sub(max_chars);
+
+    let mut preview = String::new();
+    if first > 0 {
+        preview.push('…');
+    }
+    preview.extend(line.chars().skip(first).take(last - first));
+    if last < total {
+        preview.push('…');
+    }
+    preview
+}
+
+// 1-based column of a byte offset within a line, counted in characters.
+// Columns reported by search tools are always in characters, never bytes
+fn char_column(line: &str, byte_offset: usize) -> usize {
+    line[..floor_char_boundary(line, byte_offset)].chars().count() + 1
+}
+
+// Largest char boundary at or before `index`, so slicing at it never panics
+fn floor_char_boundary(line: &str, index: usize) -> usize {
+    if index >= line.len() {
+        return line.len();
+    }
+    (0..=index).rev().find(|&i| line.is_char_boundary(i)).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    fn project_config(follow_symlinks: bool, max_depth: Option<usize>) -> ProjectConfig {
+        ProjectConfig {
+            directory: None,
+            default_extension: None,
+            apply_default_extension: None,
+            state_directory: None,
+            exclude_patterns: None,
+            auto_detect_root: None,
+            follow_symlinks: Some(follow_symlinks),
+            max_depth,
+            respect_ignore_files: Some(true),
+            walk_concurrency: None,
+            write_allowlist: None,
+            checkpoint_session_start: None,
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_walk_terminates_on_symlink_cycle() {
+        let temp_dir = TempDir::new().unwrap();
+        let nested = temp_dir.path().join("a").join("b");
+        std::fs::create_dir_all(&nested).unwrap();
+        std::fs::write(nested.join("main.rs"), "fn main() {}").unwrap();
+        // b/loop points back at the project root
+        std::os::unix::fs::symlink(temp_dir.path(), nested.join("loop")).unwrap();
+
+        let analyzer =
+            ProjectAnalyzer::new(temp_dir.path().to_path_buf(), &project_config(true, None));
+
+        let files = tokio::time::timeout(Duration::from_secs(10), analyzer.list_files(None))
+            .await
+            .expect("walk did not terminate")
+            .unwrap();
+        assert_eq!(files, vec![nested.join("main.rs")]);
+
+        let analysis = tokio::time::timeout(Duration::from_secs(10), analyzer.analyze_project(None, &AnalysisSections::default(), false, None))
+            .await
+            .expect("walk did not terminate")
+            .unwrap();
+        assert_eq!(analysis["stats"]["total_files"], 1);
+    }
+
+    #[test]
+    fn test_preview_line_centers_match() {
+        let line = format!("{}needle{}", "a".repeat(50), "b".repeat(50));
+        let start = line.find("needle").unwrap();
+
+        assert_eq!(
+            preview_line(&line, start, start + 6, 16),
+            format!("…{}needle{}…", "a".repeat(5), "b".repeat(5))
+        );
+        assert_eq!(preview_line(&line, start, start + 6, 0), line);
+        assert_eq!(preview_line("short", 0, 5, 16), "short");
+    }
+
+    #[test]
+    fn test_preview_and_column_respect_multibyte_characters() {
+        let line = format!("{}café 🎉 naïve needle{}", "é".repeat(30), "🎉".repeat(30));
+        let start = line.find("needle").unwrap();
+
+        assert_eq!(char_column(&line, start), 30 + "café 🎉 naïve ".chars().count() + 1);
+        // An offset inside a multibyte character snaps back to its start
+        assert_eq!(char_column(&line, 1), 1);
+
+        let preview = preview_line(&line, start, start + 6, 20);
+        assert!(preview.contains("needle"));
+        assert_eq!(preview.chars().count(), 22);
+
+        // Offsets that split a character don't panic
+        let emoji = line.find('🎉').unwrap();
+        let preview = preview_line(&line, emoji + 1, emoji + 3, 10);
+        assert!(preview.starts_with('…'));
+    }
+
+    #[tokio::test]
+    async fn test_list_files_stops_at_limit() {
+        let temp_dir = TempDir::new().unwrap();
+        for i in 0..5 {
+            std::fs::write(temp_dir.path().join(format!("{}.txt", i)), "").unwrap();
+        }
+        let analyzer = ProjectAnalyzer::new(temp_dir.path().to_path_buf(), &project_config(false, None));
+
+        assert_eq!(analyzer.list_files_with_metadata(None, Some(3)).await.unwrap().len(), 3);
+        assert_eq!(analyzer.list_files_with_metadata(None, None).await.unwrap().len(), 5);
+    }
+
+    // Compares listing a large tree with and without a cap. Run it with
+    // `cargo test --release list_files_benchmark -- --ignored --nocapture`
+    #[tokio::test]
+    #[ignore = "benchmark"]
+    async fn list_files_benchmark() {
+        let temp_dir = TempDir::new().unwrap();
+        for dir in 0..200 {
+            let dir = temp_dir.path().join(format!("dir{}", dir));
+            std::fs::create_dir(&dir).unwrap();
+            for file in 0..250 {
+                std::fs::write(dir.join(format!("file{}.txt", file)), "").unwrap();
             }
         }
+        let analyzer = ProjectAnalyzer::new(temp_dir.path().to_path_buf(), &project_config(false, None));
+
+        for limit in [None, Some(DEFAULT_LIST_MAX_RESULTS), Some(100)] {
+            let start = Instant::now();
+            let files = analyzer.list_files_with_metadata(None, limit).await.unwrap();
+            println!(
+                "limit {:?}: {} files in {:?}",
+                limit,
+                files.len(),
+                start.elapsed()
+            );
+        }
+    }
 
-        Ok(())
+    #[tokio::test]
+    async fn test_walk_stops_at_max_depth() {
+        let temp_dir = TempDir::new().unwrap();
+        let nested = temp_dir.path().join("a").join("b");
+        std::fs::create_dir_all(&nested).unwrap();
+        std::fs::write(temp_dir.path().join("a").join("top.rs"), "").unwrap();
+        std::fs::write(nested.join("deep.rs"), "").unwrap();
+
+        let analyzer =
+            ProjectAnalyzer::new(temp_dir.path().to_path_buf(), &project_config(false, Some(1)));
+
+        let files = analyzer.list_files(None).await.unwrap();
+        assert_eq!(files, vec![temp_dir.path().join("a").join("top.rs")]);
+
+        let analysis = analyzer.analyze_project(None, &AnalysisSections::default(), false, None).await.unwrap();
+        assert_eq!(analysis["depth_limited"], true);
+        let deep_enough =
+            ProjectAnalyzer::new(temp_dir.path().to_path_buf(), &project_config(false, Some(2)));
+        let analysis = deep_enough.analyze_project(None, &AnalysisSections::default(), false, None).await.unwrap();
+        assert_eq!(analysis["depth_limited"], false);
+
+        // The key files and project type are sent ahead of the walk
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+        let analysis = deep_enough.analyze_project(None, &AnalysisSections::default(), false, Some(sender)).await.unwrap();
+        let early = receiver.try_recv().unwrap();
+        assert_eq!(early["project_type"], analysis["project_type"]);
+        assert!(early.get("stats").is_none());
+
+        // Sections that don't need a walk leave the walk's results out
+        let sections = AnalysisSections::parse(["key_files"]).unwrap();
+        let analysis = deep_enough.analyze_project(None, &sections, false, None).await.unwrap();
+        assert!(analysis.get("key_files").is_some());
+        assert!(analysis.get("stats").is_none());
+        assert!(analysis.get("depth_limited").is_none());
+        assert!(AnalysisSections::parse(["bogus"]).is_err());
+    }
+
+    #[test]
+    fn test_search_query_combines_patterns() {
+        let patterns = vec!["fn".to_string(), "await".to_string()];
+        let exclude = vec!["test".to_string()];
+
+        let any = SearchQuery::new(&patterns, MatchMode::Any, &exclude).unwrap();
+        assert_eq!(any.find("let x = f().await;"), Some((12, 17)));
+        assert_eq!(any.find("async fn test_a() {}"), None);
+
+        let all = SearchQuery::new(&patterns, MatchMode::All, &[]).unwrap();
+        assert_eq!(all.find("async fn f() { g().await }"), Some((6, 8)));
+        assert_eq!(all.find("fn f() {}"), None);
+    }
+
+    #[test]
+    fn test_replace_in_matching_lines_only() {
+        let query = SearchQuery::new(
+            &[r"\bcount\b".to_string()],
+            MatchMode::All,
+            &["keep".to_string()],
+        )
+        .unwrap();
+        let content = "let count = 1;\r\nlet counter = count + 1;\r\ncount // keep\n";
+
+        let (replaced, lines) = query.replace_in(content, "total");
+        assert_eq!(
+            replaced,
+            "let total = 1;\r\nlet counter = total + 1;\r\ncount // keep\n"
+        );
+        assert_eq!(lines, 2);
+    }
+
+    #[tokio::test]
+    async fn test_search_within_path_and_glob() {
+        let temp_dir = TempDir::new().unwrap();
+        let src = temp_dir.path().join("src").join("a");
+        std::fs::create_dir_all(&src).unwrap();
+        std::fs::create_dir_all(temp_dir.path().join("docs")).unwrap();
+        std::fs::write(src.join("x.rs"), "needle\n").unwrap();
+        std::fs::write(temp_dir.path().join("src").join("y.txt"), "needle\n").unwrap();
+        std::fs::write(temp_dir.path().join("docs").join("z.rs"), "needle\n").unwrap();
+
+        let analyzer =
+            ProjectAnalyzer::new(temp_dir.path().to_path_buf(), &project_config(false, None));
+        let query = SearchQuery::single("needle").unwrap();
+        let files = |search: Value| -> Vec<String> {
+            let mut files: Vec<String> = search["results"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|r| r["file"].as_str().unwrap().to_string())
+                .collect();
+            files.sort();
+            files
+        };
+
+        let in_src = SearchOptions {
+            path: Some(PathBuf::from("src")),
+            ..SearchOptions::default()
+        };
+        let search = analyzer.search_files(&query, &in_src).await.unwrap();
+        assert_eq!(files(search), vec!["src/a/x.rs", "src/y.txt"]);
+
+        let rust_in_src = SearchOptions {
+            path_glob: Some("**/*.rs".to_string()),
+            ..in_src
+        };
+        let search = analyzer.search_files(&query, &rust_in_src).await.unwrap();
+        assert_eq!(search["files_searched"], 1);
+        assert_eq!(search["files_skipped"]["outside_path_glob"], 1);
+        assert_eq!(files(search), vec!["src/a/x.rs"]);
+
+        let escaping = SearchOptions {
+            path: Some(PathBuf::from("..")),
+            ..SearchOptions::default()
+        };
+        assert!(analyzer.search_files(&query, &escaping).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_analyze_subdirectory_reports_subpath() {
+        let temp_dir = TempDir::new().unwrap();
+        let package = temp_dir.path().join("packages").join("core");
+        std::fs::create_dir_all(&package).unwrap();
+        std::fs::write(package.join("lib.rs"), "fn main() {}\n").unwrap();
+        std::fs::write(temp_dir.path().join("README.md"), "# Top\n").unwrap();
+
+        let analyzer =
+            ProjectAnalyzer::new(temp_dir.path().to_path_buf(), &project_config(false, None));
+        let analysis = analyzer
+            .analyze_project(
+                Some(Path::new("./packages/core/")),
+                &AnalysisSections::default(),
+                false,
+                None,
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(analysis["project_directory"], "packages/core");
+        assert_eq!(analysis["stats"]["total_files"], 1);
     }
 }
@@ -0,0 +1,182 @@
+use serde_json::{json, Map, Value};
+use std::path::Path;
+
+// Manifests read for the dependency summary, with the ecosystem each belongs to
+const MANIFESTS: [(&str, &str); 4] = [
+    ("Cargo.toml", "Rust"),
+    ("package.json", "Node.js"),
+    ("go.mod", "Go"),
+    ("requirements.txt", "Python"),
+];
+
+// Summarizes the dependencies declared by the manifests at the top of
+// `root`. Best effort: a manifest that's missing or doesn't parse is skipped
+pub async fn dependencies(root: &Path) -> Value {
+    let mut manifests = Vec::new();
+
+    for (file, ecosystem) in MANIFESTS {
+        let Ok(content) = tokio::fs::read_to_string(root.join(file)).await else {
+            continue;
+        };
+        let parsed = match file {
+            "Cargo.toml" => parse_cargo(&content),
+            "package.json" => parse_package_json(&content),
+            "go.mod" => Some((parse_go_mod(&content), Map::new())),
+            _ => Some((parse_requirements(&content), Map::new())),
+        };
+        if let Some((dependencies, dev_dependencies)) = parsed {
+            manifests.push(json!({
+                "file": file,
+                "ecosystem": ecosystem,
+                "dependencies": dependencies,
+                "dev_dependencies": dev_dependencies
+            }));
+        }
+    }
+
+    json!({ "manifests": manifests })
+}
+
+type Dependencies = Map<String, Value>;
+
+fn parse_cargo(content: &str) -> Option<(Dependencies, Dependencies)> {
+    let manifest: toml::Table = content.parse().ok()?;
+
+    let section = |name: &str| -> Dependencies {
+        let Some(table) = manifest.get(name).and_then(|t| t.as_table()) else {
+            return Map::new();
+        };
+        table
+            .iter()
+            .map(|(name, spec)| (name.clone(), json!(cargo_version(spec))))
+            .collect()
+    };
+
+    let mut dependencies = section("dependencies");
+    dependencies.extend(section("build-dependencies"));
+    Some((dependencies, section("dev-dependencies")))
+}
+
+// The version requirement of a Cargo dependency, or where it comes from
+// when it has none
+fn cargo_version(spec: &toml::Value) -> String {
+    if let Some(version) = spec.as_str() {
+        return version.to_string();
+    }
+    let table = spec.as_table();
+    let field = |key: &str| table.and_then(|t| t.get(key));
+    if let Some(version) = field("version").and_then(|v| v.as_str()) {
+        version.to_string()
+    } else if field("workspace").and_then(|w| w.as_bool()) == Some(true) {
+        "workspace".to_string()
+    } else if field("path").is_some() {
+        "path".to_string()
+    } else if field("git").is_some() {
+        "git".to_string()
+    } else {
+        "*".to_string()
+    }
+}
+
+fn parse_package_json(content: &str) -> Option<(Dependencies, Dependencies)> {
+    let package: Value = serde_json::from_str(content).ok()?;
+    let section = |name: &str| {
+        package
+            .get(name)
+            .and_then(|d| d.as_object())
+            .cloned()
+            .unwrap_or_default()
+    };
+    Some((section("dependencies"), section("devDependencies")))
+}
+
+// `require` lines of a go.mod, in both the single-line and block forms
+fn parse_go_mod(content: &str) -> Dependencies {
+    let mut dependencies = Map::new();
+    let mut in_block = false;
+
+    for line in content.lines() {
+        let line = line.split("//").next().unwrap_or("").trim();
+        let requirement = if in_block {
+            if line == ")" {
+                in_block = false;
+                continue;
+            }
+            line
+        } else if line == "require (" {
+            in_block = true;
+            continue;
+        } else if let Some(requirement) = line.strip_prefix("require ") {
+            requirement
+        } else {
+            continue;
+        };
+
+        let mut parts = requirement.split_whitespace();
+        if let (Some(module), Some(version)) = (parts.next(), parts.next()) {
+            dependencies.insert(module.to_string(), json!(version));
+        }
+    }
+
+    dependencies
+}
+
+// Requirement lines of a requirements.txt; unpinned packages get "*"
+fn parse_requirements(content: &str) -> Dependencies {
+    let mut dependencies = Map::new();
+
+    for line in content.lines() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        // Options such as -r and -e aren't packages
+        if line.is_empty() || line.starts_with('-') {
+            continue;
+        }
+        let line = line.split(';').next().unwrap_or("").trim();
+        match line.find(|c| "=<>!~".contains(c)) {
+            Some(at) => {
+                let (name, version) = line.split_at(at);
+                dependencies.insert(name.trim().to_string(), json!(version.trim()));
+            }
+            None => {
+                dependencies.insert(line.to_string(), json!("*"));
+            }
+        }
+    }
+
+    dependencies
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_dependencies_from_manifests() {
+        let temp = TempDir::new().unwrap();
+        std::fs::write(
+            temp.path().join("Cargo.toml"),
+            "[package]\nname = \"demo\"\n\n[dependencies]\nserde = \"1.0\"\ntokio = { version = \"1\", features = [\"full\"] }\nlocal = { path = \"../local\" }\n\n[dev-dependencies]\ntempfile = \"3\"\n",
+        )
+        .unwrap();
+        std::fs::write(temp.path().join("package.json"), "{ not json").unwrap();
+        std::fs::write(
+            temp.path().join("requirements.txt"),
+            "# pinned\nrequests==2.31.0\nflask\n-r other.txt\n",
+        )
+        .unwrap();
+
+        let summary = dependencies(temp.path()).await;
+        let manifests = summary["manifests"].as_array().unwrap();
+
+        // The broken package.json is skipped
+        assert_eq!(manifests.len(), 2);
+        assert_eq!(manifests[0]["file"], "Cargo.toml");
+        assert_eq!(manifests[0]["dependencies"]["serde"], "1.0");
+        assert_eq!(manifests[0]["dependencies"]["tokio"], "1");
+        assert_eq!(manifests[0]["dependencies"]["local"], "path");
+        assert_eq!(manifests[0]["dev_dependencies"]["tempfile"], "3");
+        assert_eq!(manifests[1]["dependencies"]["requests"], "==2.31.0");
+        assert_eq!(manifests[1]["dependencies"]["flask"], "*");
+    }
+}
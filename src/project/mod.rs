@@ -1,3 +1,8 @@
 pub mod analyzer;
+pub mod manifest;
+pub mod outline;
+pub mod tasks;
+pub mod tree;
+pub mod walker;
 
 pub use analyzer::ProjectAnalyzer;
@@ -0,0 +1,191 @@
+use crate::shared::language::Language;
+use regex::Regex;
+use serde_json::{json, Value};
+
+// Non-blank lines returned for files without a known structure
+pub const FALLBACK_LINES: usize = 10;
+
+// One entry of a file outline, with a 1-based line number
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutlineEntry {
+    pub kind: String,
+    pub title: String,
+    pub line: usize,
+    // Heading depth for Markdown entries
+    pub level: Option<usize>,
+}
+
+impl OutlineEntry {
+    pub fn to_json(&self) -> Value {
+        let mut entry = json!({
+            "kind": self.kind,
+            "title": self.title,
+            "line": self.line
+        });
+        if let Some(level) = self.level {
+            entry["level"] = json!(level);
+        }
+        entry
+    }
+}
+
+// Patterns matching top-level declarations. The first capture group, if
+// present, names the kind of declaration; otherwise the given kind is used
+fn declaration_patterns(language: Language) -> Option<(&'static str, &'static str)> {
+    let patterns = match language {
+        Language::Rust => (
+            r#"^(?:pub(?:\([^)]*\))?\s+)?(?:(?:async|unsafe|const|extern\s+"[^"]*")\s+)*(fn|struct|enum|trait|impl|mod|type|const|static|union|macro_rules!)\b"#,
+            "declaration",
+        ),
+        Language::Python => (r"^(?:async\s+)?(def|class)\b", "declaration"),
+        Language::JavaScript | Language::TypeScript => (
+            r"^(?:export\s+)?(?:default\s+)?(?:declare\s+)?(?:abstract\s+)?(?:async\s+)?(function|class|interface|type|enum|const|let|var)\b",
+            "declaration",
+        ),
+        Language::Go => (r"^(func|type|var|const)\b", "declaration"),
+        Language::Ruby => (r"^(def|class|module)\b", "declaration"),
+        Language::Php => (
+            r"^(?:(?:abstract|final)\s+)?(function|class|interface|trait)\b",
+            "declaration",
+        ),
+        Language::Java | Language::CSharp => (
+            r"^(?:(?:public|private|protected|internal|static|abstract|final|sealed|partial)\s+)*(class|interface|enum|record|struct|namespace)\b",
+            "declaration",
+        ),
+        Language::Terraform => (
+            r"^(resource|data|module|variable|output|provider|locals|terraform)\b",
+            "block",
+        ),
+        Language::Shell => (
+            r"^(?:function\s+)?[A-Za-z_][A-Za-z0-9_]*\s*\(\)",
+            "function",
+        ),
+        Language::Makefile => (r"^[A-Za-z0-9_./%-]+\s*:(?:[^=]|$)", "target"),
+        _ => return None,
+    };
+
+    Some(patterns)
+}
+
+// Headings of a Markdown document, skipping fenced code blocks
+fn markdown_outline(content: &str) -> Vec<OutlineEntry> {
+    let mut entries = Vec::new();
+    let mut in_fence = false;
+
+    for (i, line) in content.lines().enumerate() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_fence = !in_fence;
+            continue;
+        }
+        if in_fence {
+            continue;
+        }
+
+        let level = line.chars().take_while(|&c| c == '#').count();
+        if (1..=6).contains(&level) {
+            let rest = &line[level..];
+            if rest.is_empty() || rest.starts_with(' ') {
+                entries.push(OutlineEntry {
+                    kind: "heading".to_string(),
+                    title: rest.trim().trim_end_matches('#').trim_end().to_string(),
+                    line: i + 1,
+                    level: Some(level),
+                });
+            }
+        }
+    }
+
+    entries
+}
+
+// Top-level declarations of a source file, found by unindented lines that
+// start with a declaration keyword
+fn code_outline(content: &str, pattern: &str, default_kind: &str) -> Vec<OutlineEntry> {
+    let regex = match Regex::new(pattern) {
+        Ok(regex) => regex,
+        Err(_) => return Vec::new(),
+    };
+
+    content
+        .lines()
+        .enumerate()
+        .filter_map(|(i, line)| {
+            let captures = regex.captures(line)?;
+            let kind = captures
+                .get(1)
+                .map(|m| m.as_str().trim_end_matches('!'))
+                .unwrap_or(default_kind);
+
+            Some(OutlineEntry {
+                kind: kind.to_string(),
+                title: line.trim_end().trim_end_matches('{').trim_end().to_string(),
+                line: i + 1,
+                level: None,
+            })
+        })
+        .collect()
+}
+
+// The first few non-blank lines, for files without a known structure
+fn leading_lines(content: &str) -> Vec<OutlineEntry> {
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .take(FALLBACK_LINES)
+        .map(|(i, line)| OutlineEntry {
+            kind: "line".to_string(),
+            title: line.trim().to_string(),
+            line: i + 1,
+            level: None,
+        })
+        .collect()
+}
+
+/// Builds a cheap structural outline of a file: Markdown headings, top-level
+/// declarations for known languages, or the leading lines otherwise
+pub fn outline(language: Option<Language>, content: &str) -> Vec<OutlineEntry> {
+    let entries = match language {
+        Some(Language::Markdown) => markdown_outline(content),
+        Some(language) => match declaration_patterns(language) {
+            Some((pattern, default_kind)) => code_outline(content, pattern, default_kind),
+            None => Vec::new(),
+        },
+        None => Vec::new(),
+    };
+
+    if entries.is_empty() {
+        leading_lines(content)
+    } else {
+        entries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_markdown_outline_skips_code_blocks() {
+        let content = "# Title\n\ntext\n\n```sh\n# not a heading\n```\n## Usage ##\n";
+        let entries = outline(Some(Language::Markdown), content);
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].title, "Title");
+        assert_eq!(entries[0].level, Some(1));
+        assert_eq!(entries[1].title, "Usage");
+        assert_eq!(entries[1].line, 8);
+    }
+
+    #[test]
+    fn test_code_outline_finds_top_level_declarations() {
+        let content = "use std::fs;\n\npub struct A {\n    x: u8,\n}\n\nimpl A {\n    fn inner() {}\n}\n\npub async fn run() {\n}\n";
+        let entries = outline(Some(Language::Rust), content);
+        let kinds: Vec<&str> = entries.iter().map(|e| e.kind.as_str()).collect();
+
+        assert_eq!(kinds, vec!["struct", "impl", "fn"]);
+        assert_eq!(entries[2].title, "pub async fn run()");
+        assert_eq!(entries[2].line, 11);
+    }
+}
@@ -0,0 +1,283 @@
+use crate::config::TasksConfig;
+use crate::shared::logging;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tokio::io::AsyncReadExt;
+use tokio::process::{Child, Command};
+use tokio::sync::mpsc::UnboundedSender;
+
+// Seconds a task may run when the config doesn't say
+pub const DEFAULT_TASK_TIMEOUT_SECS: u64 = 300;
+
+// Bytes of each output stream kept when the config doesn't say
+pub const DEFAULT_MAX_OUTPUT_BYTES: usize = 64 * 1024;
+
+#[derive(Error, Debug)]
+pub enum TaskError {
+    #[error("Unknown task: {name} (configured tasks: {available})")]
+    UnknownTask { name: String, available: String },
+
+    #[error("Task {0} has an empty command")]
+    EmptyCommand(String),
+
+    #[error("Failed to run task {name}: {source}")]
+    SpawnFailed {
+        name: String,
+        source: std::io::Error,
+    },
+}
+
+// A piece of a running task's output, as it is produced
+#[derive(Debug)]
+pub struct TaskOutput {
+    // "stdout" or "stderr"
+    pub stream: &'static str,
+    pub text: String,
+}
+
+// Output of one stream, bounded by keeping only its first and last bytes
+struct CappedOutput {
+    head: Vec<u8>,
+    tail: VecDeque<u8>,
+    limit: usize,
+    total: usize,
+}
+
+impl CappedOutput {
+    fn new(limit: usize) -> Self {
+        Self {
+            head: Vec::new(),
+            tail: VecDeque::new(),
+            limit,
+            total: 0,
+        }
+    }
+
+    fn push(&mut self, bytes: &[u8]) {
+        self.total += bytes.len();
+
+        let head_room = (self.limit / 2).saturating_sub(self.head.len());
+        let (to_head, rest) = bytes.split_at(head_room.min(bytes.len()));
+        self.head.extend_from_slice(to_head);
+
+        let tail_limit = self.limit - self.limit / 2;
+        self.tail.extend(rest);
+        let excess = self.tail.len().saturating_sub(tail_limit);
+        self.tail.drain(..excess);
+    }
+
+    fn truncated(&self) -> bool {
+        self.total > self.head.len() + self.tail.len()
+    }
+
+    fn into_string(mut self) -> String {
+        let omitted = self.total - self.head.len() - self.tail.len();
+        let head = String::from_utf8_lossy(&self.head).into_owned();
+        let tail = String::from_utf8_lossy(self.tail.make_contiguous()).into_owned();
+
+        if omitted == 0 {
+            head + &tail
+        } else {
+            format!("{}\n…truncated {} bytes…\n{}", head, omitted, tail)
+        }
+    }
+}
+
+// Runs the commands allowlisted in the tasks section of the config, by name
+// only, so clients can build and test the project without getting a shell
+pub struct TaskRunner {
+    directory: PathBuf,
+    commands: BTreeMap<String, String>,
+    timeout: Duration,
+    max_output_bytes: usize,
+}
+
+impl TaskRunner {
+    pub fn new(directory: &Path, config: &TasksConfig) -> Self {
+        Self {
+            directory: directory.to_path_buf(),
+            commands: config.commands.clone().unwrap_or_default(),
+            timeout: Duration::from_secs(
+                config.timeout_seconds.unwrap_or(DEFAULT_TASK_TIMEOUT_SECS),
+            ),
+            max_output_bytes: config.max_output_bytes.unwrap_or(DEFAULT_MAX_OUTPUT_BYTES),
+        }
+    }
+
+    // Runs a task to completion, or until it times out and is killed along
+    // with everything it started. Output is passed to `progress` as it
+    // arrives, if given, and kept up to the configured size for the result
+    pub async fn run(
+        &self,
+        name: &str,
+        progress: Option<UnboundedSender<TaskOutput>>,
+    ) -> Result<Value, TaskError> {
+        let command = self
+            .commands
+            .get(name)
+            .ok_or_else(|| TaskError::UnknownTask {
+                name: name.to_string(),
+                available: if self.commands.is_empty() {
+                    "none".to_string()
+                } else {
+                    self.commands.keys().cloned().collect::<Vec<_>>().join(", ")
+                },
+            })?;
+
+        let mut words = command.split_whitespace();
+        let program = words
+            .next()
+            .ok_or_else(|| TaskError::EmptyCommand(name.to_string()))?;
+
+        logging::info(&format!(
+            "Running task {}: {} (in {})",
+            name,
+            command,
+            self.directory.display()
+        ));
+
+        let spawn_failed = |source| TaskError::SpawnFailed {
+            name: name.to_string(),
+            source,
+        };
+
+        let mut process = Command::new(program);
+        process
+            .args(words)
+            .current_dir(&self.directory)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        // Its own process group, so a timeout can kill whatever it started too
+        #[cfg(unix)]
+        process.process_group(0);
+        let mut child = process.spawn().map_err(spawn_failed)?;
+
+        let started = Instant::now();
+        let mut stdout = CappedOutput::new(self.max_output_bytes);
+        let mut stderr = CappedOutput::new(self.max_output_bytes);
+
+        let finished = tokio::time::timeout(
+            self.timeout,
+            collect_output(&mut child, &mut stdout, &mut stderr, progress.as_ref()),
+        )
+        .await;
+
+        let status = match finished {
+            Ok(status) => Some(status.map_err(spawn_failed)?),
+            Err(_) => {
+                logging::warn(&format!(
+                    "Task {} timed out after {}s and was killed",
+                    name,
+                    self.timeout.as_secs()
+                ));
+                kill_process_group(&mut child);
+                let _ = child.wait().await;
+                None
+            }
+        };
+
+        Ok(json!({
+            "task": name,
+            "command": command,
+            "success": status.map(|s| s.success()).unwrap_or(false),
+            "timed_out": status.is_none(),
+            // None when the process timed out or was ended by a signal
+            "exit_code": status.and_then(|s| s.code()),
+            "duration_ms": started.elapsed().as_millis() as u64,
+            "truncated": stdout.truncated() || stderr.truncated(),
+            "stdout": stdout.into_string(),
+            "stderr": stderr.into_string()
+        }))
+    }
+}
+
+// Reads both output streams of a task until they close, then waits for it
+// to exit
+async fn collect_output(
+    child: &mut Child,
+    stdout: &mut CappedOutput,
+    stderr: &mut CappedOutput,
+    progress: Option<&UnboundedSender<TaskOutput>>,
+) -> std::io::Result<std::process::ExitStatus> {
+    let mut out_pipe = child.stdout.take();
+    let mut err_pipe = child.stderr.take();
+    let mut out_buf = [0u8; 8192];
+    let mut err_buf = [0u8; 8192];
+
+    let record = |output: &mut CappedOutput, stream: &'static str, bytes: &[u8]| {
+        output.push(bytes);
+        if let Some(progress) = progress {
+            let _ = progress.send(TaskOutput {
+                stream,
+                text: String::from_utf8_lossy(bytes).into_owned(),
+            });
+        }
+    };
+
+    while out_pipe.is_some() || err_pipe.is_some() {
+        tokio::select! {
+            read = read_some(&mut out_pipe, &mut out_buf) => match read {
+                Some(n) => record(stdout, "stdout", &out_buf[..n]),
+                None => out_pipe = None,
+            },
+            read = read_some(&mut err_pipe, &mut err_buf) => match read {
+                Some(n) => record(stderr, "stderr", &err_buf[..n]),
+                None => err_pipe = None,
+            },
+        }
+    }
+
+    child.wait().await
+}
+
+// Reads from a pipe, or never completes once the pipe is gone. None means
+// the pipe closed
+async fn read_some<R: AsyncReadExt + Unpin>(pipe: &mut Option<R>, buf: &mut [u8]) -> Option<usize> {
+    match pipe {
+        Some(pipe) => match pipe.read(buf).await {
+            Ok(0) | Err(_) => None,
+            Ok(n) => Some(n),
+        },
+        None => std::future::pending().await,
+    }
+}
+
+fn kill_process_group(child: &mut Child) {
+    #[cfg(unix)]
+    if let Some(pid) = child.id() {
+        // The group id is the task's pid, as it leads its own group
+        unsafe {
+            libc::killpg(pid as libc::pid_t, libc::SIGKILL);
+        }
+        return;
+    }
+
+    let _ = child.start_kill();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capped_output_keeps_head_and_tail() {
+        let mut output = CappedOutput::new(8);
+        output.push(b"abc");
+        output.push(b"defghijkl");
+        output.push(b"mn");
+
+        assert!(output.truncated());
+        assert_eq!(output.into_string(), "abcd\n…truncated 6 bytes…\nklmn");
+
+        let mut short = CappedOutput::new(8);
+        short.push(b"abcdef");
+        assert!(!short.truncated());
+        assert_eq!(short.into_string(), "abcdef");
+    }
+}
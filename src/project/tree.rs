@@ -0,0 +1,179 @@
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+// Levels shown when the caller doesn't say
+pub const DEFAULT_TREE_DEPTH: usize = 3;
+
+// Entries shown when the caller doesn't say
+pub const DEFAULT_TREE_ENTRIES: usize = 500;
+
+// A directory of the tree, with everything the walk found below it
+#[derive(Default)]
+struct DirNode {
+    dirs: BTreeMap<String, DirNode>,
+    files: Vec<String>,
+}
+
+impl DirNode {
+    fn file_count(&self) -> usize {
+        self.files.len() + self.dirs.values().map(DirNode::file_count).sum::<usize>()
+    }
+}
+
+// A directory listing like the `tree` command, built from walked paths and
+// rendered both as text and as nested JSON
+pub struct DirectoryTree {
+    root: DirNode,
+}
+
+impl DirectoryTree {
+    pub fn new() -> Self {
+        Self {
+            root: DirNode::default(),
+        }
+    }
+
+    // Adds an entry by its path relative to the root of the tree
+    pub fn insert(&mut self, relative: &Path, is_dir: bool) {
+        let names: Vec<String> = relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().into_owned())
+            .collect();
+        let (last, parents) = match names.split_last() {
+            Some(split) => split,
+            None => return,
+        };
+
+        let mut node = &mut self.root;
+        for name in parents {
+            node = node.dirs.entry(name.clone()).or_default();
+        }
+        if is_dir {
+            node.dirs.entry(last.clone()).or_default();
+        } else {
+            node.files.push(last.clone());
+        }
+    }
+
+    // Renders the tree `depth` levels deep, listing at most `max_entries`
+    // entries. Directories at the depth limit show how many files they hold
+    pub fn render(
+        &mut self,
+        root_name: &str,
+        depth: usize,
+        dirs_only: bool,
+        max_entries: usize,
+    ) -> Value {
+        let mut renderer = Renderer {
+            lines: vec![format!("{}/", root_name)],
+            shown: 0,
+            omitted: 0,
+            depth,
+            dirs_only,
+            max_entries,
+        };
+        let children = renderer.render_dir(&mut self.root, "", 1);
+
+        let mut text = renderer.lines.join("\n");
+        if renderer.omitted > 0 {
+            text.push_str(&format!("\n… {} more entries", renderer.omitted));
+        }
+
+        json!({
+            "tree": text,
+            "structure": {
+                "name": root_name,
+                "type": "directory",
+                "children": children
+            },
+            "entries": renderer.shown,
+            "truncated": renderer.omitted > 0
+        })
+    }
+}
+
+struct Renderer {
+    lines: Vec<String>,
+    shown: usize,
+    // Entries left out once max_entries was reached
+    omitted: usize,
+    depth: usize,
+    dirs_only: bool,
+    max_entries: usize,
+}
+
+impl Renderer {
+    fn render_dir(&mut self, node: &mut DirNode, prefix: &str, level: usize) -> Vec<Value> {
+        node.files.sort();
+
+        // Directories and files together in name order, like `tree`
+        let mut entries: Vec<(&String, Option<&mut DirNode>)> = node
+            .dirs
+            .iter_mut()
+            .map(|(name, dir)| (name, Some(dir)))
+            .collect();
+        if !self.dirs_only {
+            entries.extend(node.files.iter().map(|name| (name, None)));
+        }
+        entries.sort_by(|a, b| a.0.cmp(b.0));
+
+        let count = entries.len();
+        let mut children = Vec::new();
+
+        for (index, (name, dir)) in entries.into_iter().enumerate() {
+            if self.shown >= self.max_entries {
+                self.omitted += 1;
+                if let Some(dir) = dir {
+                    self.omitted += count_entries(dir, self.dirs_only);
+                }
+                continue;
+            }
+            self.shown += 1;
+
+            let last = index + 1 == count;
+            let branch = if last { "└── " } else { "├── " };
+
+            match dir {
+                None => {
+                    self.lines.push(format!("{}{}{}", prefix, branch, name));
+                    children.push(json!({ "name": name, "type": "file" }));
+                }
+                Some(dir) if level >= self.depth => {
+                    let files = dir.file_count();
+                    self.lines
+                        .push(format!("{}{}{}/ ({} files)", prefix, branch, name, files));
+                    children.push(json!({
+                        "name": name,
+                        "type": "directory",
+                        "file_count": files
+                    }));
+                }
+                Some(dir) => {
+                    self.lines.push(format!("{}{}{}/", prefix, branch, name));
+                    let child_prefix = format!("{}{}", prefix, if last { "    " } else { "│   " });
+                    let grandchildren = self.render_dir(dir, &child_prefix, level + 1);
+                    children.push(json!({
+                        "name": name,
+                        "type": "directory",
+                        "children": grandchildren
+                    }));
+                }
+            }
+        }
+
+        children
+    }
+}
+
+// Entries that would have been listed below a directory left out entirely
+fn count_entries(node: &DirNode, dirs_only: bool) -> usize {
+    let files = if dirs_only { 0 } else { node.files.len() };
+    files
+        + node.dirs.len()
+        + node
+            .dirs
+            .values()
+            .map(|dir| count_entries(dir, dirs_only))
+            .sum::<usize>()
+}
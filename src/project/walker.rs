@@ -0,0 +1,566 @@
+use crate::config::ProjectConfig;
+use crate::shared::logging;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::Match;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::fs;
+use tokio::sync::Semaphore;
+
+// Guards against runaway recursion when no max_depth is configured
+pub const DEFAULT_MAX_DEPTH: usize = 64;
+
+// Upper bound of the default walk_concurrency, however many CPUs there are
+const MAX_DEFAULT_WALK_CONCURRENCY: usize = 16;
+
+// Per-directory ignore files, read in every directory a walk enters
+const IGNORE_FILES: &[&str] = &[".gitignore", ".mceditignore"];
+
+// Rules deciding which entries a walk visits
+#[derive(Debug, Clone)]
+pub struct WalkOptions {
+    // Whether entries whose name starts with a dot are visited
+    pub include_hidden: bool,
+    pub follow_symlinks: bool,
+    // Deepest directory level a walk descends to below the project root
+    pub max_depth: usize,
+    // Gitignore-style patterns skipped by every walk, relative to the project
+    // root; a later `!pattern` re-includes what an earlier one excluded
+    pub exclude_patterns: Vec<String>,
+    // Whether .gitignore and .mceditignore files are honored
+    pub respect_ignore_files: bool,
+    // Limits how many directories the walks sharing these options read at
+    // once, so they can't run out of file descriptors
+    pub open_directories: Arc<Semaphore>,
+    // Canonical directories the client allows access to; entries outside
+    // them are left out. None allows the whole project
+    pub client_roots: Option<Vec<PathBuf>>,
+}
+
+impl WalkOptions {
+    pub fn from_config(config: &ProjectConfig) -> Self {
+        Self {
+            include_hidden: false,
+            follow_symlinks: config.follow_symlinks.unwrap_or(false),
+            max_depth: config.max_depth.unwrap_or(DEFAULT_MAX_DEPTH),
+            exclude_patterns: config.exclude_patterns.clone().unwrap_or_default(),
+            respect_ignore_files: config.respect_ignore_files.unwrap_or(true),
+            open_directories: Arc::new(Semaphore::new(walk_concurrency(config))),
+            client_roots: None,
+        }
+    }
+}
+
+// The configured walk_concurrency, or the number of CPUs up to a limit
+pub fn walk_concurrency(config: &ProjectConfig) -> usize {
+    config.walk_concurrency.unwrap_or_else(|| {
+        std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1)
+            .min(MAX_DEFAULT_WALK_CONCURRENCY)
+    })
+}
+
+// A file or directory reached by a walk
+pub struct WalkEntry {
+    pub path: PathBuf,
+    // Metadata of the entry, following the symlink if it is one
+    pub metadata: std::fs::Metadata,
+}
+
+impl WalkEntry {
+    pub fn is_dir(&self) -> bool {
+        self.metadata.is_dir()
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.metadata.is_file()
+    }
+}
+
+// The exclude_patterns of a walk, compiled as gitignore rules rooted at the
+// project. Unlike gitignore, a `!pattern` can re-include an entry inside an
+// excluded directory: such directories are still walked, without being
+// yielded themselves, when a negation could match below them
+struct Excludes {
+    root: PathBuf,
+    rules: Gitignore,
+    negations: Vec<String>,
+}
+
+impl Excludes {
+    fn new(root: &Path, patterns: &[String]) -> Self {
+        let mut builder = GitignoreBuilder::new(root);
+        for pattern in patterns {
+            if let Err(err) = builder.add_line(None, pattern) {
+                logging::warn(&format!("Invalid exclude pattern {}: {}", pattern, err));
+            }
+        }
+
+        let rules = builder.build().unwrap_or_else(|err| {
+            logging::warn(&format!("Failed to compile exclude patterns: {}", err));
+            Gitignore::empty()
+        });
+        let negations = patterns
+            .iter()
+            .filter_map(|p| p.strip_prefix('!'))
+            .map(|p| p.trim_start_matches('/').to_string())
+            .collect();
+
+        Self {
+            root: root.to_path_buf(),
+            rules,
+            negations,
+        }
+    }
+
+    // The rule matching the entry itself decides, failing that the one
+    // matching its nearest parent directory
+    fn is_excluded(&self, path: &Path, is_dir: bool) -> bool {
+        let mut is_dir = is_dir;
+        for ancestor in path.ancestors() {
+            if ancestor == self.root || !ancestor.starts_with(&self.root) {
+                break;
+            }
+            match self.rules.matched(ancestor, is_dir) {
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => return false,
+                Match::None => {}
+            }
+            is_dir = true;
+        }
+        false
+    }
+
+    // Whether a negation could match something inside the excluded `dir`
+    fn may_reinclude_below(&self, dir: &Path) -> bool {
+        let relative = match dir.strip_prefix(&self.root) {
+            Ok(relative) => format!("{}/", relative.to_string_lossy().replace('\\', "/")),
+            Err(_) => return false,
+        };
+
+        self.negations.iter().any(|pattern| {
+            // Without a slash a pattern matches names at any depth
+            if !pattern.trim_end_matches('/').contains('/') {
+                return true;
+            }
+            let literal_end = pattern.find(['*', '?', '[', '{']).unwrap_or(pattern.len());
+            let literal = &pattern[..literal_end];
+            literal.starts_with(&relative) || relative.starts_with(literal)
+        })
+    }
+}
+
+// A directory being walked, with the ignore rules that apply inside it
+struct Frame {
+    entries: std::vec::IntoIter<(PathBuf, std::fs::FileType)>,
+    depth: usize,
+    ignores: Vec<Arc<Gitignore>>,
+}
+
+// Depth-first walk of a directory tree, yielding entries one at a time.
+// Directories are yielded before their contents
+pub struct Walk {
+    options: Arc<WalkOptions>,
+    excludes: Excludes,
+    // Canonical project root, used to keep followed symlinks inside the project
+    canonical_root: PathBuf,
+    // Directories already walked, keyed by canonical path, so a symlink
+    // cycle is never walked twice
+    visited: HashSet<PathBuf>,
+    stack: Vec<Frame>,
+    // Whether a directory was left out for lying deeper than max_depth
+    depth_limited: bool,
+    // Entries left out by ignore files or exclude_patterns; an excluded
+    // directory counts once, whatever it holds
+    excluded: usize,
+}
+
+impl Walk {
+    // Starts a walk at `walk_root`, which lies inside `project_root`. Ignore
+    // files between the two apply to the walk as well
+    pub async fn new(
+        options: Arc<WalkOptions>,
+        project_root: &Path,
+        canonical_root: &Path,
+        walk_root: &Path,
+    ) -> anyhow::Result<Self> {
+        let mut visited = HashSet::new();
+        visited.insert(canonical_root.to_path_buf());
+        if let Ok(canonical) = fs::canonicalize(walk_root).await {
+            visited.insert(canonical);
+        }
+
+        let mut ignores = Vec::new();
+        if options.respect_ignore_files {
+            let mut dir = project_root.to_path_buf();
+            ignores.extend(load_ignore_file(&dir));
+            if let Ok(relative) = walk_root.strip_prefix(project_root) {
+                for component in relative.components() {
+                    dir.push(component);
+                    ignores.extend(load_ignore_file(&dir));
+                }
+            }
+        }
+
+        let entries = read_directory(&options, walk_root).await?;
+        let excludes = Excludes::new(project_root, &options.exclude_patterns);
+
+        Ok(Self {
+            options,
+            excludes,
+            canonical_root: canonical_root.to_path_buf(),
+            visited,
+            stack: vec![Frame {
+                entries,
+                depth: 0,
+                ignores,
+            }],
+            depth_limited: false,
+            excluded: 0,
+        })
+    }
+
+    // Whether the walk so far skipped a directory deeper than max_depth, so
+    // its results are incomplete
+    pub fn depth_limited(&self) -> bool {
+        self.depth_limited
+    }
+
+    // Number of entries the walk so far left out by ignore files or
+    // exclude_patterns
+    pub fn excluded(&self) -> usize {
+        self.excluded
+    }
+
+    // The next entry of the walk, or None once it is finished
+    pub async fn next_entry(&mut self) -> anyhow::Result<Option<WalkEntry>> {
+        while let Some(frame) = self.stack.last_mut() {
+            let (path, file_type) = match frame.entries.next() {
+                Some(entry) => entry,
+                None => {
+                    self.stack.pop();
+                    continue;
+                }
+            };
+            let depth = frame.depth;
+
+            if !self.allows_name(&path) {
+                continue;
+            }
+            if file_type.is_symlink() && !self.allows_symlink(&path).await {
+                continue;
+            }
+
+            // Follows the symlink, if any, since the policy allowed walking it
+            let metadata = match fs::metadata(&path).await {
+                Ok(metadata) => metadata,
+                Err(_) => continue,
+            };
+
+            // Still the frame the entry was read from
+            let ignores = match self.stack.last() {
+                Some(frame) => &frame.ignores,
+                None => continue,
+            };
+            if is_ignored(ignores, &path, metadata.is_dir()) {
+                self.excluded += 1;
+                continue;
+            }
+
+            if self.excludes.is_excluded(&path, metadata.is_dir()) {
+                // Walk through excluded directories that may hold
+                // re-included entries, but don't report them
+                if metadata.is_dir() && self.excludes.may_reinclude_below(&path) {
+                    let ignores = ignores.clone();
+                    self.enter_directory(&path, depth, ignores).await;
+                }
+                self.excluded += 1;
+                continue;
+            }
+
+            if !self.allows_client_roots(&path, metadata.is_dir()).await {
+                continue;
+            }
+
+            // Still the frame the entry was read from
+            let ignores = match self.stack.last() {
+                Some(frame) => &frame.ignores,
+                None => continue,
+            };
+            if metadata.is_dir() {
+                let ignores = ignores.clone();
+                if !self.enter_directory(&path, depth, ignores).await {
+                    continue;
+                }
+            } else if !metadata.is_file() {
+                continue;
+            }
+
+            return Ok(Some(WalkEntry { path, metadata }));
+        }
+
+        Ok(None)
+    }
+
+    fn allows_name(&self, path: &Path) -> bool {
+        let name = match path.file_name() {
+            Some(name) => name.to_string_lossy(),
+            None => return false,
+        };
+
+        self.options.include_hidden || !name.starts_with('.')
+    }
+
+    // Whether an entry lies inside the client's roots. Directories leading
+    // down to a root are walked too, so the roots below them are reached
+    async fn allows_client_roots(&self, path: &Path, is_dir: bool) -> bool {
+        let roots = match &self.options.client_roots {
+            Some(roots) => roots,
+            None => return true,
+        };
+        let canonical = match fs::canonicalize(path).await {
+            Ok(canonical) => canonical,
+            Err(_) => return false,
+        };
+
+        roots
+            .iter()
+            .any(|root| canonical.starts_with(root) || (is_dir && root.starts_with(&canonical)))
+    }
+
+    // Followed links must stay inside the project
+    async fn allows_symlink(&self, path: &Path) -> bool {
+        if !self.options.follow_symlinks {
+            return false;
+        }
+
+        match fs::canonicalize(path).await {
+            Ok(target) => target.starts_with(&self.canonical_root),
+            // Dangling link
+            Err(_) => false,
+        }
+    }
+
+    // Queues a directory to be read next, unless it lies deeper than
+    // max_depth or was already walked (a symlink cycle)
+    async fn enter_directory(
+        &mut self,
+        path: &Path,
+        depth: usize,
+        mut ignores: Vec<Arc<Gitignore>>,
+    ) -> bool {
+        if depth >= self.options.max_depth {
+            self.depth_limited = true;
+            logging::debug(&format!(
+                "Skipping {}: deeper than max depth {}",
+                path.display(),
+                self.options.max_depth
+            ));
+            return false;
+        }
+
+        let is_new = match fs::canonicalize(path).await {
+            Ok(canonical) => self.visited.insert(canonical),
+            Err(_) => false,
+        };
+        if !is_new {
+            return false;
+        }
+
+        let entries = match read_directory(&self.options, path).await {
+            Ok(entries) => entries,
+            Err(err) => {
+                logging::warn(&format!("Skipping {}: {}", path.display(), err));
+                return false;
+            }
+        };
+
+        if self.options.respect_ignore_files {
+            ignores.extend(load_ignore_file(path));
+        }
+
+        self.stack.push(Frame {
+            entries,
+            depth: depth + 1,
+            ignores,
+        });
+        true
+    }
+}
+
+// The entries of a directory with their types. The directory is read in
+// full and closed right away, so a walk holds none open between entries and
+// deep trees need no more than one of the shared permits
+async fn read_directory(
+    options: &WalkOptions,
+    dir: &Path,
+) -> std::io::Result<std::vec::IntoIter<(PathBuf, std::fs::FileType)>> {
+    let _permit = options
+        .open_directories
+        .acquire()
+        .await
+        .expect("the walk semaphore is never closed");
+
+    let mut read_dir = fs::read_dir(dir).await?;
+    let mut entries = Vec::new();
+    while let Some(entry) = read_dir.next_entry().await? {
+        if let Ok(file_type) = entry.file_type().await {
+            entries.push((entry.path(), file_type));
+        }
+    }
+    Ok(entries.into_iter())
+}
+
+// Rules from the ignore files directly inside `dir`, if there are any
+fn load_ignore_file(dir: &Path) -> Option<Arc<Gitignore>> {
+    let mut builder = GitignoreBuilder::new(dir);
+    let mut found = false;
+
+    for name in IGNORE_FILES {
+        let file = dir.join(name);
+        if file.is_file() {
+            found = true;
+            if let Some(err) = builder.add(&file) {
+                logging::warn(&format!("Invalid rule in {}: {}", file.display(), err));
+            }
+        }
+    }
+
+    if !found {
+        return None;
+    }
+
+    match builder.build() {
+        Ok(gitignore) => Some(Arc::new(gitignore)),
+        Err(err) => {
+            logging::warn(&format!(
+                "Failed to load ignore rules in {}: {}",
+                dir.display(),
+                err
+            ));
+            None
+        }
+    }
+}
+
+// Rules from deeper directories take precedence, so the first decisive match
+// walking up from the entry wins
+fn is_ignored(ignores: &[Arc<Gitignore>], path: &Path, is_dir: bool) -> bool {
+    for gitignore in ignores.iter().rev() {
+        match gitignore.matched(path, is_dir) {
+            Match::Ignore(_) => return true,
+            Match::Whitelist(_) => return false,
+            Match::None => {}
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn options() -> Arc<WalkOptions> {
+        Arc::new(WalkOptions {
+            include_hidden: false,
+            follow_symlinks: false,
+            max_depth: DEFAULT_MAX_DEPTH,
+            exclude_patterns: vec!["target".to_string()],
+            respect_ignore_files: true,
+            open_directories: Arc::new(Semaphore::new(4)),
+            client_roots: None,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_walk_deeper_than_its_permits() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        std::fs::create_dir_all(root.join("a/b/c")).unwrap();
+        std::fs::write(root.join("a/b/c/deep.txt"), "").unwrap();
+
+        let options = Arc::new(WalkOptions {
+            open_directories: Arc::new(Semaphore::new(1)),
+            ..(*options()).clone()
+        });
+        let mut walk = Walk::new(options.clone(), root, root, root).await.unwrap();
+        let mut count = 0;
+        while walk.next_entry().await.unwrap().is_some() {
+            count += 1;
+        }
+
+        assert_eq!(count, 4);
+        assert_eq!(options.open_directories.available_permits(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_walk_honors_ignore_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        std::fs::create_dir_all(root.join("src/generated")).unwrap();
+        std::fs::create_dir_all(root.join("target")).unwrap();
+        std::fs::write(root.join(".gitignore"), "*.log\ngenerated/\n").unwrap();
+        std::fs::write(root.join("src/.mceditignore"), "!keep.log\n").unwrap();
+        std::fs::write(root.join("src/main.rs"), "").unwrap();
+        std::fs::write(root.join("src/debug.log"), "").unwrap();
+        std::fs::write(root.join("src/keep.log"), "").unwrap();
+        std::fs::write(root.join("src/generated/out.rs"), "").unwrap();
+        std::fs::write(root.join("target/app"), "").unwrap();
+
+        let mut walk = Walk::new(options(), root, root, root).await.unwrap();
+        let mut paths = Vec::new();
+        while let Some(entry) = walk.next_entry().await.unwrap() {
+            paths.push(entry.path.strip_prefix(root).unwrap().to_path_buf());
+        }
+        paths.sort();
+
+        assert_eq!(
+            paths,
+            vec![
+                PathBuf::from("src"),
+                PathBuf::from("src/keep.log"),
+                PathBuf::from("src/main.rs"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_exclude_patterns_negation_reincludes() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        std::fs::create_dir_all(root.join("target/debug")).unwrap();
+        std::fs::write(root.join("target/important.txt"), "").unwrap();
+        std::fs::write(root.join("target/debug/app"), "").unwrap();
+        std::fs::write(root.join("main.rs"), "").unwrap();
+        std::fs::write(root.join("build.log"), "").unwrap();
+        std::fs::write(root.join("keep.log"), "").unwrap();
+
+        let options = Arc::new(WalkOptions {
+            exclude_patterns: vec![
+                "target".to_string(),
+                "!target/important.txt".to_string(),
+                "*.log".to_string(),
+                "!keep.log".to_string(),
+            ],
+            ..(*options()).clone()
+        });
+        let mut walk = Walk::new(options, root, root, root).await.unwrap();
+        let mut paths = Vec::new();
+        while let Some(entry) = walk.next_entry().await.unwrap() {
+            paths.push(entry.path.strip_prefix(root).unwrap().to_path_buf());
+        }
+        paths.sort();
+
+        assert_eq!(
+            paths,
+            vec![
+                PathBuf::from("keep.log"),
+                PathBuf::from("main.rs"),
+                PathBuf::from("target/important.txt"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,287 @@
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Language {
+    Rust,
+    Go,
+    JavaScript,
+    TypeScript,
+    Python,
+    Java,
+    C,
+    Cpp,
+    CHeader,
+    CppHeader,
+    CSharp,
+    Ruby,
+    Php,
+    Html,
+    Css,
+    Json,
+    Markdown,
+    Yaml,
+    Toml,
+    Xml,
+    Text,
+    Shell,
+    Batch,
+    PowerShell,
+    Terraform,
+    Sql,
+    Dockerfile,
+    Makefile,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Confidence {
+    High,
+    Medium,
+}
+
+impl Confidence {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Confidence::High => "high",
+            Confidence::Medium => "medium",
+        }
+    }
+}
+
+// What a language detection was based on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DetectionSource {
+    Filename,
+    Extension,
+    Shebang,
+}
+
+impl DetectionSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DetectionSource::Filename => "filename",
+            DetectionSource::Extension => "extension",
+            DetectionSource::Shebang => "shebang",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Detection {
+    pub language: Language,
+    pub confidence: Confidence,
+    pub source: DetectionSource,
+}
+
+impl Language {
+    // Human readable language name
+    pub fn name(&self) -> &'static str {
+        match self {
+            Language::Rust => "Rust",
+            Language::Go => "Go",
+            Language::JavaScript => "JavaScript",
+            Language::TypeScript => "TypeScript",
+            Language::Python => "Python",
+            Language::Java => "Java",
+            Language::C => "C",
+            Language::Cpp => "C++",
+            Language::CHeader => "C/C++ Header",
+            Language::CppHeader => "C++ Header",
+            Language::CSharp => "C#",
+            Language::Ruby => "Ruby",
+            Language::Php => "PHP",
+            Language::Html => "HTML",
+            Language::Css => "CSS",
+            Language::Json => "JSON",
+            Language::Markdown => "Markdown",
+            Language::Yaml => "YAML",
+            Language::Toml => "TOML",
+            Language::Xml => "XML",
+            Language::Text => "Text",
+            Language::Shell => "Shell",
+            Language::Batch => "Batch",
+            Language::PowerShell => "PowerShell",
+            Language::Terraform => "Terraform",
+            Language::Sql => "SQL",
+            Language::Dockerfile => "Dockerfile",
+            Language::Makefile => "Makefile",
+        }
+    }
+
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        let language = match extension.to_lowercase().as_str() {
+            "rs" => Language::Rust,
+            "go" => Language::Go,
+            "js" | "jsx" | "mjs" | "cjs" => Language::JavaScript,
+            "ts" | "tsx" | "mts" | "cts" => Language::TypeScript,
+            "py" | "pyi" => Language::Python,
+            "java" => Language::Java,
+            "c" => Language::C,
+            "cpp" | "cc" | "cxx" => Language::Cpp,
+            "h" => Language::CHeader,
+            "hpp" | "hh" | "hxx" => Language::CppHeader,
+            "cs" => Language::CSharp,
+            "rb" => Language::Ruby,
+            "php" => Language::Php,
+            "html" | "htm" => Language::Html,
+            "css" => Language::Css,
+            "json" => Language::Json,
+            "md" | "markdown" => Language::Markdown,
+            "yml" | "yaml" => Language::Yaml,
+            "toml" => Language::Toml,
+            "xml" => Language::Xml,
+            "txt" => Language::Text,
+            "sh" | "bash" | "zsh" => Language::Shell,
+            "bat" | "cmd" => Language::Batch,
+            "ps1" => Language::PowerShell,
+            "tf" => Language::Terraform,
+            "sql" => Language::Sql,
+            "mk" => Language::Makefile,
+            _ => return None,
+        };
+
+        Some(language)
+    }
+
+    pub fn from_filename(filename: &str) -> Option<Self> {
+        match filename {
+            "Dockerfile" | "Containerfile" => Some(Language::Dockerfile),
+            "Makefile" | "makefile" | "GNUmakefile" => Some(Language::Makefile),
+            _ if filename.starts_with("Dockerfile.") => Some(Language::Dockerfile),
+            _ => None,
+        }
+    }
+
+    // Detects the interpreter named in a shebang line such as
+    // `#!/usr/bin/env python3` or `#!/bin/bash`
+    pub fn from_shebang(line: &str) -> Option<Self> {
+        let command = line.strip_prefix("#!")?.trim();
+        let mut parts = command.split_whitespace();
+        let program = parts.next()?.rsplit('/').next()?;
+
+        let interpreter = if program == "env" {
+            parts.find(|part| !part.starts_with('-'))?
+        } else {
+            program
+        };
+
+        // Strip version suffixes like python3.11
+        let interpreter = interpreter.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+
+        match interpreter {
+            "python" => Some(Language::Python),
+            "node" | "nodejs" | "deno" => Some(Language::JavaScript),
+            "ts-node" => Some(Language::TypeScript),
+            "sh" | "bash" | "zsh" | "dash" | "ksh" => Some(Language::Shell),
+            "ruby" => Some(Language::Ruby),
+            "php" => Some(Language::Php),
+            "pwsh" => Some(Language::PowerShell),
+            _ => None,
+        }
+    }
+}
+
+/// Detects the language of a file from its name, extension, and the shebang
+/// in its first line, reporting how confident the detection is
+pub fn detect(path: &Path, first_line: Option<&str>) -> Option<Detection> {
+    if let Some(language) = path
+        .file_name()
+        .and_then(|n| Language::from_filename(&n.to_string_lossy()))
+    {
+        return Some(Detection {
+            language,
+            confidence: Confidence::High,
+            source: DetectionSource::Filename,
+        });
+    }
+
+    if let Some(language) = path
+        .extension()
+        .and_then(|e| Language::from_extension(&e.to_string_lossy()))
+    {
+        // .h is shared between C and C++
+        let confidence = if language == Language::CHeader {
+            Confidence::Medium
+        } else {
+            Confidence::High
+        };
+
+        return Some(Detection {
+            language,
+            confidence,
+            source: DetectionSource::Extension,
+        });
+    }
+
+    first_line
+        .and_then(Language::from_shebang)
+        .map(|language| Detection {
+            language,
+            confidence: Confidence::High,
+            source: DetectionSource::Shebang,
+        })
+}
+
+/// Detects the language of a file from its name, extension, and the shebang
+/// in its first line
+pub fn detect_language(path: &Path, first_line: Option<&str>) -> Option<Language> {
+    detect(path, first_line).map(|detection| detection.language)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_language_by_name_and_extension() {
+        assert_eq!(
+            detect_language(Path::new("src/main.rs"), None),
+            Some(Language::Rust)
+        );
+        assert_eq!(
+            detect_language(Path::new("App.TSX"), None),
+            Some(Language::TypeScript)
+        );
+        assert_eq!(
+            detect_language(Path::new("Dockerfile.dev"), None),
+            Some(Language::Dockerfile)
+        );
+        // The extension wins over a shebang
+        assert_eq!(
+            detect_language(Path::new("run.sh"), Some("#!/usr/bin/env python3")),
+            Some(Language::Shell)
+        );
+
+        let header = detect(Path::new("util.h"), None).unwrap();
+        assert_eq!(header.language, Language::CHeader);
+        assert_eq!(header.confidence, Confidence::Medium);
+    }
+
+    #[test]
+    fn test_detect_language_by_shebang() {
+        let cases = [
+            ("#!/usr/bin/env python3.11", Language::Python),
+            ("#!/usr/bin/env -S node --no-warnings", Language::JavaScript),
+            ("#!/bin/bash -e", Language::Shell),
+            ("#! /usr/bin/ruby", Language::Ruby),
+        ];
+        for (shebang, language) in cases {
+            let detection = detect(Path::new("bin/tool"), Some(shebang)).unwrap();
+            assert_eq!(detection.language, language, "{}", shebang);
+            assert_eq!(detection.source, DetectionSource::Shebang);
+        }
+    }
+
+    #[test]
+    fn test_detect_language_unknown() {
+        assert_eq!(detect_language(Path::new("data.xyz"), None), None);
+        assert_eq!(detect_language(Path::new("LICENSE"), None), None);
+        assert_eq!(
+            detect_language(Path::new("bin/tool"), Some("just text")),
+            None
+        );
+        assert_eq!(
+            detect_language(Path::new("bin/tool"), Some("#!/usr/bin/env perl")),
+            None
+        );
+    }
+}
@@ -1,10 +1,11 @@
 use chrono::Local;
 use serde_json::json;
 use std::fmt::Display;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
 
 use crate::mcp::stdio::{Message, Transport};
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
 pub enum LogLevel {
     Debug,
     Info,
@@ -23,8 +24,73 @@ impl Display for LogLevel {
     }
 }
 
+impl LogLevel {
+    /// Parse a level as MCEDIT_LOG_LEVEL names it
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "debug" => Some(LogLevel::Debug),
+            "info" => Some(LogLevel::Info),
+            "warn" | "warning" => Some(LogLevel::Warning),
+            "error" => Some(LogLevel::Error),
+            _ => None,
+        }
+    }
+}
+
+// Least severe level logged to stderr, as a LogLevel discriminant
+static MIN_LEVEL: AtomicU8 = AtomicU8::new(LogLevel::Info as u8);
+
+// Whether --quiet or MCEDIT_LOG_LEVEL chose the level, which the config
+// file then leaves alone
+static LEVEL_FROM_ENVIRONMENT: AtomicBool = AtomicBool::new(false);
+
+/// Only log messages at `level` or above from now on
+pub fn set_level(level: LogLevel) {
+    MIN_LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+/// Set the level from MCEDIT_LOG_LEVEL, defaulting to info, or to warnings
+/// and errors only when `quiet`
+pub fn init(quiet: bool) {
+    if quiet {
+        set_level(LogLevel::Warning);
+        LEVEL_FROM_ENVIRONMENT.store(true, Ordering::Relaxed);
+        return;
+    }
+
+    match std::env::var("MCEDIT_LOG_LEVEL") {
+        Ok(name) if !name.is_empty() => match LogLevel::parse(&name) {
+            Some(level) => {
+                set_level(level);
+                LEVEL_FROM_ENVIRONMENT.store(true, Ordering::Relaxed);
+            }
+            None => warn(&format!(
+                "Ignoring MCEDIT_LOG_LEVEL={} (expected debug, info, warn or error)",
+                name
+            )),
+        },
+        _ => set_level(LogLevel::Info),
+    }
+}
+
+/// Set the level the config file names, or info when it names none, unless
+/// --quiet or MCEDIT_LOG_LEVEL already chose one. Called again on reload
+pub fn set_configured_level(level: Option<LogLevel>) {
+    if !LEVEL_FROM_ENVIRONMENT.load(Ordering::Relaxed) {
+        set_level(level.unwrap_or(LogLevel::Info));
+    }
+}
+
+/// Whether messages at `level` are logged
+pub fn enabled(level: LogLevel) -> bool {
+    level as u8 >= MIN_LEVEL.load(Ordering::Relaxed)
+}
+
 /// Log a message to stderr with timestamp and log level
 pub fn log(level: LogLevel, message: &str) {
+    if !enabled(level) {
+        return;
+    }
     let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S%.3f");
     eprintln!("[{}] [{}] {}", timestamp, level, message);
 }
@@ -50,7 +116,7 @@ pub fn error(message: &str) {
 }
 
 /// Send a log message to the client via MCP
-pub async fn send_log_message<T: Transport>(
+pub async fn send_log_message<T: Transport + ?Sized>(
     transport: &T,
     level: LogLevel,
     message: &str,
@@ -69,7 +135,7 @@ pub async fn send_log_message<T: Transport>(
 }
 
 /// Log a message both to stderr and to the client via MCP
-pub async fn log_both<T: Transport>(
+pub async fn log_both<T: Transport + ?Sized>(
     transport: &T,
     level: LogLevel,
     message: &str,
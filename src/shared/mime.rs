@@ -0,0 +1,139 @@
+use std::path::Path;
+use tokio::io::AsyncReadExt;
+
+// Reported for text files with no more specific type
+pub const TEXT_PLAIN: &str = "text/plain";
+
+// Reported for binary files with no more specific type
+pub const OCTET_STREAM: &str = "application/octet-stream";
+
+// Bytes inspected when guessing whether unknown content is binary
+pub const SNIFF_LENGTH: usize = 8192;
+
+// Share of control characters above which content is treated as binary
+const MAX_CONTROL_RATIO: f64 = 0.3;
+
+pub fn from_extension(extension: &str) -> Option<&'static str> {
+    let mime = match extension.to_lowercase().as_str() {
+        // Source code
+        "rs" => "text/x-rust",
+        "go" => "text/x-go",
+        "js" | "mjs" | "cjs" | "jsx" => "text/javascript",
+        "ts" | "mts" | "cts" | "tsx" => "text/x-typescript",
+        "py" | "pyi" => "text/x-python",
+        "java" => "text/x-java",
+        "c" | "h" => "text/x-c",
+        "cpp" | "cc" | "cxx" | "hpp" | "hh" | "hxx" => "text/x-c++",
+        "cs" => "text/x-csharp",
+        "rb" => "text/x-ruby",
+        "php" => "text/x-php",
+        "sh" | "bash" | "zsh" => "text/x-shellscript",
+        "ps1" => "text/x-powershell",
+        "bat" | "cmd" => "text/x-bat",
+        "sql" => "application/sql",
+        "tf" => "text/x-terraform",
+        // Markup and data
+        "html" | "htm" => "text/html",
+        "css" => "text/css",
+        "md" | "markdown" => "text/markdown",
+        "json" => "application/json",
+        "xml" => "application/xml",
+        "yml" | "yaml" => "application/yaml",
+        "toml" => "application/toml",
+        "csv" => "text/csv",
+        "txt" | "log" => TEXT_PLAIN,
+        // Images
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "svg" => "image/svg+xml",
+        "ico" => "image/x-icon",
+        "bmp" => "image/bmp",
+        // Other binary formats
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "gz" => "application/gzip",
+        "tar" => "application/x-tar",
+        "wasm" => "application/wasm",
+        _ => return None,
+    };
+
+    Some(mime)
+}
+
+// Content is treated as binary if it contains a NUL byte, isn't UTF-8, or
+// is mostly control characters
+pub fn looks_binary(sample: &[u8]) -> bool {
+    if sample.is_empty() {
+        return false;
+    }
+    if sample.contains(&0) {
+        return true;
+    }
+    // A character cut off at the end of the sample doesn't count as invalid
+    if let Err(err) = std::str::from_utf8(sample) {
+        if err.error_len().is_some() {
+            return true;
+        }
+    }
+
+    let control = sample
+        .iter()
+        .filter(|&&b| (b < 0x20 && !matches!(b, b'\t' | b'\n' | b'\r' | 0x0c)) || b == 0x7f)
+        .count();
+    control as f64 / sample.len() as f64 > MAX_CONTROL_RATIO
+}
+
+/// Whether a file looks binary, judging by its first few kilobytes.
+/// Files that can't be read are not reported as binary
+pub async fn is_probably_binary(path: &Path) -> bool {
+    let file = match tokio::fs::File::open(path).await {
+        Ok(file) => file,
+        Err(_) => return false,
+    };
+
+    let mut sample = Vec::with_capacity(SNIFF_LENGTH);
+    match file
+        .take(SNIFF_LENGTH as u64)
+        .read_to_end(&mut sample)
+        .await
+    {
+        Ok(_) => looks_binary(&sample),
+        Err(_) => false,
+    }
+}
+
+/// MIME type of a file from its extension, falling back to `text/plain` or
+/// `application/octet-stream` depending on a sample of its content
+pub fn mime_type(path: &Path, sample: Option<&[u8]>) -> &'static str {
+    if let Some(mime) = path
+        .extension()
+        .and_then(|e| from_extension(&e.to_string_lossy()))
+    {
+        return mime;
+    }
+
+    match sample {
+        Some(sample) if looks_binary(sample) => OCTET_STREAM,
+        _ => TEXT_PLAIN,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_looks_binary() {
+        assert!(!looks_binary(b""));
+        assert!(!looks_binary(
+            "fn main() {\n\tprintln!(\"héllo\");\r\n}".as_bytes()
+        ));
+        assert!(looks_binary(b"PK\x03\x04\x00\x00"));
+        assert!(looks_binary(b"\xff\xfe\xfd text"));
+        assert!(looks_binary(b"\x01\x02\x03\x04ab"));
+        // A multibyte character cut off by the sample length is still text
+        assert!(!looks_binary(&"abcé".as_bytes()[..4]));
+    }
+}
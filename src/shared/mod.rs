@@ -1,2 +1,5 @@
+pub mod language;
 pub mod logging;
+pub mod mime;
+pub mod tokens;
 pub mod utils;
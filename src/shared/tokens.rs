@@ -0,0 +1,7 @@
+// Rough number of characters per token for typical source code and prose
+const CHARS_PER_TOKEN: usize = 4;
+
+/// Estimates how many tokens text takes up in a model's context window
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(CHARS_PER_TOKEN)
+}
@@ -1,7 +1,6 @@
 use dirs::home_dir;
 use std::path::{Path, PathBuf};
 
-#[allow(dead_code)]
 pub fn expand_tilde(path: &str) -> PathBuf {
     if !path.starts_with('~') {
         return PathBuf::from(path);
@@ -50,10 +49,69 @@ pub fn contract_tilde(path: &Path) -> String {
     }
 }
 
+/// Formats a path for tool output. Paths always use forward slashes, so
+/// clients see the same separator whatever platform the server runs on
+pub fn to_display_path(path: &Path) -> String {
+    let path = path.to_string_lossy();
+    // A backslash is an ordinary file name character outside Windows
+    if std::path::MAIN_SEPARATOR == '\\' {
+        path.replace('\\', "/")
+    } else {
+        path.into_owned()
+    }
+}
+
+/// The path of a `file://` URI, percent-decoded. None for other schemes or
+/// escapes that don't decode to UTF-8
+pub fn path_from_file_uri(uri: &str) -> Option<String> {
+    let encoded = uri.strip_prefix("file://")?.as_bytes();
+    let mut decoded = Vec::with_capacity(encoded.len());
+
+    let mut i = 0;
+    while i < encoded.len() {
+        let escaped = match encoded[i] {
+            b'%' => encoded
+                .get(i + 1..i + 3)
+                .and_then(|hex| std::str::from_utf8(hex).ok())
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+            _ => None,
+        };
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(encoded[i]);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8(decoded).ok()
+}
+
+// Files and directories whose presence marks the root of a project
+const PROJECT_ROOT_MARKERS: [&str; 3] = [".git", "Cargo.toml", "package.json"];
+
+/// Walks up from `start` looking for the nearest directory containing a
+/// project root marker (`.git`, `Cargo.toml`, `package.json`)
+pub fn find_project_root(start: &Path) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .find(|dir| {
+            PROJECT_ROOT_MARKERS
+                .iter()
+                .any(|marker| dir.join(marker).exists())
+        })
+        .map(|dir| dir.to_path_buf())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::path::PathBuf;
+    use tempfile::TempDir;
 
     #[test]
     fn test_expand_tilde_no_tilde() {
@@ -103,4 +161,39 @@ mod tests {
         let contracted = contract_tilde(&sub_path);
         assert_eq!(contracted, "~/Pictures");
     }
+
+    #[test]
+    fn test_find_project_root_from_subdir() {
+        let temp = TempDir::new().unwrap();
+        std::fs::write(temp.path().join("Cargo.toml"), "").unwrap();
+        let nested = temp.path().join("src").join("nested");
+        std::fs::create_dir_all(&nested).unwrap();
+
+        assert_eq!(find_project_root(&nested), Some(temp.path().to_path_buf()));
+    }
+
+    #[test]
+    fn test_path_from_file_uri() {
+        assert_eq!(
+            path_from_file_uri("file:///src/main.rs").as_deref(),
+            Some("/src/main.rs")
+        );
+        assert_eq!(
+            path_from_file_uri("file:///my%20notes/caf%C3%A9.md").as_deref(),
+            Some("/my notes/café.md")
+        );
+        assert_eq!(path_from_file_uri("file:///100%").as_deref(), Some("/100%"));
+        assert_eq!(path_from_file_uri("https://example.com/a"), None);
+    }
+
+    #[test]
+    fn test_find_project_root_prefers_nearest_marker() {
+        let temp = TempDir::new().unwrap();
+        std::fs::create_dir(temp.path().join(".git")).unwrap();
+        let package = temp.path().join("packages").join("web");
+        std::fs::create_dir_all(&package).unwrap();
+        std::fs::write(package.join("package.json"), "{}").unwrap();
+
+        assert_eq!(find_project_root(&package.join("src")), Some(package));
+    }
 }
@@ -1,7 +1,8 @@
 use std::path::{Path, PathBuf};
 use serde_json::{json, Value};
-use crate::editor::file_editor::FileEditor;
+use crate::editor::file_editor::{region_range, splice_lines, FileEditor, LineBuffer};
 use crate::shared::logging;
+use crate::shared::utils::path::to_display_path;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -26,7 +27,7 @@ pub struct SuggestionApplier {
 impl SuggestionApplier {
     pub fn new() -> Self {
         Self {
-            editor: FileEditor::new(),
+            editor: FileEditor::default(),
         }
     }
 
@@ -90,7 +91,7 @@ impl SuggestionApplier {
         Ok(json!({
             "success": true,
             "action": "replace",
-            "file": file_path.to_string_lossy()
+            "file": to_display_path(file_path)
         }))
     }
 
@@ -114,7 +115,7 @@ impl SuggestionApplier {
             return Ok(json!({
                 "success": true,
                 "action": "edit",
-                "file": file_path.to_string_lossy(),
+                "file": to_display_path(file_path),
                 "edits_applied": 0,
                 "message": "No edits to apply"
             }));
@@ -133,7 +134,7 @@ impl SuggestionApplier {
 
         // We need to read the file content first
         let original_content = self.editor.read_file(file_path).await?;
-        let mut lines: Vec<&str> = original_content.lines().collect();
+        let mut buffer = LineBuffer::parse(&original_content);
 
         for edit in edits {
             let action = edit.get("action").and_then(|a| a.as_str()).unwrap_or("unknown");
@@ -147,9 +148,9 @@ impl SuggestionApplier {
                         let line_num = line as usize;
 
                         // Line number might be 1-based, so handle both possibilities
-                        if line_num <= lines.len() {
+                        if line_num <= buffer.lines.len() {
                             // Insert at the given position
-                            lines.insert(line_num, content);
+                            buffer.lines.insert(line_num, content.to_string());
                             edits_applied += 1;
 
                             results.push(json!({
@@ -174,9 +175,9 @@ impl SuggestionApplier {
                     ) {
                         let line_num = line as usize;
 
-                        if line_num < lines.len() {
+                        if line_num < buffer.lines.len() {
                             // Replace the line
-                            lines[line_num] = content;
+                            buffer.lines[line_num] = content.to_string();
                             edits_applied += 1;
 
                             results.push(json!({
@@ -198,9 +199,9 @@ impl SuggestionApplier {
                     if let Some(line) = edit.get("line").and_then(|l| l.as_u64()) {
                         let line_num = line as usize;
 
-                        if line_num < lines.len() {
+                        if line_num < buffer.lines.len() {
                             // Delete the line
-                            lines.remove(line_num);
+                            buffer.lines.remove(line_num);
                             edits_applied += 1;
 
                             results.push(json!({
@@ -227,47 +228,29 @@ impl SuggestionApplier {
                         let start_line = start as usize;
                         let end_line = end as usize;
 
-                        if start_line <= end_line && start_line < lines.len() {
-                            // Extract lines before the region
-                            let before = if start_line > 0 {
-                                lines[0..start_line].to_vec()
-                            } else {
-                                Vec::new()
-                            };
-
-                            // Extract lines after the region
-                            let after = if end_line < lines.len() {
-                                lines[end_line + 1..].to_vec()
-                            } else {
-                                Vec::new()
-                            };
-
-                            // Split the new content into lines
-                            let new_lines: Vec<&str> = content.lines().collect();
-
-                            // Combine before, new content, and after
-                            let mut new_lines_vec = Vec::new();
-                            new_lines_vec.extend(before);
-                            new_lines_vec.extend(new_lines);
-                            new_lines_vec.extend(after);
-
-                            lines = new_lines_vec;
-                            edits_applied += 1;
-
-                            results.push(json!({
-                                "action": "region",
-                                "start": start_line,
-                                "end": end_line,
-                                "status": "success"
-                            }));
-                        } else {
-                            results.push(json!({
-                                "action": "region",
-                                "start": start_line,
-                                "end": end_line,
-                                "status": "error",
-                                "message": "Invalid line range"
-                            }));
+                        // Lines start_line through end_line, 1-based and
+                        // inclusive, as FileEditor::edit_region takes them
+                        match region_range(start_line, end_line, buffer.lines.len()) {
+                            Ok(range) => {
+                                splice_lines(&mut buffer, range.start, range.end, content);
+                                edits_applied += 1;
+
+                                results.push(json!({
+                                    "action": "region",
+                                    "start": start_line,
+                                    "end": end_line,
+                                    "status": "success"
+                                }));
+                            }
+                            Err(err) => {
+                                results.push(json!({
+                                    "action": "region",
+                                    "start": start_line,
+                                    "end": end_line,
+                                    "status": "error",
+                                    "message": err.to_string()
+                                }));
+                            }
                         }
                     }
                 },
@@ -281,14 +264,13 @@ impl SuggestionApplier {
             }
         }
 
-        // Write the updated content back to the file
-        let new_content = lines.join("\n");
-        self.editor.write_file(file_path, &new_content).await?;
+        // Write the updated content back to the file, keeping how its lines end
+        self.editor.write_file(file_path, &buffer.render()).await?;
 
         Ok(json!({
             "success": true,
             "action": "edit",
-            "file": file_path.to_string_lossy(),
+            "file": to_display_path(file_path),
             "edits_applied": edits_applied,
             "results": results
         }))
@@ -335,8 +317,36 @@ impl SuggestionApplier {
         Ok(json!({
             "success": true,
             "action": "create",
-            "file": file_path.to_string_lossy(),
+            "file": to_display_path(file_path),
             "overwritten": file_path.exists()
         }))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_region_edit_agrees_with_edit_region() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("f.txt");
+        let applier = SuggestionApplier::new();
+
+        // Same cases as FileEditor::edit_region's test
+        let cases = [
+            (2, 2, "B", "a\nB\nc\nd\n"),
+            (3, 9, "C", "a\nb\nC\n"),
+            (2, 3, "", "a\nd\n"),
+        ];
+        for (start, end, content, expected) in cases {
+            std::fs::write(&path, "a\nb\nc\nd\n").unwrap();
+            let suggestion = json!({
+                "type": "edit",
+                "edits": [{ "action": "region", "start": start, "end": end, "content": content }]
+            });
+            applier.apply_suggestion(&path, &suggestion).await.unwrap();
+            assert_eq!(std::fs::read_to_string(&path).unwrap(), expected);
+        }
+    }
+}
@@ -78,6 +78,15 @@ impl SuggestionParser {
             "replace" => value.get("content").is_some(),
             "edit" => value.get("edits").is_some() && value.get("edits").unwrap().is_array(),
             "create" => value.get("content").is_some(),
+            "patch" => value.get("diff").map(|d| d.is_string()).unwrap_or(false),
+            "multi" => match value.get("files").and_then(|f| f.as_array()) {
+                Some(files) => files.iter().all(|file| {
+                    file.get("path").map(|p| p.is_string()).unwrap_or(false)
+                        && file.get("type").and_then(|t| t.as_str()) != Some("multi")
+                        && Self::is_valid_json_suggestion(file)
+                }),
+                None => false,
+            },
             _ => false,
         }
     }